@@ -405,6 +405,8 @@ impl ASTParser {
             "or"
         } else if op.eq("not") {
             "not"
+        } else if op.eq("as") {
+            "as"
         } else if op.eq("!") {
             "!"
         } else if op.eq("!=") {
@@ -431,6 +433,8 @@ impl ASTParser {
             "/="
         } else if op.eq("%=") {
             "%="
+        } else if op.eq("->") {
+            "->"
         } else {
             "asdfasdf"
         };
@@ -2,6 +2,7 @@ use std::fmt::Display;
 use std::rc::Rc;
 
 use crate::ast::SyntaxError;
+use crate::ast::parse::ASTParser;
 use crate::ast::token::defer::FSRDefer;
 use crate::ast::token::module::FSRModuleFrontEnd;
 use crate::ast::token::xtruct::FSRStructFrontEnd;
@@ -241,6 +242,8 @@ impl Display for FSRPosition {
 pub struct FSRTypeName {
     pub name: String,
     pub subtype: Option<Vec<Box<FSRTypeName>>>,
+    /// return type of a function type, its params are in `subtype`
+    pub ret: Option<Box<FSRTypeName>>,
 }
 
 impl FSRTypeName {
@@ -248,8 +251,53 @@ impl FSRTypeName {
         Self {
             name: name.to_string(),
             subtype: None,
+            ret: None,
         }
     }
+
+    /// `fn(params) -> ret`, the type of a function pointer
+    pub fn new_fn(params: Vec<Box<FSRTypeName>>, ret: Option<FSRTypeName>) -> Self {
+        Self {
+            name: "fn".to_string(),
+            subtype: Some(params),
+            ret: ret.map(Box::new),
+        }
+    }
+
+    pub fn is_fn(&self) -> bool {
+        self.name == "fn"
+    }
+
+    /// Read the type parameters `[T, U]` at the start of `source`, the
+    /// names and the length read including the brackets
+    pub fn parse_params(
+        source: &[char],
+        meta: &FSRPosition,
+    ) -> Result<(Vec<String>, usize), SyntaxError> {
+        let Some(end) = source.iter().position(|c| *c == ']') else {
+            return Err(SyntaxError::new(meta, "type parameters must end with ']'"));
+        };
+
+        let params = source[1..end]
+            .iter()
+            .collect::<String>()
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .collect::<Vec<_>>();
+        for param in params.iter() {
+            let mut chars = param.chars();
+            if !chars.next().is_some_and(ASTParser::is_name_letter_first)
+                || !chars.all(ASTParser::is_name_letter)
+            {
+                return Err(SyntaxError::new(
+                    meta,
+                    format!("invalid type parameter: `{}`", param),
+                ));
+            }
+        }
+
+        Ok((params, end + 1))
+    }
 }
 
 // &[char] to string, export to use
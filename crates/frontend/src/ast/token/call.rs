@@ -1,5 +1,5 @@
 use super::{
-    base::{FSRPosition, FSRToken},
+    base::{FSRPosition, FSRToken, FSRTypeName},
     expr::{FSRExpr, SingleOp},
     ASTContext,
 };
@@ -14,6 +14,8 @@ pub struct FSRCall {
     pub single_op: Option<SingleOp>,
     meta: FSRPosition,
     pub is_defined: bool,
+    /// type of the variable called, set when it holds a function pointer
+    pub var_type: Option<FSRTypeName>,
 }
 
 #[derive(PartialEq)]
@@ -121,6 +123,7 @@ impl FSRCall {
             single_op: None,
             meta,
             is_defined: false,
+            var_type: None,
         })
     }
 
@@ -186,7 +186,7 @@ impl<'a> Node<'a> {
             return 2;
         }
 
-        if op.eq(".") || op.eq("::") {
+        if op.eq(".") || op.eq("::") || op.eq("as") {
             return 3;
         }
 
@@ -219,6 +219,11 @@ impl<'a> Node<'a> {
             return 6;
         }
 
+        // `f: fn(u64) -> u64` is `f: (fn(u64) -> u64)`
+        if op.eq("->") {
+            return 7;
+        }
+
         if op.eq(",") {
             return -7;
         }
@@ -842,8 +847,9 @@ impl FSRExpr {
         Ok(())
     }
 
+    /// Keywords parsed as operators, `as` is the static cast
     fn is_logic_keyword(name: &str) -> bool {
-        name.eq("and") || name.eq("or") || name.eq("not")
+        name.eq("and") || name.eq("or") || name.eq("not") || name.eq("as")
     }
 
     #[inline]
@@ -1139,6 +1145,8 @@ impl FSRExpr {
             false,
         )?;
 
+        call.var_type = context.get_var_type(call.get_name());
+
         // if reference to defined variable, will set is_defined to true
         if context.is_variable_defined_in_curr(call.get_name()) {
             call.is_defined = true;
@@ -1444,14 +1452,51 @@ impl FSRExpr {
         Ok(())
     }
 
+    /// `Pair[u8, u64]` reads its type arguments as one `,` expression
+    fn parse_type_args(args: &FSRToken, meta: &FSRPosition, res: &mut Vec<Box<FSRTypeName>>) {
+        match args {
+            FSRToken::Expr(e) if e.get_op() == "," => {
+                Self::parse_type_args(e.get_left(), meta, res);
+                Self::parse_type_args(e.get_right(), meta, res);
+            }
+            _ => res.push(Box::new(Self::parse_type_hint(args, meta))),
+        }
+    }
+
+    /// `fn(u64, u64) -> bool` reads as the call `fn(u64, u64)` on the left of `->`
+    fn parse_fn_type(
+        params: &FSRCall,
+        ret: Option<&FSRToken>,
+        meta: &FSRPosition,
+    ) -> FSRTypeName {
+        let params = params
+            .get_args()
+            .iter()
+            .map(|x| Box::new(Self::parse_type_hint(x, meta)))
+            .collect();
+        let ret = ret.map(|x| Self::parse_type_hint(x, meta));
+        FSRTypeName::new_fn(params, ret)
+    }
+
     pub fn parse_type_hint(type_hint: &FSRToken, meta: &FSRPosition) -> FSRTypeName {
-        if let FSRToken::Variable(type_name) = type_hint {
+        if let FSRToken::Call(c) = type_hint
+            && c.get_name() == "fn"
+        {
+            Self::parse_fn_type(c, None, meta)
+        } else if let FSRToken::Expr(e) = type_hint
+            && e.get_op() == "->"
+        {
+            let FSRToken::Call(c) = e.get_left() else {
+                panic!("{}: `->` in a type hint must follow fn(...)", meta);
+            };
+            Self::parse_fn_type(c, Some(e.get_right()), meta)
+        } else if let FSRToken::Variable(type_name) = type_hint {
             FSRTypeName::new(type_name.get_name())
         } else if let FSRToken::Getter(type_inner) = type_hint {
-            let sub_type = type_inner.get_getter();
-            let sub_type_inner = Self::parse_type_hint(sub_type, meta);
+            let mut sub_types = vec![];
+            Self::parse_type_args(type_inner.get_getter(), meta, &mut sub_types);
             let mut new_type = FSRTypeName::new(type_inner.get_name());
-            new_type.subtype = Some(vec![Box::new(sub_type_inner)]);
+            new_type.subtype = Some(sub_types);
             new_type
         } else if let FSRToken::List(l) = type_hint {
             // Process like [Type, len]
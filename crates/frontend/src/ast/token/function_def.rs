@@ -24,6 +24,8 @@ pub struct FSRFnDef {
     len: usize,
     meta: FSRPosition,
     pub ret_type: Option<FSRTypeName>,
+    /// type parameters of a generic function, `fn max[T](a: T, b: T) -> T`
+    pub generics: Vec<String>,
     pub ref_map: Rc<RefCell<HashMap<String, ASTVariableState>>>,
 }
 
@@ -81,6 +83,13 @@ impl FSRFnDef {
             .unwrap_or(false)
     }
 
+    pub fn is_guard(&self) -> bool {
+        self.teller
+            .as_ref()
+            .map(|x| x.value.iter().any(|x| x.eq("@guard")))
+            .unwrap_or(false)
+    }
+
     pub fn is_static(&self) -> bool {
         self.teller
             .as_ref()
@@ -126,7 +135,10 @@ impl FSRFnDef {
 
         // let args_s = std::str::from_utf8(args).unwrap();
         let args_s = chrs2str!(args);
-        let mut arg_collect = if args_s.trim().is_empty() {
+        context.push_scope();
+        let mut arg_collect = if args.contains(&':') {
+            Self::parse_typed_lambda_args(args, meta.new_offset(1), context)?
+        } else if args_s.trim().is_empty() {
             vec![]
         } else {
             let args_define = args_s
@@ -179,11 +191,16 @@ impl FSRFnDef {
             arg_collect
         };
 
+        let ret_start = args_len + 1;
         while source[args_len] != '{' {
             args_len += 1;
         }
+        let ret_type = Self::parse_ret_type(
+            &source[ret_start..args_len],
+            meta.new_offset(ret_start),
+            context,
+        )?;
 
-        context.push_scope();
         for arg in &arg_collect {
             if let FSRToken::Variable(v) = arg {
                 context.add_variable(&v.name, Some(arg.clone()));
@@ -216,11 +233,37 @@ impl FSRFnDef {
             meta,
             lambda: true,
             ref_map: scope,
-            ret_type: None,
+            ret_type,
+            generics: vec![],
             teller: None,
         })
     }
 
+    /// Args of `|a: u64, b: u64| -> bool { ... }`, read like the args of a
+    /// function definition. Every arg needs a type once one has it
+    fn parse_typed_lambda_args(
+        args: &[char],
+        meta: FSRPosition,
+        context: &mut ASTContext,
+    ) -> Result<Vec<FSRToken>, SyntaxError> {
+        let (expr, _) = FSRExpr::parse(args, true, meta.clone(), context)?;
+        let mut arg_collect = vec![];
+        for arg in expr.flatten_comma() {
+            let FSRToken::Variable(mut variable) = arg else {
+                let err = SyntaxError::new(&meta, "Invalid lambda function, invalid arg");
+                return Err(err);
+            };
+            if variable.get_type_hint().is_none() {
+                let err = SyntaxError::new(&meta, "Invalid lambda function, untyped arg");
+                return Err(err);
+            }
+            variable.is_defined = true;
+            arg_collect.push(FSRToken::Variable(variable));
+        }
+
+        Ok(arg_collect)
+    }
+
     fn parse_ret_type(
         source: &[char],
         meta: FSRPosition,
@@ -394,9 +437,27 @@ impl FSRFnDef {
         let fn_args = &source[start_fn_name..start_fn_name + len];
         let sub_meta = meta.new_offset(start);
 
+        // `fn max[T](...)`, the type parameters are cut out before the call is read
+        let name_end = fn_args
+            .iter()
+            .position(|c| !ASTParser::is_blank_char(*c))
+            .and_then(|i| {
+                fn_args[i..]
+                    .iter()
+                    .position(|c| !ASTParser::is_name_letter(*c))
+                    .map(|x| x + i)
+            })
+            .unwrap_or(fn_args.len());
+        let (generics, generic_len) = if fn_args.get(name_end) == Some(&'[') {
+            FSRTypeName::parse_params(&fn_args[name_end..], &sub_meta)?
+        } else {
+            (vec![], 0)
+        };
+        let call_src = [&fn_args[..name_end], &fn_args[name_end + generic_len..]].concat();
+
         context.push_scope();
-        let mut fn_call = FSRCall::parse(fn_args, sub_meta, context, true)?;
-        let call_len = fn_call.get_len();
+        let mut fn_call = FSRCall::parse(&call_src, sub_meta, context, true)?;
+        let call_len = fn_call.get_len() + generic_len;
         let name = fn_call.get_name().to_string();
 
         let mut gap_call_len = 0;
@@ -462,6 +523,7 @@ impl FSRFnDef {
             lambda: false,
             ref_map: cur,
             ret_type,
+            generics,
             teller,
         };
 
@@ -486,4 +548,45 @@ mod test {
         assert!(result.is_ok());
         println!("{:#?}", result.unwrap());
     }
+
+    #[test]
+    fn test_typed_lambda() {
+        let source = "|f: fn(u64) -> u64, n: u64| -> u64 { return f(n) }";
+        let meta = FSRPosition::new();
+        let mut context = super::ASTContext::new_context();
+        let source = source.chars().collect::<Vec<char>>();
+        let result = super::FSRFnDef::parse_lambda(&source, meta, "lambda_typed", &mut context)
+            .unwrap();
+        assert_eq!(result.ret_type.as_ref().unwrap().name, "u64");
+        let Some(crate::ast::token::base::FSRToken::Variable(f)) = result.get_args().first() else {
+            panic!("f is not a variable");
+        };
+        let f_type = f.get_type_hint().unwrap();
+        assert!(f_type.is_fn());
+        assert_eq!(f_type.subtype.as_ref().unwrap()[0].name, "u64");
+        assert_eq!(f_type.ret.as_ref().unwrap().name, "u64");
+
+        let source = "|a: u64, b| { return a }".chars().collect::<Vec<char>>();
+        let meta = FSRPosition::new();
+        assert!(super::FSRFnDef::parse_lambda(&source, meta, "lambda_bad", &mut context).is_err());
+    }
+
+    #[test]
+    fn test_generic_fn() {
+        let source = "fn max[T, U](a: T, b: Ptr[Pair[T, U]]) -> T { return a }";
+        let meta = FSRPosition::new();
+        let mut context = super::ASTContext::new_context();
+        let source = source.chars().collect::<Vec<char>>();
+        let result = super::FSRFnDef::parse(&source, meta, &mut context, None).unwrap();
+        assert_eq!(result.get_name(), "max");
+        assert_eq!(result.generics, vec!["T", "U"]);
+        assert_eq!(result.ret_type.as_ref().unwrap().name, "T");
+        let Some(crate::ast::token::base::FSRToken::Variable(b)) = result.get_args().get(1) else {
+            panic!("b is not a variable");
+        };
+        let pair = &b.get_type_hint().unwrap().subtype.as_ref().unwrap()[0];
+        assert_eq!(pair.name, "Pair");
+        let args = pair.subtype.as_ref().unwrap();
+        assert_eq!(args.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(), ["T", "U"]);
+    }
 }
@@ -3,11 +3,13 @@ use crate::{
     ast::{SyntaxError, parse::ASTParser, token::block::FSRBlock}, chrs2str
 };
 
-use super::{base::FSRPosition, ASTContext};
+use super::{base::{FSRPosition, FSRTypeName}, ASTContext};
 use std::str;
 #[derive(Debug, Clone)]
 pub struct FSRStructFrontEnd {
     name: String,
+    /// type parameters of a generic struct, `struct Pair[A, B]`
+    generics: Vec<String>,
     block: FSRBlock,
     meta: FSRPosition,
 }
@@ -17,6 +19,10 @@ impl FSRStructFrontEnd {
         self.name.as_str()
     }
 
+    pub fn get_generics(&self) -> &[String] {
+        &self.generics
+    }
+
     pub fn get_block(&self) -> &FSRBlock {
         &self.block
     }
@@ -59,6 +65,15 @@ impl FSRStructFrontEnd {
         let name = chrs2str!(&source[start..start + length]);
         start += length;
 
+        let generics = if source[start] == '[' {
+            let (generics, len) =
+                FSRTypeName::parse_params(&source[start..], &meta.new_offset(start))?;
+            start += len;
+            generics
+        } else {
+            vec![]
+        };
+
         while start < source.len() && ASTParser::is_blank_char(source[start]) {
             start += 1;
         }
@@ -77,6 +92,6 @@ impl FSRStructFrontEnd {
             }
         }
         context.add_variable(&name, None);
-        Ok((Self { name: name.to_string(), block, meta }, start + len))
+        Ok((Self { name: name.to_string(), generics, block, meta }, start + len))
     }
 }
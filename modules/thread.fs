@@ -4,15 +4,10 @@ class Thread {
     }
 
     fn join(self) {
-        self.handle.join()
+        return self.handle.join()
     }
 
     fn thread_id() {
         return __get_cur_thread_id()
     }
-}
-
-class Mutex {
-    fn __new__(self) {
-    }
 }
\ No newline at end of file
@@ -5,15 +5,19 @@ use frontend::ast::token::call;
 use frontend::ast::token::defer::FSRDefer;
 use std::{
     cell::Cell,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    rc::Rc,
     str::FromStr,
     sync::{
-        Arc,
+        Arc, OnceLock,
         atomic::{AtomicU64, Ordering},
     },
 };
 
+use crate::backend::compiler::jit::jit_wrapper::FSRStaticStr;
+use crate::backend::compiler::type_checker::hint_class_name;
 use crate::backend::types::base::ObjId;
+use crate::backend::vm::inline_cache::AttrInlineCache;
 use crate::utils::error::FSRErrCode;
 use crate::utils::error::FSRError;
 use frontend::ast::token::constant::FSROrinStr;
@@ -34,6 +38,7 @@ use frontend::ast::token::{
     module::FSRModuleFrontEnd,
     return_def::FSRReturn,
     slice::FSRGetter,
+    tell::FSRTell,
     try_expr::FSRTryBlock,
     variable::FSRVariable,
     while_statement::FSRWhile,
@@ -278,6 +283,18 @@ pub enum BytecodeOperator {
     SAlloc = 62,
     SFree = 63,
     Raise = 64,
+    /// `Call` in tail position (`return f(...)`), reuses the current call frame
+    TailCall = 65,
+    /// Check a hinted argument of a `@guard` function against its class
+    TypeGuard = 66,
+    /// `x as u32` in static code, converts the value on top of the stack
+    SCast = 67, //jit used only
+    /// `len` / `slice` on a static string, the receiver and args are on the stack
+    SStrMethod = 68, //jit used only
+    /// Typed accessor of a dynamic `Object`, the receiver and args are on the stack
+    SObjMethod = 69, //jit used only
+    /// `f32x4(a, b, c, d)` or `f32x4(a)`, the lanes are on the stack
+    SVecNew = 70, //jit used only
     LoadConst = 252,
     LoadVar = 253,
     Load = 254,
@@ -466,6 +483,79 @@ impl FromStr for OpAssign {
     }
 }
 
+/// Methods of a static `string`, lowered inline by the jit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrMethod {
+    Len,
+    Slice,
+}
+
+impl StrMethod {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "len" => Some(StrMethod::Len),
+            "slice" => Some(StrMethod::Slice),
+            _ => None,
+        }
+    }
+
+    fn ret_type_name(&self) -> &'static str {
+        match self {
+            StrMethod::Len => "u64",
+            StrMethod::Slice => "string",
+        }
+    }
+
+    pub fn args_len(&self) -> usize {
+        match self {
+            StrMethod::Len => 0,
+            StrMethod::Slice => 2,
+        }
+    }
+}
+
+/// Typed accessors of a dynamic `Object` in static code, the jit calls into
+/// the vm for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjMethod {
+    Len,
+    Get,
+    GetInt,
+    GetFloat,
+    AsInt,
+    AsFloat,
+}
+
+impl ObjMethod {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "len" => Some(ObjMethod::Len),
+            "get" => Some(ObjMethod::Get),
+            "get_int" => Some(ObjMethod::GetInt),
+            "get_float" => Some(ObjMethod::GetFloat),
+            "as_int" => Some(ObjMethod::AsInt),
+            "as_float" => Some(ObjMethod::AsFloat),
+            _ => None,
+        }
+    }
+
+    fn ret_type_name(&self) -> &'static str {
+        match self {
+            ObjMethod::Len => "u64",
+            ObjMethod::Get => "Object",
+            ObjMethod::GetInt | ObjMethod::AsInt => "i64",
+            ObjMethod::GetFloat | ObjMethod::AsFloat => "f64",
+        }
+    }
+
+    pub fn args_len(&self) -> usize {
+        match self {
+            ObjMethod::Len | ObjMethod::AsInt | ObjMethod::AsFloat => 0,
+            ObjMethod::Get | ObjMethod::GetInt | ObjMethod::GetFloat => 1,
+        }
+    }
+}
+
 impl OpAssign {
     pub fn get_offset(&self) -> FastAttr {
         match self {
@@ -602,6 +692,10 @@ pub enum ArgType {
     Alloc((String, usize, bool)),               // type name, size
     TypeInfo(Option<Arc<FSRSType>>),            // Contain full type information
     AssignContainer((Option<OpAssign>, Option<Arc<FSRSType>>)), // assign container with type info, for optimize like list += [1, 2, 3]
+    TypeGuard((u64, String, String)), // arg id, arg name, class name
+    StrMethod(StrMethod),
+    ObjMethod(ObjMethod),
+    VecNew((Arc<FSRSType>, usize)), // vector type, args count
     None,
 }
 
@@ -616,6 +710,8 @@ pub enum FSRDbgFlag {
 pub struct FSRByteInfo {
     pos: FSRPos,
     dbg_flag: Cell<FSRDbgFlag>,
+    /// Filled on first attribute lookup by `BinaryDot` / `CallMethod`
+    attr_cache: OnceLock<Box<AttrInlineCache>>,
 }
 
 struct FnDesc {
@@ -645,6 +741,7 @@ impl FSRByteInfo {
                     column: meta.get_offset(),
                 },
                 dbg_flag: Cell::new(FSRDbgFlag::None),
+                attr_cache: OnceLock::new(),
             };
         }
 
@@ -657,6 +754,7 @@ impl FSRByteInfo {
                     column: offset,
                 },
                 dbg_flag: Cell::new(FSRDbgFlag::None),
+                attr_cache: OnceLock::new(),
             };
         }
 
@@ -673,6 +771,7 @@ impl FSRByteInfo {
         Self {
             pos,
             dbg_flag: Cell::new(FSRDbgFlag::None),
+            attr_cache: OnceLock::new(),
         }
     }
 }
@@ -713,6 +812,12 @@ impl BytecodeArg {
         self.info.dbg_flag.set(dbg_flag);
     }
 
+    /// Inline cache of this instruction, created on first use.
+    #[inline(always)]
+    pub fn get_attr_cache(&self) -> &AttrInlineCache {
+        self.info.attr_cache.get_or_init(Box::default)
+    }
+
     pub fn is_dbg_once(&self) -> bool {
         match self.info.dbg_flag.get() {
             FSRDbgFlag::None => false,
@@ -890,6 +995,7 @@ pub struct FnDef {
 pub struct FSRStruct {
     pub name: String,
     pub fields: HashMap<String, (usize, Arc<FSRSType>)>, // field name to index
+    pub methods: HashMap<String, Arc<FnCallSig>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -910,9 +1016,74 @@ pub enum FSRSType {
     Fn(Arc<FnCallSig>),
     Struct(Arc<FSRStruct>),
     Ptr(Arc<FSRSType>),
+    /// Dynamic object of the vm passed to static code, an opaque `ObjId`
+    Object,
+    /// SIMD vector like `f32x4`, lane type and lane count
+    Vector(Arc<FSRSType>, usize),
 }
 
 impl FSRSType {
+    pub fn is_float(&self) -> bool {
+        matches!(self, FSRSType::Float32 | FSRSType::Float64)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            FSRSType::UInt8
+                | FSRSType::UInt16
+                | FSRSType::UInt32
+                | FSRSType::UInt64
+                | FSRSType::IInt8
+                | FSRSType::IInt16
+                | FSRSType::IInt32
+                | FSRSType::IInt64
+        )
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            FSRSType::IInt8 | FSRSType::IInt16 | FSRSType::IInt32 | FSRSType::IInt64
+        )
+    }
+
+    /// Scalars live in registers and are converted between each other by the JIT
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float() || matches!(self, FSRSType::Bool)
+    }
+
+    /// Name of the type as written in a type hint, instances of generic
+    /// functions and structs are named after their type arguments with it
+    pub fn type_key(&self) -> String {
+        match self {
+            FSRSType::Bool => "bool".to_string(),
+            FSRSType::UInt8 => "u8".to_string(),
+            FSRSType::UInt16 => "u16".to_string(),
+            FSRSType::UInt32 => "u32".to_string(),
+            FSRSType::UInt64 => "u64".to_string(),
+            FSRSType::IInt8 => "i8".to_string(),
+            FSRSType::IInt16 => "i16".to_string(),
+            FSRSType::IInt32 => "i32".to_string(),
+            FSRSType::IInt64 => "i64".to_string(),
+            FSRSType::Float32 => "f32".to_string(),
+            FSRSType::Float64 => "f64".to_string(),
+            FSRSType::String => "string".to_string(),
+            FSRSType::List(t, len) => format!("List[{},{}]", t.type_key(), len),
+            FSRSType::Fn(sig) => {
+                let params = sig.params.iter().map(|x| x.type_key()).collect::<Vec<_>>();
+                match &sig.return_type {
+                    Some(ret) => format!("fn({})->{}", params.join(","), ret.type_key()),
+                    None => format!("fn({})", params.join(",")),
+                }
+            }
+            FSRSType::Struct(s) => s.name.clone(),
+            FSRSType::Ptr(t) => format!("Ptr[{}]", t.type_key()),
+            FSRSType::Object => "Object".to_string(),
+            FSRSType::Vector(t, lanes) => format!("{}x{}", t.type_key(), lanes),
+        }
+    }
+
     pub fn size_of(&self) -> usize {
         match self {
             FSRSType::Bool => 1,
@@ -926,7 +1097,7 @@ impl FSRSType {
             FSRSType::IInt64 => 8,
             FSRSType::Float32 => 4,
             FSRSType::Float64 => 8,
-            FSRSType::String => std::mem::size_of::<String>(),
+            FSRSType::String => std::mem::size_of::<FSRStaticStr>(),
             FSRSType::Struct(s) => {
                 let mut size = 0;
                 for (offset, attr_type) in s.fields.values() {
@@ -944,10 +1115,21 @@ impl FSRSType {
             }
             FSRSType::Fn(fn_call_sig) => std::mem::size_of::<usize>(),
             FSRSType::List(fsrstype, len) => fsrstype.size_of() * (*len),
+            FSRSType::Object => std::mem::size_of::<ObjId>(),
+            FSRSType::Vector(t, lanes) => t.size_of() * (*lanes),
         }
     }
 }
 
+/// Integer and float types of static code
+pub(crate) const NUMBER_TYPES: [&str; 10] =
+    ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "f32", "f64"];
+
+/// Vector types of static code, 128 or 256 bits wide
+pub(crate) const VECTOR_TYPES: [&str; 8] = [
+    "f32x4", "f32x8", "f64x2", "f64x4", "i32x4", "i32x8", "i64x2", "i64x4",
+];
+
 #[derive(Debug)]
 pub struct FSRSTypeInfo {
     types: HashMap<Vec<String>, Arc<FSRSType>>,
@@ -996,17 +1178,46 @@ impl FSRSTypeInfo {
                 //self.types.insert(search, new_ptr.clone());
                 return Some(new_ptr);
             }
+            "fn" if type_name.is_fn() => {
+                let params = type_name.subtype.iter().flatten();
+                let params = params.map(|x| self.get_type(x)).collect::<Option<Vec<_>>>()?;
+                let return_type = match &type_name.ret {
+                    Some(ret) => Some(self.get_type(ret)?),
+                    None => None,
+                };
+                return Some(Arc::new(FSRSType::Fn(Arc::new(FnCallSig {
+                    params,
+                    return_type,
+                }))));
+            }
             _ => {}
         };
+        if let Some(args) = &type_name.subtype {
+            // an instance of a generic struct, loaded by `Bytecode::resolve_type`
+            let args = args
+                .iter()
+                .map(|x| self.get_type(x).map(|t| t.type_key()))
+                .collect::<Option<Vec<_>>>()?;
+            return self.types.get(&vec![generic_key(name, &args)]).cloned();
+        }
         self.types.get(&search).cloned()
     }
+
+    /// Call sig of the method `name` defined in struct `struct_name`
+    pub fn get_method(&self, struct_name: &str, name: &str) -> Option<Arc<FnCallSig>> {
+        let key = vec![struct_name.to_string(), name.to_string()];
+        match self.types.get(&key)?.as_ref() {
+            FSRSType::Fn(sig) => Some(sig.clone()),
+            _ => None,
+        }
+    }
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
         let mut types = HashMap::new();
-        for name in [
-            "u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "f32", "f64", "string", "array",
-            "bool",
-        ] {
+        for name in NUMBER_TYPES
+            .into_iter()
+            .chain(["string", "array", "bool", "Object"])
+        {
             let v = match name {
                 "u8" => Some(FSRSType::UInt8),
                 "u16" => Some(FSRSType::UInt16),
@@ -1020,6 +1231,7 @@ impl FSRSTypeInfo {
                 "f64" => Some(FSRSType::Float64),
                 "string" => Some(FSRSType::String),
                 "bool" => Some(FSRSType::Bool),
+                "Object" => Some(FSRSType::Object),
                 _ => None,
             };
 
@@ -1027,6 +1239,12 @@ impl FSRSTypeInfo {
                 types.insert(vec![name.to_string()], Arc::new(v));
             }
         }
+        for name in VECTOR_TYPES {
+            let (lane, lanes) = name.split_once('x').unwrap();
+            let lane = types[&vec![lane.to_string()]].clone();
+            let v = FSRSType::Vector(lane, lanes.parse().unwrap());
+            types.insert(vec![name.to_string()], Arc::new(v));
+        }
         Self {
             types,
             structs: vec![],
@@ -1035,6 +1253,23 @@ impl FSRSTypeInfo {
     }
 }
 
+/// Name of the instance of the generic function or struct `name`, `max[u64]`
+fn generic_key(name: &str, args: &[String]) -> String {
+    format!("{}[{}]", name, args.join(","))
+}
+
+/// Generic functions and structs of a module, loaded once for each list of
+/// type arguments they are used with
+#[derive(Debug, Default)]
+pub(crate) struct Generics {
+    fns: HashMap<String, Rc<FSRFnDef>>,
+    structs: HashMap<String, Rc<FSRStructFrontEnd>>,
+    /// generic struct and type arguments of each struct instance
+    instances: HashMap<String, (String, Vec<Arc<FSRSType>>)>,
+    /// instances loaded by this pass
+    loaded: HashSet<String>,
+}
+
 #[derive(Debug)]
 pub struct BytecodeContext {
     //pub(crate) const_map: HashMap<FSROrinStr2, u64>,
@@ -1049,6 +1284,9 @@ pub struct BytecodeContext {
     pub(crate) def_fn_ret: Vec<Option<Arc<FSRSType>>>,
     pub(crate) is_pre_compile: bool,
     pub(crate) defer_stack: Vec<Vec<BytecodeArg>>,
+    pub(crate) generics: Generics,
+    /// first error of static code or a generic, returned by `load_ast`
+    pub(crate) error: Option<FSRError>,
 }
 
 #[allow(clippy::new_without_default)]
@@ -1072,6 +1310,8 @@ impl BytecodeContext {
             def_fn_ret: vec![],
             is_pre_compile: false,
             defer_stack: vec![],
+            generics: Generics::default(),
+            error: None,
         }
     }
 
@@ -1213,7 +1453,6 @@ pub struct FnInfo {
 #[derive(Debug)]
 pub struct Bytecode {
     pub(crate) name: String,
-    pub(crate) context: BytecodeContext,
     pub(crate) bytecode: Vec<Vec<BytecodeArg>>,
     pub(crate) var_map: VarMap,
     // pub(crate) is_jit: bool,
@@ -1331,7 +1570,7 @@ impl<'a> Bytecode {
                             .unwrap();
                         let inner_type = if let FSRSType::Ptr(t) = &*var_type {
                             Some(t.clone())
-                        } else if let FSRSType::List(t, _) = &*var_type {
+                        } else if let FSRSType::List(t, _) | FSRSType::Vector(t, _) = &*var_type {
                             Some(t.clone())
                         } else {
                             None
@@ -1385,8 +1624,11 @@ impl<'a> Bytecode {
         let ret_type = if let Some(t) = type_info_full {
             if let FSRSType::Ptr(inner) = t.as_ref() {
                 Some(inner.clone())
-            } else if let FSRSType::List(inner, _) = t.as_ref() {
+            } else if let FSRSType::List(inner, _) | FSRSType::Vector(inner, _) = t.as_ref() {
                 Some(inner.clone())
+            } else if let FSRSType::String = t.as_ref() {
+                // indexing a static string reads a byte
+                const_map.type_info.get_type(&FSRTypeName::new("u8"))
             } else {
                 Some(t.clone())
             }
@@ -1482,32 +1724,143 @@ impl<'a> Bytecode {
         // }
     }
 
+    /// Sig of the function pointer `call` goes through, when it calls a local
+    /// variable of static code typed `fn(...)`
+    fn fn_ptr_sig(
+        call: &FSRCall,
+        father_type: Option<&Arc<FSRSType>>,
+        context: &mut BytecodeContext,
+    ) -> Result<Option<Arc<FnCallSig>>, FSRError> {
+        if !context.is_static
+            || father_type.is_some()
+            || !context.variable_is_defined(call.get_name())
+        {
+            return Ok(None);
+        }
+
+        let Some(var_type) = call
+            .var_type
+            .as_ref()
+            .and_then(|t| Self::resolve_type(t, context))
+        else {
+            return Ok(None);
+        };
+        match var_type.as_ref() {
+            FSRSType::Fn(sig) => Ok(Some(sig.clone())),
+            _ => {
+                let msg = format!(
+                    "{} is a {}, not a function",
+                    call.get_name(),
+                    var_type.type_key()
+                );
+                Err(FSRError::new(msg, FSRErrCode::NotValidArgs))
+            }
+        }
+    }
+
+    /// The pointer called by `call`, lowered to an indirect call like any
+    /// static call
+    fn load_fn_ptr(
+        call: &FSRCall,
+        var_map: &mut Vec<VarMap>,
+        context: &BytecodeContext,
+        sig: &Arc<FnCallSig>,
+    ) -> BytecodeArg {
+        let name = call.get_name();
+        let arg_id = ensure_var_id!(var_map, name);
+        let mut var = LocalVar::new(arg_id, name.to_string(), false, None);
+        var.var_type = Some(Arc::new(FSRSType::Fn(sig.clone())));
+        BytecodeArg {
+            operator: BytecodeOperator::LoadVar,
+            arg: Box::new(ArgType::Local(var)),
+            info: Box::new(FSRByteInfo::new(&context.lines, call.get_meta().clone())),
+            arg_n: arg_id as i64,
+        }
+    }
+
+    fn struct_name_of(t: &FSRSType) -> Option<&str> {
+        match t {
+            FSRSType::Struct(s) => Some(&s.name),
+            FSRSType::Ptr(inner) => match inner.as_ref() {
+                FSRSType::Struct(s) => Some(&s.name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether `sig` takes a `self: Ptr[struct_name]` first argument
+    fn is_instance_method(sig: &FnCallSig, struct_name: &str) -> bool {
+        match sig.params.first().map(|x| x.as_ref()) {
+            Some(FSRSType::Ptr(inner)) => {
+                matches!(inner.as_ref(), FSRSType::Struct(s) if s.name == struct_name)
+            }
+            _ => false,
+        }
+    }
+
+    /// Instance method called on a `Ptr[T]` value, resolved through the
+    /// struct's methods in type info
     fn get_method_sig(
         call: &FSRCall,
         context: &mut BytecodeContext,
         father_type: Option<Arc<FSRSType>>,
-    ) -> Option<Arc<FnCallSig>> {
-        let method_fn_sig = if context.is_static {
-            let obj_type = father_type.expect("Object type is required for static method calls");
-            if let FSRSType::Ptr(t) = obj_type.as_ref() {
-                if let FSRSType::Struct(s) = t.as_ref() {
-                    let method = s.fields.get(call.get_name()).unwrap();
-                    let method = if let FSRSType::Fn(f) = method.1.as_ref() {
-                        f.clone()
-                    } else {
-                        panic!("Method {} is not a function", call.get_name());
-                    };
-                    Some(method)
-                } else {
-                    None
-                }
-            } else {
-                None
+    ) -> Result<Option<Arc<FnCallSig>>, FSRError> {
+        if !context.is_static {
+            return Ok(None);
+        }
+
+        let obj_type = father_type.expect("Object type is required for static method calls");
+        let struct_name = match obj_type.as_ref() {
+            FSRSType::Ptr(t) if matches!(t.as_ref(), FSRSType::Struct(_)) => {
+                Self::struct_name_of(&obj_type).unwrap()
             }
-        } else {
-            None
+            _ => {
+                let msg = format!(
+                    "Method {} must be called on a Ptr to a struct, got {}",
+                    call.get_name(),
+                    obj_type.type_key()
+                );
+                return Err(FSRError::new(msg, FSRErrCode::NoSuchMethod));
+            }
+        };
+        let Some(method) = context.type_info.get_method(struct_name, call.get_name()) else {
+            let msg = format!("struct {} has no method {}", struct_name, call.get_name());
+            return Err(FSRError::new(msg, FSRErrCode::NoSuchMethod));
+        };
+        if !Self::is_instance_method(&method, struct_name) {
+            let msg = format!(
+                "{}::{} is a static method, call it as {}.{}(...)",
+                struct_name,
+                call.get_name(),
+                struct_name,
+                call.get_name()
+            );
+            return Err(FSRError::new(msg, FSRErrCode::NotValidArgs));
+        }
+        Ok(Some(method))
+    }
+
+    /// Static method called on the struct name, it takes no `self`
+    fn get_static_method_sig(
+        call: &FSRCall,
+        context: &mut BytecodeContext,
+        struct_name: &str,
+    ) -> Result<Arc<FnCallSig>, FSRError> {
+        let Some(method) = context.type_info.get_method(struct_name, call.get_name()) else {
+            let msg = format!("struct {} has no method {}", struct_name, call.get_name());
+            return Err(FSRError::new(msg, FSRErrCode::NoSuchMethod));
         };
-        method_fn_sig
+        if Self::is_instance_method(&method, struct_name) {
+            let msg = format!(
+                "{}::{} takes self, call it on a Ptr[{}]",
+                struct_name,
+                call.get_name(),
+                struct_name
+            );
+            return Err(FSRError::new(msg, FSRErrCode::NotValidArgs));
+        }
+        Ok(method)
     }
 
     fn load_call(
@@ -1524,9 +1877,26 @@ impl<'a> Bytecode {
         let mut result = Vec::new();
 
         let name = call.get_name();
+        if context.is_static
+            && father_type.is_none()
+            && let Some(t) = context.type_info.get_type(&FSRTypeName::new(name))
+            && let FSRSType::Vector(_, _) = t.as_ref()
+        {
+            return Self::load_vec_new(call, var_map, context, t);
+        }
+
+        let fn_ptr_sig = match Self::fn_ptr_sig(call, father_type.as_ref(), context) {
+            Ok(sig) => sig,
+            Err(e) => {
+                Self::static_error(context, e);
+                return (vec![], None);
+            }
+        };
         let mut attr_id_arg = None;
         if !name.is_empty() {
-            if context.is_static {
+            if let Some(sig) = &fn_ptr_sig {
+                result.push(Self::load_fn_ptr(call, var_map, context, sig));
+            } else if context.is_static {
                 Self::load_call_static(call, &mut result, context, name, father_type.clone());
             } else {
                 Self::call_helper(
@@ -1542,11 +1912,32 @@ impl<'a> Bytecode {
             }
         }
 
+        let mut arg_types = vec![];
         for arg in call.get_args() {
             let mut v = Self::load_token_with_map(arg, var_map, context, false, false).unwrap();
             result.append(&mut v.value[0]);
+            arg_types.push(v.ret_type);
         }
 
+        let generic_name;
+        let name = if context.is_static
+            && father_type.is_none()
+            && fn_ptr_sig.is_none()
+            && context.generics.fns.contains_key(name)
+        {
+            match Self::instantiate_fn(call, &arg_types, context) {
+                Ok(key) => generic_name = key,
+                Err(e) => {
+                    Self::static_error(context, e);
+                    return (vec![], None);
+                }
+            }
+            *result[0].arg = ArgType::JitFunction(None, generic_name.clone());
+            generic_name.as_str()
+        } else {
+            name
+        };
+
         let call_or_callmethod = if is_method_call {
             BytecodeOperator::CallMethod
         } else {
@@ -1556,8 +1947,15 @@ impl<'a> Bytecode {
         let mut ret_type = None;
 
         let arg = if is_method_call {
-            let method_fn_sig = Self::get_method_sig(call, context, father_type.clone());
+            let method_fn_sig = match Self::get_method_sig(call, context, father_type.clone()) {
+                Ok(sig) => sig,
+                Err(e) => {
+                    Self::static_error(context, e);
+                    return (vec![], None);
+                }
+            };
             if context.is_static {
+                ret_type = method_fn_sig.as_ref().and_then(|x| x.return_type.clone());
                 ArgType::CallArgsNumber((call.get_args().len(), method_fn_sig))
             } else {
                 ArgType::CallArgsNumberWithAttr((
@@ -1568,7 +1966,20 @@ impl<'a> Bytecode {
                 ))
             }
         } else {
-            let call_sig = context.type_info.fn_call_sig_map.get(name).cloned();
+            let struct_name = father_type.as_deref().and_then(Self::struct_name_of);
+            let call_sig = match struct_name {
+                Some(struct_name) if context.is_static => {
+                    match Self::get_static_method_sig(call, context, struct_name) {
+                        Ok(sig) => Some(sig),
+                        Err(e) => {
+                            Self::static_error(context, e);
+                            return (vec![], None);
+                        }
+                    }
+                }
+                _ if fn_ptr_sig.is_some() => fn_ptr_sig,
+                _ => context.type_info.fn_call_sig_map.get(name).cloned(),
+            };
             if context.is_static {
                 if let Some(sig) = &call_sig {
                     ret_type = sig.return_type.clone();
@@ -1621,6 +2032,39 @@ impl<'a> Bytecode {
         }
     }
 
+    /// Instance of a generic struct named as a value, like `Pair[u8, u64].alloc`
+    fn static_generic_struct(
+        getter: &FSRGetter,
+        context: &mut BytecodeContext,
+    ) -> Option<Arc<FSRSType>> {
+        if !context.is_static
+            || !context.generics.structs.contains_key(getter.get_name())
+            || context.variable_is_defined(getter.get_name())
+        {
+            return None;
+        }
+
+        let type_name =
+            FSRExpr::parse_type_hint(&FSRToken::Getter(getter.clone()), getter.get_meta());
+        Self::resolve_type(&type_name, context)
+    }
+
+    /// Struct type named by `var` when static code uses a struct name as a
+    /// value, like `Test.alloc` or `Test.make(1)`
+    fn static_struct_name(var: &FSRVariable, context: &BytecodeContext) -> Option<Arc<FSRSType>> {
+        if !context.is_static
+            || var.var_type.is_some()
+            || context.variable_is_defined(var.get_name())
+        {
+            return None;
+        }
+
+        let t = context
+            .type_info
+            .get_type(&FSRTypeName::new(var.get_name()))?;
+        matches!(t.as_ref(), FSRSType::Struct(_)).then_some(t)
+    }
+
     fn load_not_is_attr(
         var: &FSRVariable,
         var_map: &mut Vec<VarMap>,
@@ -1733,7 +2177,7 @@ impl<'a> Bytecode {
                 let arg_id = var_map.last_mut().unwrap().get_var(var.get_name()).unwrap();
                 let type_info = if context.is_static {
                     let type_hint = var.get_type_hint();
-                    let type_info = type_hint.and_then(|x| context.type_info.get_type(&x));
+                    let type_info = type_hint.and_then(|x| Self::resolve_type(x, context));
                     ret_type = type_info.clone();
                     type_info
                 } else {
@@ -1783,6 +2227,10 @@ impl<'a> Bytecode {
             return Self::load_keyword_var(var, context);
         }
 
+        if !is_attr && let Some(v) = Self::load_static_fn_value(var, context) {
+            return v;
+        }
+
         if context.is_variable_in_ref_stack(var.get_name()) && !var.is_defined {
             return Self::load_closure_var(var, var_map, is_attr, context);
         }
@@ -1790,6 +2238,27 @@ impl<'a> Bytecode {
         Self::load_var_fallback(var, var_map, is_attr, context)
     }
 
+    /// A static function named as a value in static code is the address of
+    /// its code, typed `fn(params) -> ret`
+    fn load_static_fn_value(
+        var: &FSRVariable,
+        context: &BytecodeContext,
+    ) -> Option<(AttrIdOrCode, Option<Arc<FSRSType>>)> {
+        if !context.is_static || context.variable_is_defined(var.get_name()) {
+            return None;
+        }
+
+        let sig = context.type_info.fn_call_sig_map.get(var.get_name())?;
+        let op_arg = BytecodeArg {
+            operator: BytecodeOperator::Load,
+            arg: Box::new(ArgType::JitFunction(None, var.get_name().to_string())),
+            info: Box::new(FSRByteInfo::new(&context.lines, var.get_meta().clone())),
+            arg_n: 0,
+        };
+        let fn_type = Arc::new(FSRSType::Fn(sig.clone()));
+        Some((AttrIdOrCode::Bytecode(vec![op_arg]), Some(fn_type)))
+    }
+
     fn load_assign_arg(
         var: &'a FSRVariable,
         var_map: &mut Vec<VarMap>,
@@ -1823,7 +2292,7 @@ impl<'a> Bytecode {
         let mut v = LocalVar::new(*arg_id, var.get_name().to_string(), false, None);
         let type_info = if context.is_static {
             let type_hint = var.get_type_hint();
-            let type_info = type_hint.and_then(|x| context.type_info.get_type(&x));
+            let type_info = type_hint.and_then(|x| Self::resolve_type(x, context));
             type_info
         } else {
             None
@@ -1956,15 +2425,16 @@ impl<'a> Bytecode {
                 None,
             )
         } else if var.get_name().eq("alloc") {
-            let type_name = if let FSRToken::Variable(v) = father {
-                v.get_name()
-            } else {
-                panic!("Alloc must be a Struct name");
+            let type_name = match father {
+                FSRToken::Variable(v) => Some(FSRTypeName::new(v.get_name())),
+                FSRToken::Getter(_) => Some(FSRExpr::parse_type_hint(father, var.get_meta())),
+                _ => None,
+            };
+            let Some(var_type) = type_name.and_then(|t| Self::resolve_type(&t, context)) else {
+                let msg = "Alloc must be a Struct name";
+                Self::static_error(context, FSRError::new(msg, FSRErrCode::NoSuchObject));
+                return RetWithType::new(Some(vec![]), None);
             };
-            let var_type = context
-                .type_info
-                .get_type(&FSRTypeName::new(type_name))
-                .unwrap();
             let struct_size = var_type.size_of();
             (
                 Some(vec![BytecodeArg {
@@ -2062,16 +2532,11 @@ impl<'a> Bytecode {
                 }]),
                 None,
             );
-        } else if call.get_name().eq("alloc") {
-            let type_name = if let FSRToken::Variable(v) = father {
-                v.get_name()
-            } else {
-                panic!("Alloc must be a Struct name");
-            };
-            let var_type = context
-                .type_info
-                .get_type(&FSRTypeName::new(type_name))
-                .unwrap();
+        } else if call.get_name().eq("alloc")
+            && let FSRToken::Variable(v) = father
+            && let Some(var_type) = context.type_info.get_type(&FSRTypeName::new(v.get_name()))
+        {
+            // `alloc` of anything but a static type is a plain call, like `ffi.alloc`
             let struct_size = var_type.size_of();
             if call.get_args().len() != 1 {
                 panic!("Alloc must have exactly one argument for heap allocation");
@@ -2112,7 +2577,26 @@ impl<'a> Bytecode {
             return Some(l.clone());
         }
 
-        None
+        // mixed numeric operands are converted by the jit, float beats integer
+        // and the wider type wins
+        if l.is_numeric() && r.is_numeric() {
+            if l.is_float() != r.is_float() {
+                return Some(if l.is_float() { l.clone() } else { r.clone() });
+            }
+
+            return Some(if r.size_of() > l.size_of() {
+                r.clone()
+            } else {
+                l.clone()
+            });
+        }
+
+        // a scalar operand of a vector is applied to every lane
+        match (l.as_ref(), r.as_ref()) {
+            (FSRSType::Vector(_, _), r) if r.is_numeric() => Some(l.clone()),
+            (l, FSRSType::Vector(_, _)) if l.is_numeric() => Some(r.clone()),
+            _ => None,
+        }
     }
 
     fn is_same_two_type(left: &Option<Arc<FSRSType>>, right: &Option<Arc<FSRSType>>) -> bool {
@@ -2143,7 +2627,7 @@ impl<'a> Bytecode {
     ) {
         let name = v.get_name();
         let type_name = v.get_type_hint().unwrap();
-        let var_type = const_map.type_info.get_type(&type_name).unwrap();
+        let var_type = Self::resolve_type(type_name, const_map).unwrap();
         result.push(BytecodeArg {
             operator: BytecodeOperator::SDefAttr,
             arg: Box::new(ArgType::DefAttr(StructAttr {
@@ -2173,11 +2657,33 @@ impl<'a> Bytecode {
         struct_stmt: &FSRStructFrontEnd,
         var_map: &mut Vec<VarMap>,
         const_map: &mut BytecodeContext,
-    ) {
+    ) -> Vec<Vec<BytecodeArg>> {
+        if !struct_stmt.get_generics().is_empty() {
+            // loaded once for each list of type arguments by `resolve_type`
+            for def in struct_stmt.get_block().get_tokens() {
+                if let FSRToken::FunctionDef(def) = def
+                    && def.is_static_entry()
+                {
+                    let msg = format!(
+                        "@entry method {}::{} can not be in a generic struct",
+                        struct_stmt.get_name(),
+                        def.get_name()
+                    );
+                    Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NotValidArgs));
+                    return vec![];
+                }
+            }
+            const_map.generics.structs.insert(
+                struct_stmt.get_name().to_string(),
+                Rc::new(struct_stmt.clone()),
+            );
+            return vec![];
+        }
         let mut result = Vec::new();
         let mut struct_type = FSRStruct {
             name: struct_stmt.get_name().to_string(),
             fields: HashMap::new(),
+            methods: HashMap::new(),
         };
         ensure_var_id!(var_map, struct_stmt.get_name());
         let struct_name_id = var_map.last_mut().unwrap().get_var(struct_stmt.get_name());
@@ -2230,68 +2736,335 @@ impl<'a> Bytecode {
             );
         }
 
-        const_map
-            .cur_fn_name
-            .push(struct_stmt.get_name().to_string());
+        let name = struct_stmt.get_name();
+        let store_to_cell = if let Some(ref_map) = const_map.ref_map_stack.last() {
+            ref_map.get(name).cloned().unwrap_or(false) && const_map.is_variable_in_ref_stack(name)
+        } else {
+            false
+        };
+        // methods are not module globals, `@entry` ones are reached from
+        // dynamic code through a class named after the struct
+        let entry_defs = Self::load_struct_methods(struct_stmt, name, var_map, const_map);
+
+        if entry_defs.is_empty() || const_map.is_pre_compile {
+            return vec![];
+        }
+
+        let struct_name_id = *var_map.last_mut().unwrap().get_var(name).unwrap();
+        let info = FSRByteInfo::new(&const_map.lines, struct_stmt.get_meta().clone());
+        let mut bridge = vec![vec![BytecodeArg {
+            operator: BytecodeOperator::ClassDef,
+            arg: Box::new(ArgType::Local(LocalVar::new(
+                struct_name_id,
+                name.to_string(),
+                store_to_cell,
+                None,
+            ))),
+            info: Box::new(info.clone()),
+            arg_n: 0,
+        }]];
+        bridge.extend(entry_defs);
+        bridge.push(vec![BytecodeArg {
+            operator: BytecodeOperator::EndDefineClass,
+            arg: Box::new(ArgType::Local(LocalVar::new(
+                struct_name_id,
+                name.to_string(),
+                false,
+                None,
+            ))),
+            info: Box::new(info),
+            arg_n: 0,
+        }]);
+        bridge
+    }
+
+    /// Load the methods of `struct_stmt` as the struct `name`, the define ops
+    /// of `@entry` methods are returned
+    fn load_struct_methods(
+        struct_stmt: &FSRStructFrontEnd,
+        name: &str,
+        var_map: &mut Vec<VarMap>,
+        const_map: &mut BytecodeContext,
+    ) -> Vec<Vec<BytecodeArg>> {
+        let mut entry_defs = vec![];
+        var_map.push(VarMap::new(name));
+        const_map.cur_fn_name.push(name.to_string());
         for function in struct_stmt.get_block().get_tokens() {
             if let FSRToken::FunctionDef(def) = function {
                 let v = Self::load_function(def, var_map, const_map);
+                let fn_sig = v.fn_sig.as_ref().unwrap().clone();
+                if def.is_static_entry() && Self::is_instance_method(&fn_sig, name) {
+                    let msg = format!(
+                        "@entry method {}::{} can not take self",
+                        name,
+                        def.get_name()
+                    );
+                    Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NotValidArgs));
+                }
+
                 if const_map.is_pre_compile {
-                    let fn_type = Arc::new(FSRSType::Fn(v.fn_sig.as_ref().unwrap().clone()));
+                    let fn_type = Arc::new(FSRSType::Fn(fn_sig.clone()));
                     const_map.type_info.types.insert(
-                        vec![
-                            struct_stmt.get_name().to_string(),
-                            def.get_name().to_string(),
-                        ],
-                        fn_type.clone(),
+                        vec![name.to_string(), def.get_name().to_string()],
+                        fn_type,
                     );
 
                     // take the Arc out of the map so we can mutate its contents (clone-on-write
                     // if there are other references)
-                    let key = vec![struct_stmt.get_name().to_string()];
+                    let key = vec![name.to_string()];
                     let mut m_type = const_map.type_info.types.remove(&key).unwrap();
                     // get mutable access to the outer FSRSType (may clone if necessary)
                     match Arc::make_mut(&mut m_type) {
                         FSRSType::Struct(arc_struct) => {
                             // arc_struct is &mut Arc<FSRStruct>, make_mut to get &mut FSRStruct
                             let s = Arc::make_mut(arc_struct);
-                            s.fields
-                                .insert(def.get_name().to_string(), (0, fn_type.clone()));
+                            s.methods.insert(def.get_name().to_string(), fn_sig);
                         }
                         _ => panic!("expected struct type when adding method to struct"),
                     }
-                    // put the (possibly cloned/modified) Arc back into the map
-                    const_map.type_info.types.insert(key, m_type);
+                    // put the (possibly cloned/modified) Arc back into the map
+                    const_map.type_info.types.insert(key, m_type);
+                }
+
+                // the method body lives in `fn_def_map`, only the define op is left here
+                if def.is_static_entry() {
+                    entry_defs.extend(v.code);
+                }
+            }
+        }
+        const_map.cur_fn_name.pop();
+        var_map.pop();
+
+        entry_defs
+    }
+
+    /// Bind the type parameters `params` to `args` while an instance is
+    /// loaded, the shadowed types are returned
+    fn bind_generics(
+        params: &[String],
+        args: &[Arc<FSRSType>],
+        context: &mut BytecodeContext,
+    ) -> Vec<Option<Arc<FSRSType>>> {
+        params
+            .iter()
+            .zip(args)
+            .map(|(p, t)| context.type_info.types.insert(vec![p.clone()], t.clone()))
+            .collect()
+    }
+
+    fn unbind_generics(
+        params: &[String],
+        shadowed: Vec<Option<Arc<FSRSType>>>,
+        context: &mut BytecodeContext,
+    ) {
+        for (p, t) in params.iter().zip(shadowed) {
+            match t {
+                Some(t) => context.type_info.types.insert(vec![p.clone()], t),
+                None => context.type_info.types.remove(&vec![p.clone()]),
+            };
+        }
+    }
+
+    /// Type of a type hint, instances of generic structs it names are loaded
+    /// on first use
+    fn resolve_type(
+        type_name: &FSRTypeName,
+        context: &mut BytecodeContext,
+    ) -> Option<Arc<FSRSType>> {
+        if let Some(ret) = &type_name.ret {
+            Self::resolve_type(ret, context);
+        }
+        if let Some(args) = &type_name.subtype {
+            for arg in args {
+                Self::resolve_type(arg, context);
+            }
+            if context.generics.structs.contains_key(&type_name.name) {
+                let args = args
+                    .iter()
+                    .map(|x| context.type_info.get_type(x))
+                    .collect::<Option<Vec<_>>>()?;
+                Self::instantiate_struct(&type_name.name, args, context);
+            }
+        }
+        context.type_info.get_type(type_name)
+    }
+
+    /// Load the generic struct `name` for the type arguments `args`, the
+    /// instance is a struct named `Pair[u8,u64]`
+    fn instantiate_struct(name: &str, args: Vec<Arc<FSRSType>>, context: &mut BytecodeContext) {
+        let tmpl = context.generics.structs.get(name).unwrap().clone();
+        let params = tmpl.get_generics();
+        if params.len() != args.len() {
+            let msg = format!(
+                "struct {} takes {} type arguments, got {}",
+                name,
+                params.len(),
+                args.len()
+            );
+            Self::static_error(context, FSRError::new(msg, FSRErrCode::NotValidArgs));
+            return;
+        }
+        let key = generic_key(name, &args.iter().map(|x| x.type_key()).collect::<Vec<_>>());
+        if !context.generics.loaded.insert(key.clone()) {
+            return;
+        }
+
+        // the instance is loaded outside of the code which named it
+        let cur_fn_name = std::mem::take(&mut context.cur_fn_name);
+        let defer_stack = std::mem::take(&mut context.defer_stack);
+        let origin_is_static = context.is_static;
+        let shadowed = Self::bind_generics(params, &args, context);
+        let mut var_map = vec![VarMap::new("__main__")];
+        if !context.type_info.types.contains_key(&vec![key.clone()]) {
+            let mut struct_type = FSRStruct {
+                name: key.clone(),
+                fields: HashMap::new(),
+                methods: HashMap::new(),
+            };
+            let mut offset = 0;
+            for token in tmpl.get_block().get_tokens() {
+                if let FSRToken::Variable(v) = token {
+                    let mut result = vec![];
+                    Self::load_struct_attr(v, &mut offset, &mut struct_type, &mut result, context);
+                }
+            }
+            context.type_info.structs.push(key.clone());
+            context.type_info.types.insert(
+                vec![key.clone()],
+                Arc::new(FSRSType::Struct(Arc::new(struct_type))),
+            );
+            context
+                .generics
+                .instances
+                .insert(key.clone(), (name.to_string(), args));
+
+            // method signatures first, a body may call a method defined after it
+            let is_pre_compile = std::mem::replace(&mut context.is_pre_compile, true);
+            Self::load_struct_methods(&tmpl, &key, &mut var_map, context);
+            context.is_pre_compile = is_pre_compile;
+        }
+        if !context.is_pre_compile {
+            Self::load_struct_methods(&tmpl, &key, &mut var_map, context);
+        }
+        Self::unbind_generics(params, shadowed, context);
+        context.is_static = origin_is_static;
+        context.defer_stack = defer_stack;
+        context.cur_fn_name = cur_fn_name;
+    }
+
+    /// Keep the first error, the rest of the module is only walked like in the
+    /// pre-compile pass
+    fn static_error(context: &mut BytecodeContext, e: FSRError) {
+        context.error.get_or_insert(e);
+        context.is_pre_compile = true;
+    }
+
+    fn add_generic_fn(fn_def: &FSRFnDef, context: &mut BytecodeContext) {
+        if !fn_def.is_static() || fn_def.is_static_entry() {
+            let msg = format!(
+                "generic function {} must be @static, instances are only called from static code",
+                fn_def.get_name()
+            );
+            Self::static_error(context, FSRError::new(msg, FSRErrCode::NotValidArgs));
+            return;
+        }
+        context
+            .generics
+            .fns
+            .insert(fn_def.get_name().to_string(), Rc::new(fn_def.clone()));
+    }
+
+    /// Bind the type parameters in `hint` to the matching parts of `arg`
+    fn unify_type(
+        hint: &FSRTypeName,
+        arg: &Arc<FSRSType>,
+        params: &[String],
+        generics: &Generics,
+        bound: &mut HashMap<String, Arc<FSRSType>>,
+    ) -> Result<(), FSRError> {
+        let sub = hint.subtype.as_deref().unwrap_or_default();
+        if sub.is_empty() && params.contains(&hint.name) {
+            if let Some(t) = bound.get(&hint.name)
+                && t != arg
+            {
+                return Err(FSRError::new(
+                    format!(
+                        "type parameter {} is bound to both {} and {}",
+                        hint.name,
+                        t.type_key(),
+                        arg.type_key()
+                    ),
+                    FSRErrCode::NotValidArgs,
+                ));
+            }
+            bound.insert(hint.name.clone(), arg.clone());
+            return Ok(());
+        }
+
+        match (hint.name.as_str(), arg.as_ref()) {
+            ("Ptr", FSRSType::Ptr(t)) | ("List", FSRSType::List(t, _)) => {
+                Self::unify_type(&sub[0], t, params, generics, bound)?;
+            }
+            (_, FSRSType::Struct(s)) => {
+                if let Some((name, args)) = generics.instances.get(&s.name)
+                    && *name == hint.name
+                {
+                    for (hint, arg) in sub.iter().zip(args) {
+                        Self::unify_type(hint, arg, params, generics, bound)?;
+                    }
                 }
-                // let mut tmp_defer_stack = vec![];
-                // std::mem::swap(&mut const_map.defer_stack, &mut tmp_defer_stack);
-                // let defer_stack = const_map.defer_stack;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
 
-                let defer_stack = std::mem::take(&mut const_map.defer_stack);
-                var_map.last_mut().unwrap().sub_fn_def.push(Bytecode {
-                    name: def.get_name().to_string(),
-                    context: BytecodeContext::new(vec![]),
-                    bytecode: v.code,
-                    var_map: v.var_map,
-                    // is_jit: def.is_static(),
-                    // is_async: def.is_async(),
-                    // is_static: def.is_static(),
-                    // is_entry: def.is_static_entry(),
-                    // fn_type: v.2,
-                    fn_info: FnInfo {
-                        is_static: def.is_static(),
-                        is_async: def.is_async(),
-                        is_entry: def.is_static_entry(),
-                        is_jit: def.is_jit(),
-                        fn_type: v.fn_sig.clone(),
-                    },
-                    defer_stack,
-                });
+    /// Load the generic function called by `call` for the types of its
+    /// arguments, the name of the instance is returned
+    fn instantiate_fn(
+        call: &FSRCall,
+        arg_types: &[Option<Arc<FSRSType>>],
+        context: &mut BytecodeContext,
+    ) -> Result<String, FSRError> {
+        let fn_def = context.generics.fns.get(call.get_name()).unwrap().clone();
+        let mut bound = HashMap::new();
+        for (arg, arg_type) in fn_def.get_args().iter().zip(arg_types) {
+            if let FSRToken::Variable(v) = arg
+                && let Some(hint) = v.get_type_hint()
+                && let Some(arg_type) = arg_type
+            {
+                Self::unify_type(hint, arg_type, &fn_def.generics, &context.generics, &mut bound)?;
             }
         }
-        const_map.cur_fn_name.pop();
+        let args = fn_def
+            .generics
+            .iter()
+            .map(|p| {
+                bound.remove(p).ok_or_else(|| {
+                    FSRError::new(
+                        format!("can not infer type parameter {} of {}", p, call.get_name()),
+                        FSRErrCode::NotValidArgs,
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = generic_key(
+            call.get_name(),
+            &args.iter().map(|x| x.type_key()).collect::<Vec<_>>(),
+        );
+        if !context.generics.loaded.insert(key.clone()) {
+            return Ok(key);
+        }
 
-        //result
+        let cur_fn_name = std::mem::take(&mut context.cur_fn_name);
+        let defer_stack = std::mem::take(&mut context.defer_stack);
+        let shadowed = Self::bind_generics(&fn_def.generics, &args, context);
+        let mut var_map = vec![VarMap::new("__main__")];
+        Self::load_function_as(&fn_def, &key, &mut var_map, context);
+        Self::unbind_generics(&fn_def.generics, shadowed, context);
+        context.defer_stack = defer_stack;
+        context.cur_fn_name = cur_fn_name;
+        Ok(key)
     }
 
     fn single_op_expr(
@@ -2308,11 +3081,183 @@ impl<'a> Bytecode {
                     arg_n: 0,
                 });
             } else {
-                panic!("not support this single op: {:?}", single_op);
+                let msg = format!("not support this single op: {:?}", single_op);
+                Self::static_error(
+                    const_map,
+                    FSRError::new(msg, FSRErrCode::NotSupportOperator),
+                );
             }
         }
     }
 
+    /// Error for a call of a builtin method with the wrong number of args
+    fn args_len_error(call: &FSRCall, expected: usize) -> FSRError {
+        let msg = format!(
+            "`{}` takes {} arguments, {} given",
+            call.get_name(),
+            expected,
+            call.get_args().len()
+        );
+        FSRError::new(msg, FSRErrCode::NotValidArgs)
+    }
+
+    fn load_str_method(
+        call: &FSRCall,
+        var_map: &mut Vec<VarMap>,
+        const_map: &mut BytecodeContext,
+    ) -> RetWithType<Vec<BytecodeArg>> {
+        let Some(method) = StrMethod::from_name(call.get_name()) else {
+            let msg = format!("string has no method `{}` in static code", call.get_name());
+            Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NoSuchMethod));
+            return RetWithType::new(vec![], None);
+        };
+        if call.get_args().len() != method.args_len() {
+            Self::static_error(const_map, Self::args_len_error(call, method.args_len()));
+            return RetWithType::new(vec![], None);
+        }
+
+        let mut result = Vec::new();
+        for arg in call.get_args() {
+            let mut v = Self::load_token_with_map(arg, var_map, const_map, false, false).unwrap();
+            result.append(&mut v.value[0]);
+        }
+
+        result.push(BytecodeArg {
+            operator: BytecodeOperator::SStrMethod,
+            arg: Box::new(ArgType::StrMethod(method)),
+            info: Box::new(FSRByteInfo::new(&const_map.lines, call.get_meta().clone())),
+            arg_n: 0,
+        });
+        RetWithType::new(
+            result,
+            const_map
+                .type_info
+                .get_type(&FSRTypeName::new(method.ret_type_name())),
+        )
+    }
+
+    fn load_obj_method(
+        call: &FSRCall,
+        var_map: &mut Vec<VarMap>,
+        const_map: &mut BytecodeContext,
+    ) -> RetWithType<Vec<BytecodeArg>> {
+        let Some(method) = ObjMethod::from_name(call.get_name()) else {
+            let msg = format!("Object has no method `{}` in static code", call.get_name());
+            Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NoSuchMethod));
+            return RetWithType::new(vec![], None);
+        };
+        if call.get_args().len() != method.args_len() {
+            Self::static_error(const_map, Self::args_len_error(call, method.args_len()));
+            return RetWithType::new(vec![], None);
+        }
+
+        let mut result = Vec::new();
+        for arg in call.get_args() {
+            let mut v = Self::load_token_with_map(arg, var_map, const_map, false, false).unwrap();
+            result.append(&mut v.value[0]);
+        }
+
+        result.push(BytecodeArg {
+            operator: BytecodeOperator::SObjMethod,
+            arg: Box::new(ArgType::ObjMethod(method)),
+            info: Box::new(FSRByteInfo::new(&const_map.lines, call.get_meta().clone())),
+            arg_n: 0,
+        });
+        RetWithType::new(
+            result,
+            const_map
+                .type_info
+                .get_type(&FSRTypeName::new(method.ret_type_name())),
+        )
+    }
+
+    /// `f32x4(a, b, c, d)` sets each lane, `f32x4(a)` sets all of them to `a`
+    fn load_vec_new(
+        call: &FSRCall,
+        var_map: &mut Vec<VarMap>,
+        context: &mut BytecodeContext,
+        vec_type: Arc<FSRSType>,
+    ) -> (Vec<BytecodeArg>, Option<Arc<FSRSType>>) {
+        let FSRSType::Vector(_, lanes) = vec_type.as_ref() else {
+            unreachable!()
+        };
+        let args_len = call.get_args().len();
+        if args_len != 1 && args_len != *lanes {
+            let msg = format!(
+                "`{}` takes 1 or {} arguments, {} given",
+                call.get_name(),
+                lanes,
+                args_len
+            );
+            Self::static_error(context, FSRError::new(msg, FSRErrCode::NotValidArgs));
+            return (vec![], None);
+        }
+
+        let mut result = Vec::new();
+        for arg in call.get_args() {
+            let mut v = Self::load_token_with_map(arg, var_map, context, false, false).unwrap();
+            result.append(&mut v.value[0]);
+        }
+
+        result.push(BytecodeArg {
+            operator: BytecodeOperator::SVecNew,
+            arg: Box::new(ArgType::VecNew((vec_type.clone(), args_len))),
+            info: Box::new(FSRByteInfo::new(&context.lines, call.get_meta().clone())),
+            arg_n: 0,
+        });
+        (result, Some(vec_type))
+    }
+
+    fn cast_target(expr: &FSRExpr, const_map: &BytecodeContext) -> Result<Arc<FSRSType>, FSRError> {
+        if !const_map.is_static {
+            let msg = "`as` cast is only supported in static functions";
+            return Err(FSRError::new(msg, FSRErrCode::NotSupportOperator));
+        }
+
+        let FSRToken::Variable(type_name) = expr.get_right() else {
+            let msg = "`as` expects a type name";
+            return Err(FSRError::new(msg, FSRErrCode::NotValidArgs));
+        };
+        let Some(target) = const_map
+            .type_info
+            .get_type(&FSRTypeName::new(type_name.get_name()))
+        else {
+            let msg = format!("unknown type in cast: {}", type_name.get_name());
+            return Err(FSRError::new(msg, FSRErrCode::NoSuchObject));
+        };
+        if !target.is_numeric() {
+            let msg = format!(
+                "`as` only converts between numeric types, found: {}",
+                type_name.get_name()
+            );
+            return Err(FSRError::new(msg, FSRErrCode::NotValidArgs));
+        }
+        Ok(target)
+    }
+
+    /// `value as type`, the right side names a static type and is not evaluated
+    fn load_cast(
+        expr: &FSRExpr,
+        mut op_code: Vec<BytecodeArg>,
+        const_map: &mut BytecodeContext,
+    ) -> RetWithType<Vec<BytecodeArg>> {
+        let target = match Self::cast_target(expr, const_map) {
+            Ok(target) => target,
+            Err(e) => {
+                Self::static_error(const_map, e);
+                return RetWithType::new(vec![], None);
+            }
+        };
+
+        op_code.push(BytecodeArg {
+            operator: BytecodeOperator::SCast,
+            arg: Box::new(ArgType::TypeInfo(Some(target.clone()))),
+            info: Box::new(FSRByteInfo::new(&const_map.lines, expr.get_meta().clone())),
+            arg_n: 0,
+        });
+        RetWithType::new(op_code, Some(target))
+    }
+
     fn load_expr(
         expr: &FSRExpr,
         var_map: &mut Vec<VarMap>,
@@ -2323,10 +3268,29 @@ impl<'a> Bytecode {
         }
         let mut op_code = Vec::new();
         let mut return_type = None;
+        // `Test.make(...)` in static code names a struct, not a value
+        let mut struct_name_type = None;
         if let FSRToken::Expr(sub_expr) = expr.get_left() {
             let mut v = Self::load_expr(sub_expr, var_map, const_map);
             return_type = v.ret_type;
             op_code.append(&mut v.value);
+        } else if let FSRToken::Variable(v) = expr.get_left()
+            && let Some(struct_type) = Self::static_struct_name(v, const_map)
+        {
+            op_code.push(Self::load_not_is_attr(v, var_map, const_map));
+            return_type = Some(struct_type.clone());
+            struct_name_type = Some(struct_type);
+        } else if let FSRToken::Getter(getter) = expr.get_left()
+            && let Some(struct_type) = Self::static_generic_struct(getter, const_map)
+        {
+            op_code.push(BytecodeArg {
+                operator: BytecodeOperator::Empty,
+                arg: Box::new(ArgType::None),
+                info: Box::new(FSRByteInfo::new(&const_map.lines, getter.get_meta().clone())),
+                arg_n: 0,
+            });
+            return_type = Some(struct_type.clone());
+            struct_name_type = Some(struct_type);
         } else if let FSRToken::Variable(v) = expr.get_left() {
             let mut attr_id_or_code = Self::load_variable(v, var_map, false, const_map);
             match attr_id_or_code.0 {
@@ -2358,6 +3322,10 @@ impl<'a> Bytecode {
         } else if let FSRToken::Constant(c) = expr.get_left() {
             let mut v = Self::load_constant(c, var_map, const_map);
             op_code.append(&mut v.0);
+            // numeric literals take the type of the other operand
+            if const_map.is_static && matches!(v.1.as_deref(), Some(FSRSType::String)) {
+                return_type = v.1;
+            }
         } else if let FSRToken::StackExpr(st) = expr.get_left() {
             let mut v = Self::load_stack_expr(st, var_map, const_map, false, false);
             op_code.append(&mut v);
@@ -2369,6 +3337,10 @@ impl<'a> Bytecode {
             unimplemented!()
         }
 
+        if expr.get_op().eq("as") {
+            return Self::load_cast(expr, op_code, const_map);
+        }
+
         let mut second = Vec::new();
         let mut attr_id = None;
         if let FSRToken::Expr(sub_expr) = expr.get_right() {
@@ -2442,7 +3414,25 @@ impl<'a> Bytecode {
             }
 
             if expr.get_op().eq(".") {
-                is_method_call = true;
+                is_method_call = struct_name_type.is_none();
+            }
+
+            if is_method_call
+                && const_map.is_static
+                && matches!(return_type.as_deref(), Some(FSRSType::String))
+            {
+                let mut v = Self::load_str_method(c, var_map, const_map);
+                op_code.append(&mut v.value);
+                return RetWithType::new(op_code, v.ret_type);
+            }
+
+            if is_method_call
+                && const_map.is_static
+                && matches!(return_type.as_deref(), Some(FSRSType::Object))
+            {
+                let mut v = Self::load_obj_method(c, var_map, const_map);
+                op_code.append(&mut v.value);
+                return RetWithType::new(op_code, v.ret_type);
             }
 
             //println!("call: {:#?}", expr);
@@ -2456,23 +3446,26 @@ impl<'a> Bytecode {
                 if is_method_call {
                     return_type.clone()
                 } else {
-                    None
+                    struct_name_type.clone()
                 },
             );
             second.append(&mut v.0);
-            return_type = Self::deduction_two_type(
-                &mut const_map.type_info,
-                &return_type,
-                &v.1,
-                expr.get_op(),
-            );
 
             //call special process
             if expr.get_op().eq(".") || expr.get_op().eq("::") {
+                // the call result, not the object the method was called on
+                return_type = v.1;
                 op_code.append(&mut second);
                 Self::single_op_expr(expr, &mut op_code, const_map);
                 return RetWithType::new(op_code, return_type);
             }
+
+            return_type = Self::deduction_two_type(
+                &mut const_map.type_info,
+                &return_type,
+                &v.1,
+                expr.get_op(),
+            );
         } else if let FSRToken::Getter(s) = expr.get_right() {
             let mut is_attr = false;
             let mut is_method_call = true;
@@ -2604,6 +3597,9 @@ impl<'a> Bytecode {
             attr_id,
             const_map,
         ) {
+            if s.get_operator() == BytecodeOperator::CompareTest && const_map.is_static {
+                return_type = const_map.type_info.get_type(&FSRTypeName::new("bool"));
+            }
             op_code.push(s);
         } else {
             unimplemented!()
@@ -3041,11 +4037,14 @@ impl<'a> Bytecode {
             let v = Self::load_constant(c, var_map, byte_context);
             return Ok(RetWithType::new(vec![v.0], v.1));
         } else if let FSRToken::FunctionDef(fn_def) = token {
+            if fn_def.is_lambda() && byte_context.is_static {
+                let v = Self::load_static_lambda(fn_def, byte_context);
+                return Ok(RetWithType::new(vec![v.0], v.1));
+            }
             if fn_def.is_lambda() {
                 let v = Self::load_function(fn_def, var_map, byte_context);
                 var_map.last_mut().unwrap().sub_fn_def.push(Bytecode {
                     name: fn_def.get_name().to_string(),
-                    context: BytecodeContext::new(vec![]),
                     bytecode: v.code,
                     var_map: v.var_map,
                     fn_info: FnInfo {
@@ -3074,6 +4073,10 @@ impl<'a> Bytecode {
                 }];
                 return Ok(RetWithType::new(vec![result], None));
             }
+            if !fn_def.generics.is_empty() {
+                Self::add_generic_fn(fn_def, byte_context);
+                return Ok(RetWithType::new(vec![], None));
+            }
             if byte_context.is_pre_compile {
                 return Ok(RetWithType::new(vec![], None));
             }
@@ -3111,7 +4114,7 @@ impl<'a> Bytecode {
             return Ok(RetWithType::new(v, None));
         } else if let FSRToken::Struct(struct_stmt) = token {
             let v = Self::load_struct(struct_stmt, var_map, byte_context);
-            return Ok(RetWithType::new(vec![], None));
+            return Ok(RetWithType::new(v, None));
         } else if let FSRToken::EmptyExpr(_) = token {
             return Ok(RetWithType::new(vec![], None));
         } else if let FSRToken::Defer(defer) = token {
@@ -3252,9 +4255,7 @@ impl<'a> Bytecode {
 
             let t = if let Some(type_name) = v.var_type.as_ref() {
                 if const_map.is_static {
-                    let type_id = const_map
-                        .type_info
-                        .get_type(type_name)
+                    let type_id = Self::resolve_type(type_name, const_map)
                         .expect("Type not found for getter assignment");
                     Some(type_id)
                 } else {
@@ -3331,9 +4332,7 @@ impl<'a> Bytecode {
                 if let Some(type_hint) = v.get_type_hint() {
                     // .....Wait to implement
                     let type_name = type_hint.name.as_str();
-                    let type_id = bc_map
-                        .type_info
-                        .get_type(type_hint)
+                    let type_id = Self::resolve_type(type_hint, bc_map)
                         .expect("wait to impl: if not getting type_id");
                     Self::load_assign_helper(
                         token,
@@ -3507,8 +4506,10 @@ impl<'a> Bytecode {
         result_list
     }
 
-    fn check_ret_type(v: &Option<Arc<FSRSType>>, const_map: &BytecodeContext) {
-        if const_map.is_static {
+    fn check_ret_type(v: &Option<Arc<FSRSType>>, const_map: &mut BytecodeContext) {
+        // struct methods are walked in the pre-compile pass for their sigs,
+        // expressions are not typed there
+        if const_map.is_static && !const_map.is_pre_compile {
             if v.is_some()
                 && const_map
                     .def_fn_ret
@@ -3519,10 +4520,12 @@ impl<'a> Bytecode {
                 let ret_type = const_map.def_fn_ret.last().unwrap();
                 if let Some(ret_type_id) = ret_type {
                     if !&v.as_ref().unwrap().eq(ret_type_id) {
-                        panic!(
-                            "Return type mismatch: expected {:?}, got {:?}",
-                            ret_type_id, v
+                        let msg = format!(
+                            "Return type mismatch: expected {}, got {}",
+                            ret_type_id.type_key(),
+                            v.as_ref().unwrap().type_key()
                         );
+                        Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NotValidArgs));
                     }
                 }
             } else if v.is_none()
@@ -3532,10 +4535,17 @@ impl<'a> Bytecode {
                     .expect("Function define should has a ret type stack")
                     .is_some()
             {
-                panic!(
-                    "Return type mismatch: expected {:?}, got None",
-                    const_map.def_fn_ret.last().unwrap()
+                let msg = format!(
+                    "Return type mismatch: expected {}, got None",
+                    const_map
+                        .def_fn_ret
+                        .last()
+                        .unwrap()
+                        .as_ref()
+                        .unwrap()
+                        .type_key()
                 );
+                Self::static_error(const_map, FSRError::new(msg, FSRErrCode::NotValidArgs));
             }
         }
     }
@@ -3612,9 +4622,7 @@ impl<'a> Bytecode {
         };
 
         let type_name = type_hint.name.as_str();
-        let type_id = bytecontext
-            .type_info
-            .get_type(type_hint)
+        let type_id = Self::resolve_type(type_hint, bytecontext)
             .expect("wait to impl: if not getting type_id");
         call_sig.params.push(type_id.clone());
     }
@@ -3639,6 +4647,42 @@ impl<'a> Bytecode {
         }
     }
 
+    /// Guards run after every argument is assigned, closure args are skipped
+    fn load_type_guards(
+        args: &[FSRToken],
+        bytecontext: &BytecodeContext,
+        load_args: &mut Vec<BytecodeArg>,
+    ) {
+        let mut guards = vec![];
+        for arg in load_args.iter() {
+            let ArgType::Local(local) = arg.get_arg() else {
+                continue;
+            };
+
+            let hint = args.iter().find_map(|x| match x {
+                FSRToken::Variable(v) if v.get_name() == local.name => {
+                    v.get_type_hint().map(|hint| (v, hint))
+                }
+                _ => None,
+            });
+
+            if let Some((v, hint)) = hint {
+                guards.push(BytecodeArg {
+                    operator: BytecodeOperator::TypeGuard,
+                    arg: Box::new(ArgType::TypeGuard((
+                        local.id,
+                        local.name.clone(),
+                        hint_class_name(hint).to_string(),
+                    ))),
+                    info: Box::new(FSRByteInfo::new(&bytecontext.lines, v.get_meta().clone())),
+                    arg_n: 0,
+                });
+            }
+        }
+
+        load_args.append(&mut guards);
+    }
+
     fn should_store_to_cell(name: &str, ctx: &BytecodeContext) -> bool {
         if let Some(ref_map) = ctx.ref_map_stack.last() {
             ref_map.get(name).copied().unwrap_or(false) && ctx.is_variable_in_ref_stack(name)
@@ -3666,10 +4710,22 @@ impl<'a> Bytecode {
         names
     }
 
+    /// Mark `return f(...)` calls, the call is the last operator before `ReturnValue`
+    fn mark_tail_calls(fn_body: &mut [Vec<BytecodeArg>]) {
+        for expr in fn_body.iter_mut() {
+            if let [.., call, ret] = expr.as_mut_slice()
+                && call.operator == BytecodeOperator::Call
+                && ret.operator == BytecodeOperator::ReturnValue
+            {
+                call.operator = BytecodeOperator::TailCall;
+            }
+        }
+    }
+
     fn ret_ensure(
         fn_body: &mut Vec<Vec<BytecodeArg>>,
         fn_def: &FSRFnDef,
-        bytecontext: &BytecodeContext,
+        bytecontext: &mut BytecodeContext,
     ) {
         if let Some(last) = fn_body.last() {
             if last.last().is_none()
@@ -3695,9 +4751,7 @@ impl<'a> Bytecode {
         bytecontext: &mut BytecodeContext,
     ) {
         if let Some(type_hint) = ret_type {
-            let type_id = bytecontext
-                .type_info
-                .get_type(type_hint)
+            let type_id = Self::resolve_type(type_hint, bytecontext)
                 .expect("wait to impl: if not getting type_id");
             call_sig.return_type = Some(type_id.clone());
             bytecontext.def_fn_ret.push(Some(type_id.clone()));
@@ -3738,17 +4792,82 @@ impl<'a> Bytecode {
         call_sig_maybe
     }
 
+    /// A lambda in static code is loaded as a static function of its own, the
+    /// lambda is the address of its code. It can not capture the locals of
+    /// the code defining it
+    fn load_static_lambda(
+        fn_def: &FSRFnDef,
+        context: &mut BytecodeContext,
+    ) -> (Vec<BytecodeArg>, Option<Arc<FSRSType>>) {
+        // locals of the defining code referenced by an inner scope are
+        // marked, static code has no closure
+        if let Some(ref_map) = context.ref_map_stack.last()
+            && let Some((name, _)) = ref_map.iter().find(|x| *x.1)
+        {
+            let msg = format!("lambda in static code can not capture `{}`", name);
+            Self::static_error(context, FSRError::new(msg, FSRErrCode::NotValidArgs));
+            return (vec![], None);
+        }
+        for arg in fn_def.get_args() {
+            // untyped lambda args are hinted `Function`, which static code has not
+            let hint = match arg {
+                FSRToken::Variable(v) => v.get_type_hint(),
+                _ => None,
+            };
+            if hint.and_then(|x| Self::resolve_type(x, context)).is_none() {
+                let msg = "lambda in static code needs a type for each arg";
+                Self::static_error(context, FSRError::new(msg, FSRErrCode::NotValidArgs));
+                return (vec![], None);
+            }
+        }
+
+        let mut lambda = fn_def.clone();
+        lambda.teller = Some(FSRTell {
+            position: fn_def.get_meta().clone(),
+            value: vec!["@static".to_string()],
+            len: 0,
+        });
+        // loaded like a top level function, its code slot is found by name
+        let cur_fn_name = std::mem::take(&mut context.cur_fn_name);
+        let defer_stack = std::mem::take(&mut context.defer_stack);
+        let mut var_map = vec![VarMap::new("__main__")];
+        let v = Self::load_function(&lambda, &mut var_map, context);
+        context.defer_stack = defer_stack;
+        context.cur_fn_name = cur_fn_name;
+
+        let op_arg = BytecodeArg {
+            operator: BytecodeOperator::Load,
+            arg: Box::new(ArgType::JitFunction(None, fn_def.get_name().to_string())),
+            info: Box::new(FSRByteInfo::new(&context.lines, fn_def.get_meta().clone())),
+            arg_n: 0,
+        };
+        (
+            vec![op_arg],
+            Some(Arc::new(FSRSType::Fn(v.fn_sig.unwrap()))),
+        )
+    }
+
     fn load_function(
         fn_def: &FSRFnDef,
         var_map: &mut Vec<VarMap>,
         bytecontext: &mut BytecodeContext,
     ) -> FnDesc {
-        let name = fn_def.get_name();
+        Self::load_function_as(fn_def, fn_def.get_name(), var_map, bytecontext)
+    }
+
+    /// Load `fn_def` under `name`, instances of generic functions are named
+    /// after their type arguments
+    fn load_function_as(
+        fn_def: &FSRFnDef,
+        name: &str,
+        var_map: &mut Vec<VarMap>,
+        bytecontext: &mut BytecodeContext,
+    ) -> FnDesc {
         let arg_id = ensure_var_id!(var_map, name);
 
         let store_to_cell = Self::should_store_to_cell(name, bytecontext);
 
-        let fn_var_map = VarMap::new(fn_def.get_name());
+        let fn_var_map = VarMap::new(name);
         var_map.push(fn_var_map);
 
         let hash_map_ref_map = Self::build_ref_map_for_fn(fn_def);
@@ -3766,6 +4885,10 @@ impl<'a> Bytecode {
         let mut load_args = Vec::new();
         Self::args_process(args, var_map, bytecontext, &mut load_args, &mut call_sig);
 
+        if fn_def.is_guard() && !fn_def.is_jit() {
+            Self::load_type_guards(args, bytecontext, &mut load_args);
+        }
+
         let args_save = Self::collect_arg_names(args);
 
         let body = fn_def.get_body();
@@ -3773,6 +4896,17 @@ impl<'a> Bytecode {
         let ret_type = fn_def.ret_type.as_ref();
 
         let call_sig_maybe = Self::set_fn_call_sig(call_sig, ret_type, bytecontext);
+        if fn_def.is_static_entry()
+            && let Some(sig) = &call_sig_maybe
+            && sig
+                .params
+                .iter()
+                .chain(sig.return_type.iter())
+                .any(|x| matches!(x.as_ref(), FSRSType::Fn(_)))
+        {
+            let msg = format!("@entry function {} can not take or return a function", name);
+            Self::static_error(bytecontext, FSRError::new(msg, FSRErrCode::NotValidArgs));
+        }
 
         let cur_name = bytecontext.cur_fn_name.join("::").to_string();
 
@@ -3809,6 +4943,15 @@ impl<'a> Bytecode {
 
         Self::ret_ensure(&mut fn_body, fn_def, bytecontext);
 
+        // a frame with pending defer must stay alive until the defer runs
+        if !fn_def.is_static()
+            && !fn_def.is_jit()
+            && !fn_def.is_async()
+            && bytecontext.defer_stack.is_empty()
+        {
+            Self::mark_tail_calls(&mut fn_body);
+        }
+
         bytecontext.def_fn_ret.pop();
         bytecontext.is_static = origin_is_static;
 
@@ -3955,7 +5098,6 @@ impl<'a> Bytecode {
             "__main__".to_string(),
             Bytecode {
                 name: "__main__".to_string(),
-                context: BytecodeContext::new(vec![]),
                 bytecode: result,
                 var_map: vs.1,
                 defer_stack: vec![],
@@ -3981,7 +5123,6 @@ impl<'a> Bytecode {
         for code in codes {
             let bytecode = Bytecode {
                 name: code.0.to_string(),
-                context: BytecodeContext::new(vec![]),
                 bytecode: code.1.code,
                 var_map: code.1.var_map,
 
@@ -4002,11 +5143,20 @@ impl<'a> Bytecode {
     }
 
     pub fn load_ast(name: &str, token: FSRToken, lines: Vec<usize>) -> Result<BytecodeResult> {
-        let type_info = Self::pre_load_ast(name, &token, lines.clone())?;
-        let type_info = type_info.type_info;
+        let mut pre_context = Self::pre_load_ast(name, &token, lines.clone())?;
+        if let Some(e) = pre_context.error.take() {
+            return Err(e.into());
+        }
         let mut const_table = BytecodeContext::new(lines);
-        const_table.type_info = type_info;
+        const_table.type_info = pre_context.type_info;
+        // generic templates are known before their first use, instances are
+        // loaded again by this pass
+        const_table.generics = pre_context.generics;
+        const_table.generics.loaded.clear();
         let vs = Self::load_isolate_block(&token, &mut const_table);
+        if let Some(e) = const_table.error.take() {
+            return Err(e.into());
+        }
         let mut res = Self::load_main_ast(name, token, vs)?;
         let type_info = Self::load_sub_fn_ast(const_table, &mut res);
 
@@ -4027,7 +5177,7 @@ impl<'a> Bytecode {
 
 #[allow(unused)]
 mod test {
-    use crate::backend::compiler::bytecode::Bytecode;
+    use crate::backend::compiler::bytecode::{Bytecode, BytecodeOperator};
 
     use frontend::ast::token::{
         base::{FSRPosition, FSRToken},
@@ -4417,6 +5567,41 @@ abc()
         println!("{:#?}", v);
     }
 
+    #[test]
+    fn test_tail_call() {
+        let expr = "
+        fn abc(n) {
+            if n == 0 {
+                return 0
+            }
+            return abc(n - 1)
+        }
+
+        fn not_tail(n) {
+            return not_tail(n - 1) + 1
+        }
+
+        fn with_defer(n) {
+            defer 1
+            return with_defer(n - 1)
+        }
+        ";
+
+        let v = Bytecode::compile("main", expr).unwrap();
+        let count_tail = |name: &str| {
+            v.bytecode_map[name]
+                .bytecode
+                .iter()
+                .flatten()
+                .filter(|x| x.operator == BytecodeOperator::TailCall)
+                .count()
+        };
+
+        assert_eq!(count_tail("abc"), 1);
+        assert_eq!(count_tail("not_tail"), 0);
+        assert_eq!(count_tail("with_defer"), 0);
+    }
+
     #[test]
     fn test_format_string() {
         let expr = r#"f"Hello, {name}""#;
@@ -4427,4 +5612,97 @@ abc()
         let v = Bytecode::load_ast("main", FSRToken::Module(token.0), token.1);
         println!("{:#?}", v);
     }
+
+    #[test]
+    fn test_struct_methods() {
+        let expr = "
+struct Point {
+    x: u64
+    y: u64
+
+    @static
+    fn origin() -> Ptr[Point] {
+        p: Ptr[Point] = Point.alloc
+        return p
+    }
+
+    @static
+    fn sum(self: Ptr[Point]) -> u64 {
+        return self.x + self.y
+    }
+}
+";
+
+        let v = Bytecode::compile("main", expr).unwrap();
+        let point = v.type_info.get_type(&super::FSRTypeName::new("Point")).unwrap();
+        // methods take no room in the struct
+        assert_eq!(point.size_of(), 16);
+
+        let sum = v.type_info.get_method("Point", "sum").unwrap();
+        assert!(Bytecode::is_instance_method(&sum, "Point"));
+        let origin = v.type_info.get_method("Point", "origin").unwrap();
+        assert!(!Bytecode::is_instance_method(&origin, "Point"));
+
+        // methods are not module globals
+        let defines = v.bytecode_map["__main__"]
+            .bytecode
+            .iter()
+            .flatten()
+            .filter(|x| x.operator == BytecodeOperator::DefineFn)
+            .count();
+        assert_eq!(defines, 0);
+        assert!(v.bytecode_map.contains_key("Point::sum"));
+    }
+
+    #[test]
+    fn test_generic_errors() {
+        let expr = "
+@static
+fn max[T](a: T, b: T) -> T {
+    return a
+}
+
+@entry
+fn test_max() -> u64 {
+    a: u32 = 7
+    c: u64 = 40
+    return max(a, c)
+}
+";
+        let err = Bytecode::compile("main", expr).unwrap_err();
+        assert!(err.to_string().contains("bound to both u32 and u64"));
+
+        let expr = "
+fn ident[T](a: T) -> T {
+    return a
+}
+";
+        let err = Bytecode::compile("main", expr).unwrap_err();
+        assert!(err.to_string().contains("must be @static"));
+    }
+
+    #[test]
+    fn test_static_errors() {
+        let expr = "
+struct Point {
+    x: u64
+}
+
+@static
+fn read_point(p: Ptr[Point]) -> u64 {
+    return p.nope()
+}
+";
+        let err = Bytecode::compile("main", expr).unwrap_err();
+        assert!(err.to_string().contains("struct Point has no method nope"));
+
+        let expr = "
+@entry
+fn cast_foo(a: f64) -> f64 {
+    return a as Foo
+}
+";
+        let err = Bytecode::compile("main", expr).unwrap_err();
+        assert!(err.to_string().contains("unknown type in cast: Foo"));
+    }
 }
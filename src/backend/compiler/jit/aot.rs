@@ -0,0 +1,413 @@
+// Static functions compiled to a library, runtime addresses are loaded from a
+// pointer table `load_library` fills for the module the library was built from
+use std::{
+    ffi::{CStr, c_char},
+    path::Path,
+    process::Command,
+    sync::atomic::Ordering,
+};
+
+use anyhow::{Context, Result, bail};
+use cranelift::{
+    codegen,
+    prelude::{
+        Configurable, FunctionBuilder, FunctionBuilderContext, InstBuilder, MemFlags, settings,
+    },
+};
+use cranelift_module::{DataDescription, DataId, Linkage, Module, default_libcall_names};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use super::{
+    cranelift::{CraneLiftJitBackend, helper_symbols, opt_level},
+    debug,
+};
+use crate::backend::{
+    types::base::{FSRObject, ObjId},
+    vm::inline_cache::AttrInlineCache,
+};
+
+const TABLE_SYMBOL: &str = "__fscript_aot_table";
+const MANIFEST_SYMBOL: &str = "__fscript_aot_manifest";
+const MANIFEST_HEADER: &str = "fscript-aot 1";
+
+/// Runtime address library code loads from its table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AotReloc {
+    /// helper listed by `helper_symbols`
+    Helper(String),
+    TrueId,
+    NoneId,
+    /// code slot of a static function by its identify name
+    FnSlot(String),
+    /// fresh cache of an attribute lookup
+    InlineCache,
+    /// return type of an entry function by its identify name
+    RetType(String),
+}
+
+impl AotReloc {
+    fn to_manifest(&self) -> String {
+        match self {
+            AotReloc::Helper(name) => format!("helper {}", name),
+            AotReloc::TrueId => "true".to_string(),
+            AotReloc::NoneId => "none".to_string(),
+            AotReloc::FnSlot(name) => format!("fn {}", name),
+            AotReloc::InlineCache => "cache".to_string(),
+            AotReloc::RetType(name) => format!("ret {}", name),
+        }
+    }
+
+    fn from_manifest(line: &str) -> Result<Self> {
+        let reloc = match line.split_once(' ') {
+            Some(("helper", name)) => AotReloc::Helper(name.to_string()),
+            Some(("fn", name)) => AotReloc::FnSlot(name.to_string()),
+            Some(("ret", name)) => AotReloc::RetType(name.to_string()),
+            None if line == "true" => AotReloc::TrueId,
+            None if line == "none" => AotReloc::NoneId,
+            None if line == "cache" => AotReloc::InlineCache,
+            _ => bail!("unknown aot table entry: {}", line),
+        };
+        Ok(reloc)
+    }
+}
+
+/// Table entries of the library being built
+pub(crate) struct AotRelocs {
+    pub(super) table: DataId,
+    relocs: Vec<AotReloc>,
+}
+
+impl AotRelocs {
+    /// Index of `reloc` in the table, shared by every function of the library
+    /// except inline caches which belong to one lookup
+    pub(super) fn slot(&mut self, reloc: AotReloc) -> usize {
+        if reloc != AotReloc::InlineCache
+            && let Some(i) = self.relocs.iter().position(|x| *x == reloc)
+        {
+            return i;
+        }
+
+        self.relocs.push(reloc);
+        self.relocs.len() - 1
+    }
+}
+
+impl CraneLiftJitBackend<ObjectModule> {
+    pub fn new_aot() -> Result<Self> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false")?;
+        // the object is linked into a shared library
+        flag_builder.set("is_pic", "true")?;
+        flag_builder.set("opt_level", opt_level())?;
+        let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
+            panic!("host machine is not supported: {}", msg);
+        });
+        let isa = isa_builder.finish(settings::Flags::new(flag_builder))?;
+        let builder = ObjectBuilder::new(isa, "fscript_aot", default_libcall_names())?;
+        let mut module = ObjectModule::new(builder);
+        let table = module.declare_data(TABLE_SYMBOL, Linkage::Export, true, false)?;
+
+        Ok(CraneLiftJitBackend {
+            ctx: codegen::Context::new(),
+            builder_context: FunctionBuilderContext::new(),
+            module,
+            aot: Some(AotRelocs {
+                table,
+                relocs: vec![],
+            }),
+            dump: debug::dump_enabled(),
+        })
+    }
+
+    /// Define the helpers the functions imported as trampolines jumping
+    /// through the table
+    fn define_trampolines(&mut self) -> Result<()> {
+        let imports = self
+            .module
+            .declarations()
+            .get_functions()
+            .filter(|(_, decl)| decl.linkage == Linkage::Import)
+            .map(|(id, decl)| (decl.linkage_name(id).into_owned(), decl.signature.clone()))
+            .collect::<Vec<_>>();
+
+        let ptr = self.module.target_config().pointer_type();
+        for (name, sig) in imports {
+            let aot = self.aot.as_mut().unwrap();
+            let offset = aot.slot(AotReloc::Helper(name.clone())) * ptr.bytes() as usize;
+            let table = aot.table;
+            let id = self.module.declare_function(&name, Linkage::Local, &sig)?;
+
+            self.ctx.func.signature = sig.clone();
+            let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+            let entry_block = builder.create_block();
+            builder.append_block_params_for_function_params(entry_block);
+            builder.switch_to_block(entry_block);
+            builder.seal_block(entry_block);
+            let args = builder.block_params(entry_block).to_vec();
+            let table = self.module.declare_data_in_func(table, builder.func);
+            let base = builder.ins().global_value(ptr, table);
+            let helper = builder
+                .ins()
+                .load(ptr, MemFlags::trusted(), base, offset as i32);
+            let sig_ref = builder.import_signature(sig);
+            let call = builder.ins().call_indirect(sig_ref, helper, &args);
+            let rets = builder.inst_results(call).to_vec();
+            builder.ins().return_(&rets);
+            builder.finalize();
+
+            self.module.define_function(id, &mut self.ctx)?;
+            self.module.clear_context(&mut self.ctx);
+        }
+
+        Ok(())
+    }
+
+    /// Emit the object with its table and manifest, `fns` are the identify
+    /// names and symbols of the compiled functions
+    fn finish(mut self, fns: &[(String, String)]) -> Result<Vec<u8>> {
+        self.define_trampolines()?;
+        let aot = self.aot.take().unwrap();
+        let ptr = self.module.target_config().pointer_type();
+
+        let mut table = DataDescription::new();
+        table.define_zeroinit(aot.relocs.len().max(1) * ptr.bytes() as usize);
+        table.set_align(ptr.bytes() as u64);
+        self.module.define_data(aot.table, &table)?;
+
+        let mut manifest = vec![MANIFEST_HEADER.to_string()];
+        for (name, symbol) in fns {
+            manifest.push(format!("code {} {}", name, symbol));
+        }
+        for reloc in aot.relocs.iter() {
+            manifest.push(format!("slot {}", reloc.to_manifest()));
+        }
+        let mut bytes = manifest.join("\n").into_bytes();
+        bytes.push(0);
+        let id = self
+            .module
+            .declare_data(MANIFEST_SYMBOL, Linkage::Export, false, false)?;
+        let mut data = DataDescription::new();
+        data.define(bytes.into_boxed_slice());
+        self.module.define_data(id, &data)?;
+
+        Ok(self.module.finish().emit()?)
+    }
+}
+
+/// Compile the static functions of `module` to `out`, a relocatable object if
+/// the path ends with `.o`, else a shared library linked by the system `cc`
+pub fn compile_library(module: ObjId, out: &Path) -> Result<()> {
+    let module_obj = FSRObject::id_to_obj(module).as_module();
+    let mut jit = CraneLiftJitBackend::new_aot()?;
+    let mut fns = vec![];
+    for (i, name) in module_obj.static_fn_names().into_iter().enumerate() {
+        let code = module_obj.get_fn(&name).unwrap();
+        let fn_info = &code.as_code().get_bytecode().fn_info;
+        let symbol = format!("fscript_aot_{}", i);
+        jit.compile_fn(
+            &symbol,
+            &name,
+            code.as_code().get_bytecode(),
+            FSRObject::obj_to_id(code),
+            fn_info.is_entry,
+            fn_info.fn_type.clone(),
+        )?;
+        fns.push((name, symbol));
+    }
+
+    let object = jit.finish(&fns)?;
+    if out.extension().is_some_and(|x| x == "o") {
+        std::fs::write(out, object).with_context(|| format!("can not write {}", out.display()))?;
+        return Ok(());
+    }
+
+    let object_path = out.with_extension("o");
+    std::fs::write(&object_path, object)
+        .with_context(|| format!("can not write {}", object_path.display()))?;
+    let status = Command::new("cc")
+        .arg("-shared")
+        .arg("-o")
+        .arg(out)
+        .arg(&object_path)
+        .status();
+    std::fs::remove_file(&object_path)?;
+    if !status?.success() {
+        bail!("failed to link {}", out.display());
+    }
+
+    Ok(())
+}
+
+/// Address a table entry holds in the running module
+fn resolve(module: ObjId, reloc: &AotReloc) -> Result<usize> {
+    let module_obj = FSRObject::id_to_obj(module).as_module();
+    let addr = match reloc {
+        AotReloc::Helper(name) => match helper_symbols().into_iter().find(|x| x.0 == name) {
+            Some((_, ptr)) => ptr as usize,
+            None => bail!("unknown helper: {}", name),
+        },
+        AotReloc::TrueId => FSRObject::true_id(),
+        AotReloc::NoneId => FSRObject::none_id(),
+        AotReloc::FnSlot(name) => match module_obj.get_jit_code_slot(name) {
+            Some(slot) => slot as *const _ as usize,
+            None => bail!("module has no static function {}", name),
+        },
+        // lives as long as the library code
+        AotReloc::InlineCache => Box::leak(Box::<AttrInlineCache>::default()) as *const _ as usize,
+        AotReloc::RetType(name) => {
+            let ret_type = module_obj
+                .get_fn(name)
+                .and_then(|x| x.as_code().get_bytecode().fn_info.fn_type.clone())
+                .and_then(|x| x.return_type.clone());
+            match ret_type {
+                // the signature is kept by the bytecode of the module
+                Some(ret_type) => std::sync::Arc::as_ptr(&ret_type) as usize,
+                None => bail!("static function {} has no return type", name),
+            }
+        }
+    };
+    Ok(addr)
+}
+
+/// Load a library built by `compile_library` and store its functions in the
+/// jit code slots of `module`, the slots must already exist
+pub fn load_library(module: ObjId, path: &Path) -> Result<()> {
+    // the code runs until the process exits, the library is never unloaded
+    let lib = Box::leak(Box::new(unsafe { libloading::Library::new(path)? }));
+    let manifest = unsafe {
+        let ptr = *lib.get::<*const c_char>(MANIFEST_SYMBOL.as_bytes())?;
+        CStr::from_ptr(ptr).to_str()?.to_string()
+    };
+    let table = unsafe { *lib.get::<*mut usize>(TABLE_SYMBOL.as_bytes())? };
+
+    let mut lines = manifest.lines();
+    if lines.next() != Some(MANIFEST_HEADER) {
+        bail!("{} is not an fscript aot library", path.display());
+    }
+
+    let module_obj = FSRObject::id_to_obj(module).as_module();
+    let mut loaded = vec![];
+    let mut relocs = vec![];
+    for line in lines {
+        match line.split_once(' ') {
+            Some(("code", rest)) => {
+                let Some((name, symbol)) = rest.split_once(' ') else {
+                    bail!("bad aot manifest line: {}", line);
+                };
+                let code = unsafe { *lib.get::<*const u8>(symbol.as_bytes())? };
+                loaded.push((name.to_string(), code as usize));
+            }
+            Some(("slot", reloc)) => relocs.push(AotReloc::from_manifest(reloc)?),
+            _ => bail!("bad aot manifest line: {}", line),
+        }
+    }
+
+    let mut names = loaded.iter().map(|x| x.0.clone()).collect::<Vec<_>>();
+    names.sort();
+    if names != module_obj.static_fn_names() {
+        bail!("{} was built from another script", path.display());
+    }
+
+    for (i, reloc) in relocs.iter().enumerate() {
+        let addr = resolve(module, reloc)?;
+        unsafe { table.add(i).write(addr) };
+    }
+
+    for (name, code) in loaded {
+        module_obj
+            .get_jit_code_slot(&name)
+            .unwrap()
+            .store(code, Ordering::Relaxed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use crate::backend::{
+        types::{base::FSRObject, code::FSRCode, module::FSRModule},
+        vm::{thread::FSRThreadRuntime, virtual_machine::FSRVM},
+    };
+
+    use super::{compile_library, load_library};
+
+    const SOURCE: &str = r#"
+    struct Named {
+        id: u64
+        name: string
+    }
+
+    @static
+    fn greet(name: string) -> string {
+        return "hello, " + name
+    }
+
+    @entry
+    fn entry_greet(name: string) -> string {
+        s: string = greet(name)
+        return s + "!"
+    }
+
+    @entry
+    fn count_byte(s: string, b: u8) -> u64 {
+        n: u64 = 0
+        i: u64 = 0
+        while i < s.len() {
+            if s[i] == b {
+                n += 1
+            }
+            i += 1
+        }
+        return n
+    }
+
+    @entry
+    fn struct_field(s: string) -> string {
+        named: Ptr[Named] = Named.alloc
+        named.name = s
+        return named.name + "?"
+    }
+
+    assert(entry_greet("aot") == "hello, aot!")
+    assert(count_byte("banana", 97) == 3)
+    assert(struct_field("name") == "name?")
+    "#;
+
+    fn new_module(source: &str) -> usize {
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        obj_id
+    }
+
+    #[test]
+    fn test_aot_library() {
+        FSRVM::single();
+        let path = std::env::temp_dir().join(format!("fscript_aot_{}.so", std::process::id()));
+        compile_library(new_module(SOURCE), &path).unwrap();
+
+        let module = new_module(SOURCE);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.set_aot_library(&path);
+        runtime.start(module, false).unwrap();
+        let module_obj = FSRObject::id_to_obj(module).as_module();
+        for name in ["greet", "entry_greet", "count_byte", "struct_field"] {
+            let slot = module_obj.get_jit_code_slot(name).unwrap();
+            assert_ne!(slot.load(Ordering::Relaxed), 0);
+        }
+
+        // a library only loads into the script it was built from
+        let other = new_module("@static\nfn other() -> i64 {\n    return 1\n}\n");
+        FSRObject::id_to_mut_obj(other)
+            .unwrap()
+            .as_mut_module()
+            .add_jit_code_slot("other");
+        assert!(load_library(other, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
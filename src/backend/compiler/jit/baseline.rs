@@ -0,0 +1,1333 @@
+// Baseline tier: hot functions and loops compiled without type hints, each
+// operator calls the helper the interpreter uses. Hot baseline code is compiled
+// again with unboxed fast paths for the types it saw.
+use std::mem::offset_of;
+
+use anyhow::{Result, bail};
+use cranelift::{
+    codegen::{
+        self,
+        ir::{BlockArg, SourceLoc},
+    },
+    prelude::{
+        AbiParam, Block, Configurable, FloatCC, FunctionBuilder, FunctionBuilderContext,
+        InstBuilder, IntCC, MemFlags, StackSlotData, StackSlotKind, Value, settings, types,
+    },
+};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::Module;
+
+use super::cranelift::opt_level;
+use super::debug::{self, CodeDump, CodeLines};
+use super::jit_wrapper::{
+    c_assign_arg, c_binary_op, c_box_integer, c_call, c_call_method, c_compare, c_deopt, c_for_end,
+    c_for_iter, c_for_next, c_get_attr, c_getter, c_load_closure, c_load_global, c_load_var, c_op_assign_var,
+    c_osr_exit, c_osr_return, c_range, c_safepoint, c_store_var, c_type_guard, get_current_fn_id, load_float, save_to_exp,
+};
+use crate::backend::{
+    compiler::bytecode::{
+        ArgType, Bytecode, BytecodeArg, BytecodeOperator, CompareOperator, FastAttr,
+    },
+    types::{
+        base::{FSRObject, FSRValue, GlobalObj, ObjId},
+        class::FSRClass,
+        fn_def::{FSRJitInfo, FeedbackType, TypeFeedback},
+    },
+    vm::{thread::IndexMapObj, virtual_machine::gid},
+};
+
+/// Returned by specialized code after a failed guard handed the frame to the
+/// interpreter and by OSR code leaving its loop, never the address of an object
+pub const DEOPT_RET: ObjId = 1;
+
+/// Interpreter state at an operator of specialized code
+#[derive(Debug)]
+pub struct DeoptPoint {
+    /// operator the interpreter runs next, its operands are on the frame stack
+    pub(crate) ip: (usize, usize),
+    /// `last_if_test` of the enclosing `if`s
+    pub(crate) if_tests: Vec<bool>,
+    /// start and break line of the enclosing loops
+    pub(crate) loops: Vec<(usize, usize)>,
+}
+
+pub struct BaselineJitBackend {
+    ctx: codegen::Context,
+    builder_context: FunctionBuilderContext,
+    module: JITModule,
+    /// print the IR and machine code of each compiled function
+    dump: bool,
+}
+
+/// Value on the ssa stack, numbers computed by specialized code stay unboxed
+/// until something needs the object
+#[derive(Debug, Clone, Copy)]
+enum StackValue {
+    Boxed(Value),
+    Integer(Value),
+    Float(Value),
+}
+
+impl StackValue {
+    fn accepts(&self, ty: FeedbackType) -> bool {
+        match self {
+            StackValue::Boxed(_) => true,
+            StackValue::Integer(_) => ty == FeedbackType::Integer,
+            StackValue::Float(_) => ty == FeedbackType::Float,
+        }
+    }
+}
+
+struct LoopBlocks {
+    header: Block,
+    exit: Block,
+    is_for: bool,
+    /// start and break line the interpreter keeps for the loop
+    lines: (usize, usize),
+}
+
+/// Loop an OSR entry starts in, the interpreter already keeps its state
+#[derive(Debug, Clone, Copy)]
+struct OsrLoop {
+    header: usize,
+    /// line of the block end operator
+    end: usize,
+    break_line: usize,
+    is_for: bool,
+}
+
+/// Code `compile_tier` emits
+#[derive(Debug, Clone, Copy)]
+enum Tier {
+    Baseline,
+    Specialized,
+    Osr(OsrLoop),
+}
+
+struct IfBlocks {
+    /// block testing the next `else if` or running `else`, none after `else`
+    next: Option<Block>,
+    end: Block,
+    /// `last_if_test` of the interpreter in the branch being compiled
+    taken: bool,
+}
+
+struct BaselineBuilder<'a> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut JITModule,
+    const_map: &'a IndexMapObj,
+    jit_info: &'a FSRJitInfo,
+    feedback: &'a [TypeFeedback],
+    /// emit fast paths for the types in `feedback`
+    specialize: bool,
+    /// compiling an OSR entry, `return` pops the frame of the interpreter
+    osr: bool,
+    /// index in `feedback` of the next profiled operator
+    site: usize,
+    /// line and operator being compiled
+    ip: (usize, usize),
+    /// boxed, the code embeds their addresses
+    #[allow(clippy::vec_box, reason = "jitted code embeds the address of each point")]
+    deopt_points: Vec<Box<DeoptPoint>>,
+    /// block and stack of each entry in `deopt_points`
+    deopts: Vec<(Block, Vec<StackValue>)>,
+    thread_runtime: Value,
+    exp: Vec<StackValue>,
+    loops: Vec<LoopBlocks>,
+    /// header of the while loop whose test is compiled
+    while_header: Option<Block>,
+    ifs: Vec<IfBlocks>,
+    /// open `and`/`or`: operators left in the right side and the joining block
+    logic: Vec<(usize, Block)>,
+    error_block: Block,
+}
+
+/// Offset of the number in an object, the layout of `FSRValue` is only known
+/// to rustc so it is measured on a value
+fn payload_offset(value: &FSRValue, payload: *const u8) -> i32 {
+    (offset_of!(FSRObject, value) + (payload as usize - value as *const FSRValue as usize)) as i32
+}
+
+fn integer_offset() -> i32 {
+    let value = FSRValue::Integer(0);
+    let FSRValue::Integer(i) = &value else {
+        unreachable!()
+    };
+    payload_offset(&value, i as *const i64 as *const u8)
+}
+
+fn float_offset() -> i32 {
+    let value = FSRValue::Float(0.0);
+    let FSRValue::Float(f) = &value else {
+        unreachable!()
+    };
+    payload_offset(&value, f as *const f64 as *const u8)
+}
+
+fn class_ptr(cls: GlobalObj) -> i64 {
+    FSRObject::id_to_obj(gid(cls)).as_class() as *const FSRClass as i64
+}
+
+impl BaselineBuilder<'_> {
+    fn ptr_type(&self) -> types::Type {
+        self.module.target_config().pointer_type()
+    }
+
+    fn obj_const(&mut self, id: ObjId) -> Value {
+        let ptr = self.ptr_type();
+        self.builder.ins().iconst(ptr, id as i64)
+    }
+
+    fn ref_const<T>(&mut self, value: &T) -> Value {
+        let ptr = self.ptr_type();
+        self.builder
+            .ins()
+            .iconst(ptr, value as *const T as i64)
+    }
+
+    /// Calls a helper registered in `BaselineJitBackend::new`, the thread
+    /// runtime is passed as the last argument
+    fn call_helper(&mut self, name: &str, args: &[Value], ret: bool) -> Option<Value> {
+        let ptr = self.ptr_type();
+        let mut args = args.to_vec();
+        args.push(self.thread_runtime);
+        let mut sig = self.module.make_signature();
+        for arg in &args {
+            sig.params
+                .push(AbiParam::new(self.builder.func.dfg.value_type(*arg)));
+        }
+        if ret {
+            sig.returns.push(AbiParam::new(ptr));
+        }
+        let fn_id = self
+            .module
+            .declare_function(name, cranelift_module::Linkage::Import, &sig)
+            .unwrap();
+        let func_ref = self.module.declare_func_in_func(fn_id, self.builder.func);
+        let call = self.builder.ins().call(func_ref, &args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    /// Calls a helper returning an object, 0 means the helper raised an error
+    fn call_checked(&mut self, name: &str, args: &[Value]) -> Value {
+        let value = self.call_helper(name, args, true).unwrap();
+        let ok_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(value, ok_block, &[], self.error_block, &[]);
+        self.builder.switch_to_block(ok_block);
+        value
+    }
+
+    fn push(&mut self, value: Value) {
+        self.exp.push(StackValue::Boxed(value));
+    }
+
+    fn pop(&mut self) -> Value {
+        let value = self.exp.pop().unwrap();
+        self.box_value(value)
+    }
+
+    /// Allocation does not collect, the new object is safe until the next
+    /// safepoint
+    fn box_value(&mut self, value: StackValue) -> Value {
+        match value {
+            StackValue::Boxed(v) => v,
+            StackValue::Integer(v) => self.call_helper("c_box_integer", &[v], true).unwrap(),
+            StackValue::Float(v) => self.call_helper("load_float", &[v], true).unwrap(),
+        }
+    }
+
+    /// Values left on the stack may outlive a collection run by the callee
+    fn spill(&mut self) {
+        if self.exp.is_empty() {
+            return;
+        }
+
+        let mut values = vec![];
+        for i in 0..self.exp.len() {
+            let value = self.box_value(self.exp[i]);
+            self.exp[i] = StackValue::Boxed(value);
+            values.push(value);
+        }
+        let len = self.obj_const(values.len());
+        let values = self.stack_array(&values);
+        self.call_helper("save_to_exp", &[values, len], false);
+    }
+
+    fn stack_array(&mut self, values: &[Value]) -> Value {
+        let ptr = self.ptr_type();
+        let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            (values.len().max(1) * std::mem::size_of::<ObjId>()) as u32,
+            3,
+        ));
+        for (i, value) in values.iter().enumerate() {
+            let offset = (i * std::mem::size_of::<ObjId>()) as i32;
+            self.builder.ins().stack_store(*value, slot, offset);
+        }
+        self.builder.ins().stack_addr(ptr, slot, 0)
+    }
+
+    fn safepoint(&mut self) {
+        self.call_helper("c_safepoint", &[], false);
+    }
+
+    /// Like the interpreter, everything except `false` and `none` is true
+    fn is_true(&mut self, value: Value) -> Value {
+        use codegen::ir::condcodes::IntCC;
+        let false_id = self.obj_const(FSRObject::false_id());
+        let none_id = self.obj_const(FSRObject::none_id());
+        let not_false = self.builder.ins().icmp(IntCC::NotEqual, value, false_id);
+        let not_none = self.builder.ins().icmp(IntCC::NotEqual, value, none_id);
+        self.builder.ins().band(not_false, not_none)
+    }
+
+    /// Code after a jump or return is unreachable, but still needs a block
+    fn start_dead_block(&mut self) {
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+    }
+
+    fn next_site(&mut self) -> usize {
+        self.site += 1;
+        self.site - 1
+    }
+
+    /// Type to specialize a profiled operator for, none to call the helper
+    fn site_type(&self, site: usize, operands: &[StackValue]) -> Option<FeedbackType> {
+        if !self.specialize {
+            return None;
+        }
+
+        let ty = self.feedback[site].observed()?;
+        operands.iter().all(|x| x.accepts(ty)).then_some(ty)
+    }
+
+    fn is_unboxed_op(ty: FeedbackType, op: FastAttr) -> bool {
+        match ty {
+            FeedbackType::Integer => matches!(
+                op,
+                FastAttr::Add | FastAttr::Sub | FastAttr::Mul | FastAttr::Div | FastAttr::Reminder
+            ),
+            // float has no `%`
+            FeedbackType::Float => {
+                matches!(op, FastAttr::Add | FastAttr::Sub | FastAttr::Mul | FastAttr::Div)
+            }
+        }
+    }
+
+    /// Block giving the frame back to the interpreter at the operator being
+    /// compiled, `operands` go back on the stack above the current values.
+    /// The block is filled by `emit_deopts` once the function is compiled.
+    fn deopt_block(&mut self, operands: &[StackValue]) -> Block {
+        let block = self.builder.create_block();
+        let stack = self.exp.iter().chain(operands).copied().collect();
+        let point = Box::new(DeoptPoint {
+            ip: self.ip,
+            if_tests: self.ifs.iter().map(|x| x.taken).collect(),
+            loops: self.loops.iter().map(|x| x.lines).collect(),
+        });
+        self.deopts.push((block, stack));
+        self.deopt_points.push(point);
+        block
+    }
+
+    fn emit_deopts(&mut self) {
+        let deopts = std::mem::take(&mut self.deopts);
+        for (i, (block, stack)) in deopts.into_iter().enumerate() {
+            self.builder.switch_to_block(block);
+            let values = stack
+                .into_iter()
+                .map(|x| self.box_value(x))
+                .collect::<Vec<_>>();
+            let len = self.obj_const(values.len());
+            let values = self.stack_array(&values);
+            self.call_helper("save_to_exp", &[values, len], false);
+
+            let ptr = self.ptr_type();
+            let point = self.deopt_points[i].as_ref() as *const DeoptPoint;
+            let point = self.builder.ins().iconst(ptr, point as i64);
+            self.call_helper("c_deopt", &[point], false);
+            let ret = self.obj_const(DEOPT_RET);
+            self.builder.ins().return_(&[ret]);
+        }
+    }
+
+    /// Number in `value`, a boxed value of another class jumps to `deopt`
+    fn guard(&mut self, value: StackValue, ty: FeedbackType, deopt: Block) -> Value {
+        let obj = match value {
+            StackValue::Integer(v) | StackValue::Float(v) => return v,
+            StackValue::Boxed(obj) => obj,
+        };
+
+        let ptr = self.ptr_type();
+        let (cls, payload_type, offset) = match ty {
+            FeedbackType::Integer => {
+                (class_ptr(GlobalObj::IntegerCls), types::I64, integer_offset())
+            }
+            FeedbackType::Float => (class_ptr(GlobalObj::FloatCls), types::F64, float_offset()),
+        };
+        let cls_offset = offset_of!(FSRObject, cls) as i32;
+        let obj_cls = self
+            .builder
+            .ins()
+            .load(ptr, MemFlags::trusted(), obj, cls_offset);
+        let is_cls = self.builder.ins().icmp_imm(IntCC::Equal, obj_cls, cls);
+        let ok_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(is_cls, ok_block, &[], deopt, &[]);
+        self.builder.switch_to_block(ok_block);
+        self.builder
+            .ins()
+            .load(payload_type, MemFlags::trusted(), obj, offset)
+    }
+
+    /// Continue in a new block unless `cond` is set, then deoptimize
+    fn deopt_if(&mut self, cond: Value, deopt: Block) {
+        let ok_block = self.builder.create_block();
+        self.builder.ins().brif(cond, deopt, &[], ok_block, &[]);
+        self.builder.switch_to_block(ok_block);
+    }
+
+    /// Arithmetic on unboxed numbers, an integer overflow deoptimizes so the
+    /// interpreter decides what it means
+    fn unboxed_binary(
+        &mut self,
+        op: FastAttr,
+        ty: FeedbackType,
+        left: StackValue,
+        right: StackValue,
+        deopt: Block,
+    ) -> StackValue {
+        let l = self.guard(left, ty, deopt);
+        let r = self.guard(right, ty, deopt);
+        if ty == FeedbackType::Float {
+            let ret = match op {
+                FastAttr::Add => self.builder.ins().fadd(l, r),
+                FastAttr::Sub => self.builder.ins().fsub(l, r),
+                FastAttr::Mul => self.builder.ins().fmul(l, r),
+                FastAttr::Div => self.builder.ins().fdiv(l, r),
+                _ => unreachable!("float {:?} is rejected by is_unboxed_op", op),
+            };
+            return StackValue::Float(ret);
+        }
+
+        let (ret, overflow) = match op {
+            FastAttr::Add => self.builder.ins().sadd_overflow(l, r),
+            FastAttr::Sub => self.builder.ins().ssub_overflow(l, r),
+            FastAttr::Mul => self.builder.ins().smul_overflow(l, r),
+            // integer division makes a float like `integer::div`
+            FastAttr::Div => {
+                let l = self.builder.ins().fcvt_from_sint(types::F64, l);
+                let r = self.builder.ins().fcvt_from_sint(types::F64, r);
+                return StackValue::Float(self.builder.ins().fdiv(l, r));
+            }
+            FastAttr::Reminder => {
+                // `srem` traps on 0 and on `i64::MIN % -1`
+                let is_zero = self.builder.ins().icmp_imm(IntCC::Equal, r, 0);
+                let is_minus_one = self.builder.ins().icmp_imm(IntCC::Equal, r, -1);
+                let trap = self.builder.ins().bor(is_zero, is_minus_one);
+                self.deopt_if(trap, deopt);
+                return StackValue::Integer(self.builder.ins().srem(l, r));
+            }
+            _ => unreachable!("integer {:?} is rejected by is_unboxed_op", op),
+        };
+        self.deopt_if(overflow, deopt);
+        StackValue::Integer(ret)
+    }
+
+    fn load(&mut self, arg: &BytecodeArg) {
+        let value = match arg.get_arg() {
+            ArgType::Local(var) => {
+                let var = self.ref_const(var);
+                self.call_checked("c_load_var", &[var])
+            }
+            ArgType::Const(index, _) => {
+                let id = *self.const_map.get(index).unwrap();
+                self.obj_const(id)
+            }
+            ArgType::Global(name) => {
+                let name = self.ref_const(name);
+                self.call_checked("c_load_global", &[name])
+            }
+            ArgType::ClosureVar(var) => {
+                let var = self.ref_const(var);
+                self.call_checked("c_load_closure", &[var])
+            }
+            ArgType::CurrentFn => self.call_helper("get_current_fn_id", &[], true).unwrap(),
+            ArgType::LoadTrue => self.obj_const(FSRObject::true_id()),
+            ArgType::LoadFalse => self.obj_const(FSRObject::false_id()),
+            ArgType::LoadNone => self.obj_const(FSRObject::none_id()),
+            _ => unreachable!("load {:?} is rejected by check_supported", arg.get_arg()),
+        };
+        self.push(value);
+    }
+
+    fn binary_op(&mut self, op: FastAttr) {
+        let site = self.next_site();
+        let right = self.exp.pop().unwrap();
+        let left = self.exp.pop().unwrap();
+        if let Some(ty) = self.site_type(site, &[left, right])
+            && Self::is_unboxed_op(ty, op)
+        {
+            let deopt = self.deopt_block(&[left, right]);
+            let ret = self.unboxed_binary(op, ty, left, right, deopt);
+            self.exp.push(ret);
+            return;
+        }
+
+        let left = self.box_value(left);
+        let right = self.box_value(right);
+        self.spill();
+        let op = self.builder.ins().iconst(types::I32, op as i64);
+        let feedback = self.ref_const(&self.feedback[site]);
+        let ret = self.call_checked("c_binary_op", &[left, right, op, feedback]);
+        self.push(ret);
+    }
+
+    fn unboxed_compare(
+        &mut self,
+        op: CompareOperator,
+        ty: FeedbackType,
+        left: StackValue,
+        right: StackValue,
+    ) -> Value {
+        let deopt = self.deopt_block(&[left, right]);
+        let l = self.guard(left, ty, deopt);
+        let r = self.guard(right, ty, deopt);
+        let is_true = match ty {
+            FeedbackType::Integer => {
+                let cc = match op {
+                    CompareOperator::Equal => IntCC::Equal,
+                    CompareOperator::NotEqual => IntCC::NotEqual,
+                    CompareOperator::Greater => IntCC::SignedGreaterThan,
+                    CompareOperator::GreaterEqual => IntCC::SignedGreaterThanOrEqual,
+                    CompareOperator::Less => IntCC::SignedLessThan,
+                    CompareOperator::LessEqual => IntCC::SignedLessThanOrEqual,
+                };
+                self.builder.ins().icmp(cc, l, r)
+            }
+            FeedbackType::Float => {
+                let cc = match op {
+                    CompareOperator::Equal => FloatCC::Equal,
+                    CompareOperator::NotEqual => FloatCC::NotEqual,
+                    CompareOperator::Greater => FloatCC::GreaterThan,
+                    CompareOperator::GreaterEqual => FloatCC::GreaterThanOrEqual,
+                    CompareOperator::Less => FloatCC::LessThan,
+                    CompareOperator::LessEqual => FloatCC::LessThanOrEqual,
+                };
+                self.builder.ins().fcmp(cc, l, r)
+            }
+        };
+        let true_id = self.obj_const(FSRObject::true_id());
+        let false_id = self.obj_const(FSRObject::false_id());
+        self.builder.ins().select(is_true, true_id, false_id)
+    }
+
+    fn compare(&mut self, arg: &BytecodeArg) {
+        let site = self.next_site();
+        let right = self.exp.pop().unwrap();
+        let left = self.exp.pop().unwrap();
+        let op = CompareOperator::try_from(arg.arg_n as u8).ok();
+        if let (Some(ty), Some(op)) = (self.site_type(site, &[left, right]), op) {
+            let ret = self.unboxed_compare(op, ty, left, right);
+            self.push(ret);
+            return;
+        }
+
+        let left = self.box_value(left);
+        let right = self.box_value(right);
+        self.spill();
+        let op = self.builder.ins().iconst(types::I64, arg.arg_n);
+        let feedback = self.ref_const(&self.feedback[site]);
+        let ret = self.call_checked("c_compare", &[left, right, op, feedback]);
+        self.push(ret);
+    }
+
+    fn not(&mut self) {
+        let value = self.pop();
+        let is_true = self.is_true(value);
+        let true_id = self.obj_const(FSRObject::true_id());
+        let false_id = self.obj_const(FSRObject::false_id());
+        let ret = self.builder.ins().select(is_true, false_id, true_id);
+        self.push(ret);
+    }
+
+    fn getter(&mut self) {
+        let index = self.pop();
+        let container = self.pop();
+        self.spill();
+        let ret = self.call_checked("c_getter", &[container, index]);
+        self.push(ret);
+    }
+
+    fn range(&mut self) {
+        let right = self.pop();
+        let left = self.pop();
+        self.spill();
+        let ret = self.call_checked("c_range", &[left, right]);
+        self.push(ret);
+    }
+
+    fn assign(&mut self, arg: &BytecodeArg) {
+        let ArgType::Local(var) = arg.get_arg() else {
+            unreachable!("assign {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let Some(op_assign) = var.op_assign else {
+            let value = self.pop();
+            let var = self.ref_const(var);
+            self.call_helper("c_store_var", &[var, value], false);
+            return;
+        };
+
+        let site = self.next_site();
+        let value = self.exp.pop().unwrap();
+        let op = op_assign.get_offset();
+        if let Some(ty) = self.site_type(site, &[value])
+            && Self::is_unboxed_op(ty, op)
+        {
+            let deopt = self.deopt_block(&[value]);
+            let var = self.ref_const(var);
+            let left = self.call_checked("c_load_var", &[var]);
+            let ret = self.unboxed_binary(op, ty, StackValue::Boxed(left), value, deopt);
+            let ret = self.box_value(ret);
+            self.call_helper("c_store_var", &[var, ret], false);
+            return;
+        }
+
+        let value = self.box_value(value);
+        self.spill();
+        let var = self.ref_const(var);
+        let feedback = self.ref_const(&self.feedback[site]);
+        self.call_checked("c_op_assign_var", &[var, value, feedback]);
+    }
+
+    fn assign_args(&mut self, arg: &BytecodeArg) {
+        let ArgType::Local(var) = arg.get_arg() else {
+            unreachable!("assign args {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let id = self.builder.ins().iconst(types::I64, var.id as i64);
+        self.call_checked("c_assign_arg", &[id]);
+    }
+
+    fn type_guard(&mut self, arg: &BytecodeArg) {
+        let ArgType::TypeGuard(guard) = arg.get_arg() else {
+            unreachable!("type guard {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let guard = self.ref_const(guard);
+        self.call_checked("c_type_guard", &[guard]);
+    }
+
+    fn call(&mut self, arg: &BytecodeArg) {
+        let ArgType::CallArgsNumber((len, ret_type)) = arg.get_arg() else {
+            unreachable!("call {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let args = self.exp.split_off(self.exp.len() - len);
+        let args = args
+            .into_iter()
+            .map(|x| self.box_value(x))
+            .collect::<Vec<_>>();
+        let fn_obj = self.pop();
+        self.spill();
+        let args = self.stack_array(&args);
+        let len = self.obj_const(*len);
+        let ret_type = self.ref_const(ret_type);
+        let ret = self.call_checked("c_call", &[fn_obj, args, len, ret_type]);
+        self.push(ret);
+    }
+
+    fn get_attr(&mut self, arg: &BytecodeArg) {
+        let ArgType::Attr(attr) = arg.get_arg() else {
+            unreachable!("dot {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let father = self.pop();
+        let attr_ref = self.ref_const(attr);
+        let cache = self.ref_const(arg.get_attr_cache());
+        let ret = self.call_checked("c_get_attr", &[father, attr_ref, cache]);
+        self.push(ret);
+    }
+
+    fn call_method(&mut self, arg: &BytecodeArg) {
+        let ArgType::CallArgsNumberWithAttr((len, _, name, _)) = arg.get_arg() else {
+            unreachable!("call method {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let args = self.exp.split_off(self.exp.len() - len);
+        let args = args
+            .into_iter()
+            .map(|x| self.box_value(x))
+            .collect::<Vec<_>>();
+        let father = self.pop();
+        self.spill();
+        let args = self.stack_array(&args);
+        let len = self.obj_const(*len);
+        let name = self.ref_const(name);
+        let cache = self.ref_const(arg.get_attr_cache());
+        let ret = self.call_checked("c_call_method", &[father, args, len, name, cache]);
+        self.push(ret);
+    }
+
+    fn ret(&mut self) {
+        let value = match self.exp.pop() {
+            Some(s) => self.box_value(s),
+            None => self.obj_const(FSRObject::none_id()),
+        };
+        if self.osr {
+            self.call_checked("c_osr_return", &[value]);
+            let ret = self.obj_const(DEOPT_RET);
+            self.builder.ins().return_(&[ret]);
+        } else {
+            self.builder.ins().return_(&[value]);
+        }
+        self.start_dead_block();
+    }
+
+    fn if_test(&mut self) {
+        let value = self.pop();
+        let is_true = self.is_true(value);
+        let body = self.builder.create_block();
+        let next = self.builder.create_block();
+        let end = self.builder.create_block();
+        self.builder.ins().brif(is_true, body, &[], next, &[]);
+        self.builder.switch_to_block(body);
+        self.ifs.push(IfBlocks {
+            next: Some(next),
+            end,
+            taken: true,
+        });
+    }
+
+    /// Leave the running branch and go on with the test of the next one
+    fn next_branch(&mut self) {
+        let top = self.ifs.last_mut().unwrap();
+        top.taken = false;
+        let (next, end) = (top.next.take().unwrap(), top.end);
+        self.builder.ins().jump(end, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn else_if_test(&mut self) {
+        let value = self.pop();
+        let is_true = self.is_true(value);
+        let body = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(is_true, body, &[], next, &[]);
+        self.builder.switch_to_block(body);
+        let top = self.ifs.last_mut().unwrap();
+        top.next = Some(next);
+        top.taken = true;
+    }
+
+    fn if_end(&mut self) {
+        let top = self.ifs.pop().unwrap();
+        self.builder.ins().jump(top.end, &[]);
+        if let Some(next) = top.next {
+            self.builder.switch_to_block(next);
+            self.builder.ins().jump(top.end, &[]);
+        }
+        self.builder.switch_to_block(top.end);
+    }
+
+    fn while_test(&mut self, arg: &BytecodeArg) {
+        let ArgType::WhileTest(n) = arg.get_arg() else {
+            unreachable!("while {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let value = self.pop();
+        let is_true = self.is_true(value);
+        let body = self.builder.create_block();
+        let exit = self.builder.create_block();
+        self.builder.ins().brif(is_true, body, &[], exit, &[]);
+        self.builder.switch_to_block(body);
+        self.loops.push(LoopBlocks {
+            header: self.while_header.take().unwrap(),
+            exit,
+            is_for: false,
+            lines: (self.ip.0, self.ip.0 + *n as usize + 1),
+        });
+    }
+
+    fn for_iter(&mut self, arg: &BytecodeArg) {
+        let obj = self.pop();
+        self.spill();
+        self.call_checked("c_for_iter", &[obj]);
+        let header = self.builder.create_block();
+        let exit = self.builder.create_block();
+        self.builder.ins().jump(header, &[]);
+        self.builder.switch_to_block(header);
+        self.loops.push(LoopBlocks {
+            header,
+            exit,
+            is_for: true,
+            lines: (self.ip.0 + 1, self.ip.0 + arg.arg_n as usize),
+        });
+    }
+
+    fn for_next(&mut self, arg: &BytecodeArg) {
+        let ArgType::Local(var) = arg.get_arg() else {
+            unreachable!("for {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let next = self.call_checked("c_for_next", &[]);
+        let none_id = self.obj_const(FSRObject::none_id());
+        let is_none = self.builder.ins().icmp(
+            codegen::ir::condcodes::IntCC::Equal,
+            next,
+            none_id,
+        );
+        let body = self.builder.create_block();
+        let exit = self.loops.last().unwrap().exit;
+        self.builder.ins().brif(is_none, exit, &[], body, &[]);
+        self.builder.switch_to_block(body);
+        let var = self.ref_const(var);
+        self.call_helper("c_store_var", &[var, next], false);
+    }
+
+    /// Jump back to the loop header, the iterations of baseline code are a
+    /// safepoint like the end of a line in the interpreter
+    fn loop_back(&mut self) {
+        if !self.specialize {
+            // same plain increment as `FSRJitInfo::backedge`
+            let counter = self.ref_const(self.jit_info.backedge_counter());
+            let count = self
+                .builder
+                .ins()
+                .load(types::I32, MemFlags::trusted(), counter, 0);
+            let count = self.builder.ins().iadd_imm(count, 1);
+            self.builder
+                .ins()
+                .store(MemFlags::trusted(), count, counter, 0);
+        }
+        self.safepoint();
+        let header = self.loops.last().unwrap().header;
+        self.builder.ins().jump(header, &[]);
+    }
+
+    fn loop_end(&mut self) {
+        self.loop_back();
+        let top = self.loops.pop().unwrap();
+        self.builder.switch_to_block(top.exit);
+        if top.is_for {
+            self.call_helper("c_for_end", &[], false);
+        }
+    }
+
+    fn loop_break(&mut self) {
+        let exit = self.loops.last().unwrap().exit;
+        self.builder.ins().jump(exit, &[]);
+        self.start_dead_block();
+    }
+
+    fn loop_continue(&mut self) {
+        self.loop_back();
+        self.start_dead_block();
+    }
+
+    /// `or` keeps the left value when it is true, `and` when it is false, like
+    /// the interpreter the kept value is `true`/`false`
+    fn logic_jump(&mut self, arg: &BytecodeArg, is_or: bool) {
+        let ArgType::AddOffset(offset) = arg.get_arg() else {
+            unreachable!("logic jump {:?} is rejected by check_supported", arg.get_arg());
+        };
+
+        let ptr = self.ptr_type();
+        let value = self.pop();
+        let is_true = self.is_true(value);
+        let rest = self.builder.create_block();
+        let end = self.builder.create_block();
+        self.builder.append_block_param(end, ptr);
+        if is_or {
+            let true_id = BlockArg::Value(self.obj_const(FSRObject::true_id()));
+            self.builder.ins().brif(is_true, end, &[true_id], rest, &[]);
+        } else {
+            let false_id = BlockArg::Value(self.obj_const(FSRObject::false_id()));
+            self.builder.ins().brif(is_true, rest, &[], end, &[false_id]);
+        }
+        self.builder.switch_to_block(rest);
+        self.logic.push((*offset, end));
+    }
+
+    /// Count down the open `and`/`or` and join the ones whose right side is done
+    fn logic_step(&mut self, open: usize) {
+        for (rest, _) in self.logic.iter_mut().take(open) {
+            *rest -= 1;
+        }
+
+        while let Some((0, end)) = self.logic.last().copied() {
+            self.logic.pop();
+            let value = BlockArg::Value(self.pop());
+            self.builder.ins().jump(end, &[value]);
+            self.builder.switch_to_block(end);
+            self.push(self.builder.block_params(end)[0]);
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &[BytecodeArg]) {
+        if let Some(last) = expr.last()
+            && last.get_operator() == BytecodeOperator::WhileTest
+        {
+            let header = self.builder.create_block();
+            self.builder.ins().jump(header, &[]);
+            self.builder.switch_to_block(header);
+            self.while_header = Some(header);
+        }
+
+        for (i, arg) in expr.iter().enumerate() {
+            self.ip.1 = i;
+            let open = self.logic.len();
+            match arg.get_operator() {
+                BytecodeOperator::Load | BytecodeOperator::LoadVar => self.load(arg),
+                BytecodeOperator::LoadConst => {
+                    let id = *self.const_map.get(&(arg.arg_n as u64)).unwrap();
+                    let value = self.obj_const(id);
+                    self.push(value);
+                }
+                BytecodeOperator::Assign => self.assign(arg),
+                BytecodeOperator::AssignArgs => self.assign_args(arg),
+                BytecodeOperator::TypeGuard => self.type_guard(arg),
+                BytecodeOperator::BinaryAdd => self.binary_op(FastAttr::Add),
+                BytecodeOperator::BinarySub => self.binary_op(FastAttr::Sub),
+                BytecodeOperator::BinaryMul => self.binary_op(FastAttr::Mul),
+                BytecodeOperator::BinaryDiv => self.binary_op(FastAttr::Div),
+                BytecodeOperator::BinaryReminder => self.binary_op(FastAttr::Reminder),
+                BytecodeOperator::BinaryRange => self.range(),
+                BytecodeOperator::CompareTest => self.compare(arg),
+                BytecodeOperator::NotOperator => self.not(),
+                BytecodeOperator::Getter => self.getter(),
+                BytecodeOperator::Call => self.call(arg),
+                BytecodeOperator::BinaryDot => self.get_attr(arg),
+                BytecodeOperator::CallMethod => self.call_method(arg),
+                BytecodeOperator::ReturnValue => self.ret(),
+                BytecodeOperator::IfTest => self.if_test(),
+                BytecodeOperator::ElseIf | BytecodeOperator::Else => self.next_branch(),
+                BytecodeOperator::ElseIfTest => self.else_if_test(),
+                BytecodeOperator::IfBlockEnd => self.if_end(),
+                BytecodeOperator::WhileTest => self.while_test(arg),
+                BytecodeOperator::WhileBlockEnd | BytecodeOperator::ForBlockEnd => {
+                    self.loop_end()
+                }
+                BytecodeOperator::LoadForIter => self.for_iter(arg),
+                BytecodeOperator::SpecialLoadFor => self.for_next(arg),
+                BytecodeOperator::Break => self.loop_break(),
+                BytecodeOperator::Continue => self.loop_continue(),
+                BytecodeOperator::OrJump => self.logic_jump(arg, true),
+                BytecodeOperator::AndJump => self.logic_jump(arg, false),
+                // the iterable is kept in the frame by `c_for_iter`
+                BytecodeOperator::ForBlockRefAdd
+                | BytecodeOperator::Empty
+                | BytecodeOperator::EndFn => {}
+                op => unreachable!("operator {:?} is rejected by check_supported", op),
+            }
+            self.logic_step(open);
+        }
+    }
+}
+
+impl Default for BaselineJitBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BaselineJitBackend {
+    fn init_builder(builder: &mut JITBuilder) {
+        builder.symbol("c_load_var", c_load_var as *const u8);
+        builder.symbol("c_store_var", c_store_var as *const u8);
+        builder.symbol("c_op_assign_var", c_op_assign_var as *const u8);
+        builder.symbol("c_assign_arg", c_assign_arg as *const u8);
+        builder.symbol("c_load_global", c_load_global as *const u8);
+        builder.symbol("c_load_closure", c_load_closure as *const u8);
+        builder.symbol("c_binary_op", c_binary_op as *const u8);
+        builder.symbol("c_compare", c_compare as *const u8);
+        builder.symbol("c_getter", c_getter as *const u8);
+        builder.symbol("c_range", c_range as *const u8);
+        builder.symbol("c_call", c_call as *const u8);
+        builder.symbol("c_type_guard", c_type_guard as *const u8);
+        builder.symbol("c_get_attr", c_get_attr as *const u8);
+        builder.symbol("c_call_method", c_call_method as *const u8);
+        builder.symbol("c_for_iter", c_for_iter as *const u8);
+        builder.symbol("c_for_next", c_for_next as *const u8);
+        builder.symbol("c_for_end", c_for_end as *const u8);
+        builder.symbol("c_safepoint", c_safepoint as *const u8);
+        builder.symbol("c_deopt", c_deopt as *const u8);
+        builder.symbol("c_osr_exit", c_osr_exit as *const u8);
+        builder.symbol("c_osr_return", c_osr_return as *const u8);
+        builder.symbol("c_box_integer", c_box_integer as *const u8);
+        builder.symbol("load_float", load_float as *const u8);
+        builder.symbol("save_to_exp", save_to_exp as *const u8);
+        builder.symbol("get_current_fn_id", get_current_fn_id as *const u8);
+    }
+
+    pub fn new() -> Self {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false").unwrap();
+        flag_builder.set("is_pic", "false").unwrap();
+        flag_builder.set("opt_level", opt_level()).unwrap();
+        let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
+            panic!("host machine is not supported: {}", msg);
+        });
+        let isa = isa_builder
+            .finish(settings::Flags::new(flag_builder))
+            .unwrap();
+        let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+        Self::init_builder(&mut builder);
+
+        BaselineJitBackend {
+            ctx: codegen::Context::new(),
+            builder_context: FunctionBuilderContext::new(),
+            module: JITModule::new(builder),
+            dump: debug::dump_enabled(),
+        }
+    }
+
+    /// Print the IR and machine code of the functions this backend compiles
+    pub fn set_dump(&mut self, dump: bool) {
+        self.dump = dump;
+    }
+
+    /// Operators the baseline tier compiles, a function using anything else
+    /// stays in the interpreter
+    fn check_supported(lines: &[Vec<BytecodeArg>], const_map: &IndexMapObj) -> Result<()> {
+        use BytecodeOperator as Op;
+        for arg in lines.iter().flatten() {
+            let supported = match (arg.get_operator(), arg.get_arg()) {
+                (
+                    Op::Load | Op::LoadVar,
+                    ArgType::Local(_)
+                    | ArgType::Global(_)
+                    | ArgType::ClosureVar(_)
+                    | ArgType::CurrentFn
+                    | ArgType::LoadTrue
+                    | ArgType::LoadFalse
+                    | ArgType::LoadNone,
+                ) => true,
+                (Op::Load | Op::LoadVar, ArgType::Const(index, _)) => {
+                    const_map.get(index).is_some()
+                }
+                (Op::LoadConst, _) => const_map.get(&(arg.arg_n as u64)).is_some(),
+                (Op::Assign | Op::AssignArgs | Op::SpecialLoadFor, ArgType::Local(_)) => true,
+                (Op::Call, ArgType::CallArgsNumber(_)) => true,
+                (Op::CallMethod, ArgType::CallArgsNumberWithAttr(_)) => true,
+                (Op::TypeGuard, ArgType::TypeGuard(_)) => true,
+                (Op::BinaryDot, ArgType::Attr(attr)) => attr.op_assign.is_none(),
+                (Op::OrJump | Op::AndJump, ArgType::AddOffset(_)) => true,
+                (Op::WhileTest, ArgType::WhileTest(_)) => true,
+                (
+                    Op::BinaryAdd
+                    | Op::BinarySub
+                    | Op::BinaryMul
+                    | Op::BinaryDiv
+                    | Op::BinaryReminder
+                    | Op::BinaryRange
+                    | Op::CompareTest
+                    | Op::NotOperator
+                    | Op::Getter
+                    | Op::ReturnValue
+                    | Op::IfTest
+                    | Op::ElseIf
+                    | Op::ElseIfTest
+                    | Op::Else
+                    | Op::IfBlockEnd
+                    | Op::WhileBlockEnd
+                    | Op::ForBlockRefAdd
+                    | Op::LoadForIter
+                    | Op::ForBlockEnd
+                    | Op::Break
+                    | Op::Continue
+                    | Op::Empty
+                    | Op::EndFn,
+                    _,
+                ) => true,
+                _ => false,
+            };
+
+            if !supported {
+                bail!(
+                    "baseline tier does not support {:?} with {:?}",
+                    arg.get_operator(),
+                    arg.get_arg()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Operators with a `TypeFeedback` entry, in the order they are compiled
+    fn is_profiled(arg: &BytecodeArg) -> bool {
+        match arg.get_operator() {
+            BytecodeOperator::BinaryAdd
+            | BytecodeOperator::BinarySub
+            | BytecodeOperator::BinaryMul
+            | BytecodeOperator::BinaryDiv
+            | BytecodeOperator::BinaryReminder
+            | BytecodeOperator::CompareTest => true,
+            BytecodeOperator::Assign => {
+                matches!(arg.get_arg(), ArgType::Local(var) if var.op_assign.is_some())
+            }
+            _ => false,
+        }
+    }
+
+    /// Compile a script function to `extern "C" fn(&mut FSRThreadRuntime) -> ObjId`,
+    /// the code returns 0 when an operator raised an error
+    pub fn compile(
+        &mut self,
+        full_name: &str,
+        bs_code: &Bytecode,
+        const_map: &IndexMapObj,
+        jit_info: &FSRJitInfo,
+    ) -> Result<*const u8> {
+        Self::check_supported(&bs_code.bytecode, const_map)?;
+        let sites = bs_code
+            .bytecode
+            .iter()
+            .flatten()
+            .filter(|x| Self::is_profiled(x))
+            .count();
+        let feedback = jit_info.init_feedback(sites);
+        let (code, _) =
+            self.compile_tier(full_name, bs_code, const_map, jit_info, feedback, Tier::Baseline)?;
+        Ok(code)
+    }
+
+    /// Compile again with fast paths for the operand types the baseline code
+    /// recorded, the code returns `DEOPT_RET` after a guard failed
+    pub fn compile_specialized(
+        &mut self,
+        full_name: &str,
+        bs_code: &Bytecode,
+        const_map: &IndexMapObj,
+        jit_info: &FSRJitInfo,
+    ) -> Result<(*const u8, Vec<Box<DeoptPoint>>)> {
+        Self::check_supported(&bs_code.bytecode, const_map)?;
+        let Some(feedback) = jit_info.get_feedback() else {
+            bail!("function has no baseline code to specialize");
+        };
+        if feedback.iter().all(|x| x.observed().is_none()) {
+            bail!("no operator saw a single number type");
+        }
+
+        self.compile_tier(full_name, bs_code, const_map, jit_info, feedback, Tier::Specialized)
+    }
+
+    /// Loop whose backedge jumps to `header`, for a `for` loop that is the line
+    /// fetching the next item
+    fn osr_loop(bs_code: &Bytecode, header: usize) -> Result<OsrLoop> {
+        use BytecodeOperator as Op;
+        let lines = &bs_code.bytecode;
+        let op_at = |line: usize, last: bool| {
+            let expr = lines.get(line)?;
+            let arg = if last { expr.last() } else { expr.first() };
+            arg.map(|x| (x.get_operator(), x))
+        };
+
+        let osr = match (op_at(header, true), op_at(header, false)) {
+            (Some((Op::WhileTest, arg)), _) => {
+                let ArgType::WhileTest(n) = arg.get_arg() else {
+                    bail!("while {:?} is not a loop header", arg.get_arg());
+                };
+                OsrLoop {
+                    header,
+                    end: header + *n as usize,
+                    break_line: header + *n as usize + 1,
+                    is_for: false,
+                }
+            }
+            (_, Some((Op::SpecialLoadFor, _))) if header > 0 => {
+                let Some((Op::LoadForIter, arg)) = op_at(header - 1, true) else {
+                    bail!("line {} does not start a for loop", header - 1);
+                };
+                let break_line = header - 1 + arg.arg_n as usize;
+                OsrLoop {
+                    header,
+                    end: break_line - 1,
+                    break_line,
+                    is_for: true,
+                }
+            }
+            _ => bail!("line {} is not a loop header", header),
+        };
+
+        let has_end = lines.get(osr.end).is_some_and(|expr| {
+            expr.iter()
+                .any(|x| matches!(x.get_operator(), Op::WhileBlockEnd | Op::ForBlockEnd))
+        });
+        if !has_end {
+            bail!("loop at line {} has no end at line {}", header, osr.end);
+        }
+
+        Ok(osr)
+    }
+
+    /// Compile the loop at `header` of a function the interpreter is running,
+    /// the code is entered at the backedge and returns `DEOPT_RET` once the
+    /// frame is back in the interpreter
+    pub fn compile_osr(
+        &mut self,
+        full_name: &str,
+        bs_code: &Bytecode,
+        const_map: &IndexMapObj,
+        jit_info: &FSRJitInfo,
+        header: usize,
+    ) -> Result<*const u8> {
+        let osr = Self::osr_loop(bs_code, header)?;
+        Self::check_supported(&bs_code.bytecode[osr.header..=osr.end], const_map)?;
+        let sites = bs_code
+            .bytecode
+            .iter()
+            .flatten()
+            .filter(|x| Self::is_profiled(x))
+            .count();
+        let feedback = jit_info.init_feedback(sites);
+        let tier = Tier::Osr(osr);
+        let (code, _) = self.compile_tier(full_name, bs_code, const_map, jit_info, feedback, tier)?;
+        Ok(code)
+    }
+
+    #[allow(clippy::vec_box, reason = "jitted code embeds the address of each point")]
+    fn compile_tier(
+        &mut self,
+        full_name: &str,
+        bs_code: &Bytecode,
+        const_map: &IndexMapObj,
+        jit_info: &FSRJitInfo,
+        feedback: &[TypeFeedback],
+        tier: Tier,
+    ) -> Result<(*const u8, Vec<Box<DeoptPoint>>)> {
+        let osr = match tier {
+            Tier::Osr(osr) => Some(osr),
+            _ => None,
+        };
+        let ptr = self.module.target_config().pointer_type();
+        self.ctx.func.signature.params.push(AbiParam::new(ptr)); // thread runtime
+        self.ctx.func.signature.returns.push(AbiParam::new(ptr));
+
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        let thread_runtime = builder.block_params(entry_block)[0];
+        let error_block = builder.create_block();
+
+        let mut trans = BaselineBuilder {
+            builder,
+            module: &mut self.module,
+            const_map,
+            jit_info,
+            feedback,
+            specialize: matches!(tier, Tier::Specialized),
+            osr: osr.is_some(),
+            site: 0,
+            ip: (0, 0),
+            deopt_points: vec![],
+            deopts: vec![],
+            thread_runtime,
+            exp: vec![],
+            loops: vec![],
+            while_header: None,
+            ifs: vec![],
+            logic: vec![],
+            error_block,
+        };
+
+        let lines = match osr {
+            Some(osr) => osr.header..osr.end + 1,
+            None => 0..bs_code.bytecode.len(),
+        };
+        if let Some(osr) = osr {
+            // the operators before the loop keep their feedback entries
+            trans.site = bs_code.bytecode[..osr.header]
+                .iter()
+                .flatten()
+                .filter(|x| Self::is_profiled(x))
+                .count();
+            if osr.is_for {
+                // the iterator was made by the interpreter
+                let header = trans.builder.create_block();
+                let exit = trans.builder.create_block();
+                trans.builder.ins().jump(header, &[]);
+                trans.builder.switch_to_block(header);
+                trans.loops.push(LoopBlocks {
+                    header,
+                    exit,
+                    is_for: true,
+                    lines: (osr.header, osr.break_line),
+                });
+            }
+        }
+
+        let mut in_args = osr.is_none();
+        for line in lines {
+            let expr = &bs_code.bytecode[line];
+            let is_args = expr
+                .first()
+                .is_some_and(|x| x.get_operator() == BytecodeOperator::AssignArgs);
+            if in_args && !is_args {
+                // every call passes here, so recursion collects garbage too
+                trans.safepoint();
+                in_args = false;
+            }
+
+            trans.ip = (line, 0);
+            trans.builder.set_srcloc(SourceLoc::new(line as u32));
+            trans.compile_expr(expr);
+            trans.exp.clear();
+        }
+        if let Some(osr) = osr {
+            let break_line = trans.obj_const(osr.break_line);
+            trans.call_helper("c_osr_exit", &[break_line], false);
+            let ret = trans.obj_const(DEOPT_RET);
+            trans.builder.ins().return_(&[ret]);
+        } else {
+            debug_assert_eq!(trans.site, feedback.len());
+            let none_id = trans.obj_const(FSRObject::none_id());
+            trans.builder.ins().return_(&[none_id]);
+        }
+
+        trans.emit_deopts();
+        trans.builder.switch_to_block(error_block);
+        let zero = trans.builder.ins().iconst(ptr, 0);
+        trans.builder.ins().return_(&[zero]);
+
+        trans.builder.seal_all_blocks();
+        trans.builder.finalize();
+        let deopt_points = trans.deopt_points;
+
+        let id = self.module.declare_function(
+            full_name,
+            cranelift_module::Linkage::Export,
+            &self.ctx.func.signature,
+        )?;
+        // name the tier too, a function can have code of each
+        let name = match tier {
+            Tier::Baseline => format!("{} [baseline]", full_name),
+            Tier::Specialized => format!("{} [specialized]", full_name),
+            Tier::Osr(osr) => {
+                let line = bs_code.bytecode[osr.header][0].get_pos().as_human().line;
+                format!("{} [osr line {}]", full_name, line)
+            }
+        };
+        let dump = self.dump.then(|| CodeDump::begin(&mut self.ctx));
+        self.module.define_function(id, &mut self.ctx)?;
+        if let Some(dump) = dump {
+            println!("{}", dump.finish(&name, &self.ctx));
+        }
+        let lines = CodeLines::collect(&self.ctx, bs_code);
+        self.module.clear_context(&mut self.ctx);
+        self.module.finalize_definitions()?;
+
+        let code = self.module.get_finalized_function(id);
+        if let Some(lines) = lines {
+            debug::register_code(&name, code, &lines);
+        }
+        Ok((code, deopt_points))
+    }
+}
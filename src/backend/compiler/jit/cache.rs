@@ -0,0 +1,210 @@
+// `--jit-cache DIR` keeps the static functions of a script as a library in
+// `DIR`, keyed by everything that changes the generated code
+use std::{
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use anyhow::Result;
+use cranelift::prelude::settings;
+
+use super::{aot, checked::checked_enabled, cranelift::opt_level, simd::simd_enabled};
+use crate::backend::{
+    compiler::bytecode::FnCallSig,
+    types::base::{FSRObject, ObjId},
+};
+
+/// Bumped when the layout of a cached library changes
+const CACHE_VERSION: &str = "fscript-jit-cache 1";
+
+/// FNV-1a, stable across runs and builds unlike the std hasher
+pub(crate) struct StableHasher(u64);
+
+impl StableHasher {
+    pub(crate) fn new() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        // separates the fields written one after another
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hash of the source a module is compiled from
+pub(crate) fn source_hash(source: &str) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write(source.as_bytes());
+    hasher.finish()
+}
+
+fn sig_key(sig: Option<&FnCallSig>) -> String {
+    let Some(sig) = sig else {
+        return "()".to_string();
+    };
+    let params = sig
+        .params
+        .iter()
+        .map(|x| x.type_key())
+        .collect::<Vec<_>>()
+        .join(",");
+    let ret = sig.return_type.as_ref().map(|x| x.type_key());
+    format!("({}) -> {}", params, ret.unwrap_or_default())
+}
+
+/// Key of the library holding the static functions of `module` compiled for
+/// this host, `None` when the module has no static function
+pub(crate) fn cache_key(module: ObjId) -> Result<Option<String>> {
+    let module_obj = FSRObject::id_to_obj(module).as_module();
+    let names = module_obj.static_fn_names();
+    let Some(first) = names.first() else {
+        return Ok(None);
+    };
+
+    let mut hasher = StableHasher::new();
+    hasher.write(CACHE_VERSION.as_bytes());
+    hasher.write(env!("CARGO_PKG_VERSION").as_bytes());
+    // a rebuilt interpreter may move helpers or change the code generator
+    let exe = std::env::current_exe()?.metadata()?;
+    hasher.write(&exe.len().to_le_bytes());
+    let mtime = exe.modified()?.duration_since(UNIX_EPOCH)?;
+    hasher.write(&mtime.as_nanos().to_le_bytes());
+
+    let code = module_obj.get_fn(first).unwrap().as_code();
+    hasher.write(&code.source_hash().to_le_bytes());
+    for name in names.iter() {
+        let code = module_obj.get_fn(name).unwrap().as_code();
+        let fn_type = code.get_bytecode().fn_info.fn_type.as_deref();
+        hasher.write(name.as_bytes());
+        hasher.write(sig_key(fn_type).as_bytes());
+    }
+
+    let isa = cranelift_native::builder()
+        .map_err(anyhow::Error::msg)?
+        .finish(settings::Flags::new(settings::builder()))?;
+    hasher.write(isa.triple().to_string().as_bytes());
+    for flag in isa.isa_flags() {
+        hasher.write(flag.to_string().as_bytes());
+    }
+    hasher.write(opt_level().as_bytes());
+    hasher.write(&[checked_enabled() as u8, simd_enabled(isa.as_ref()) as u8]);
+
+    Ok(Some(format!("{:016x}", hasher.finish())))
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.so", key))
+}
+
+/// Load the static functions of `module` from the cache in `dir`, compiling
+/// and storing them first on a miss. Returns whether the entry was already
+/// there, `Ok(false)` without loading anything if the module has no static
+/// function
+pub fn load_or_compile(module: ObjId, dir: &Path) -> Result<bool> {
+    let Some(key) = cache_key(module)? else {
+        return Ok(false);
+    };
+
+    let path = entry_path(dir, &key);
+    let hit = path.exists();
+    if !hit {
+        std::fs::create_dir_all(dir)?;
+        // another process may build the same entry, only whole files are
+        // renamed into place
+        let tmp = dir.join(format!("{}.{}.tmp.so", key, std::process::id()));
+        let res = aot::compile_library(module, &tmp)
+            .and_then(|_| std::fs::rename(&tmp, &path).map_err(Into::into));
+        if res.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        res?;
+    }
+
+    aot::load_library(module, &path)?;
+    Ok(hit)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use crate::backend::{
+        types::{base::FSRObject, code::FSRCode, module::FSRModule},
+        vm::virtual_machine::FSRVM,
+    };
+
+    use super::{cache_key, load_or_compile, source_hash};
+
+    const SOURCE: &str = r#"
+    @static
+    fn square(n: i64) -> i64 {
+        return n * n
+    }
+
+    @entry
+    fn sum_squares(n: i64) -> i64 {
+        i: i64 = 0
+        total: i64 = 0
+        while i < n {
+            total = total + square(i)
+            i = i + 1
+        }
+        return total
+    }
+    "#;
+
+    fn new_module(source: &str) -> usize {
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        for name in obj.as_module().static_fn_names() {
+            obj.as_mut_module().add_jit_code_slot(&name);
+        }
+        obj_id
+    }
+
+    #[test]
+    fn test_source_hash() {
+        assert_eq!(source_hash("fn a() {}"), source_hash("fn a() {}"));
+        assert_ne!(source_hash("fn a() {}"), source_hash("fn b() {}"));
+    }
+
+    #[test]
+    fn test_jit_cache() {
+        FSRVM::single();
+        let dir = std::env::temp_dir().join(format!("fscript_jit_cache_{}", std::process::id()));
+        let module = new_module(SOURCE);
+        assert!(!load_or_compile(module, &dir).unwrap());
+
+        // a warm start loads the stored library
+        let warm = new_module(SOURCE);
+        assert_eq!(cache_key(module).unwrap(), cache_key(warm).unwrap());
+        assert!(load_or_compile(warm, &dir).unwrap());
+        let module_obj = FSRObject::id_to_obj(warm).as_module();
+        for name in ["square", "sum_squares"] {
+            let slot = module_obj.get_jit_code_slot(name).unwrap();
+            assert_ne!(slot.load(Ordering::Relaxed), 0);
+        }
+
+        // an edited script gets its own entry
+        let edited = new_module(&SOURCE.replace("n * n", "n * n * n"));
+        assert_ne!(cache_key(module).unwrap(), cache_key(edited).unwrap());
+        assert!(!load_or_compile(edited, &dir).unwrap());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+
+        let no_static = new_module("a = 1\n");
+        assert_eq!(cache_key(no_static).unwrap(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,213 @@
+// Helpers checking list indexes and pointers of static code in checked mode, a
+// failed check raises like `raise` in static code
+use std::{
+    alloc::Layout,
+    cell::Cell,
+    collections::BTreeMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use crate::{
+    backend::vm::thread::FSRThreadRuntime,
+    utils::error::{FSRErrCode, FSRError},
+};
+
+static CHECKED: AtomicBool = AtomicBool::new(cfg!(debug_assertions));
+static HEAP: Mutex<Heap> = Mutex::new(Heap::new());
+/// Bumped by every free, a block checked under an older generation may be gone
+static FREE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Last live block a check found, `(generation, start, size)`, so loops
+    /// over one block skip the heap lock
+    static LAST_BLOCK: Cell<(u64, usize, usize)> = const { Cell::new((u64::MAX, 0, 0)) };
+}
+
+/// Check the memory accesses of the static code compiled from now on
+pub fn set_checked(checked: bool) {
+    CHECKED.store(checked, Ordering::Relaxed);
+}
+
+pub fn checked_enabled() -> bool {
+    CHECKED.load(Ordering::Relaxed)
+}
+
+/// Blocks handed out by `alloc` in checked mode, start address to size
+struct Heap {
+    live: BTreeMap<usize, usize>,
+    freed: BTreeMap<usize, usize>,
+}
+
+fn block_of(blocks: &BTreeMap<usize, usize>, ptr: usize) -> Option<(usize, usize)> {
+    let (start, size) = blocks.range(..=ptr).next_back()?;
+    (ptr < start + (*size).max(1)).then_some((*start, *size))
+}
+
+fn check_in_block(
+    ptr: usize,
+    offset: i64,
+    size: usize,
+    start: usize,
+    len: usize,
+) -> Result<(), String> {
+    let addr = ptr as i64 + offset;
+    if addr < start as i64 || addr + size as i64 > (start + len) as i64 {
+        return Err(format!(
+            "pointer access out of bounds: byte {} of a {} byte block",
+            addr - start as i64,
+            len
+        ));
+    }
+    Ok(())
+}
+
+fn layout(size: usize) -> Layout {
+    Layout::from_size_align(size.max(1), 16).unwrap()
+}
+
+impl Heap {
+    const fn new() -> Self {
+        Self {
+            live: BTreeMap::new(),
+            freed: BTreeMap::new(),
+        }
+    }
+
+    fn alloc(&mut self, size: usize) -> usize {
+        let layout = layout(size);
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+
+        let ptr = ptr as usize;
+        // the allocator reused memory of freed blocks
+        let end = ptr + size.max(1);
+        let reused = self
+            .freed
+            .range(..end)
+            .filter(|(start, len)| **start + (**len).max(1) > ptr)
+            .map(|(start, _)| *start)
+            .collect::<Vec<_>>();
+        for start in reused {
+            self.freed.remove(&start);
+        }
+        self.live.insert(ptr, size);
+        ptr
+    }
+
+    fn free(&mut self, ptr: usize) -> Result<(), String> {
+        if ptr == 0 {
+            return Err("free of a null pointer".to_string());
+        }
+
+        if let Some(size) = self.live.remove(&ptr) {
+            FREE_GENERATION.fetch_add(1, Ordering::Relaxed);
+            unsafe { std::alloc::dealloc(ptr as *mut u8, layout(size)) };
+            self.freed.insert(ptr, size);
+            return Ok(());
+        }
+
+        if block_of(&self.freed, ptr).is_some() {
+            return Err(format!("double free of pointer 0x{:x}", ptr));
+        }
+
+        Err(format!("free of pointer 0x{:x} not returned by alloc", ptr))
+    }
+
+    /// `size` bytes at `ptr + offset` are inside the block `ptr` points to
+    fn check(&self, ptr: usize, offset: i64, size: usize) -> Result<(), String> {
+        if ptr == 0 {
+            return Err("null pointer dereference".to_string());
+        }
+
+        if let Some((start, len)) = block_of(&self.live, ptr) {
+            let generation = FREE_GENERATION.load(Ordering::Relaxed);
+            LAST_BLOCK.with(|x| x.set((generation, start, len)));
+            return check_in_block(ptr, offset, size, start, len);
+        }
+
+        if block_of(&self.freed, ptr).is_some() {
+            return Err(format!("use of pointer 0x{:x} after free", ptr));
+        }
+
+        // not from `alloc`: stack structs, list and string storage, or a block
+        // allocated while unchecked. Its bounds are unknown, it passes
+        Ok(())
+    }
+}
+
+fn fail(thread: &mut FSRThreadRuntime, msg: String, code: FSRErrCode) -> i8 {
+    thread.set_jit_error(FSRError::new(msg, code));
+    0
+}
+
+/// `alloc` of checked code, the block is known to the checks until freed
+pub extern "C" fn c_checked_alloc(size: usize) -> usize {
+    HEAP.lock().unwrap().alloc(size)
+}
+
+/// 1 when `ptr` was freed, else the error is left in the thread
+pub extern "C" fn c_checked_free(thread: &mut FSRThreadRuntime, ptr: usize) -> i8 {
+    match HEAP.lock().unwrap().free(ptr) {
+        Ok(()) => 1,
+        Err(e) => fail(thread, e, FSRErrCode::NotValidArgs),
+    }
+}
+
+/// 1 when `size` bytes at `ptr + offset` may be accessed
+pub extern "C" fn c_check_ptr(
+    thread: &mut FSRThreadRuntime,
+    ptr: usize,
+    offset: i64,
+    size: usize,
+) -> i8 {
+    let (generation, start, len) = LAST_BLOCK.with(|x| x.get());
+    if generation == FREE_GENERATION.load(Ordering::Relaxed)
+        && ptr >= start
+        && ptr < start + len.max(1)
+        && check_in_block(ptr, offset, size, start, len).is_ok()
+    {
+        return 1;
+    }
+
+    match HEAP.lock().unwrap().check(ptr, offset, size) {
+        Ok(()) => 1,
+        Err(e) => fail(thread, e, FSRErrCode::NotValidArgs),
+    }
+}
+
+/// 1 when `index` is inside a list of `len` items
+pub extern "C" fn c_check_index(thread: &mut FSRThreadRuntime, index: i64, len: usize) -> i8 {
+    if index >= 0 && (index as usize) < len {
+        return 1;
+    }
+
+    let msg = format!("list index out of range: {} of {}", index, len);
+    fail(thread, msg, FSRErrCode::IndexOutOfRange)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Heap;
+
+    #[test]
+    fn test_heap_checks() {
+        let mut heap = Heap::new();
+        let ptr = heap.alloc(16);
+        assert!(heap.check(ptr, 8, 8).is_ok());
+        assert!(heap.check(ptr, 16, 1).is_err());
+        assert!(heap.check(ptr, -1, 1).is_err());
+        assert!(heap.check(0, 0, 8).is_err());
+        // unknown memory, like the stack, is only checked against null
+        assert!(heap.check(&heap as *const Heap as usize, 0, 8).is_ok());
+
+        assert!(heap.free(ptr).is_ok());
+        assert!(heap.check(ptr, 0, 8).is_err());
+        assert!(heap.free(ptr).is_err());
+        assert!(heap.free(0).is_err());
+    }
+}
@@ -1,11 +1,18 @@
 use core::panic;
-use std::{collections::HashMap, os::unix::thread, sync::Arc};
+use std::{
+    collections::HashMap,
+    os::unix::thread,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
-use anyhow::{Context, Ok, Result};
+use anyhow::{Context, Ok, Result, bail};
 use cranelift::{
     codegen::{
         self,
-        ir::{self, BlockArg},
+        ir::{self, BlockArg, SourceLoc},
     },
     prelude::{
         AbiParam, Block, Configurable, EntityRef, FunctionBuilder, FunctionBuilderContext,
@@ -14,7 +21,7 @@ use cranelift::{
     },
 };
 use cranelift_jit::{JITBuilder, JITModule};
-use cranelift_module::Module;
+use cranelift_module::{DataDescription, FuncId, Module};
 
 use super::jit_wrapper::{
     binary_op, binary_range, c_next_obj, call_fn, check_gc, free, gc_collect, get_constant,
@@ -24,34 +31,80 @@ use crate::backend::{
     compiler::{
         bytecode::{
             ArgType, Bytecode, BytecodeArg, BytecodeOperator, CompareOperator, FSRSType, FastAttr,
-            FnCallSig, LocalVar, OpAssign,
+            FnCallSig, LocalVar, ObjMethod, OpAssign, StrMethod,
         },
+        jit::aot::{AotReloc, AotRelocs},
+        jit::checked::{self, c_check_index, c_check_ptr, c_checked_alloc, c_checked_free},
+        jit::debug::{self, CodeDump, CodeLines},
+        jit::simd,
         jit::jit_wrapper::{
-            c_println, clear_exp, get_current_fn_id, get_obj_method, load_list, memcpy,
-            ret_process, save_to_exp,
+            FSRStaticStr, ObjKey, c_entry_obj_arg, c_fmod, c_fmodf, c_jit_div_error, c_jit_raise,
+            c_jit_safepoint, c_obj_as_float, c_obj_as_int, c_obj_get, c_obj_get_float,
+            c_obj_get_int, c_obj_len, c_println, c_str_byte, c_str_compare, c_str_concat,
+            c_str_from_obj, c_str_slice, clear_exp, get_current_fn_id, get_obj_method, load_list,
+            memcpy, ret_process, save_to_exp,
         },
     },
     types::base::{FSRObject, ObjId},
+    vm::{inline_cache::AttrInlineCache, thread::FSRThreadRuntime},
 };
 use frontend::ast::token::{constant::FSROrinStr2, expr::SingleOp, variable};
 
 const CALL_ARGS_LEN: i64 = 16;
 const UNROLL_THRESHOLD: usize = 128;
 
-pub struct CraneLiftJitBackend {
-    ctx: codegen::Context,
-    builder_context: FunctionBuilderContext,
+/// Compiles static functions into `M`, `JITModule` for the in-memory code or
+/// `ObjectModule` for an ahead-of-time library
+pub struct CraneLiftJitBackend<M: Module = JITModule> {
+    pub(super) ctx: codegen::Context,
+    pub(super) builder_context: FunctionBuilderContext,
     //variable: HashMap<String, Variable>,
-    module: JITModule,
+    pub(super) module: M,
+    /// addresses ahead-of-time code loads from its table, none when jitting
+    pub(super) aot: Option<AotRelocs>,
+    /// print the IR and machine code of each compiled function
+    pub(super) dump: bool,
+}
+
+/// Values of the cranelift `opt_level` setting, `OPT_LEVEL` indexes them
+const OPT_LEVELS: [&str; 3] = ["none", "speed", "speed_and_size"];
+static OPT_LEVEL: AtomicUsize = AtomicUsize::new(0);
+
+/// Set the cranelift `opt_level` of every tier for the code compiled from now on
+pub fn set_opt_level(level: &str) -> Result<()> {
+    let Some(index) = OPT_LEVELS.iter().position(|x| *x == level) else {
+        bail!(
+            "unknown jit opt level `{}`, expected {}",
+            level,
+            OPT_LEVELS.join("|")
+        );
+    };
+    OPT_LEVEL.store(index, Ordering::Relaxed);
+    Ok(())
+}
+
+pub(super) fn opt_level() -> &'static str {
+    OPT_LEVELS[OPT_LEVEL.load(Ordering::Relaxed)]
 }
 
 struct JitBuilder<'a> {
     builder: FunctionBuilder<'a>,
     variables: HashMap<String, Variable>,
-    module: &'a mut JITModule,
+    module: &'a mut dyn Module,
+    aot: Option<&'a mut AotRelocs>,
+    /// identify name of the function, `Struct::method` for methods
+    full_name: &'a str,
     var_index: usize,
     self_call_sig: Arc<FnCallSig>,
     ret_slot: Option<Value>,
+    // static type of scalar and string ssa values, untyped values are literals
+    value_types: HashMap<Value, Arc<FSRSType>>,
+    /// check memory accesses, see `checked`
+    checked: bool,
+    /// the function holds `Object` values, its loops poll the gc
+    has_object: bool,
+    /// lower vector arithmetic to vector instructions, see `simd`
+    simd: bool,
 }
 
 struct OperatorContext {
@@ -113,8 +166,14 @@ impl JitBuilder<'_> {
                             let negated_value = -int_value;
                             negated_value
                         }
-                        SingleOp::Not => todo!(),
-                        SingleOp::Reverse => todo!(),
+                        SingleOp::Not => {
+                            let not_value =
+                                self.builder.ins().iconst(types::I8, (int_value == 0) as i64);
+                            self.set_type(not_value, &Arc::new(FSRSType::Bool));
+                            context.exp.push(not_value);
+                            return;
+                        }
+                        SingleOp::Reverse => !int_value,
                     }
                 } else {
                     int_value
@@ -133,27 +192,47 @@ impl JitBuilder<'_> {
                             let negated_value = -float_value;
                             negated_value
                         }
-                        SingleOp::Not => todo!(),
-                        SingleOp::Reverse => todo!(),
+                        SingleOp::Not => {
+                            let not_value =
+                                self.builder.ins().iconst(types::I8, (float_value == 0.0) as i64);
+                            self.set_type(not_value, &Arc::new(FSRSType::Bool));
+                            context.exp.push(not_value);
+                            return;
+                        }
+                        SingleOp::Reverse => {
+                            let value = self.builder.ins().f64const(float_value);
+                            let reversed = self.builder.ins().fneg(value);
+                            context.exp.push(reversed);
+                            return;
+                        }
                     }
                 } else {
                     float_value
                 };
-                let float_bits = float_value.to_bits() as i64;
-                let const_value = self
-                    .builder
-                    .ins()
-                    .iconst(self.module.target_config().pointer_type(), float_bits);
+                let const_value = self.builder.ins().f64const(float_value);
                 context.exp.push(const_value);
             }
             FSROrinStr2::String(s) => {
+                // the constant is owned by the bytecode, which outlives the compiled code
                 let str_bytes = s.as_bytes();
                 let str_len = str_bytes.len() as i64;
-                let str_ptr = self.builder.ins().iconst(
-                    self.module.target_config().pointer_type(),
-                    str_bytes.as_ptr() as i64,
+                let str_ptr = self.bytes_const(str_bytes);
+                let len = self.builder.ins().iconst(types::I64, str_len);
+                let slot = self.new_str_slot();
+                self.builder.ins().store(
+                    cranelift::codegen::ir::MemFlags::new(),
+                    str_ptr,
+                    slot,
+                    std::mem::offset_of!(FSRStaticStr, ptr) as i32,
+                );
+                self.builder.ins().store(
+                    cranelift::codegen::ir::MemFlags::new(),
+                    len,
+                    slot,
+                    std::mem::offset_of!(FSRStaticStr, len) as i32,
                 );
-                context.exp.push(str_ptr);
+                self.set_type(slot, &Arc::new(FSRSType::String));
+                context.exp.push(slot);
             }
         }
     }
@@ -226,15 +305,12 @@ impl JitBuilder<'_> {
     // }
 
     fn load_is_not_true(&mut self, context: &mut OperatorContext) -> Value {
-        if let Some(value) = context.exp.last() {
-            let true_id = self.builder.ins().iconst(
-                self.module.target_config().pointer_type(),
-                FSRObject::true_id() as i64,
-            );
+        if let Some(&value) = context.exp.last() {
+            let true_id = self.addr_const(AotReloc::TrueId, || FSRObject::true_id());
             let is_not_true =
                 self.builder
                     .ins()
-                    .icmp(codegen::ir::condcodes::IntCC::NotEqual, *value, true_id);
+                    .icmp(codegen::ir::condcodes::IntCC::NotEqual, value, true_id);
             is_not_true
         } else {
             panic!("IsNotTrue requires a value operand");
@@ -249,39 +325,47 @@ impl JitBuilder<'_> {
                 panic!("......not op")
             };
 
-            let result = match op {
-                CompareOperator::Equal => {
-                    self.builder
-                        .ins()
-                        .icmp(codegen::ir::condcodes::IntCC::Equal, left, right)
-                }
-                CompareOperator::NotEqual => {
-                    self.builder
-                        .ins()
-                        .icmp(codegen::ir::condcodes::IntCC::NotEqual, left, right)
-                }
-                CompareOperator::Greater => self.builder.ins().icmp(
-                    codegen::ir::condcodes::IntCC::SignedGreaterThan,
-                    left,
-                    right,
-                ),
-                CompareOperator::GreaterEqual => self.builder.ins().icmp(
-                    codegen::ir::condcodes::IntCC::SignedGreaterThanOrEqual,
-                    left,
-                    right,
-                ),
-                CompareOperator::Less => self.builder.ins().icmp(
-                    codegen::ir::condcodes::IntCC::SignedLessThan,
-                    left,
-                    right,
-                ),
-                CompareOperator::LessEqual => self.builder.ins().icmp(
-                    codegen::ir::condcodes::IntCC::SignedLessThanOrEqual,
-                    left,
-                    right,
-                ),
+            let ty = self.join_types(left, right);
+            let (left, right, ty) = if let FSRSType::String = ty.as_ref() {
+                // compare the ordering of the two strings with 0
+                let order = self
+                    .call_extern("c_str_compare", &[left, right], Some(types::I64))
+                    .unwrap();
+                let zero = self.builder.ins().iconst(types::I64, 0);
+                (order, zero, Arc::new(FSRSType::IInt64))
+            } else {
+                (self.coerce(left, &ty), self.coerce(right, &ty), ty)
+            };
+            let result = if ty.is_float() {
+                use codegen::ir::condcodes::FloatCC;
+                let cc = match op {
+                    CompareOperator::Equal => FloatCC::Equal,
+                    CompareOperator::NotEqual => FloatCC::NotEqual,
+                    CompareOperator::Greater => FloatCC::GreaterThan,
+                    CompareOperator::GreaterEqual => FloatCC::GreaterThanOrEqual,
+                    CompareOperator::Less => FloatCC::LessThan,
+                    CompareOperator::LessEqual => FloatCC::LessThanOrEqual,
+                };
+                self.builder.ins().fcmp(cc, left, right)
+            } else {
+                use codegen::ir::condcodes::IntCC;
+                let signed = ty.is_signed();
+                let cc = match op {
+                    CompareOperator::Equal => IntCC::Equal,
+                    CompareOperator::NotEqual => IntCC::NotEqual,
+                    CompareOperator::Greater if signed => IntCC::SignedGreaterThan,
+                    CompareOperator::Greater => IntCC::UnsignedGreaterThan,
+                    CompareOperator::GreaterEqual if signed => IntCC::SignedGreaterThanOrEqual,
+                    CompareOperator::GreaterEqual => IntCC::UnsignedGreaterThanOrEqual,
+                    CompareOperator::Less if signed => IntCC::SignedLessThan,
+                    CompareOperator::Less => IntCC::UnsignedLessThan,
+                    CompareOperator::LessEqual if signed => IntCC::SignedLessThanOrEqual,
+                    CompareOperator::LessEqual => IntCC::UnsignedLessThanOrEqual,
+                };
+                self.builder.ins().icmp(cc, left, right)
             };
 
+            self.set_type(result, &Arc::new(FSRSType::Bool));
             context.exp.push(result);
         } else {
             panic!("CompareTest requires both left and right operands");
@@ -306,6 +390,9 @@ impl JitBuilder<'_> {
 
     fn load_while_end(&mut self, context: &mut OperatorContext) -> Result<()> {
         context.is_body_jump = false;
+        if self.has_object {
+            self.load_safepoint();
+        }
         self.builder
             .ins()
             .jump(*context.loop_blocks.last().unwrap(), &[]);
@@ -668,14 +755,652 @@ impl JitBuilder<'_> {
             FSRSType::UInt64 | FSRSType::IInt64 => types::I64,
             FSRSType::Float32 => types::F32,
             FSRSType::Float64 => types::F64,
-            FSRSType::String => todo!(),
+            FSRSType::String => ptr,
             FSRSType::Struct(_) => ptr,
             FSRSType::Ptr(_) => ptr,
+            FSRSType::Object => ptr,
+            FSRSType::Vector(_, _) => ptr,
             FSRSType::Fn(_) => ptr,
             FSRSType::List(_, _) => ptr,
         }
     }
 
+    fn set_type(&mut self, value: Value, ty: &Arc<FSRSType>) -> Value {
+        if ty.is_numeric()
+            || matches!(
+                ty.as_ref(),
+                FSRSType::String | FSRSType::Object | FSRSType::Vector(_, _)
+            )
+        {
+            self.value_types.insert(value, ty.clone());
+        }
+        if let FSRSType::Object = ty.as_ref() {
+            self.has_object = true;
+        }
+        value
+    }
+
+    /// Static type of a value, literals fall back to the type of their cranelift value
+    fn type_of(&self, value: Value) -> Arc<FSRSType> {
+        if let Some(ty) = self.value_types.get(&value) {
+            return ty.clone();
+        }
+
+        let ty = match self.builder.func.dfg.value_type(value) {
+            types::I8 => FSRSType::IInt8,
+            types::I16 => FSRSType::IInt16,
+            types::I32 => FSRSType::IInt32,
+            types::F32 => FSRSType::Float32,
+            types::F64 => FSRSType::Float64,
+            _ => FSRSType::IInt64,
+        };
+        Arc::new(ty)
+    }
+
+    /// Like the usual arithmetic conversions: float beats integer, the wider
+    /// type wins, and on a tie the left operand decides.
+    fn numeric_join(left: &Arc<FSRSType>, right: &Arc<FSRSType>) -> Arc<FSRSType> {
+        if left.as_ref() == &FSRSType::Bool {
+            return right.clone();
+        }
+
+        if right.as_ref() == &FSRSType::Bool {
+            return left.clone();
+        }
+
+        if left.is_float() != right.is_float() {
+            return if left.is_float() {
+                left.clone()
+            } else {
+                right.clone()
+            };
+        }
+
+        if right.size_of() > left.size_of() {
+            right.clone()
+        } else {
+            left.clone()
+        }
+    }
+
+    /// Type both operands of a binary operation are converted to. A literal takes
+    /// the type of the typed side, unless it is a float literal next to an integer.
+    fn join_types(&self, left: Value, right: Value) -> Arc<FSRSType> {
+        let left_type = self.type_of(left);
+        let right_type = self.type_of(right);
+        match (
+            self.value_types.contains_key(&left),
+            self.value_types.contains_key(&right),
+        ) {
+            (true, false) if !right_type.is_float() || left_type.is_float() => left_type,
+            (false, true) if !left_type.is_float() || right_type.is_float() => right_type,
+            _ => Self::numeric_join(&left_type, &right_type),
+        }
+    }
+
+    fn convert(&mut self, value: Value, from: &FSRSType, to: &Arc<FSRSType>) -> Value {
+        let ptr = self.module.target_config().pointer_type();
+        let from_cl = self.builder.func.dfg.value_type(value);
+        let to_cl = Self::get_cl_type(ptr, to);
+        let ret = if to.as_ref() == &FSRSType::Bool {
+            if from.is_float() {
+                let zero = if from_cl == types::F32 {
+                    self.builder.ins().f32const(0.0)
+                } else {
+                    self.builder.ins().f64const(0.0)
+                };
+                self.builder
+                    .ins()
+                    .fcmp(codegen::ir::condcodes::FloatCC::NotEqual, value, zero)
+            } else {
+                self.builder
+                    .ins()
+                    .icmp_imm(codegen::ir::condcodes::IntCC::NotEqual, value, 0)
+            }
+        } else if from.is_float() && to.is_float() {
+            if from_cl == to_cl {
+                value
+            } else if to_cl == types::F64 {
+                self.builder.ins().fpromote(types::F64, value)
+            } else {
+                self.builder.ins().fdemote(types::F32, value)
+            }
+        } else if from.is_float() {
+            // convert through i64 so narrow targets wrap like an integer narrowing
+            let wide = if to.is_signed() {
+                self.builder.ins().fcvt_to_sint_sat(types::I64, value)
+            } else {
+                self.builder.ins().fcvt_to_uint_sat(types::I64, value)
+            };
+            if to_cl == types::I64 {
+                wide
+            } else {
+                self.builder.ins().ireduce(to_cl, wide)
+            }
+        } else if to.is_float() {
+            let wide = match (from_cl == types::I64, from.is_signed()) {
+                (true, _) => value,
+                (false, true) => self.builder.ins().sextend(types::I64, value),
+                (false, false) => self.builder.ins().uextend(types::I64, value),
+            };
+            if from.is_signed() {
+                self.builder.ins().fcvt_from_sint(to_cl, wide)
+            } else {
+                self.builder.ins().fcvt_from_uint(to_cl, wide)
+            }
+        } else if from_cl.bits() < to_cl.bits() {
+            if from.is_signed() {
+                self.builder.ins().sextend(to_cl, value)
+            } else {
+                self.builder.ins().uextend(to_cl, value)
+            }
+        } else if from_cl.bits() > to_cl.bits() {
+            self.builder.ins().ireduce(to_cl, value)
+        } else {
+            value
+        };
+
+        let ret = if ret == value && self.value_types.contains_key(&value) {
+            // same representation but another static type, e.g. i64 to u64, needs
+            // its own ssa value so the original keeps its type
+            self.builder.ins().iadd_imm(value, 0)
+        } else {
+            ret
+        };
+        self.set_type(ret, to)
+    }
+
+    /// Converts a scalar to `to`, other values are passed through untouched
+    fn coerce(&mut self, value: Value, to: &Arc<FSRSType>) -> Value {
+        if !to.is_numeric() {
+            return value;
+        }
+
+        let from = self.type_of(value);
+        if &from == to {
+            return value;
+        }
+
+        self.convert(value, &from, to)
+    }
+
+    fn thread_runtime(&self) -> Value {
+        let entry_block = self.builder.func.layout.entry_block().unwrap();
+        self.builder.block_params(entry_block)[0]
+    }
+
+    /// Address of a runtime object, ahead-of-time code loads it from the table
+    /// the loader fills with `reloc`
+    fn addr_const(&mut self, reloc: AotReloc, addr: impl FnOnce() -> usize) -> Value {
+        let ptr = self.module.target_config().pointer_type();
+        let Some(aot) = self.aot.as_mut() else {
+            return self.builder.ins().iconst(ptr, addr() as i64);
+        };
+
+        let offset = aot.slot(reloc) * ptr.bytes() as usize;
+        let table = self.module.declare_data_in_func(aot.table, self.builder.func);
+        let base = self.builder.ins().global_value(ptr, table);
+        self.builder
+            .ins()
+            .load(ptr, cranelift::codegen::ir::MemFlags::trusted(), base, offset as i32)
+    }
+
+    /// Address of bytes owned by the bytecode, which outlives the compiled
+    /// code. Ahead-of-time code keeps its own copy in the library.
+    fn bytes_const(&mut self, bytes: &[u8]) -> Value {
+        let ptr = self.module.target_config().pointer_type();
+        if self.aot.is_none() {
+            return self.builder.ins().iconst(ptr, bytes.as_ptr() as i64);
+        }
+
+        let id = self.module.declare_anonymous_data(false, false).unwrap();
+        let mut data = DataDescription::new();
+        data.define(bytes.into());
+        self.module.define_data(id, &data).unwrap();
+        let gv = self.module.declare_data_in_func(id, self.builder.func);
+        self.builder.ins().global_value(ptr, gv)
+    }
+
+    /// Calls a helper registered in `init_builder`, the signature follows the types of `args`
+    fn call_extern(
+        &mut self,
+        name: &str,
+        args: &[Value],
+        ret: Option<types::Type>,
+    ) -> Option<Value> {
+        let mut sig = self.module.make_signature();
+        for arg in args {
+            sig.params
+                .push(AbiParam::new(self.builder.func.dfg.value_type(*arg)));
+        }
+        if let Some(ret) = ret {
+            sig.returns.push(AbiParam::new(ret));
+        }
+        let fn_id = self
+            .module
+            .declare_function(name, cranelift_module::Linkage::Import, &sig)
+            .unwrap();
+        let func_ref = self.module.declare_func_in_func(fn_id, self.builder.func);
+        let call = self.builder.ins().call(func_ref, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    /// Stack slot holding a `FSRStaticStr`, static strings are passed by its address
+    fn new_str_slot(&mut self) -> Value {
+        let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            std::mem::size_of::<FSRStaticStr>() as u32,
+            0,
+        ));
+        self.builder
+            .ins()
+            .stack_addr(self.module.target_config().pointer_type(), slot, 0)
+    }
+
+    /// Leave the function with its error waiting in the thread, the caller
+    /// checks the thread when the call returns
+    fn return_error(&mut self) {
+        let ret_type = self.builder.func.signature.returns[0].value_type;
+        let zero = match ret_type {
+            types::F32 => self.builder.ins().f32const(0.0),
+            types::F64 => self.builder.ins().f64const(0.0),
+            _ => self.builder.ins().iconst(ret_type, 0),
+        };
+        self.builder.ins().return_(&[zero]);
+    }
+
+    /// Return at once unless `ok` is set, the error of the failed check is
+    /// waiting in the thread
+    fn exit_unless(&mut self, ok: Value) {
+        let fail_block = self.builder.create_block();
+        let cont_block = self.builder.create_block();
+        self.builder.set_cold_block(fail_block);
+        self.builder.ins().brif(ok, cont_block, &[], fail_block, &[]);
+
+        self.builder.switch_to_block(fail_block);
+        self.builder.seal_block(fail_block);
+        self.return_error();
+
+        self.builder.switch_to_block(cont_block);
+        self.builder.seal_block(cont_block);
+    }
+
+    /// `value.raise`, static code raises strings
+    fn load_raise(&mut self, context: &mut OperatorContext) -> Result<()> {
+        let value = context.exp.pop().unwrap();
+        if !matches!(self.value_types.get(&value).map(|x| x.as_ref()), Some(FSRSType::String)) {
+            bail!("static code can only raise a string");
+        }
+
+        let thread_runtime = self.thread_runtime();
+        self.call_extern("c_jit_raise", &[thread_runtime, value], None);
+        self.return_error();
+        // code after the raise is unreachable
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+        self.builder.seal_block(block);
+        Ok(())
+    }
+
+    /// Raise instead of trapping on a zero divisor, or on the minimum value
+    /// divided by -1
+    fn check_divisor(&mut self, left: Value, right: Value, ty: &FSRSType, op: &FastAttr) {
+        use codegen::ir::condcodes::IntCC;
+        let thread_runtime = self.thread_runtime();
+        let value_type = self.builder.func.dfg.value_type(right);
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, right, 0);
+        let mut fail = zero;
+        if matches!(op, FastAttr::Div) && ty.is_signed() {
+            let min = match value_type {
+                types::I8 => i8::MIN as i64,
+                types::I16 => i16::MIN as i64,
+                types::I32 => i32::MIN as i64,
+                _ => i64::MIN,
+            };
+            let is_min = self.builder.ins().icmp_imm(IntCC::Equal, left, min);
+            let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, right, -1);
+            let overflow = self.builder.ins().band(is_min, minus_one);
+            fail = self.builder.ins().bor(zero, overflow);
+        }
+
+        let fail_block = self.builder.create_block();
+        let cont_block = self.builder.create_block();
+        self.builder.set_cold_block(fail_block);
+        self.builder.ins().brif(fail, fail_block, &[], cont_block, &[]);
+
+        self.builder.switch_to_block(fail_block);
+        self.builder.seal_block(fail_block);
+        let overflow = self.builder.ins().bxor_imm(zero, 1);
+        self.call_extern("c_jit_div_error", &[thread_runtime, overflow], None);
+        self.return_error();
+
+        self.builder.switch_to_block(cont_block);
+        self.builder.seal_block(cont_block);
+    }
+
+    /// `size` bytes at `ptr + offset` must be inside the block of `ptr`
+    fn check_ptr(&mut self, ptr: Value, offset: Value, size: usize) {
+        if !self.checked {
+            return;
+        }
+
+        let thread_runtime = self.thread_runtime();
+        let size = self.builder.ins().iconst(types::I64, size as i64);
+        let ok = self
+            .call_extern("c_check_ptr", &[thread_runtime, ptr, offset, size], Some(types::I8))
+            .unwrap();
+        self.exit_unless(ok);
+    }
+
+    fn check_index(&mut self, index: Value, len: usize) {
+        if !self.checked {
+            return;
+        }
+
+        let thread_runtime = self.thread_runtime();
+        let len = self.builder.ins().iconst(types::I64, len as i64);
+        let ok = self
+            .call_extern("c_check_index", &[thread_runtime, index, len], Some(types::I8))
+            .unwrap();
+        self.exit_unless(ok);
+    }
+
+    /// Leave too when the static function just called raised or failed a
+    /// check, the flag is read in place of a helper call
+    fn check_callee(&mut self) {
+        let thread_runtime = self.thread_runtime();
+        let failed = self.builder.ins().load(
+            types::I8,
+            cranelift::codegen::ir::MemFlags::trusted(),
+            thread_runtime,
+            std::mem::offset_of!(FSRThreadRuntime, jit_failed) as i32,
+        );
+        let ok = self.builder.ins().bxor_imm(failed, 1);
+        self.exit_unless(ok);
+    }
+
+    fn load_str_method(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+        let ArgType::StrMethod(method) = arg.get_arg() else {
+            panic!("SStrMethod requires a StrMethod argument");
+        };
+
+        let ret = match method {
+            StrMethod::Len => {
+                let s = context.exp.pop().unwrap();
+                let len = self.builder.ins().load(
+                    types::I64,
+                    cranelift::codegen::ir::MemFlags::new(),
+                    s,
+                    std::mem::offset_of!(FSRStaticStr, len) as i32,
+                );
+                self.set_type(len, &Arc::new(FSRSType::UInt64))
+            }
+            StrMethod::Slice => {
+                let end = context.exp.pop().unwrap();
+                let start = context.exp.pop().unwrap();
+                let s = context.exp.pop().unwrap();
+                let start = self.index_value(start);
+                let end = self.index_value(end);
+                let out = self.new_str_slot();
+                self.call_extern("c_str_slice", &[s, start, end, out], None);
+                self.set_type(out, &Arc::new(FSRSType::String))
+            }
+        };
+        context.exp.push(ret);
+    }
+
+    fn load_obj_method(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+        let ArgType::ObjMethod(method) = arg.get_arg() else {
+            panic!("SObjMethod requires an ObjMethod argument");
+        };
+
+        let thread_runtime = self.thread_runtime();
+        let key = if method.args_len() == 1 {
+            let key = context.exp.pop().unwrap();
+            let (key, kind) = match self.type_of(key).as_ref() {
+                FSRSType::String => (key, ObjKey::Str),
+                FSRSType::Object => (key, ObjKey::Obj),
+                _ => (self.index_value(key), ObjKey::Int),
+            };
+            Some((key, self.builder.ins().iconst(types::I8, kind as i64)))
+        } else {
+            None
+        };
+        let obj = context.exp.pop().unwrap();
+
+        let (name, ret_type) = match method {
+            ObjMethod::Len => ("c_obj_len", FSRSType::UInt64),
+            ObjMethod::Get => ("c_obj_get", FSRSType::Object),
+            ObjMethod::GetInt => ("c_obj_get_int", FSRSType::IInt64),
+            ObjMethod::GetFloat => ("c_obj_get_float", FSRSType::Float64),
+            ObjMethod::AsInt => ("c_obj_as_int", FSRSType::IInt64),
+            ObjMethod::AsFloat => ("c_obj_as_float", FSRSType::Float64),
+        };
+        let ptr = self.module.target_config().pointer_type();
+        let ret_type = Arc::new(ret_type);
+        let mut args = vec![thread_runtime, obj];
+        if let Some((key, kind)) = key {
+            args.extend([key, kind]);
+        }
+        let ret = self
+            .call_extern(name, &args, Some(Self::get_cl_type(ptr, &ret_type)))
+            .unwrap();
+        // a missing key or a value of the wrong type raises in the vm
+        self.check_callee();
+        let ret = self.set_type(ret, &ret_type);
+        context.exp.push(ret);
+    }
+
+    /// Let the gc run at a loop back-edge of code holding `Object` values
+    fn load_safepoint(&mut self) {
+        let thread_runtime = self.thread_runtime();
+        self.call_extern("c_jit_safepoint", &[thread_runtime], None);
+    }
+
+    /// Stack slot of a vector, 16-byte aligned for the vector loads
+    fn new_vector_slot(&mut self, ty: &FSRSType) -> Value {
+        let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            ty.size_of() as u32,
+            4,
+        ));
+        self.builder
+            .ins()
+            .stack_addr(self.module.target_config().pointer_type(), slot, 0)
+    }
+
+    fn load_vec_new(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+        let ArgType::VecNew((vec_type, len)) = arg.get_arg() else {
+            panic!("SVecNew requires a VecNew argument");
+        };
+        let FSRSType::Vector(lane, lanes) = vec_type.as_ref() else {
+            panic!("SVecNew requires a vector type");
+        };
+
+        let mut values = context.exp.split_off(context.exp.len() - len);
+        if values.len() == 1 {
+            values = vec![values[0]; *lanes];
+        }
+        let out = self.new_vector_slot(vec_type);
+        for (i, value) in values.into_iter().enumerate() {
+            let value = self.coerce(value, lane);
+            self.builder.ins().store(
+                cranelift::codegen::ir::MemFlags::new(),
+                value,
+                out,
+                (i * lane.size_of()) as i32,
+            );
+        }
+        let out = self.set_type(out, vec_type);
+        context.exp.push(out);
+    }
+
+    /// `a op b` where one side is a vector, a scalar side applies to every
+    /// lane. With simd each 16 bytes are one cranelift vector op, else and
+    /// for integer division the lanes are done one by one
+    fn vector_arith(&mut self, op: FastAttr, left: Value, right: Value) -> Result<Value> {
+        let left_type = self.type_of(left);
+        let right_type = self.type_of(right);
+        let ty = match (left_type.as_ref(), right_type.as_ref()) {
+            (FSRSType::Vector(_, _), FSRSType::Vector(_, _)) if left_type != right_type => {
+                bail!(
+                    "vector operands differ: {} and {}",
+                    left_type.type_key(),
+                    right_type.type_key()
+                )
+            }
+            (FSRSType::Vector(_, _), _) => left_type.clone(),
+            _ => right_type.clone(),
+        };
+        let FSRSType::Vector(lane, lanes) = ty.as_ref() else {
+            unreachable!()
+        };
+        if !matches!(
+            op,
+            FastAttr::Add | FastAttr::Sub | FastAttr::Mul | FastAttr::Div | FastAttr::Reminder
+        ) {
+            bail!("vector does not support {:?}", op);
+        }
+
+        let out = self.new_vector_slot(&ty);
+        let by_lane = matches!(op, FastAttr::Reminder)
+            || (lane.is_integer() && matches!(op, FastAttr::Div));
+        if self.simd && !by_lane {
+            let ptr = self.module.target_config().pointer_type();
+            let chunk_lanes = 16 / lane.size_of();
+            let vector = Self::get_cl_type(ptr, lane)
+                .by(chunk_lanes as u32)
+                .unwrap();
+            for chunk in 0..lanes / chunk_lanes {
+                let offset = (chunk * 16) as i32;
+                let a = self.vector_operand(left, &left_type, lane, vector, offset);
+                let b = self.vector_operand(right, &right_type, lane, vector, offset);
+                let ins = self.builder.ins();
+                let ret = match (op, lane.is_float()) {
+                    (FastAttr::Add, false) => ins.iadd(a, b),
+                    (FastAttr::Sub, false) => ins.isub(a, b),
+                    (FastAttr::Mul, false) => ins.imul(a, b),
+                    (FastAttr::Add, true) => ins.fadd(a, b),
+                    (FastAttr::Sub, true) => ins.fsub(a, b),
+                    (FastAttr::Mul, true) => ins.fmul(a, b),
+                    (FastAttr::Div, true) => ins.fdiv(a, b),
+                    _ => unreachable!(),
+                };
+                self.builder
+                    .ins()
+                    .store(cranelift::codegen::ir::MemFlags::new(), ret, out, offset);
+            }
+        } else {
+            for i in 0..*lanes {
+                let offset = i * lane.size_of();
+                let a = self.lane_operand(left, &left_type, lane, offset);
+                let b = self.lane_operand(right, &right_type, lane, offset);
+                let ret = self.arith(op, a, b)?;
+                let ret = self.coerce(ret, lane);
+                self.builder.ins().store(
+                    cranelift::codegen::ir::MemFlags::new(),
+                    ret,
+                    out,
+                    offset as i32,
+                );
+            }
+        }
+        Ok(self.set_type(out, &ty))
+    }
+
+    fn vector_operand(
+        &mut self,
+        value: Value,
+        ty: &Arc<FSRSType>,
+        lane: &Arc<FSRSType>,
+        vector: types::Type,
+        offset: i32,
+    ) -> Value {
+        if let FSRSType::Vector(_, _) = ty.as_ref() {
+            self.builder
+                .ins()
+                .load(vector, cranelift::codegen::ir::MemFlags::new(), value, offset)
+        } else {
+            let scalar = self.coerce(value, lane);
+            self.builder.ins().splat(vector, scalar)
+        }
+    }
+
+    fn lane_operand(
+        &mut self,
+        value: Value,
+        ty: &Arc<FSRSType>,
+        lane: &Arc<FSRSType>,
+        offset: usize,
+    ) -> Value {
+        if let FSRSType::Vector(_, _) = ty.as_ref() {
+            self.load_ptr_data(lane, value, offset)
+        } else {
+            let scalar = self.coerce(value, lane);
+            self.set_type(scalar, lane)
+        }
+    }
+
+    fn index_value(&mut self, index: Value) -> Value {
+        self.coerce(index, &Arc::new(FSRSType::IInt64))
+    }
+
+    fn arith(&mut self, op: FastAttr, left: Value, right: Value) -> Result<Value> {
+        if matches!(self.type_of(left).as_ref(), FSRSType::Vector(_, _))
+            || matches!(self.type_of(right).as_ref(), FSRSType::Vector(_, _))
+        {
+            return self.vector_arith(op, left, right);
+        }
+        let ty = self.join_types(left, right);
+        if let FSRSType::String = ty.as_ref() {
+            if !matches!(op, FastAttr::Add) {
+                bail!("static string only supports `+`, found: {:?}", op);
+            }
+            let thread_runtime = self.thread_runtime();
+            let out = self.new_str_slot();
+            self.call_extern("c_str_concat", &[thread_runtime, left, right, out], None);
+            return Ok(self.set_type(out, &ty));
+        }
+
+        let left = self.coerce(left, &ty);
+        let right = self.coerce(right, &ty);
+        let ret = if ty.is_float() {
+            match op {
+                FastAttr::Add => self.builder.ins().fadd(left, right),
+                FastAttr::Sub => self.builder.ins().fsub(left, right),
+                FastAttr::Mul => self.builder.ins().fmul(left, right),
+                FastAttr::Div => self.builder.ins().fdiv(left, right),
+                FastAttr::Reminder => {
+                    let float_type = self.builder.func.dfg.value_type(left);
+                    let helper = if float_type == types::F32 {
+                        "c_fmodf"
+                    } else {
+                        "c_fmod"
+                    };
+                    self.call_extern(helper, &[left, right], Some(float_type))
+                        .unwrap()
+                }
+                _ => bail!("static code does not support {:?} on {}", op, ty.type_key()),
+            }
+        } else {
+            if matches!(op, FastAttr::Div | FastAttr::Reminder) {
+                self.check_divisor(left, right, &ty, &op);
+            }
+            match op {
+                FastAttr::Add => self.builder.ins().iadd(left, right),
+                FastAttr::Sub => self.builder.ins().isub(left, right),
+                FastAttr::Mul => self.builder.ins().imul(left, right),
+                FastAttr::Div if ty.is_signed() => self.builder.ins().sdiv(left, right),
+                FastAttr::Div => self.builder.ins().udiv(left, right),
+                FastAttr::Reminder if ty.is_signed() => self.builder.ins().srem(left, right),
+                FastAttr::Reminder => self.builder.ins().urem(left, right),
+                _ => bail!("static code does not support {:?} on {}", op, ty.type_key()),
+            }
+        };
+
+        Ok(self.set_type(ret, &ty))
+    }
+
     fn make_inner_call_fn(&self, call_sig: &FnCallSig) -> Signature {
         let mut inner_call_fn_sig = self.module.make_signature();
         let ptr = self.module.target_config().pointer_type();
@@ -702,12 +1427,16 @@ impl JitBuilder<'_> {
         inner_call_fn_sig
     }
 
-    fn load_getter(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+    fn load_getter(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) -> Result<()> {
         if let ArgType::TypeInfo(v) = arg.get_arg() {
             let index = context.exp.pop().unwrap();
+            let index = self.index_value(index);
             let father_obj_id = context.exp.pop().unwrap();
             let type_info = v.as_ref().unwrap();
-            if let FSRSType::List(inner_type, l) = type_info.as_ref() {
+            if let FSRSType::List(inner_type, l) | FSRSType::Vector(inner_type, l) =
+                type_info.as_ref()
+            {
+                self.check_index(index, *l);
                 let type_size = inner_type.size_of() as i64;
                 // target = father_obj_id + index * type_size
                 let index_size = self.builder.ins().imul_imm(index, type_size);
@@ -719,12 +1448,19 @@ impl JitBuilder<'_> {
             } else if let FSRSType::Ptr(pointer_inner) = type_info.as_ref() {
                 let type_size = pointer_inner.size_of() as i64;
                 let index_size = self.builder.ins().imul_imm(index, type_size);
+                self.check_ptr(father_obj_id, index_size, pointer_inner.size_of());
                 let target_ptr = self.builder.ins().iadd(father_obj_id, index_size);
                 //let tmp_ptr = Arc::new(FSRSType::Ptr(pointer_inner.clone()));
                 let new_value = Self::load_ptr_data(self, &pointer_inner, target_ptr, 0);
                 context.exp.push(new_value);
+            } else if let FSRSType::String = type_info.as_ref() {
+                let byte = self
+                    .call_extern("c_str_byte", &[father_obj_id, index], Some(types::I8))
+                    .unwrap();
+                self.set_type(byte, &Arc::new(FSRSType::UInt8));
+                context.exp.push(byte);
             } else {
-                panic!("Getter only supports List type currently");
+                bail!("static code can not index a {}", type_info.type_key());
             }
 
             // } else {
@@ -733,6 +1469,7 @@ impl JitBuilder<'_> {
         } else {
             unimplemented!()
         }
+        Ok(())
     }
 
     fn load_call(&mut self, arg: &BytecodeArg, context: &mut OperatorContext) {
@@ -750,6 +1487,7 @@ impl JitBuilder<'_> {
             }
             let fn_ptr = context.exp.pop().unwrap();
             rev_args.reverse();
+            let mut rev_args = self.coerce_args(rev_args, call_sig.as_ref().unwrap());
             rev_args.insert(0, self.builder.block_params(context.entry_block)[0]); // insert thread runtime at the beginning
             let null_ptr = self
                 .builder
@@ -757,7 +1495,9 @@ impl JitBuilder<'_> {
                 .iconst(self.module.target_config().pointer_type(), 0);
             let helper_ret = if let Some(ret_type) = call_sig.as_ref().unwrap().return_type.as_ref()
             {
-                let v = if let FSRSType::Struct(s) = ret_type.as_ref() {
+                let v = if let FSRSType::Struct(_) | FSRSType::String | FSRSType::Vector(_, _) =
+                    ret_type.as_ref()
+                {
                     // allocate stack space for struct return
                     let struct_size = ret_type.size_of();
                     let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
@@ -800,6 +1540,10 @@ impl JitBuilder<'_> {
                 .ins()
                 .call_indirect(call_fn_sig_ref, fn_ptr, &rev_args);
             let ret = self.builder.inst_results(call_inst)[0];
+            if let Some(ret_type) = call_sig.as_ref().unwrap().return_type.as_ref() {
+                self.set_type(ret, ret_type);
+            }
+            self.check_callee();
 
             // Free the argument list after the call
             //self.load_free_arg_list(list_ptr, context, *v as i64);
@@ -810,8 +1554,15 @@ impl JitBuilder<'_> {
         }
     }
 
-    fn get_obj_method(&mut self, father: Value, name: &str) -> Value {
-        // pub extern "C" fn get_obj_method(father: ObjId, name: *const u8, len: usize) -> ObjId {
+    fn coerce_args(&mut self, args: Vec<Value>, call_sig: &FnCallSig) -> Vec<Value> {
+        args.into_iter()
+            .zip(call_sig.params.iter())
+            .map(|(arg, param)| self.coerce(arg, param))
+            .collect()
+    }
+
+    fn get_obj_method(&mut self, father: Value, name: &str, cache: &AttrInlineCache) -> Value {
+        // pub extern "C" fn get_obj_method(father: ObjId, name: *const u8, len: usize, cache: &AttrInlineCache) -> ObjId {
         let mut get_obj_method_sig = self.module.make_signature();
         get_obj_method_sig
             .params
@@ -820,6 +1571,9 @@ impl JitBuilder<'_> {
             .params
             .push(AbiParam::new(self.module.target_config().pointer_type())); // name pointer
         get_obj_method_sig.params.push(AbiParam::new(types::I64)); // name length
+        get_obj_method_sig
+            .params
+            .push(AbiParam::new(self.module.target_config().pointer_type())); // inline cache
         get_obj_method_sig
             .returns
             .push(AbiParam::new(self.module.target_config().pointer_type())); // return type (ObjId)
@@ -832,15 +1586,16 @@ impl JitBuilder<'_> {
             )
             .unwrap();
         let func_ref = self.module.declare_func_in_func(fn_id, self.builder.func);
-        let name_ptr = self.builder.ins().iconst(
-            self.module.target_config().pointer_type(),
-            name.as_ptr() as i64,
-        );
+        let name_ptr = self.bytes_const(name.as_bytes());
         let name_len = self.builder.ins().iconst(types::I64, name.len() as i64);
+        // cache is owned by the bytecode, which outlives the compiled code
+        let cache_ptr = self.addr_const(AotReloc::InlineCache, || {
+            cache as *const AttrInlineCache as usize
+        });
         let call = self
             .builder
             .ins()
-            .call(func_ref, &[father, name_ptr, name_len]);
+            .call(func_ref, &[father, name_ptr, name_len, cache_ptr]);
         let ret = self.builder.inst_results(call)[0];
         ret
     }
@@ -864,6 +1619,7 @@ impl JitBuilder<'_> {
             let father_obj = context.exp.pop().unwrap();
             rev_args.reverse();
             rev_args.insert(0, father_obj);
+            let mut rev_args = self.coerce_args(rev_args, call_sig.as_ref().unwrap());
             rev_args.insert(0, self.builder.block_params(context.entry_block)[0]); // insert thread runtime at the beginning
             let null_ptr = self
                 .builder
@@ -871,7 +1627,9 @@ impl JitBuilder<'_> {
                 .iconst(self.module.target_config().pointer_type(), 0);
             let helper_ret = if let Some(ret_type) = call_sig.as_ref().unwrap().return_type.as_ref()
             {
-                let v = if let FSRSType::Struct(s) = ret_type.as_ref() {
+                let v = if let FSRSType::Struct(_) | FSRSType::String | FSRSType::Vector(_, _) =
+                    ret_type.as_ref()
+                {
                     // allocate stack space for struct return
                     let struct_size = ret_type.size_of();
                     let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
@@ -914,6 +1672,10 @@ impl JitBuilder<'_> {
                 .ins()
                 .call_indirect(call_fn_sig_ref, fn_ptr, &rev_args);
             let ret = self.builder.inst_results(call_inst)[0];
+            if let Some(ret_type) = call_sig.as_ref().unwrap().return_type.as_ref() {
+                self.set_type(ret, ret_type);
+            }
+            self.check_callee();
 
             // Free the argument list after the call
             //self.load_free_arg_list(list_ptr, context, *v as i64);
@@ -921,7 +1683,7 @@ impl JitBuilder<'_> {
             context.exp.push(ret);
             //panic!("CallMethod is not implemented yet in Cranelift JIT backend");
             // let father_obj_id = *context.exp.last().unwrap();
-            // let fn_obj_id = self.get_obj_method(father_obj_id, v.2.as_str());
+            // let fn_obj_id = self.get_obj_method(father_obj_id, v.2.as_str(), arg.get_attr_cache());
 
             // let call_fn_sig = self.make_call_fn();
             // let fn_id = self
@@ -951,11 +1713,7 @@ impl JitBuilder<'_> {
     }
 
     fn load_none(&mut self, context: &mut OperatorContext) {
-        let none_id = FSRObject::none_id();
-        let none_value = self
-            .builder
-            .ins()
-            .iconst(self.module.target_config().pointer_type(), none_id as i64);
+        let none_value = self.addr_const(AotReloc::NoneId, || FSRObject::none_id());
         // let ret = self.builder.inst_results(call)[0];
         context.exp.push(none_value);
     }
@@ -989,38 +1747,11 @@ impl JitBuilder<'_> {
         binary_op_sig
     }
 
-    fn load_binary_op(&mut self, context: &mut OperatorContext, op: FastAttr) {
+    fn load_binary_op(&mut self, context: &mut OperatorContext, op: FastAttr) -> Result<()> {
         if let (Some(right), Some(left)) = (context.exp.pop(), context.exp.pop()) {
-            let ret = match op {
-                FastAttr::Add => {
-                    // For addition, we can use integer addition directly
-                    self.builder.ins().iadd(left, right)
-                }
-                FastAttr::Sub => {
-                    // For subtraction, we can use integer subtraction directly
-                    self.builder.ins().isub(left, right)
-                }
-                FastAttr::Mul => {
-                    // For multiplication, we can use integer multiplication directly
-                    self.builder.ins().imul(left, right)
-                }
-                FastAttr::Div => {
-                    // For division, we can use integer division directly
-                    self.builder.ins().sdiv(left, right)
-                }
-                FastAttr::Reminder => {
-                    // For modulus, we can use integer remainder directly
-                    self.builder.ins().srem(left, right)
-                }
-                _ => {
-                    unimplemented!("Binary operation {:?} is not implemented yet", op);
-                }
-            };
+            let ret = self.arith(op, left, right)?;
             context.exp.push(ret);
-            // context.middle_value.push(ret);
-            // context.middle_value.push(right);
-            // context.middle_value.push(left);
-            // self.clear_middle_value(context);
+            Ok(())
         } else {
             unimplemented!("BinaryAdd requires both left and right operands");
         }
@@ -1119,9 +1850,11 @@ impl JitBuilder<'_> {
                 value,
                 0,
             ),
-            FSRSType::String => todo!(),
+            FSRSType::String => value,
             FSRSType::Struct(fsrstruct) => value,
             FSRSType::Ptr(fsrstype) => value,
+            FSRSType::Object => value,
+            FSRSType::Vector(_, _) => value,
             FSRSType::Fn(fn_call_sig) => value,
             FSRSType::List(fsrstype, _) => value,
         }
@@ -1132,8 +1865,8 @@ impl JitBuilder<'_> {
         let index = context.args_index;
         if let ArgType::Local(v) = arg.get_arg() {
             if let Some(var_type) = &v.var_type {
-                let new_type = self.get_var_type(&v.var_type.as_ref().unwrap());
-                let var_type = new_type.unwrap();
+                // the variable holds the address of its stack slot
+                let var_type = self.module.target_config().pointer_type();
                 let mut var_id = self.var_index;
                 let new_var = declare_variable(
                     var_type,
@@ -1149,6 +1882,7 @@ impl JitBuilder<'_> {
             let data = self.builder.block_params(context.entry_block)[base + index];
             let variable = *self.variables.get(v.name.as_str()).unwrap();
             let var_type = &v.var_type.as_ref().unwrap();
+            self.set_type(data, var_type);
             let type_size = var_type.size_of();
             let stack_addr = Self::init_get_stack_addr(self, v, var_type, true);
             let stack_addr_value = self.builder.use_var(variable);
@@ -1193,8 +1927,8 @@ impl JitBuilder<'_> {
 
         if let ArgType::Local(v) = arg.get_arg() {
             if let Some(var_type) = &v.var_type {
-                let new_type = self.get_var_type(&v.var_type.as_ref().unwrap());
-                let var_type = new_type.unwrap();
+                // the variable holds the address of its stack slot
+                let var_type = self.module.target_config().pointer_type();
                 let mut var_id = self.var_index;
                 let new_var = declare_variable(
                     var_type,
@@ -1207,7 +1941,16 @@ impl JitBuilder<'_> {
                 self.var_index = var_id;
             }
 
-            let trans_data = Self::load_data(self, v.var_type.as_ref().unwrap(), ret);
+            let var_type = v.var_type.as_ref().unwrap();
+            let trans_data = if let FSRSType::Object = var_type.as_ref() {
+                let ptr = self.module.target_config().pointer_type();
+                let obj = self
+                    .call_extern("c_entry_obj_arg", &[thread_runtime, index_value], Some(ptr))
+                    .unwrap();
+                self.set_type(obj, var_type)
+            } else {
+                Self::load_entry_arg_data(self, var_type, ret)
+            };
             let variable = *self.variables.get(v.name.as_str()).unwrap();
             let var_type = &v.var_type.as_ref().unwrap();
             let type_size = var_type.size_of();
@@ -1224,6 +1967,34 @@ impl JitBuilder<'_> {
         context.args_index += 1;
     }
 
+    /// Entry args point into the caller's objects: integers are stored as i64,
+    /// floats as f64, bools and strings are the object itself.
+    fn load_entry_arg_data(&mut self, var_type: &Arc<FSRSType>, value: Value) -> Value {
+        if var_type.is_integer() {
+            let data = Self::load_data(self, &Arc::new(FSRSType::IInt64), value);
+            self.set_type(data, &Arc::new(FSRSType::IInt64));
+            self.coerce(data, var_type)
+        } else if var_type.is_float() {
+            let data = Self::load_data(self, &Arc::new(FSRSType::Float64), value);
+            self.set_type(data, &Arc::new(FSRSType::Float64));
+            self.coerce(data, var_type)
+        } else if let FSRSType::Bool = var_type.as_ref() {
+            let true_id = self.addr_const(AotReloc::TrueId, || FSRObject::true_id());
+            let is_true = self.builder.ins().icmp(
+                codegen::ir::condcodes::IntCC::Equal,
+                value,
+                true_id,
+            );
+            self.set_type(is_true, var_type)
+        } else if let FSRSType::String = var_type.as_ref() {
+            let out = self.new_str_slot();
+            self.call_extern("c_str_from_obj", &[value, out], None);
+            self.set_type(out, var_type)
+        } else {
+            Self::load_data(self, var_type, value)
+        }
+    }
+
     fn load_or_jump(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
         // process or logic like a or b
         //let last_ssa_value = *context.exp.last().unwrap();
@@ -1378,10 +2149,7 @@ impl JitBuilder<'_> {
                 )
                 .unwrap();
             let func_ref = self.module.declare_func_in_func(fn_id, self.builder.func);
-            let value_ptr = self.builder.ins().iconst(
-                self.module.target_config().pointer_type(),
-                s.as_ptr() as i64,
-            );
+            let value_ptr = self.bytes_const(s.as_bytes());
             let value_len = self
                 .builder
                 .ins()
@@ -1415,6 +2183,7 @@ impl JitBuilder<'_> {
         src: Value,
         src_type: Arc<FSRSType>,
     ) {
+        let src = self.coerce(src, &src_type);
         match src_type.as_ref() {
             FSRSType::Bool => {
                 self.builder
@@ -1435,12 +2204,12 @@ impl JitBuilder<'_> {
                     .ins()
                     .store(cranelift::codegen::ir::MemFlags::new(), src, dest, 0);
             }
-            FSRSType::Ptr(_) => {
+            FSRSType::Ptr(_) | FSRSType::Object | FSRSType::Fn(_) => {
                 self.builder
                     .ins()
                     .store(cranelift::codegen::ir::MemFlags::new(), src, dest, 0);
             }
-            FSRSType::Struct(s) => {
+            FSRSType::Struct(_) | FSRSType::String | FSRSType::Vector(_, _) => {
                 let struct_size = src_type.size_of();
                 self.memcpy_fix(context, dest, src, struct_size);
             }
@@ -1514,39 +2283,41 @@ impl JitBuilder<'_> {
     fn load_ptr_data(&mut self, var_type: &Arc<FSRSType>, value: Value, offset: usize) -> Value {
         // input a data ptr to get value
         match var_type.as_ref() {
-            FSRSType::UInt64 => {
-                // like &i64 to i64
-                self.builder.ins().load(
-                    types::I64,
+            FSRSType::Bool
+            | FSRSType::UInt8
+            | FSRSType::UInt16
+            | FSRSType::UInt32
+            | FSRSType::UInt64
+            | FSRSType::IInt8
+            | FSRSType::IInt16
+            | FSRSType::IInt32
+            | FSRSType::IInt64
+            | FSRSType::Float32
+            | FSRSType::Float64 => {
+                let ptr = self.module.target_config().pointer_type();
+                let data = self.builder.ins().load(
+                    Self::get_cl_type(ptr, var_type),
                     cranelift::codegen::ir::MemFlags::new(),
                     value,
                     offset as i32,
-                )
+                );
+                self.set_type(data, var_type)
             }
-            FSRSType::UInt32 => self.builder.ins().load(
-                types::I32,
-                cranelift::codegen::ir::MemFlags::new(),
-                value,
-                offset as i32,
-            ),
-            FSRSType::IInt64 => self.builder.ins().load(
-                types::I64,
-                cranelift::codegen::ir::MemFlags::new(),
-                value,
-                offset as i32,
-            ),
-            FSRSType::UInt8 => self.builder.ins().load(
-                types::I8,
-                cranelift::codegen::ir::MemFlags::new(),
-                value,
-                offset as i32,
-            ),
-            FSRSType::Ptr(fsrstype) => self.builder.ins().load(
+            FSRSType::Ptr(_) | FSRSType::Fn(_) => self.builder.ins().load(
                 self.module.target_config().pointer_type(),
                 cranelift::codegen::ir::MemFlags::new(),
                 value,
                 offset as i32,
             ),
+            FSRSType::Object => {
+                let obj = self.builder.ins().load(
+                    self.module.target_config().pointer_type(),
+                    cranelift::codegen::ir::MemFlags::new(),
+                    value,
+                    offset as i32,
+                );
+                self.set_type(obj, var_type)
+            }
             FSRSType::List(l, _) => {
                 // add value and offset
                 let addr = self.builder.ins().iadd_imm(value, offset as i64);
@@ -1557,10 +2328,10 @@ impl JitBuilder<'_> {
                 let addr = self.builder.ins().iadd_imm(value, offset as i64);
                 addr
             }
-            FSRSType::String => {
+            FSRSType::String | FSRSType::Vector(_, _) => {
                 // add value and offset
                 let addr = self.builder.ins().iadd_imm(value, offset as i64);
-                addr
+                self.set_type(addr, var_type)
             }
             _ => panic!("load_ptr_data expects a Ptr type: {:?}", var_type),
         }
@@ -1570,7 +2341,9 @@ impl JitBuilder<'_> {
         if let ArgType::Attr(attr_var) = arg.get_arg() {
             let attr_type = attr_var.attr_type.as_ref().unwrap().clone();
             let offset = attr_var.offset.unwrap();
-            let father_value = *context.exp.last().unwrap();
+            let father_value = context.exp.pop().unwrap();
+            let offset_value = self.builder.ins().iconst(types::I64, offset as i64);
+            self.check_ptr(father_value, offset_value, attr_type.size_of());
 
             // let addr = self.builder.ins().iadd_imm(father_value, offset as i64);
             let addr = father_value;
@@ -1637,6 +2410,15 @@ impl JitBuilder<'_> {
 
     fn pointer_free(&mut self, context: &mut OperatorContext) {
         let ptr = context.exp.pop().unwrap();
+        if self.checked {
+            let thread_runtime = self.thread_runtime();
+            let ok = self
+                .call_extern("c_checked_free", &[thread_runtime, ptr], Some(types::I8))
+                .unwrap();
+            self.exit_unless(ok);
+            return;
+        }
+
         let mut free_sig = self.module.make_signature();
         free_sig
             .params
@@ -1656,6 +2438,7 @@ impl JitBuilder<'_> {
         let size_value = if let ArgType::Alloc((_, size, is_array)) = arg.get_arg() {
             if *is_array {
                 let num = context.exp.pop().unwrap();
+                let num = self.index_value(num);
                 let size_value = self
                     .builder
                     .ins()
@@ -1670,6 +2453,13 @@ impl JitBuilder<'_> {
         } else {
             panic!("StructAlloc requires a StructSize argument");
         };
+        if self.checked {
+            let ptr = self.module.target_config().pointer_type();
+            let ret = self.call_extern("c_checked_alloc", &[size_value], Some(ptr));
+            context.exp.push(ret.unwrap());
+            return;
+        }
+
         let mut malloc_sig = self.module.make_signature();
         malloc_sig
             .params
@@ -1696,21 +2486,14 @@ impl JitBuilder<'_> {
         current_value: Value,
         value_to_store: Value,
         op_assign: &Option<OpAssign>,
-    ) -> Value {
+    ) -> Result<Value> {
         let value_to_store = if let Some(op_assign) = op_assign {
-            let new_value = match op_assign {
-                OpAssign::Add => self.builder.ins().iadd(current_value, value_to_store),
-                OpAssign::Sub => self.builder.ins().isub(current_value, value_to_store),
-                OpAssign::Mul => self.builder.ins().imul(current_value, value_to_store),
-                OpAssign::Div => self.builder.ins().sdiv(current_value, value_to_store),
-                OpAssign::Reminder => self.builder.ins().srem(current_value, value_to_store),
-            };
-            new_value
+            self.arith(op_assign.get_offset(), current_value, value_to_store)?
         } else {
             value_to_store
         };
 
-        value_to_store
+        Ok(value_to_store)
     }
 
     fn store_container(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
@@ -1719,12 +2502,15 @@ impl JitBuilder<'_> {
                 panic!("StoreContainer does not support OpAssign");
             }
             let value_index = context.exp.pop().unwrap();
+            let value_index = self.index_value(value_index);
             let container_ptr = context.exp.pop().unwrap();
             let value_assign = context.exp.pop().unwrap();
 
             let type_info = v.as_ref().unwrap();
             let sub_type;
-            let type_size = if let FSRSType::List(l, _) = type_info.as_ref() {
+            let type_size = if let FSRSType::List(l, _) | FSRSType::Vector(l, _) =
+                type_info.as_ref()
+            {
                 sub_type = l.clone();
                 l.size_of() as i64
             } else if let FSRSType::Ptr(l) = type_info.as_ref() {
@@ -1733,7 +2519,13 @@ impl JitBuilder<'_> {
             } else {
                 panic!("StoreContainer requires a List type");
             };
+            if let FSRSType::List(_, len) | FSRSType::Vector(_, len) = type_info.as_ref() {
+                self.check_index(value_index, *len);
+            }
             let offset = self.builder.ins().imul_imm(value_index, type_size);
+            if let FSRSType::Ptr(_) = type_info.as_ref() {
+                self.check_ptr(container_ptr, offset, sub_type.size_of());
+            }
             let addr = self.builder.ins().iadd(container_ptr, offset);
 
             // self.builder.ins().store(
@@ -1748,30 +2540,29 @@ impl JitBuilder<'_> {
         }
     }
 
-    fn store_attr(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+    fn store_attr(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) -> Result<()> {
         if let ArgType::Attr(attr_var) = arg.get_arg() {
             let attr_type = attr_var.attr_type.as_ref().unwrap().clone();
             let offset = attr_var.offset.unwrap();
             let father_value = context.exp.pop().unwrap();
             let value_to_store = context.exp.pop().unwrap();
             let op_assign = attr_var.op_assign;
+            let offset_value = self.builder.ins().iconst(types::I64, offset as i64);
+            self.check_ptr(father_value, offset_value, attr_type.size_of());
 
-            let value_to_store = if let Some(op_assign) = op_assign {
+            let value_to_store = if op_assign.is_some() {
                 // load current value
-                let addr = self.builder.ins().iadd_imm(father_value, offset as i64);
-                let current_value = Self::load_data(self, &attr_type, addr);
-                // perform operation
-                let new_value = match op_assign {
-                    OpAssign::Add => self.builder.ins().iadd(current_value, value_to_store),
-                    OpAssign::Sub => self.builder.ins().isub(current_value, value_to_store),
-                    OpAssign::Mul => self.builder.ins().imul(current_value, value_to_store),
-                    OpAssign::Div => self.builder.ins().sdiv(current_value, value_to_store),
-                    OpAssign::Reminder => self.builder.ins().srem(current_value, value_to_store),
-                };
-                new_value
+                let current_value = Self::load_ptr_data(self, &attr_type, father_value, offset);
+                self.ret_op_value(current_value, value_to_store, &op_assign)?
             } else {
                 value_to_store
             };
+            let value_to_store = self.coerce(value_to_store, &attr_type);
+            if let FSRSType::String = attr_type.as_ref() {
+                let addr = self.builder.ins().iadd_imm(father_value, offset as i64);
+                self.memcpy_fix(context, addr, value_to_store, attr_type.size_of());
+                return Ok(());
+            }
 
             //let addr = self.builder.ins().iadd_imm(father_value, offset as i64);
             let addr = father_value;
@@ -1784,6 +2575,17 @@ impl JitBuilder<'_> {
         } else {
             panic!("StoreAttr requires an Attr argument");
         }
+        Ok(())
+    }
+
+    fn load_cast(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+        if let ArgType::TypeInfo(Some(target)) = arg.get_arg() {
+            let value = context.exp.pop().unwrap();
+            let ret = self.coerce(value, target);
+            context.exp.push(ret);
+        } else {
+            panic!("SCast requires a TypeInfo argument");
+        }
     }
 
     fn get_var_type(&self, s_type: &FSRSType) -> Option<types::Type> {
@@ -1802,6 +2604,8 @@ impl JitBuilder<'_> {
             FSRSType::Struct(_) => Some(self.module.target_config().pointer_type()),
             FSRSType::Bool => Some(types::I8),
             FSRSType::Ptr(fsrstype) => Some(self.module.target_config().pointer_type()),
+            FSRSType::Object => Some(self.module.target_config().pointer_type()),
+            FSRSType::Vector(_, _) => Some(self.module.target_config().pointer_type()),
             FSRSType::Fn(fn_call_sig) => Some(self.module.target_config().pointer_type()),
             FSRSType::List(fsrstype, _) => Some(self.module.target_config().pointer_type()),
         }
@@ -1976,44 +2780,23 @@ impl JitBuilder<'_> {
         }
     }
 
-    fn assign_routine(&mut self, context: &mut OperatorContext, v: &LocalVar) {
+    fn assign_routine(&mut self, context: &mut OperatorContext, v: &LocalVar) -> Result<()> {
         let op_assign = v.op_assign;
+        let var_type = v.var_type.as_ref().unwrap();
         //let var = context.exp.pop().unwrap();
-        let var = if let Some(op) = op_assign {
+        let var = if op_assign.is_some() {
             // load the current value
             let variable = self.variables.get(v.name.as_str()).unwrap();
             let current_value_addr = self.builder.use_var(*variable);
-            let current_value =
-                Self::load_data(self, v.var_type.as_ref().unwrap(), current_value_addr);
-            let assign_value = context.exp.pop().unwrap();
+            let current_value = Self::load_ptr_data(self, var_type, current_value_addr, 0);
             // the value to assign is already on the stack
-            let result = match op {
-                crate::backend::compiler::bytecode::OpAssign::Add => {
-                    let result = self.builder.ins().iadd(current_value, assign_value);
-                    result
-                }
-                crate::backend::compiler::bytecode::OpAssign::Sub => {
-                    let result = self.builder.ins().isub(current_value, assign_value);
-                    result
-                }
-                crate::backend::compiler::bytecode::OpAssign::Mul => {
-                    let result = self.builder.ins().imul(current_value, assign_value);
-                    result
-                }
-                crate::backend::compiler::bytecode::OpAssign::Div => {
-                    let result = self.builder.ins().sdiv(current_value, assign_value);
-                    result
-                }
-                crate::backend::compiler::bytecode::OpAssign::Reminder => {
-                    let result = self.builder.ins().srem(current_value, assign_value);
-                    result
-                }
-            };
-            result
+            let assign_value = context.exp.pop().unwrap();
+            self.ret_op_value(current_value, assign_value, &op_assign)?
         } else {
             let var = context.exp.pop().unwrap();
             var
         };
+        let var = self.coerce(var, var_type);
 
         match v.var_type.as_ref().unwrap().as_ref() {
             FSRSType::Bool
@@ -2035,9 +2818,16 @@ impl JitBuilder<'_> {
                     stack_addr,
                     0,
                 );
-                return;
+                return Ok(());
+            }
+            FSRSType::String | FSRSType::Vector(_, _) => {
+                let variable = self.variables.get(v.name.as_str()).unwrap();
+                let stack_addr = self.builder.use_var(*variable);
+                let str_size = var_type.size_of();
+                self.memcpy_fix(context, stack_addr, var, str_size);
+                return Ok(());
             }
-            FSRSType::Ptr(_) | FSRSType::String => {
+            FSRSType::Ptr(_) | FSRSType::Object | FSRSType::Fn(_) => {
                 let variable = self.variables.get(v.name.as_str()).unwrap();
 
                 let stack_addr = self.builder.use_var(*variable);
@@ -2048,15 +2838,15 @@ impl JitBuilder<'_> {
                     0,
                 );
 
-                return;
+                return Ok(());
             }
             FSRSType::Struct(_) => {
                 self.struct_assign(context, v, var);
-                return;
+                return Ok(());
             }
             FSRSType::List(_, _) => {
                 self.list_assing(context, v, var);
-                return;
+                return Ok(());
             }
             _ => {
                 panic!("Assign routine does not support type {:?}", v.var_type);
@@ -2067,6 +2857,7 @@ impl JitBuilder<'_> {
 
         // self.builder.def_var(*variable, var);
         panic!("Assign routine does not support type {:?}", v.var_type);
+        Ok(())
     }
 
     fn init_get_stack_addr(
@@ -2097,7 +2888,7 @@ impl JitBuilder<'_> {
 
                 stack_slot_addr
             }
-            FSRSType::Struct(s) => {
+            FSRSType::Struct(_) | FSRSType::String | FSRSType::Vector(_, _) => {
                 let stack_slot_addr = if is_define {
                     // allocate stack slot for struct
                     let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
@@ -2119,10 +2910,13 @@ impl JitBuilder<'_> {
             }
             FSRSType::UInt64
             | FSRSType::Float32
+            | FSRSType::IInt8
+            | FSRSType::IInt16
             | FSRSType::IInt32
             | FSRSType::IInt64
             | FSRSType::Bool
             | FSRSType::Float64
+            | FSRSType::UInt16
             | FSRSType::UInt32
             | FSRSType::UInt8 => {
                 let stack_slot_addr = if is_define {
@@ -2145,7 +2939,7 @@ impl JitBuilder<'_> {
                 };
                 stack_slot_addr
             }
-            FSRSType::Ptr(_) => {
+            FSRSType::Ptr(_) | FSRSType::Object | FSRSType::Fn(_) => {
                 let stack_slot_addr = if is_define {
                     // allocate stack slot for ptr
                     let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
@@ -2172,7 +2966,7 @@ impl JitBuilder<'_> {
         stack_addr
     }
 
-    fn assign_process(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) {
+    fn assign_process(&mut self, context: &mut OperatorContext, arg: &BytecodeArg) -> Result<()> {
         let mut is_define = false;
         let v = if let ArgType::Local(v) = arg.get_arg() {
             v
@@ -2210,11 +3004,11 @@ impl JitBuilder<'_> {
 
             if context.is_uninit {
                 context.is_uninit = false;
-                return;
+                return Ok(());
             }
         }
 
-        Self::assign_routine(self, context, v);
+        Self::assign_routine(self, context, v)
     }
 
     fn load_process(&mut self, arg: &BytecodeArg, context: &mut OperatorContext, code: ObjId) {
@@ -2225,19 +3019,25 @@ impl JitBuilder<'_> {
             let value = Self::load_ptr_data(self, v.var_type.as_ref().unwrap(), stack_addr, 0);
             context.exp.push(value);
         } else if let ArgType::JitFunction(father_struct, f_name) = arg.get_arg() {
-            let module = FSRObject::id_to_obj(code).as_code().module;
-            let module_obj = FSRObject::id_to_obj(module).as_module();
-            let target_fn_ptr = module_obj.get_fn_addr_ptr(father_struct.clone(), f_name);
-            if target_fn_ptr.is_none() {
-                panic!(
-                    "JIT function {} not found in struct {:?}",
-                    f_name, father_struct
-                );
-            }
-            let target_fn_value = self.builder.ins().iconst(
-                self.module.target_config().pointer_type(),
-                target_fn_ptr.unwrap() as i64,
-            );
+            let identify_name = match father_struct.as_deref() {
+                Some(FSRSType::Struct(s)) => format!("{}::{}", s.name, f_name),
+                Some(FSRSType::Ptr(inner)) => match inner.as_ref() {
+                    FSRSType::Struct(s) => format!("{}::{}", s.name, f_name),
+                    _ => f_name.to_string(),
+                },
+                _ => f_name.to_string(),
+            };
+            let target_fn_value = self.addr_const(AotReloc::FnSlot(identify_name), || {
+                let module = FSRObject::id_to_obj(code).as_code().module;
+                let module_obj = FSRObject::id_to_obj(module).as_module();
+                let target_fn_ptr = module_obj.get_fn_addr_ptr(father_struct.clone(), f_name);
+                target_fn_ptr.unwrap_or_else(|| {
+                    panic!(
+                        "JIT function {} not found in struct {:?}",
+                        f_name, father_struct
+                    )
+                })
+            });
             let target_fn = self.builder.ins().load(
                 self.module.target_config().pointer_type(),
                 cranelift::codegen::ir::MemFlags::new(),
@@ -2249,10 +3049,7 @@ impl JitBuilder<'_> {
         } else if let ArgType::Const(c, orig_str) = arg.get_arg() {
             self.load_constant(*c, context, orig_str);
         } else if let ArgType::Global(name) = arg.get_arg() {
-            let name_ptr = self.builder.ins().iconst(
-                self.module.target_config().pointer_type(),
-                name.as_ptr() as i64,
-            );
+            let name_ptr = self.bytes_const(name.as_bytes());
             let name_len = self.builder.ins().iconst(
                 self.module.target_config().pointer_type(),
                 name.len() as i64,
@@ -2261,10 +3058,12 @@ impl JitBuilder<'_> {
         } else if let ArgType::LoadTrue = arg.get_arg() {
             //let true_id = FSRObject::true_id();
             let true_value = self.builder.ins().iconst(types::I8, 1);
+            self.set_type(true_value, &Arc::new(FSRSType::Bool));
             context.exp.push(true_value);
         } else if let ArgType::LoadFalse = arg.get_arg() {
             //let false_id = FSRObject::false_id();
             let false_value = self.builder.ins().iconst(types::I8, 0);
+            self.set_type(false_value, &Arc::new(FSRSType::Bool));
             context.exp.push(false_value);
         } else if let ArgType::LoadNone = arg.get_arg() {
             self.load_none(context);
@@ -2317,10 +3116,8 @@ impl JitBuilder<'_> {
             )
             .unwrap();
         let func_ref = self.module.declare_func_in_func(fn_id, self.builder.func);
-        let type_ptr = self.builder.ins().iconst(
-            self.module.target_config().pointer_type(),
-            var_type as *const FSRSType as i64,
-        );
+        let ret_type = AotReloc::RetType(self.full_name.to_string());
+        let type_ptr = self.addr_const(ret_type, || var_type as *const FSRSType as usize);
         let thread_runtime = self.builder.block_params(context.entry_block)[0];
         let call = self
             .builder
@@ -2332,6 +3129,7 @@ impl JitBuilder<'_> {
 
     fn mv_to_ret(&mut self, context: &mut OperatorContext, ret_type: &FSRSType, ret_value: Value) {
         let ret_stack = self.ret_slot.unwrap();
+        let ret_value = self.coerce(ret_value, &Arc::new(ret_type.clone()));
         match ret_type {
             FSRSType::Bool
             | FSRSType::IInt8
@@ -2343,7 +3141,8 @@ impl JitBuilder<'_> {
             | FSRSType::IInt64
             | FSRSType::UInt64
             | FSRSType::Float32
-            | FSRSType::Float64 => {
+            | FSRSType::Float64
+            | FSRSType::Object => {
                 self.builder.ins().store(
                     cranelift::codegen::ir::MemFlags::new(),
                     ret_value,
@@ -2351,7 +3150,10 @@ impl JitBuilder<'_> {
                     0,
                 );
             }
-            FSRSType::Struct(_) | FSRSType::List(_, _) => {
+            FSRSType::Struct(_)
+            | FSRSType::List(_, _)
+            | FSRSType::String
+            | FSRSType::Vector(_, _) => {
                 let type_size = ret_type.size_of() as i64;
                 let size_value = self
                     .builder
@@ -2370,8 +3172,11 @@ impl JitBuilder<'_> {
             s.1 = true; // Mark the last if block as having a return value
         }
 
-        let ret_value = if let Some(ret_type) = self.self_call_sig.return_type.as_ref() {
-            if let FSRSType::Struct(_) = ret_type.as_ref() {
+        let ret_type = self.self_call_sig.return_type.clone();
+        let ret_value = if let Some(ret_type) = ret_type.as_ref() {
+            if let FSRSType::Struct(_) | FSRSType::String | FSRSType::Vector(_, _) =
+                ret_type.as_ref()
+            {
                 // handle struct return
                 let return_ptr = self.builder.block_params(context.entry_block)[1];
                 let return_value = context.exp.pop().unwrap();
@@ -2394,10 +3199,11 @@ impl JitBuilder<'_> {
                 Self::memcpy(self, context, return_ptr, return_value, size_value);
                 return_ptr
             } else {
-                context
+                let ret_value = context
                     .exp
                     .pop()
-                    .unwrap_or(self.builder.ins().iconst(types::I64, 0))
+                    .unwrap_or(self.builder.ins().iconst(types::I64, 0));
+                self.coerce(ret_value, ret_type)
             }
         } else {
             context
@@ -2424,9 +3230,9 @@ impl JitBuilder<'_> {
         context: &mut OperatorContext,
         code: ObjId,
         is_entry: bool,
-    ) {
+    ) -> Result<()> {
         if expr.last().is_none() {
-            return;
+            return Ok(());
         }
 
         if expr.last().unwrap().get_operator() == BytecodeOperator::WhileTest {
@@ -2465,22 +3271,22 @@ impl JitBuilder<'_> {
                     //unimplemented!()
                 }
                 BytecodeOperator::Assign => {
-                    self.assign_process(context, arg);
+                    self.assign_process(context, arg)?;
                 }
                 BytecodeOperator::BinaryAdd => {
-                    self.load_binary_op(context, FastAttr::Add);
+                    self.load_binary_op(context, FastAttr::Add)?;
                 }
                 BytecodeOperator::BinarySub => {
-                    self.load_binary_op(context, FastAttr::Sub);
+                    self.load_binary_op(context, FastAttr::Sub)?;
                 }
                 BytecodeOperator::BinaryMul => {
-                    self.load_binary_op(context, FastAttr::Mul);
+                    self.load_binary_op(context, FastAttr::Mul)?;
                 }
                 BytecodeOperator::BinaryDiv => {
-                    self.load_binary_op(context, FastAttr::Div);
+                    self.load_binary_op(context, FastAttr::Div)?;
                 }
                 BytecodeOperator::BinaryReminder => {
-                    self.load_binary_op(context, FastAttr::Reminder);
+                    self.load_binary_op(context, FastAttr::Reminder)?;
                 }
                 BytecodeOperator::AssignArgs => {
                     if is_entry {
@@ -2576,16 +3382,31 @@ impl JitBuilder<'_> {
                     self.pointer_free(context);
                 }
                 BytecodeOperator::AssignAttr => {
-                    self.store_attr(context, arg);
+                    self.store_attr(context, arg)?;
                 }
                 BytecodeOperator::Getter => {
-                    self.load_getter(context, arg);
+                    self.load_getter(context, arg)?;
                 }
                 BytecodeOperator::AssignContainer => {
                     self.store_container(context, arg);
                 }
+                BytecodeOperator::SCast => {
+                    self.load_cast(context, arg);
+                }
+                BytecodeOperator::SStrMethod => {
+                    self.load_str_method(context, arg);
+                }
+                BytecodeOperator::SObjMethod => {
+                    self.load_obj_method(context, arg);
+                }
+                BytecodeOperator::SVecNew => {
+                    self.load_vec_new(context, arg);
+                }
+                BytecodeOperator::Raise => {
+                    self.load_raise(context)?;
+                }
                 _ => {
-                    unimplemented!("Compile operator: {:?} not support now", arg.get_operator())
+                    bail!("static code does not support {:?}", arg.get_operator())
                 }
             }
 
@@ -2607,6 +3428,7 @@ impl JitBuilder<'_> {
                 *s -= 1;
             }
         }
+        Ok(())
     }
 }
 
@@ -2631,7 +3453,7 @@ fn declare_variable(
 }
 
 fn declare_variables(
-    module: &JITModule,
+    module: &dyn Module,
     var_type: types::Type,
     builder: &mut FunctionBuilder,
     params: &[String],
@@ -2667,37 +3489,68 @@ fn declare_variables(
     (variables, index)
 }
 
+/// Helpers static code calls by name, ahead-of-time code finds them here when
+/// it is loaded
+pub(super) fn helper_symbols() -> Vec<(&'static str, *const u8)> {
+    vec![
+        ("binary_op", binary_op as *const u8),
+        ("get_constant", get_constant as *const u8),
+        ("call_fn", call_fn as *const u8),
+        ("malloc", malloc as *const u8),
+        ("free", free as *const u8),
+        ("get_obj_by_name", get_obj_by_name as *const u8),
+        ("check_gc", check_gc as *const u8),
+        ("gc_collect", gc_collect as *const u8),
+        ("get_n_args", get_n_args as *const u8),
+        ("load_integer", load_integer as *const u8),
+        ("load_string", load_string as *const u8),
+        ("load_float", load_float as *const u8),
+        ("c_next_obj", c_next_obj as *const u8),
+        ("binary_range", binary_range as *const u8),
+        ("get_current_fn_id", get_current_fn_id as *const u8),
+        ("save_to_exp", save_to_exp as *const u8),
+        ("clear_exp", clear_exp as *const u8),
+        ("get_obj_method", get_obj_method as *const u8),
+        ("load_list", load_list as *const u8),
+        ("c_println", c_println as *const u8),
+        ("memcpy", memcpy as *const u8),
+        ("ret_process", ret_process as *const u8),
+        ("c_str_from_obj", c_str_from_obj as *const u8),
+        ("c_str_concat", c_str_concat as *const u8),
+        ("c_str_compare", c_str_compare as *const u8),
+        ("c_str_byte", c_str_byte as *const u8),
+        ("c_str_slice", c_str_slice as *const u8),
+        ("c_checked_alloc", c_checked_alloc as *const u8),
+        ("c_checked_free", c_checked_free as *const u8),
+        ("c_check_ptr", c_check_ptr as *const u8),
+        ("c_check_index", c_check_index as *const u8),
+        ("c_jit_raise", c_jit_raise as *const u8),
+        ("c_jit_div_error", c_jit_div_error as *const u8),
+        ("c_fmod", c_fmod as *const u8),
+        ("c_fmodf", c_fmodf as *const u8),
+        ("c_obj_len", c_obj_len as *const u8),
+        ("c_obj_get", c_obj_get as *const u8),
+        ("c_obj_get_int", c_obj_get_int as *const u8),
+        ("c_obj_get_float", c_obj_get_float as *const u8),
+        ("c_obj_as_int", c_obj_as_int as *const u8),
+        ("c_obj_as_float", c_obj_as_float as *const u8),
+        ("c_entry_obj_arg", c_entry_obj_arg as *const u8),
+        ("c_jit_safepoint", c_jit_safepoint as *const u8),
+    ]
+}
+
 impl CraneLiftJitBackend {
     fn init_builder(builder: &mut JITBuilder) {
-        builder.symbol("binary_op", binary_op as *const u8);
-        builder.symbol("get_constant", get_constant as *const u8);
-        builder.symbol("call_fn", call_fn as *const u8);
-        builder.symbol("malloc", malloc as *const u8);
-        builder.symbol("free", free as *const u8);
-        builder.symbol("get_obj_by_name", get_obj_by_name as *const u8);
-        builder.symbol("check_gc", check_gc as *const u8);
-        builder.symbol("gc_collect", gc_collect as *const u8);
-        builder.symbol("get_n_args", get_n_args as *const u8);
-        builder.symbol("load_integer", load_integer as *const u8);
-        builder.symbol("load_string", load_string as *const u8);
-        builder.symbol("load_float", load_float as *const u8);
-        builder.symbol("c_next_obj", c_next_obj as *const u8);
-        builder.symbol("binary_range", binary_range as *const u8);
-        builder.symbol("get_current_fn_id", get_current_fn_id as *const u8);
-        builder.symbol("save_to_exp", save_to_exp as *const u8);
-        builder.symbol("clear_exp", clear_exp as *const u8);
-        builder.symbol("get_obj_method", get_obj_method as *const u8);
-        builder.symbol("load_list", load_list as *const u8);
-        builder.symbol("c_println", c_println as *const u8);
-        builder.symbol("memcpy", memcpy as *const u8);
-        builder.symbol("ret_process", ret_process as *const u8);
+        for (name, ptr) in helper_symbols() {
+            builder.symbol(name, ptr);
+        }
     }
 
     pub fn new() -> Self {
         let mut flag_builder = settings::builder();
         flag_builder.set("use_colocated_libcalls", "false").unwrap();
         flag_builder.set("is_pic", "false").unwrap();
-        //flag_builder.set("opt_level", "speed").unwrap();
+        flag_builder.set("opt_level", opt_level()).unwrap();
         let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
             panic!("host machine is not supported: {}", msg);
         });
@@ -2714,9 +3567,16 @@ impl CraneLiftJitBackend {
             builder_context: FunctionBuilderContext::new(),
             //variable: HashMap::new(),
             module,
+            aot: None,
+            dump: debug::dump_enabled(),
         }
     }
 
+    /// Print the IR and machine code of the functions this backend compiles
+    pub fn set_dump(&mut self, dump: bool) {
+        self.dump = dump;
+    }
+
     pub fn compile(
         &mut self,
         full_name: &str,
@@ -2725,6 +3585,32 @@ impl CraneLiftJitBackend {
         is_entry: bool,
         call_sig: Option<Arc<FnCallSig>>,
     ) -> Result<*const u8> {
+        let fn_name = bs_code.name.as_str();
+        let (id, lines) =
+            self.compile_fn(fn_name, full_name, bs_code, code, is_entry, call_sig)?;
+        self.module.finalize_definitions().unwrap();
+
+        // We can now retrieve a pointer to the machine code.
+        let code = self.module.get_finalized_function(id);
+        if let Some(lines) = lines {
+            debug::register_code(full_name, code, &lines);
+        }
+        Ok(code)
+    }
+}
+
+impl<M: Module> CraneLiftJitBackend<M> {
+    /// Translate a static function and define it as `symbol` in the module,
+    /// with its line table when a profiler or debugger wants it
+    pub(super) fn compile_fn(
+        &mut self,
+        symbol: &str,
+        full_name: &str,
+        bs_code: &Bytecode,
+        code: ObjId,
+        is_entry: bool,
+        call_sig: Option<Arc<FnCallSig>>,
+    ) -> Result<(FuncId, Option<CodeLines>)> {
         let ptr = self.module.target_config().pointer_type();
 
         if is_entry {
@@ -2826,13 +3712,20 @@ impl CraneLiftJitBackend {
             .and_then(|s| s.return_type.as_ref())
             .map(|r| r.size_of())
             .unwrap_or(8);
+        let simd = simd::simd_enabled(self.module.isa());
         let mut trans = JitBuilder {
             builder,
             variables: variables.0,
             module: &mut self.module,
+            aot: self.aot.as_mut(),
+            full_name,
             var_index: variables.1,
             self_call_sig: call_sig.unwrap(),
             ret_slot: None,
+            value_types: HashMap::new(),
+            checked: checked::checked_enabled(),
+            has_object: false,
+            simd,
         };
 
         if is_entry {
@@ -2858,34 +3751,36 @@ impl CraneLiftJitBackend {
             //     context.ins_check_gc = false;
             // }
 
-            trans.compile_expr(expr, &mut context, code, is_entry);
+            trans.builder.set_srcloc(SourceLoc::new(i as u32));
+            if let Err(e) = trans.compile_expr(expr, &mut context, code, is_entry) {
+                // the function is dropped half built, the next one starts clean
+                self.module.clear_context(&mut self.ctx);
+                self.builder_context = FunctionBuilderContext::new();
+                return Err(e.context(format!("can not compile static function {}", full_name)));
+            }
             context.exp.clear();
         }
 
         trans.builder.finalize();
 
-        let fn_name = bs_code.name.as_str();
-
         let id = self
             .module
             .declare_function(
-                fn_name,
+                symbol,
                 cranelift_module::Linkage::Export,
                 &self.ctx.func.signature,
             )
             .unwrap();
-        println!("Cranelift JIT compiled function: {}", full_name);
-        println!("{}", self.ctx.func.display());
+        let dump = self.dump.then(|| CodeDump::begin(&mut self.ctx));
         self.module.define_function(id, &mut self.ctx).unwrap();
+        if let Some(dump) = dump {
+            println!("{}", dump.finish(full_name, &self.ctx));
+        }
+        let lines = CodeLines::collect(&self.ctx, bs_code);
 
         self.module.clear_context(&mut self.ctx);
         // Tell the builder we're done with this function.
-
-        self.module.finalize_definitions().unwrap();
-
-        // We can now retrieve a pointer to the machine code.
-        let code = self.module.get_finalized_function(id);
-        Ok(code)
+        Ok((id, lines))
     }
 }
 
@@ -2898,4 +3793,30 @@ mod test {
         },
         vm::{thread::FSRThreadRuntime, virtual_machine::FSRVM},
     };
+
+    #[test]
+    fn test_set_opt_level() {
+        use super::{opt_level, set_opt_level};
+
+        assert!(set_opt_level("fast").is_err());
+        assert_eq!(opt_level(), "none");
+        set_opt_level("none").unwrap();
+    }
+
+    #[test]
+    fn test_compile_error() {
+        FSRVM::single();
+        let source = "@entry\nfn str_sub(a: string, b: string) -> string {\n    return a - b\n}\n";
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let module: ObjId = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source, module).unwrap();
+        FSRObject::id_to_mut_obj(module)
+            .unwrap()
+            .as_mut_module()
+            .init_fn_map(v);
+
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        let err = runtime.start(module, false).unwrap_err();
+        assert!(err.to_string().contains("static string only supports `+`"));
+    }
 }
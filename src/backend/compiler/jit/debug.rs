@@ -0,0 +1,448 @@
+// perf map, GDB JIT interface and IR dump of jitted code, see `--perf-map`,
+// `--gdb-jit` and `--jit-dump`
+use std::{
+    fs::File,
+    io::Write,
+    ptr,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use cranelift::codegen::Context;
+use gimli::{
+    Encoding, Format, LineEncoding, LittleEndian, constants,
+    write::{Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Sections},
+};
+use object::{
+    Endianness, elf,
+    write::elf::{FileHeader, SectionHeader, Sym, Writer},
+};
+
+use crate::backend::compiler::bytecode::Bytecode;
+
+static PERF_MAP: AtomicBool = AtomicBool::new(false);
+static GDB_JIT: AtomicBool = AtomicBool::new(false);
+static DUMP: AtomicBool = AtomicBool::new(false);
+static PERF_MAP_FILE: Mutex<Option<File>> = Mutex::new(None);
+static SOURCE_PATH: OnceLock<String> = OnceLock::new();
+
+pub fn enable_perf_map() {
+    PERF_MAP.store(true, Ordering::Relaxed);
+}
+
+pub fn enable_gdb_jit() {
+    GDB_JIT.store(true, Ordering::Relaxed);
+}
+
+pub fn enable_dump() {
+    DUMP.store(true, Ordering::Relaxed);
+}
+
+/// Script file the line tables point at
+pub fn set_source_path(path: &str) {
+    let _ = SOURCE_PATH.set(path.to_string());
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|x| !x.is_empty() && x != "0")
+}
+
+pub fn perf_map_enabled() -> bool {
+    PERF_MAP.load(Ordering::Relaxed) || env_flag("FSCRIPT_PERF_MAP")
+}
+
+pub fn gdb_jit_enabled() -> bool {
+    GDB_JIT.load(Ordering::Relaxed) || env_flag("FSCRIPT_GDB_JIT")
+}
+
+pub fn dump_enabled() -> bool {
+    DUMP.load(Ordering::Relaxed) || env_flag("FSCRIPT_JIT_DUMP")
+}
+
+/// Dump of one function, started before cranelift compiles it
+pub(super) struct CodeDump {
+    before: String,
+}
+
+impl CodeDump {
+    /// Keep the IR as built and ask cranelift for the disassembly
+    pub(super) fn begin(ctx: &mut Context) -> Self {
+        ctx.set_disasm(true);
+        Self {
+            before: ctx.func.display().to_string(),
+        }
+    }
+
+    /// The dump after the function in `ctx` was compiled
+    pub(super) fn finish(self, name: &str, ctx: &Context) -> String {
+        let disasm = ctx
+            .compiled_code()
+            .and_then(|x| x.vcode.as_deref())
+            .unwrap_or_default();
+        format!(
+            "; jit dump of `{}`\n; ir before optimization\n{}\n; ir after optimization\n{}\n\
+             ; machine code\n{}",
+            name,
+            self.before,
+            ctx.func.display(),
+            disasm
+        )
+    }
+}
+
+/// Size and line table of a function compiled by cranelift
+pub(super) struct CodeLines {
+    size: u32,
+    /// Code offset where each source line starts, in code order
+    rows: Vec<(u32, u32)>,
+}
+
+impl CodeLines {
+    /// Read the code compiled in `ctx`, the source location of each
+    /// instruction is the index of its bytecode line. `None` unless a profiler
+    /// or debugger wants the code.
+    pub(super) fn collect(ctx: &Context, bs_code: &Bytecode) -> Option<Self> {
+        if !perf_map_enabled() && !gdb_jit_enabled() {
+            return None;
+        }
+
+        let code = ctx.compiled_code()?;
+        let mut rows: Vec<(u32, u32)> = vec![];
+        for src in code.buffer.get_srclocs_sorted() {
+            if src.loc.is_default() {
+                continue;
+            }
+
+            let Some(arg) = bs_code
+                .bytecode
+                .get(src.loc.bits() as usize)
+                .and_then(|x| x.first())
+            else {
+                continue;
+            };
+            let line = arg.get_pos().as_human().line as u32;
+            match rows.last() {
+                // the prologue belongs to the first line
+                None => rows.push((0, line)),
+                Some(last) if last.1 != line => rows.push((src.start, line)),
+                _ => {}
+            }
+        }
+
+        Some(Self {
+            size: code.code_info().total_size,
+            rows,
+        })
+    }
+}
+
+/// Tell the enabled profilers and debuggers about the function at `code`
+pub(super) fn register_code(name: &str, code: *const u8, lines: &CodeLines) {
+    if perf_map_enabled() {
+        write_perf_map(name, code as usize, lines.size);
+    }
+
+    if gdb_jit_enabled() {
+        gdb_register(gdb_image(name, code as u64, lines));
+    }
+}
+
+fn perf_map_line(name: &str, addr: usize, size: u32) -> String {
+    format!("{:x} {:x} {}", addr, size, name)
+}
+
+fn write_perf_map(name: &str, addr: usize, size: u32) {
+    let mut file = PERF_MAP_FILE.lock().unwrap();
+    if file.is_none() {
+        // the map of a previous process with the same pid is stale
+        *file = File::create(format!("/tmp/perf-{}.map", std::process::id())).ok();
+    }
+
+    if let Some(file) = file.as_mut() {
+        // profiling is best effort, a failed write must not stop the script
+        let _ = writeln!(file, "{}", perf_map_line(name, addr, size));
+    }
+}
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+#[repr(C)]
+struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+const JIT_REGISTER_FN: u32 = 1;
+
+static GDB_LOCK: Mutex<()> = Mutex::new(());
+
+/// Read by gdb, the names and layout are fixed by the GDB JIT interface
+#[allow(non_upper_case_globals)]
+#[unsafe(no_mangle)]
+static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: 0,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// gdb keeps a breakpoint here and reads `__jit_debug_descriptor` on each call
+#[unsafe(no_mangle)]
+#[inline(never)]
+extern "C" fn __jit_debug_register_code() {
+    std::hint::black_box(());
+}
+
+fn gdb_register(image: Vec<u8>) {
+    let image = Box::leak(image.into_boxed_slice());
+    let entry = Box::into_raw(Box::new(JitCodeEntry {
+        next_entry: ptr::null_mut(),
+        prev_entry: ptr::null_mut(),
+        symfile_addr: image.as_ptr(),
+        symfile_size: image.len() as u64,
+    }));
+
+    let _guard = GDB_LOCK.lock().unwrap();
+    unsafe {
+        let descriptor = &raw mut __jit_debug_descriptor;
+        let first = (*descriptor).first_entry;
+        (*entry).next_entry = first;
+        if !first.is_null() {
+            (*first).prev_entry = entry;
+        }
+        (*descriptor).first_entry = entry;
+        (*descriptor).relevant_entry = entry;
+        (*descriptor).action_flag = JIT_REGISTER_FN;
+        __jit_debug_register_code();
+    }
+}
+
+fn elf_machine() -> u16 {
+    match std::env::consts::ARCH {
+        "x86_64" => elf::EM_X86_64,
+        "aarch64" => elf::EM_AARCH64,
+        "riscv64" => elf::EM_RISCV,
+        _ => elf::EM_NONE,
+    }
+}
+
+/// DWARF sections of one compile unit holding the function and its lines
+fn dwarf_sections(name: &str, addr: u64, lines: &CodeLines) -> Vec<(&'static str, Vec<u8>)> {
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+    let source = SOURCE_PATH.get().map_or("<script>", String::as_str);
+    let comp_dir = std::env::current_dir()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(comp_dir.clone().into_bytes()),
+        None,
+        LineString::String(source.into()),
+        None,
+    );
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(source.into()), dir, None);
+    program.begin_sequence(Some(Address::Constant(addr)));
+    for &(offset, line) in &lines.rows {
+        let row = program.row();
+        row.address_offset = offset as u64;
+        row.file = file;
+        row.line = line as u64;
+        program.generate_row();
+    }
+    program.end_sequence(lines.size as u64);
+    dwarf.unit.line_program = program;
+
+    let low_pc = AttributeValue::Address(Address::Constant(addr));
+    let high_pc = AttributeValue::Udata(lines.size as u64);
+    let root = dwarf.unit.root();
+    let unit = dwarf.unit.get_mut(root);
+    unit.set(constants::DW_AT_name, AttributeValue::String(source.into()));
+    unit.set(constants::DW_AT_comp_dir, AttributeValue::String(comp_dir.into_bytes()));
+    unit.set(constants::DW_AT_producer, AttributeValue::String(b"fscript-rs".to_vec()));
+    unit.set(constants::DW_AT_low_pc, low_pc.clone());
+    unit.set(constants::DW_AT_high_pc, high_pc.clone());
+
+    let subprogram = dwarf.unit.add(root, constants::DW_TAG_subprogram);
+    let subprogram = dwarf.unit.get_mut(subprogram);
+    subprogram.set(constants::DW_AT_name, AttributeValue::String(name.into()));
+    subprogram.set(constants::DW_AT_low_pc, low_pc);
+    subprogram.set(constants::DW_AT_high_pc, high_pc);
+
+    let mut sections = Sections::new(EndianVec::new(LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+    let mut res = vec![];
+    sections
+        .for_each(|id, data| {
+            if !data.slice().is_empty() {
+                res.push((id.name(), data.slice().to_vec()));
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    res
+}
+
+/// An ELF object for `size` bytes of code at `addr`: a `.text` without
+/// contents placed at the code, a function symbol and the DWARF sections
+fn gdb_image(name: &str, addr: u64, lines: &CodeLines) -> Vec<u8> {
+    let dwarf = dwarf_sections(name, addr, lines);
+    let mut buffer = vec![];
+    let mut writer = Writer::new(Endianness::Little, true, &mut buffer);
+    writer.reserve_file_header();
+
+    writer.reserve_null_section_index();
+    let text_name = writer.add_section_name(b".text");
+    let text = writer.reserve_section_index();
+    let debug_names = dwarf
+        .iter()
+        .map(|(name, _)| (writer.add_section_name(name.as_bytes()), writer.reserve_section_index()))
+        .collect::<Vec<_>>();
+    writer.reserve_symtab_section_index();
+    writer.reserve_strtab_section_index();
+    writer.reserve_shstrtab_section_index();
+
+    let symbol_name = writer.add_string(name.as_bytes());
+    writer.reserve_null_symbol_index();
+    writer.reserve_symbol_index(Some(text));
+
+    let debug_offsets = dwarf
+        .iter()
+        .map(|(_, data)| writer.reserve(data.len(), 1))
+        .collect::<Vec<_>>();
+    writer.reserve_symtab();
+    writer.reserve_strtab();
+    writer.reserve_shstrtab();
+    writer.reserve_section_headers();
+
+    writer
+        .write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_REL,
+            e_machine: elf_machine(),
+            e_entry: 0,
+            e_flags: 0,
+        })
+        .unwrap();
+    for (_, data) in dwarf.iter() {
+        writer.write(data);
+    }
+
+    writer.write_null_symbol();
+    writer.write_symbol(&Sym {
+        name: Some(symbol_name),
+        section: Some(text),
+        st_info: (elf::STB_GLOBAL << 4) | elf::STT_FUNC,
+        st_other: elf::STV_DEFAULT,
+        st_shndx: 0,
+        st_value: 0,
+        st_size: lines.size as u64,
+    });
+    writer.write_strtab();
+    writer.write_shstrtab();
+
+    writer.write_null_section_header();
+    writer.write_section_header(&SectionHeader {
+        name: Some(text_name),
+        sh_type: elf::SHT_NOBITS,
+        sh_flags: (elf::SHF_ALLOC | elf::SHF_EXECINSTR) as u64,
+        sh_addr: addr,
+        sh_offset: 0,
+        sh_size: lines.size as u64,
+        sh_link: 0,
+        sh_info: 0,
+        sh_addralign: 16,
+        sh_entsize: 0,
+    });
+    for ((name, _), ((_, data), offset)) in debug_names.iter().zip(dwarf.iter().zip(debug_offsets))
+    {
+        writer.write_section_header(&SectionHeader {
+            name: Some(*name),
+            sh_type: elf::SHT_PROGBITS,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset: offset as u64,
+            sh_size: data.len() as u64,
+            sh_link: 0,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: 0,
+        });
+    }
+    // the null symbol is the only local one
+    writer.write_symtab_section_header(1);
+    writer.write_strtab_section_header();
+    writer.write_shstrtab_section_header();
+
+    buffer
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_perf_map_line() {
+        use super::perf_map_line;
+
+        assert_eq!(perf_map_line("fib", 0x7f00_1000, 0x40), "7f001000 40 fib");
+    }
+
+    #[test]
+    fn test_gdb_image() {
+        use super::{CodeLines, gdb_image};
+        use object::{Object, ObjectSection, ObjectSymbol};
+
+        let lines = CodeLines {
+            size: 0x30,
+            rows: vec![(0, 3), (0x10, 4), (0x20, 6)],
+        };
+        let image = gdb_image("fib", 0x7f00_1000, &lines);
+        let file = object::File::parse(image.as_slice()).unwrap();
+        let text = file.section_by_name(".text").unwrap();
+        assert_eq!(text.address(), 0x7f00_1000);
+        assert_eq!(text.size(), 0x30);
+        let symbol = file.symbols().find(|x| x.name() == Ok("fib")).unwrap();
+        assert_eq!(symbol.size(), 0x30);
+
+        // read the line table back
+        let load = |id: gimli::SectionId| {
+            let data = file
+                .section_by_name(id.name())
+                .and_then(|x| x.data().ok())
+                .unwrap_or(&[]);
+            Ok::<_, gimli::Error>(gimli::EndianSlice::new(data, gimli::LittleEndian))
+        };
+        let dwarf = gimli::Dwarf::load(load).unwrap();
+        let header = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(header).unwrap();
+        let program = unit.line_program.unwrap();
+        let mut rows = program.rows();
+        let mut res = vec![];
+        while let Some((_, row)) = rows.next_row().unwrap() {
+            if !row.end_sequence() {
+                res.push((row.address(), row.line().unwrap().get()));
+            }
+        }
+        assert_eq!(
+            res,
+            vec![(0x7f00_1000, 3), (0x7f00_1010, 4), (0x7f00_1020, 6)]
+        );
+    }
+}
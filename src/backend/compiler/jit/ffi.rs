@@ -0,0 +1,285 @@
+// Call and callback trampolines of the `ffi` module, values cross as one 64 bit
+// word per parameter
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use cranelift::{
+    codegen::ir::MemFlags,
+    prelude::{
+        AbiParam, FunctionBuilder, InstBuilder, Signature, StackSlotData, StackSlotKind, Type,
+        Value, types,
+    },
+};
+use cranelift_module::Module;
+
+use super::{cranelift::CraneLiftJitBackend, debug::CodeDump};
+use crate::backend::compiler::bytecode::FSRSType;
+
+/// Trampoline calling the C function `f` with the words in `args`, the
+/// result is stored in `ret`
+pub type FfiCall = unsafe extern "C" fn(f: usize, args: *const u64, ret: *mut u64);
+
+/// Called by a callback with its `data` and the words of the C arguments,
+/// the result is stored in `ret`
+pub type FfiDispatch = extern "C" fn(data: usize, args: *const u64, ret: *mut u64);
+
+const WORD: i32 = 8;
+
+/// Parameter and return types of a C function
+#[derive(Debug, Clone)]
+pub struct FfiSig {
+    pub params: Vec<Arc<FSRSType>>,
+    pub ret: Option<Arc<FSRSType>>,
+}
+
+impl FfiSig {
+    pub fn new(params: Vec<Arc<FSRSType>>, ret: Option<Arc<FSRSType>>) -> Result<Self> {
+        for ty in params.iter().chain(ret.iter()) {
+            check_type(ty)?;
+        }
+        Ok(Self { params, ret })
+    }
+}
+
+/// Types a C function takes or returns
+pub fn check_type(ty: &FSRSType) -> Result<()> {
+    match ty {
+        FSRSType::Struct(s) => bail!("struct {} must be passed to C as Ptr[{}]", s.name, s.name),
+        FSRSType::String => bail!("string must be passed to C as Ptr[u8]"),
+        FSRSType::List(_, _) | FSRSType::Fn(_) | FSRSType::Object => {
+            bail!("type {:?} can not be passed to C", ty)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn is_signed(ty: &FSRSType) -> bool {
+    matches!(
+        ty,
+        FSRSType::IInt8 | FSRSType::IInt16 | FSRSType::IInt32 | FSRSType::IInt64
+    )
+}
+
+fn cl_type(ptr: Type, ty: &FSRSType) -> Type {
+    match ty {
+        FSRSType::Bool | FSRSType::UInt8 | FSRSType::IInt8 => types::I8,
+        FSRSType::UInt16 | FSRSType::IInt16 => types::I16,
+        FSRSType::UInt32 | FSRSType::IInt32 => types::I32,
+        FSRSType::UInt64 | FSRSType::IInt64 => types::I64,
+        FSRSType::Float32 => types::F32,
+        FSRSType::Float64 => types::F64,
+        _ => ptr,
+    }
+}
+
+/// Load the word at `offset` of `base` as a value of `ty`
+fn load_word(
+    builder: &mut FunctionBuilder,
+    ptr: Type,
+    ty: &FSRSType,
+    base: Value,
+    offset: i32,
+) -> Value {
+    let flags = MemFlags::trusted();
+    match ty {
+        FSRSType::Float32 => {
+            let v = builder.ins().load(types::F64, flags, base, offset);
+            builder.ins().fdemote(types::F32, v)
+        }
+        FSRSType::Float64 => builder.ins().load(types::F64, flags, base, offset),
+        _ => {
+            let v = builder.ins().load(types::I64, flags, base, offset);
+            match cl_type(ptr, ty) {
+                types::I64 => v,
+                t => builder.ins().ireduce(t, v),
+            }
+        }
+    }
+}
+
+/// Store the value of `ty` as the word at `offset` of `base`
+fn store_word(
+    builder: &mut FunctionBuilder,
+    ty: &FSRSType,
+    value: Value,
+    base: Value,
+    offset: i32,
+) {
+    let flags = MemFlags::trusted();
+    let word = match ty {
+        FSRSType::Float32 => builder.ins().fpromote(types::F64, value),
+        FSRSType::Float64 => value,
+        _ if builder.func.dfg.value_type(value) == types::I64 => value,
+        _ if is_signed(ty) => builder.ins().sextend(types::I64, value),
+        _ => builder.ins().uextend(types::I64, value),
+    };
+    builder.ins().store(flags, word, base, offset);
+}
+
+impl CraneLiftJitBackend {
+    /// C signature of `sig` on the host
+    fn ffi_signature(&self, sig: &FfiSig) -> Signature {
+        let ptr = self.module.target_config().pointer_type();
+        let mut c_sig = self.module.make_signature();
+        for param in sig.params.iter() {
+            c_sig.params.push(AbiParam::new(cl_type(ptr, param)));
+        }
+        if let Some(ret) = &sig.ret {
+            c_sig.returns.push(AbiParam::new(cl_type(ptr, ret)));
+        }
+        c_sig
+    }
+
+    fn finish_ffi(&mut self, name: &str) -> Result<*const u8> {
+        let id = self
+            .module
+            .declare_anonymous_function(&self.ctx.func.signature)?;
+        let dump = self.dump.then(|| CodeDump::begin(&mut self.ctx));
+        self.module.define_function(id, &mut self.ctx)?;
+        if let Some(dump) = dump {
+            println!("{}", dump.finish(name, &self.ctx));
+        }
+        self.module.clear_context(&mut self.ctx);
+        self.module.finalize_definitions()?;
+        Ok(self.module.get_finalized_function(id))
+    }
+
+    /// Compile the trampoline calling C functions of `sig`
+    pub fn compile_ffi_call(&mut self, name: &str, sig: &FfiSig) -> Result<FfiCall> {
+        let ptr = self.module.target_config().pointer_type();
+        let c_sig = self.ffi_signature(sig);
+        self.ctx.func.signature = self.module.make_signature();
+        for _ in 0..3 {
+            self.ctx.func.signature.params.push(AbiParam::new(ptr)); // f, args, ret
+        }
+
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+        let (f, args, ret) = match builder.block_params(block) {
+            [f, args, ret] => (*f, *args, *ret),
+            _ => unreachable!(),
+        };
+
+        let values = sig
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| load_word(&mut builder, ptr, ty, args, i as i32 * WORD))
+            .collect::<Vec<_>>();
+        let c_sig_ref = builder.import_signature(c_sig);
+        let call = builder.ins().call_indirect(c_sig_ref, f, &values);
+        if let Some(ret_type) = &sig.ret {
+            let value = builder.inst_results(call)[0];
+            store_word(&mut builder, ret_type, value, ret, 0);
+        }
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        let code = self.finish_ffi(name)?;
+        Ok(unsafe { std::mem::transmute::<*const u8, FfiCall>(code) })
+    }
+
+    /// Compile a C function of `sig` which calls `dispatch` with `data`
+    pub fn compile_ffi_callback(
+        &mut self,
+        name: &str,
+        sig: &FfiSig,
+        dispatch: FfiDispatch,
+        data: usize,
+    ) -> Result<*const u8> {
+        let ptr = self.module.target_config().pointer_type();
+        self.ctx.func.signature = self.ffi_signature(sig);
+        let mut dispatch_sig = self.module.make_signature();
+        for _ in 0..3 {
+            dispatch_sig.params.push(AbiParam::new(ptr)); // data, args, ret
+        }
+
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+
+        // the argument words, then the result word
+        let len = sig.params.len() as i32;
+        let slot = builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            ((len + 1) * WORD) as u32,
+            3,
+        ));
+        let args = builder.ins().stack_addr(ptr, slot, 0);
+        let ret = builder.ins().stack_addr(ptr, slot, len * WORD);
+        let params = builder.block_params(block).to_vec();
+        for (i, (ty, value)) in sig.params.iter().zip(params).enumerate() {
+            store_word(&mut builder, ty, value, args, i as i32 * WORD);
+        }
+
+        let dispatch_sig_ref = builder.import_signature(dispatch_sig);
+        let dispatch = builder.ins().iconst(ptr, dispatch as usize as i64);
+        let data = builder.ins().iconst(ptr, data as i64);
+        builder
+            .ins()
+            .call_indirect(dispatch_sig_ref, dispatch, &[data, args, ret]);
+        match &sig.ret {
+            Some(ret_type) => {
+                let value = load_word(&mut builder, ptr, ret_type, ret, 0);
+                builder.ins().return_(&[value]);
+            }
+            None => {
+                builder.ins().return_(&[]);
+            }
+        }
+        builder.finalize();
+
+        self.finish_ffi(name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{FfiSig, check_type};
+    use crate::backend::compiler::{bytecode::FSRSType, jit::cranelift::CraneLiftJitBackend};
+
+    extern "C" fn scale(x: i8, y: f32) -> f64 {
+        x as f64 * y as f64
+    }
+
+    extern "C" fn twice(_: usize, args: *const u64, ret: *mut u64) {
+        unsafe { *ret = (*args as i64 * 2) as u64 };
+    }
+
+    #[test]
+    fn test_ffi_trampolines() {
+        let sig = FfiSig::new(
+            vec![Arc::new(FSRSType::IInt8), Arc::new(FSRSType::Float32)],
+            Some(Arc::new(FSRSType::Float64)),
+        )
+        .unwrap();
+        let call = CraneLiftJitBackend::new()
+            .compile_ffi_call("scale", &sig)
+            .unwrap();
+        let args = [-3i64 as u64, 1.5f64.to_bits()];
+        let mut ret = 0;
+        unsafe { call(scale as *const () as usize, args.as_ptr(), &mut ret) };
+        assert_eq!(f64::from_bits(ret), -4.5);
+
+        let sig = FfiSig::new(
+            vec![Arc::new(FSRSType::IInt32)],
+            Some(Arc::new(FSRSType::IInt32)),
+        )
+        .unwrap();
+        let code = CraneLiftJitBackend::new()
+            .compile_ffi_callback("twice", &sig, twice, 0)
+            .unwrap();
+        let f = unsafe { std::mem::transmute::<*const u8, extern "C" fn(i32) -> i32>(code) };
+        assert_eq!(f(-21), -42);
+
+        assert!(check_type(&FSRSType::String).is_err());
+        assert!(check_type(&FSRSType::Ptr(Arc::new(FSRSType::UInt8))).is_ok());
+    }
+}
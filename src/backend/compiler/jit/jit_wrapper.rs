@@ -1,17 +1,25 @@
-use std::{ops::Range, sync::atomic::Ordering};
+use std::{
+    ops::Range,
+    sync::{Arc, atomic::Ordering},
+};
 
 use crate::{
     backend::{
-        compiler::bytecode::{FastAttr, CompareOperator, FSRSType},
+        compiler::{
+            bytecode::{AttrVar, CompareOperator, FSRSType, FastAttr, FnCallSig, LocalVar, OpAssign},
+            jit::baseline::DeoptPoint,
+        },
         types::{
-            base::{FSRObject, FSRValue, GlobalObj, ObjId}, integer::FSRInteger, iterator::next_obj, list::FSRList, range::FSRRange, string::FSRString
+            base::{FSRObject, FSRValue, GlobalObj, ObjId}, ext::hashmap::FSRHashMap, fn_def::TypeFeedback, integer::FSRInteger, iterator::next_obj, list::FSRList, range::FSRRange, string::FSRString
         },
         vm::{
+            inline_cache::AttrInlineCache,
             thread::{CallFrame, FSRThreadRuntime},
             virtual_machine::{FSRVM, gid},
         },
     },
     to_rs_list,
+    utils::error::{FSRErrCode, FSRError},
 };
 
 macro_rules! obj_cls {
@@ -299,12 +307,17 @@ pub extern "C" fn get_current_fn_id(thread: &mut FSRThreadRuntime) -> ObjId {
     frame.fn_id
 }
 
-pub extern "C" fn get_obj_method(father: ObjId, name: *const u8, len: usize) -> ObjId {
+pub extern "C" fn get_obj_method(
+    father: ObjId,
+    name: *const u8,
+    len: usize,
+    cache: &AttrInlineCache,
+) -> ObjId {
     let name_slice = unsafe { std::slice::from_raw_parts(name, len) };
     let name_str = std::str::from_utf8(name_slice).unwrap();
     let father_obj = FSRObject::id_to_obj(father);
 
-    if let Some(attr) = father_obj.cls.get_attr(name_str) {
+    if let Some(attr) = cache.get_cls_attr(father_obj.cls, name_str) {
         return attr.load(Ordering::Relaxed);
     }
 
@@ -342,57 +355,632 @@ pub extern "C" fn memcpy(
     }
 }
 
+/// ABI of a static `string`: a borrowed utf-8 view. The bytes belong to a
+/// constant, to an `FSRString` passed to the entry function or to the thread's
+/// static string arena.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct FSRStaticStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl FSRStaticStr {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+pub extern "C" fn c_str_from_obj(obj: ObjId, out: &mut FSRStaticStr) {
+    let obj = FSRObject::id_to_obj(obj);
+    *out = if let FSRValue::String(s) = &obj.value {
+        FSRStaticStr::from_bytes(s.as_str().as_bytes())
+    } else {
+        FSRStaticStr::from_bytes(&[])
+    };
+}
+
+pub extern "C" fn c_str_concat(
+    thread: &mut FSRThreadRuntime,
+    left: &FSRStaticStr,
+    right: &FSRStaticStr,
+    out: &mut FSRStaticStr,
+) {
+    let buffer = [left.as_bytes(), right.as_bytes()].concat().into_boxed_slice();
+    *out = FSRStaticStr::from_bytes(&buffer);
+    thread.static_strings.push(buffer);
+}
+
+/// Byte-wise ordering: -1, 0 or 1
+pub extern "C" fn c_str_compare(left: &FSRStaticStr, right: &FSRStaticStr) -> i64 {
+    left.as_bytes().cmp(right.as_bytes()) as i64
+}
+
+/// Byte at `index`, 0 when the index is out of range
+pub extern "C" fn c_str_byte(s: &FSRStaticStr, index: i64) -> u8 {
+    if index < 0 {
+        return 0;
+    }
+    s.as_bytes().get(index as usize).copied().unwrap_or(0)
+}
+
+/// Borrowed view of `start..end`, both ends are clamped to the string
+pub extern "C" fn c_str_slice(s: &FSRStaticStr, start: i64, end: i64, out: &mut FSRStaticStr) {
+    let len = s.len as i64;
+    let start = start.clamp(0, len);
+    let end = end.clamp(start, len);
+    *out = FSRStaticStr::from_bytes(&s.as_bytes()[start as usize..end as usize]);
+}
+
+/// `s.raise` in static code, the string is the exception `catch` takes
+pub extern "C" fn c_jit_raise(thread: &mut FSRThreadRuntime, s: &FSRStaticStr) {
+    let value = FSRString::new_value(String::from_utf8_lossy(s.as_bytes()));
+    let exception = thread
+        .garbage_collect
+        .new_object(value, gid(GlobalObj::StringCls));
+    thread.set_jit_error(FSRError::new_runtime_error(exception));
+}
+
+/// Integer division of static code by zero, or of the minimum value by -1
+pub extern "C" fn c_jit_div_error(thread: &mut FSRThreadRuntime, overflow: i8) {
+    thread.set_jit_error(if overflow != 0 {
+        FSRError::new("integer overflow in division", FSRErrCode::OutOfRange)
+    } else {
+        FSRError::new("division by zero", FSRErrCode::NotValidArgs)
+    });
+}
+
+/// Float `%` of static code, the sign of the result is the sign of `a`
+pub extern "C" fn c_fmod(a: f64, b: f64) -> f64 {
+    a % b
+}
+
+pub extern "C" fn c_fmodf(a: f32, b: f32) -> f32 {
+    a % b
+}
+
+/// How static code passes the key of `Object.get`, its static type decides
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjKey {
+    /// `i64` by value
+    Int = 0,
+    /// address of a `FSRStaticStr`
+    Str = 1,
+    /// `ObjId` of a dynamic object
+    Obj = 2,
+}
+
+fn obj_key_int(key: usize, kind: u8) -> Result<i64, FSRError> {
+    if kind == ObjKey::Int as u8 {
+        return Ok(key as i64);
+    }
+    if kind == ObjKey::Obj as u8
+        && let FSRValue::Integer(i) = FSRObject::id_to_obj(key).value
+    {
+        return Ok(i);
+    }
+    Err(FSRError::new("list index must be an integer", FSRErrCode::NotValidArgs))
+}
+
+fn obj_key_str(key: usize, kind: u8) -> Result<&'static str, FSRError> {
+    if kind == ObjKey::Str as u8 {
+        let s = unsafe { &*(key as *const FSRStaticStr) };
+        return std::str::from_utf8(s.as_bytes())
+            .map_err(|_| FSRError::new("attribute name is not utf-8", FSRErrCode::NotValidArgs));
+    }
+    if kind == ObjKey::Obj as u8
+        && let FSRValue::String(s) = &FSRObject::id_to_obj(key).value
+    {
+        return Ok(s.as_str());
+    }
+    Err(FSRError::new("attribute name must be a string", FSRErrCode::NotValidArgs))
+}
+
+fn obj_get(
+    thread: &mut FSRThreadRuntime,
+    obj: ObjId,
+    key: usize,
+    kind: u8,
+) -> Result<ObjId, FSRError> {
+    let container = FSRObject::id_to_obj(obj);
+    match &container.value {
+        FSRValue::List(l) => {
+            let index = obj_key_int(key, kind)?;
+            usize::try_from(index)
+                .ok()
+                .and_then(|i| l.get(i))
+                .map(|v| v.load(Ordering::Relaxed))
+                .ok_or_else(|| {
+                    FSRError::new(
+                        format!("index {} out of range of list of {}", index, l.len()),
+                        FSRErrCode::IndexOutOfRange,
+                    )
+                })
+        }
+        FSRValue::ClassInst(inst) => {
+            let name = obj_key_str(key, kind)?;
+            inst.get_attr(name)
+                .map(|v| v.load(Ordering::Relaxed))
+                .ok_or_else(|| {
+                    FSRError::new(format!("no attribute `{}`", name), FSRErrCode::NoSuchObject)
+                })
+        }
+        FSRValue::Extension(any) => {
+            let Some(map) = any.value.as_any().downcast_ref::<FSRHashMap>() else {
+                return Err(FSRError::new("object has no items", FSRErrCode::NotSupportOperator));
+            };
+            // integer and string keys of static code are boxed for the lookup
+            let key = if kind == ObjKey::Int as u8 {
+                thread
+                    .garbage_collect
+                    .new_object(FSRValue::Integer(key as i64), gid(GlobalObj::IntegerCls))
+            } else if kind == ObjKey::Str as u8 {
+                let value = FSRString::new_value(obj_key_str(key, kind)?);
+                thread
+                    .garbage_collect
+                    .new_object(value, gid(GlobalObj::StringCls))
+            } else {
+                key
+            };
+            Ok(map.get_or_default(key, thread).unwrap_or(FSRObject::none_id()))
+        }
+        _ => Err(FSRError::new("object has no items", FSRErrCode::NotSupportOperator)),
+    }
+}
+
+fn obj_as_int(obj: ObjId) -> Result<i64, FSRError> {
+    match FSRObject::id_to_obj(obj).value {
+        FSRValue::Integer(i) => Ok(i),
+        _ => Err(FSRError::new("object is not an integer", FSRErrCode::NotValidArgs)),
+    }
+}
+
+fn obj_as_float(obj: ObjId) -> Result<f64, FSRError> {
+    match FSRObject::id_to_obj(obj).value {
+        FSRValue::Float(f) => Ok(f),
+        FSRValue::Integer(i) => Ok(i as f64),
+        _ => Err(FSRError::new("object is not a number", FSRErrCode::NotValidArgs)),
+    }
+}
+
+/// Accessors of `Object` in static code, an error waits in the thread and
+/// the static code leaves right after the call
+fn obj_ret<T: Default>(thread: &mut FSRThreadRuntime, res: Result<T, FSRError>) -> T {
+    res.unwrap_or_else(|e| {
+        thread.set_jit_error(e);
+        T::default()
+    })
+}
+
+/// Length of a list, map or string
+pub extern "C" fn c_obj_len(thread: &mut FSRThreadRuntime, obj: ObjId) -> u64 {
+    let no_len = || FSRError::new("object has no length", FSRErrCode::NotSupportOperator);
+    let res = match &FSRObject::id_to_obj(obj).value {
+        FSRValue::List(l) => Ok(l.len() as u64),
+        FSRValue::String(s) => Ok(s.as_str().len() as u64),
+        FSRValue::Extension(any) => any
+            .value
+            .as_any()
+            .downcast_ref::<FSRHashMap>()
+            .map(|map| map.len() as u64)
+            .ok_or_else(no_len),
+        _ => Err(no_len()),
+    };
+    obj_ret(thread, res)
+}
+
+/// Item of a list, value of a map or attribute of a class instance
+pub extern "C" fn c_obj_get(
+    thread: &mut FSRThreadRuntime,
+    obj: ObjId,
+    key: usize,
+    kind: u8,
+) -> ObjId {
+    let res = obj_get(thread, obj, key, kind);
+    obj_ret(thread, res)
+}
+
+pub extern "C" fn c_obj_get_int(
+    thread: &mut FSRThreadRuntime,
+    obj: ObjId,
+    key: usize,
+    kind: u8,
+) -> i64 {
+    let res = obj_get(thread, obj, key, kind).and_then(obj_as_int);
+    obj_ret(thread, res)
+}
+
+pub extern "C" fn c_obj_get_float(
+    thread: &mut FSRThreadRuntime,
+    obj: ObjId,
+    key: usize,
+    kind: u8,
+) -> f64 {
+    let res = obj_get(thread, obj, key, kind).and_then(obj_as_float);
+    obj_ret(thread, res)
+}
+
+pub extern "C" fn c_obj_as_int(thread: &mut FSRThreadRuntime, obj: ObjId) -> i64 {
+    obj_ret(thread, obj_as_int(obj))
+}
+
+pub extern "C" fn c_obj_as_float(thread: &mut FSRThreadRuntime, obj: ObjId) -> f64 {
+    obj_ret(thread, obj_as_float(obj))
+}
+
+/// Entry arg of type `Object`, the args of an entry call stay in its frame
+/// so a collection in the static code keeps them
+pub extern "C" fn c_entry_obj_arg(thread: &mut FSRThreadRuntime, index: i32) -> ObjId {
+    let frame = thread.get_cur_frame();
+    frame.middle_value.get(index as usize).copied().unwrap_or(0)
+}
+
+/// Safepoint of static code, every `Object` is reachable from the rooted
+/// entry args since static code does not change dynamic objects
+pub extern "C" fn c_jit_safepoint(thread: &mut FSRThreadRuntime) {
+    if check_gc(thread) {
+        gc_collect(thread, [].as_ptr(), 0);
+    }
+}
+
 pub extern "C" fn ret_process(
     thread_runtime: &mut FSRThreadRuntime,
     ptr: usize,
     var_type: &FSRSType
 ) -> usize {
-    match var_type {
-        FSRSType::IInt64 => {
-            let ret_ptr = ptr as *const i64;
-            let v = unsafe { *ret_ptr as usize };
-            // let v = FSRVM::leak_object(Box::new(FSRInteger::new_inst(v as i64)));
-            let v = thread_runtime.garbage_collect.new_object(
-                FSRValue::Integer(v as i64),
-                gid(GlobalObj::IntegerCls)
-            );
-            v
-        },
-        FSRSType::UInt8 => {
-            let ret_ptr = ptr as *const u8;
-            let v = unsafe { *ret_ptr as u8 };
-            // let v = FSRValue::Integer(v as i64);
-            // let v = FSRVM::leak_object(Box::new(FSRInteger::new_inst(v as i64)));
-            let v = thread_runtime.garbage_collect.new_object(
-                FSRValue::Integer(v as i64),
-                gid(GlobalObj::IntegerCls)
-            );
-            v
-        },
-        FSRSType::UInt32 => {
-            let ret_ptr = ptr as *const u32;
-            let v = unsafe { *ret_ptr as u32 };
-            // let v = FSRValue::Integer(v as i64);
-            // let v = FSRVM::leak_object(Box::new(FSRInteger::new_inst(v as i64)));
-            let v = thread_runtime.garbage_collect.new_object(
-                FSRValue::Integer(v as i64),
-                gid(GlobalObj::IntegerCls)
-            );
-            v
-        },
-        FSRSType::UInt64 => {
-            let ret_ptr = ptr as *const u64;
-            let v = unsafe { *ret_ptr as u64 };
-            // let v = FSRValue::Integer(v as i64);
-            // let v = FSRVM::leak_object(Box::new(FSRInteger::new_inst(v as i64)));
-            let v = thread_runtime.garbage_collect.new_object(
-                FSRValue::Integer(v as i64),
-                gid(GlobalObj::IntegerCls)
-            );
-            v
-        },
+    macro_rules! read_ret {
+        ($t:ty) => {
+            unsafe { *(ptr as *const $t) }
+        };
+    }
+
+    let value = match var_type {
+        FSRSType::IInt8 => FSRValue::Integer(read_ret!(i8) as i64),
+        FSRSType::IInt16 => FSRValue::Integer(read_ret!(i16) as i64),
+        FSRSType::IInt32 => FSRValue::Integer(read_ret!(i32) as i64),
+        FSRSType::IInt64 => FSRValue::Integer(read_ret!(i64)),
+        FSRSType::UInt8 => FSRValue::Integer(read_ret!(u8) as i64),
+        FSRSType::UInt16 => FSRValue::Integer(read_ret!(u16) as i64),
+        FSRSType::UInt32 => FSRValue::Integer(read_ret!(u32) as i64),
+        // values above i64::MAX wrap, integers are i64 in the vm
+        FSRSType::UInt64 => FSRValue::Integer(read_ret!(u64) as i64),
+        FSRSType::Float32 => FSRValue::Float(read_ret!(f32) as f64),
+        FSRSType::Float64 => FSRValue::Float(read_ret!(f64)),
+        FSRSType::String => {
+            let s = read_ret!(FSRStaticStr);
+            let value = FSRString::new_value(String::from_utf8_lossy(s.as_bytes()));
+            return thread_runtime
+                .garbage_collect
+                .new_object(value, gid(GlobalObj::StringCls));
+        }
+        FSRSType::Object => return read_ret!(ObjId),
+        FSRSType::Bool => {
+            return if read_ret!(u8) != 0 {
+                FSRObject::true_id()
+            } else {
+                FSRObject::false_id()
+            };
+        }
         _ => {
             unimplemented!("ret_process not support for {:?}", var_type);
         }
+    };
+
+    let cls = if let FSRValue::Float(_) = value {
+        gid(GlobalObj::FloatCls)
+    } else {
+        gid(GlobalObj::IntegerCls)
+    };
+    thread_runtime.garbage_collect.new_object(value, cls)
+}
+/// Baseline helpers return 0 on error, the error waits in the thread until the
+/// baseline code returns to `baseline_call`
+fn baseline_ret(thread: &mut FSRThreadRuntime, res: Result<ObjId, FSRError>) -> ObjId {
+    res.unwrap_or_else(|e| {
+        thread.set_jit_error(e);
+        0
+    })
+}
+
+pub extern "C" fn c_load_var(var: &LocalVar, thread: &mut FSRThreadRuntime) -> ObjId {
+    if let Some(id) = thread.get_cur_frame().get_var(&var.id) {
+        return id.get();
+    }
+
+    let res = FSRThreadRuntime::get_chains(thread, thread.get_cur_frame(), var).ok_or_else(|| {
+        FSRError::new(
+            format!("Variable '{}' not found", var.name),
+            FSRErrCode::NoSuchObject,
+        )
+    });
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_store_var(var: &LocalVar, value: ObjId, thread: &mut FSRThreadRuntime) -> ObjId {
+    thread.get_cur_mut_frame().insert_var(var.id, value);
+    value
+}
+
+/// `var op= value`, the operand types are recorded for the specialized tier
+/// Box an integer computed by specialized code, small values are shared
+pub extern "C" fn c_box_integer(value: i64, thread: &mut FSRThreadRuntime) -> ObjId {
+    thread.garbage_collect.get_integer(value)
+}
+
+pub extern "C" fn c_op_assign_var(
+    var: &LocalVar,
+    value: ObjId,
+    feedback: &TypeFeedback,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let left = match thread.get_cur_frame().get_var(&var.id) {
+        Some(s) => Some(s.get()),
+        None => FSRThreadRuntime::get_chain_by_name(thread, &var.name),
+    };
+    let res = match (left, var.op_assign) {
+        (Some(left), Some(op_assign)) => {
+            feedback.record(left, value);
+            FSRThreadRuntime::op_assign_helper(left, value, thread, op_assign.get_offset())
+        }
+        _ => Err(FSRError::new(
+            format!("variable `{}` not found for op assign", var.name),
+            FSRErrCode::NoSuchObject,
+        )),
+    };
+
+    let value = baseline_ret(thread, res);
+    if value != 0 {
+        thread.get_cur_mut_frame().insert_var(var.id, value);
+    }
+    value
+}
+
+pub extern "C" fn c_assign_arg(id: u64, thread: &mut FSRThreadRuntime) -> ObjId {
+    let frame = thread.get_cur_mut_frame();
+    let res = frame.args.pop().ok_or_else(|| {
+        FSRError::new(
+            "Failed to pop argument from stack in assign_args",
+            FSRErrCode::EmptyExpStack,
+        )
+    });
+    if let Ok(v) = res {
+        frame.insert_var(id, v);
+    }
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_load_global(name: &String, thread: &mut FSRThreadRuntime) -> ObjId {
+    let res = FSRThreadRuntime::get_chain_by_name(thread, name).ok_or_else(|| {
+        FSRError::new(
+            format!("Global variable '{}' not found", name),
+            FSRErrCode::NoSuchObject,
+        )
+    });
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_load_closure(
+    var: &(u64, String, Option<OpAssign>),
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let res = thread.fetch_closure_variable(var);
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_binary_op(
+    left: ObjId,
+    right: ObjId,
+    op: FastAttr,
+    feedback: &TypeFeedback,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    feedback.record(left, right);
+    let args = [left, right];
+    let res = match obj_cls!(left).get_rust_fn(op) {
+        Some(rust_fn) => rust_fn(args.as_ptr(), args.len(), thread),
+        None => FSRObject::invoke_offset_method(op, &args, thread),
+    };
+    baseline_ret(thread, res.map(|x| x.get_id()))
+}
+
+pub extern "C" fn c_compare(
+    left: ObjId,
+    right: ObjId,
+    op: i64,
+    feedback: &TypeFeedback,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    feedback.record(left, right);
+    let res = CompareOperator::try_from(op as u8)
+        .and_then(|op| FSRThreadRuntime::compare(&[left, right], op, thread))
+        .map(|x| {
+            if x {
+                FSRObject::true_id()
+            } else {
+                FSRObject::false_id()
+            }
+        });
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_getter(container: ObjId, index: ObjId, thread: &mut FSRThreadRuntime) -> ObjId {
+    let res = FSRObject::invoke_offset_method(FastAttr::GetItem, &[container, index], thread);
+    baseline_ret(thread, res.map(|x| x.get_id()))
+}
+
+pub extern "C" fn c_range(left: ObjId, right: ObjId, thread: &mut FSRThreadRuntime) -> ObjId {
+    let (FSRValue::Integer(start), FSRValue::Integer(end)) = (
+        &FSRObject::id_to_obj(left).value,
+        &FSRObject::id_to_obj(right).value,
+    ) else {
+        let err = FSRError::new("range bounds are not integer", FSRErrCode::NotValidArgs);
+        return baseline_ret(thread, Err(err));
+    };
+
+    let range = FSRRange {
+        range: Range {
+            start: *start,
+            end: *end,
+        },
+    };
+    thread
+        .garbage_collect
+        .new_object(FSRValue::Range(Box::new(range)), gid(GlobalObj::RangeCls))
+}
+
+pub extern "C" fn c_type_guard(
+    guard: &(u64, String, String),
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let res = thread.check_type_guard(guard).map(|_| FSRObject::none_id());
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_call(
+    fn_id: ObjId,
+    args: *const ObjId,
+    len: usize,
+    ret_type: &Option<Arc<FnCallSig>>,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let args = to_rs_list!(args, len);
+    let res = thread.call_obj(fn_id, args, ret_type);
+    baseline_ret(thread, res)
+}
+
+/// `father.name`, class attributes are found through the inline cache of the
+/// operator like in the interpreter
+pub extern "C" fn c_get_attr(
+    father: ObjId,
+    attr: &AttrVar,
+    cache: &AttrInlineCache,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let res = FSRObject::id_to_obj(father)
+        .get_attr_cached(&attr.name, attr.is_method, || Some(cache))
+        .map(|x| x.load(Ordering::Relaxed))
+        .ok_or_else(|| {
+            FSRError::new(
+                format!("not have this attr: `{}`", attr.name),
+                FSRErrCode::NoSuchObject,
+            )
+        });
+    baseline_ret(thread, res)
+}
+
+pub extern "C" fn c_call_method(
+    father: ObjId,
+    args: *const ObjId,
+    len: usize,
+    name: &String,
+    cache: &AttrInlineCache,
+    thread: &mut FSRThreadRuntime,
+) -> ObjId {
+    let args = to_rs_list!(args, len);
+    let res = FSRObject::id_to_obj(father)
+        .get_attr_cached(name, true, || Some(cache))
+        .map(|x| x.load(Ordering::Relaxed))
+        .ok_or_else(|| {
+            FSRError::new(
+                format!("not found method: {}", name),
+                FSRErrCode::NoSuchObject,
+            )
+        })
+        .and_then(|method| thread.call_method_obj(method, father, args));
+    baseline_ret(thread, res)
+}
+
+/// Start a for loop, the iterator is kept in the frame so the gc can see it
+pub extern "C" fn c_for_iter(obj: ObjId, thread: &mut FSRThreadRuntime) -> ObjId {
+    let res = match FSRObject::id_to_obj(obj).get_cls_offset_attr(FastAttr::Iterator) {
+        Some(s) => {
+            let iter_fn = FSRObject::id_to_obj(s.load(Ordering::Relaxed));
+            iter_fn.call(&[obj], thread).map(|x| x.get_id())
+        }
+        None => Ok(obj),
+    };
+
+    if let Ok(iter) = res {
+        let tracker = &mut thread.get_cur_mut_frame().flow_tracker;
+        tracker.for_iter_obj.push(iter);
+        tracker.ref_for_obj.push(obj);
+    }
+    baseline_ret(thread, res)
+}
+
+/// Next object of the innermost for loop, `none` when the loop is done
+pub extern "C" fn c_for_next(thread: &mut FSRThreadRuntime) -> ObjId {
+    let obj = thread.last_for_iter_obj();
+    let res = if FSRObject::id_to_obj(obj).cls
+        == FSRObject::id_to_obj(gid(GlobalObj::InnerIterator)).as_class()
+    {
+        next_obj([obj].as_ptr(), 1, thread)
+    } else {
+        FSRObject::invoke_offset_method(FastAttr::NextObject, &[obj], thread)
+    };
+    baseline_ret(thread, res.map(|x| x.get_id()))
+}
+
+pub extern "C" fn c_for_end(thread: &mut FSRThreadRuntime) {
+    let tracker = &mut thread.get_cur_mut_frame().flow_tracker;
+    tracker.for_iter_obj.pop();
+    tracker.ref_for_obj.pop();
+}
+
+/// Collect garbage between two statements of baseline code like the
+/// interpreter does after each line
+pub extern "C" fn c_safepoint(thread: &mut FSRThreadRuntime) {
+    let frame = thread.get_cur_mut_frame();
+    frame.clear_exp();
+    frame.middle_value.clear();
+    if thread.garbage_collect.will_collect() {
+        thread.collect_action();
     }
-}
\ No newline at end of file
+}
+
+/// A guard of specialized code failed. The values of the expression stack
+/// were saved to the frame, rebuild the loop and `if` state the interpreter
+/// keeps for the point of the failed operator so it can go on from there.
+pub extern "C" fn c_deopt(point: &DeoptPoint, thread: &mut FSRThreadRuntime) {
+    let frame = thread.get_cur_mut_frame();
+    frame.ip = point.ip;
+    let tracker = &mut frame.flow_tracker;
+    tracker.last_if_test.extend_from_slice(&point.if_tests);
+    for (start_line, break_line) in point.loops.iter() {
+        tracker.loop_start_line.push(*start_line);
+        tracker.break_line.push(*break_line);
+    }
+
+    let fn_id = frame.fn_id;
+    FSRObject::id_to_obj(fn_id).as_fn().jit_info.deopt();
+}
+
+/// OSR code left its loop, pop the loop state the interpreter pushed when it
+/// entered the loop and go on after it
+pub extern "C" fn c_osr_exit(break_line: usize, thread: &mut FSRThreadRuntime) {
+    let frame = thread.get_cur_mut_frame();
+    frame.clear_exp();
+    frame.ip = (break_line, 0);
+    let tracker = &mut frame.flow_tracker;
+    tracker.loop_start_line.pop();
+    tracker.break_line.pop();
+}
+
+/// `return` in OSR code, the frame was pushed by the interpreter so it is
+/// popped the way `ReturnValue` does
+pub extern "C" fn c_osr_return(value: ObjId, thread: &mut FSRThreadRuntime) -> ObjId {
+    thread.get_cur_mut_frame().push_exp(value);
+    let res = FSRThreadRuntime::ret_value(thread).map(|_| value);
+    baseline_ret(thread, res)
+}
@@ -1,5 +1,12 @@
+pub mod aot;
+pub mod baseline;
+pub mod cache;
+pub mod checked;
 pub mod cranelift;
+pub mod debug;
+pub mod ffi;
 pub mod jit_wrapper;
+pub mod simd;
 use crate::backend::types::code::FSRCode;
 
 use super::bytecode::{Bytecode, BytecodeArg, BytecodeOperator};
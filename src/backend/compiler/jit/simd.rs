@@ -0,0 +1,49 @@
+// Vector arithmetic is lowered to cranelift vector instructions when the host
+// has them (SSE4.1, NEON), otherwise and with `--no-simd` lane by lane
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use cranelift::codegen::isa::TargetIsa;
+
+static SIMD: AtomicBool = AtomicBool::new(true);
+
+/// Use vector instructions for the static code compiled from now on
+pub fn set_simd(simd: bool) {
+    SIMD.store(simd, Ordering::Relaxed);
+}
+
+/// Vector instructions are allowed and `isa` supports them
+pub fn simd_enabled(isa: &dyn TargetIsa) -> bool {
+    if !SIMD.load(Ordering::Relaxed) {
+        return false;
+    }
+
+    match isa.name() {
+        "aarch64" => true,
+        "x64" => isa
+            .isa_flags()
+            .iter()
+            .any(|flag| flag.name == "has_sse41" && flag.as_bool() == Some(true)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cranelift::prelude::settings;
+
+    use super::{set_simd, simd_enabled};
+
+    #[test]
+    fn test_set_simd() {
+        let isa = cranelift_native::builder()
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()))
+            .unwrap();
+        set_simd(false);
+        assert!(!simd_enabled(isa.as_ref()));
+        set_simd(true);
+        if isa.name() == "aarch64" {
+            assert!(simd_enabled(isa.as_ref()));
+        }
+    }
+}
@@ -1,4 +1,7 @@
 pub mod bytecode;
 pub mod jit;
 pub mod optimizer;
+pub mod resolver;
+pub mod type_checker;
+pub mod verifier;
 pub mod xstruct;
\ No newline at end of file
@@ -0,0 +1,592 @@
+// Names are checked against the scopes they could be found in at runtime,
+// unknown names are errors and the other findings warnings
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use frontend::ast::token::{
+    assign::FSRAssign,
+    base::{FSRPosition, FSRToken},
+    block::FSRBlock,
+    constant::FSRConstType,
+    expr::FSRExpr,
+    function_def::FSRFnDef,
+    module::FSRModuleFrontEnd,
+};
+
+use crate::backend::compiler::bytecode::{FSRByteInfo, FSRPos, NUMBER_TYPES, VECTOR_TYPES};
+
+/// Names handled by the compiler itself, never looked up at runtime.
+const KEYWORDS: [&str; 4] = ["true", "false", "none", "uninit"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResolveLevel {
+    Warning,
+    Error,
+}
+
+impl Display for ResolveLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveLevel::Warning => write!(f, "warning"),
+            ResolveLevel::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolveDiagnostic {
+    pub level: ResolveLevel,
+    pub pos: FSRPos,
+    pub msg: String,
+}
+
+impl Display for ResolveDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.pos.line + 1,
+            self.pos.column,
+            self.level,
+            self.msg
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    Function,
+    /// Class body, its names become class attributes and are not visible
+    /// from the methods
+    Class,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindKind {
+    /// Plain assignment, the only kind reported when unused
+    Local,
+    Arg,
+    Other,
+}
+
+#[derive(Debug)]
+struct Binding {
+    pos: FSRPosition,
+    kind: BindKind,
+    used: bool,
+}
+
+#[derive(Debug)]
+struct Scope {
+    kind: ScopeKind,
+    bindings: HashMap<String, Binding>,
+    /// Keep declaration order, so diagnostics are stable
+    order: Vec<String>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind) -> Self {
+        Self {
+            kind,
+            bindings: HashMap::new(),
+            order: vec![],
+        }
+    }
+}
+
+pub struct NameResolver<'a> {
+    lines: &'a [usize],
+    builtins: &'a HashSet<String>,
+    scopes: Vec<Scope>,
+    diagnostics: Vec<ResolveDiagnostic>,
+}
+
+impl<'a> NameResolver<'a> {
+    pub fn new(lines: &'a [usize], builtins: &'a HashSet<String>) -> Self {
+        Self {
+            lines,
+            builtins,
+            scopes: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    /// Resolve a whole module, diagnostics are sorted by position.
+    pub fn resolve(
+        module: &FSRModuleFrontEnd,
+        lines: &'a [usize],
+        builtins: &'a HashSet<String>,
+    ) -> Vec<ResolveDiagnostic> {
+        let mut resolver = Self::new(lines, builtins);
+        resolver.scopes.push(Scope::new(ScopeKind::Module));
+        for token in &module.tokens {
+            resolver.collect(token);
+        }
+        resolver.walk_tokens(&module.tokens);
+        resolver.scopes.pop();
+
+        resolver
+            .diagnostics
+            .sort_by_key(|x| (x.pos.line, x.pos.column));
+        resolver.diagnostics
+    }
+
+    pub fn has_error(diagnostics: &[ResolveDiagnostic]) -> bool {
+        diagnostics.iter().any(|x| x.level == ResolveLevel::Error)
+    }
+
+    fn report(&mut self, level: ResolveLevel, meta: &FSRPosition, msg: impl Into<String>) {
+        let info = FSRByteInfo::new(self.lines, meta.clone());
+        self.diagnostics.push(ResolveDiagnostic {
+            level,
+            pos: info.get_pos().clone(),
+            msg: msg.into(),
+        });
+    }
+
+    fn bind(&mut self, name: &str, meta: &FSRPosition, kind: BindKind) {
+        let scope = self.scopes.last_mut().unwrap();
+        if scope.bindings.contains_key(name) {
+            return;
+        }
+
+        scope.order.push(name.to_string());
+        scope.bindings.insert(
+            name.to_string(),
+            Binding {
+                pos: meta.clone(),
+                kind,
+                used: false,
+            },
+        );
+
+        if self.builtins.contains(name) {
+            self.report(
+                ResolveLevel::Warning,
+                meta,
+                format!("`{}` shadows a builtin", name),
+            );
+        }
+    }
+
+    /// Register every name a scope defines before any use is checked, names
+    /// are visible in the whole scope like the runtime lookup does.
+    fn collect(&mut self, token: &FSRToken) {
+        match token {
+            FSRToken::Assign(assign) => {
+                if let FSRToken::Variable(v) = assign.get_left().as_ref()
+                    && assign.op_assign == "="
+                    && !self.is_closure_assign(v.get_name(), assign.get_assign_expr())
+                {
+                    self.bind(v.get_name(), v.get_meta(), BindKind::Local);
+                }
+            }
+            FSRToken::FunctionDef(fn_def) if !fn_def.is_lambda() => {
+                self.bind(fn_def.get_name(), fn_def.get_meta(), BindKind::Other);
+            }
+            FSRToken::Class(cls) => {
+                self.bind(cls.get_name(), cls.get_meta(), BindKind::Other);
+            }
+            FSRToken::Struct(st) => {
+                self.bind(st.get_name(), st.get_meta(), BindKind::Other);
+            }
+            FSRToken::Import(import) => {
+                if let Some(name) = import.module_name.last() {
+                    self.bind(name, import.get_meta(), BindKind::Other);
+                }
+            }
+            FSRToken::ForBlock(for_block) => {
+                self.bind(
+                    for_block.get_var_name(),
+                    for_block.get_meta(),
+                    BindKind::Other,
+                );
+                self.collect_block(for_block.get_block());
+            }
+            FSRToken::IfExp(if_exp) => {
+                self.collect_block(if_exp.get_block());
+                if let Some(elses) = if_exp.get_elses() {
+                    for e in elses.get_elses() {
+                        self.collect_block(e.get_block());
+                    }
+                }
+            }
+            FSRToken::WhileExp(while_exp) => self.collect_block(while_exp.get_block()),
+            FSRToken::TryBlock(try_block) => {
+                self.collect_block(try_block.get_block());
+                self.collect_block(&try_block.get_catch().body);
+            }
+            FSRToken::Block(block) => self.collect_block(block),
+            _ => {}
+        }
+    }
+
+    /// `a = a + 1` in a nested function updates the cell of the outer `a`
+    fn is_closure_assign(&self, name: &str, expr: &FSRToken) -> bool {
+        let (cur, outer) = self.scopes.split_last().unwrap();
+        cur.kind == ScopeKind::Function
+            && !cur.bindings.contains_key(name)
+            && Self::reads_name(expr, name)
+            && outer
+                .iter()
+                .any(|x| x.kind != ScopeKind::Class && x.bindings.contains_key(name))
+    }
+
+    fn reads_name(token: &FSRToken, name: &str) -> bool {
+        match token {
+            FSRToken::Variable(v) => v.get_name() == name,
+            FSRToken::Expr(expr) => {
+                Self::reads_name(expr.get_left(), name) || Self::reads_name(expr.get_right(), name)
+            }
+            FSRToken::StackExpr((_, tokens)) => tokens.iter().any(|x| Self::reads_name(x, name)),
+            FSRToken::Call(call) => {
+                call.get_name() == name || call.get_args().iter().any(|x| Self::reads_name(x, name))
+            }
+            FSRToken::Getter(getter) => {
+                getter.get_name() == name || Self::reads_name(getter.get_getter(), name)
+            }
+            FSRToken::List(list) => list.get_items().iter().any(|x| Self::reads_name(x, name)),
+            _ => false,
+        }
+    }
+
+    fn collect_block(&mut self, block: &FSRBlock) {
+        for token in block.get_tokens() {
+            self.collect(token);
+        }
+    }
+
+    fn lookup(&mut self, name: &str, meta: &FSRPosition) {
+        if KEYWORDS.contains(&name) {
+            return;
+        }
+
+        let last = self.scopes.len() - 1;
+        for (i, scope) in self.scopes.iter_mut().enumerate().rev() {
+            if scope.kind == ScopeKind::Class && i != last {
+                continue;
+            }
+
+            if let Some(binding) = scope.bindings.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+
+        // `Pair[u8, u64]` or `f32x4(0.5)` name static types in expressions
+        if self.builtins.contains(name)
+            || NUMBER_TYPES.contains(&name)
+            || VECTOR_TYPES.contains(&name)
+        {
+            return;
+        }
+
+        self.report(
+            ResolveLevel::Error,
+            meta,
+            format!("undefined name `{}`", name),
+        );
+    }
+
+    fn walk_tokens(&mut self, tokens: &[FSRToken]) {
+        let mut terminated = false;
+        for token in tokens {
+            if let FSRToken::EmptyExpr(_) = token {
+                continue;
+            }
+
+            if terminated {
+                self.report(ResolveLevel::Warning, token.get_meta(), "unreachable code");
+                // one warning per block is enough
+                terminated = false;
+                self.walk(token);
+                break;
+            }
+
+            self.walk(token);
+            terminated = matches!(
+                token,
+                FSRToken::Return(_) | FSRToken::Break(_) | FSRToken::Continue(_)
+            );
+        }
+    }
+
+    fn walk_block(&mut self, block: &FSRBlock) {
+        self.walk_tokens(block.get_tokens());
+    }
+
+    fn walk(&mut self, token: &FSRToken) {
+        match token {
+            FSRToken::FunctionDef(fn_def) => self.walk_fn(fn_def),
+            FSRToken::IfExp(if_exp) => {
+                self.walk(if_exp.get_test());
+                self.walk_block(if_exp.get_block());
+                if let Some(elses) = if_exp.get_elses() {
+                    for e in elses.get_elses() {
+                        if let Some(test) = e.get_test() {
+                            self.walk(test);
+                        }
+                        self.walk_block(e.get_block());
+                    }
+                }
+            }
+            FSRToken::Constant(c) => {
+                if let FSRConstType::FormatString(format_string) = c.get_const_type() {
+                    for arg in &format_string.arg_expr {
+                        self.walk(&arg.expr);
+                    }
+                }
+            }
+            FSRToken::Assign(assign) => self.walk_assign(assign),
+            FSRToken::Expr(expr) => self.walk_expr(expr),
+            FSRToken::StackExpr((_, tokens)) => {
+                for token in tokens {
+                    self.walk(token);
+                }
+            }
+            FSRToken::ForBlock(for_block) => {
+                self.walk(for_block.get_expr());
+                self.walk_block(for_block.get_block());
+            }
+            FSRToken::Call(call) => {
+                if !call.get_name().is_empty() {
+                    self.lookup(call.get_name(), call.get_meta());
+                }
+                for arg in call.get_args() {
+                    self.walk(arg);
+                }
+            }
+            FSRToken::Variable(v) => self.lookup(v.get_name(), v.get_meta()),
+            FSRToken::Return(ret) => self.walk(ret.get_return_expr()),
+            FSRToken::Defer(defer) => self.walk(defer.get_defer_expr()),
+            FSRToken::Block(block) => self.walk_block(block),
+            FSRToken::WhileExp(while_exp) => {
+                self.walk(while_exp.get_test());
+                self.walk_block(while_exp.get_block());
+            }
+            FSRToken::List(list) => {
+                for item in list.get_items() {
+                    self.walk(item);
+                }
+            }
+            FSRToken::Class(cls) => {
+                self.scopes.push(Scope::new(ScopeKind::Class));
+                self.collect_block(cls.get_block());
+                self.walk_block(cls.get_block());
+                self.scopes.pop();
+            }
+            FSRToken::Getter(getter) => {
+                if !getter.is_unnamed() {
+                    self.lookup(getter.get_name(), getter.get_meta());
+                }
+                self.walk(getter.get_getter());
+            }
+            FSRToken::TryBlock(try_block) => {
+                self.walk_block(try_block.get_block());
+                self.walk_block(&try_block.get_catch().body);
+            }
+            FSRToken::Struct(st) => {
+                // type parameters are visible in the methods
+                self.scopes.push(Scope::new(ScopeKind::Function));
+                for name in st.get_generics() {
+                    self.bind(name, st.get_meta(), BindKind::Other);
+                }
+                // fields are declarations, only methods have code in them
+                for token in st.get_block().get_tokens() {
+                    if let FSRToken::FunctionDef(fn_def) = token {
+                        self.walk_fn(fn_def);
+                    }
+                }
+                self.scopes.pop();
+            }
+            FSRToken::Module(_)
+            | FSRToken::Import(_)
+            | FSRToken::Break(_)
+            | FSRToken::Continue(_)
+            | FSRToken::EmptyExpr(_)
+            | FSRToken::None => {}
+        }
+    }
+
+    /// Right side of `.` / `::` is an attribute, not a name
+    fn walk_attr(&mut self, token: &FSRToken) {
+        match token {
+            FSRToken::Variable(_) => {}
+            FSRToken::Call(call) => {
+                for arg in call.get_args() {
+                    self.walk(arg);
+                }
+            }
+            FSRToken::Getter(getter) => self.walk(getter.get_getter()),
+            FSRToken::StackExpr((_, tokens)) => {
+                if let Some((first, rest)) = tokens.split_first() {
+                    self.walk_attr(first);
+                    for token in rest {
+                        self.walk(token);
+                    }
+                }
+            }
+            _ => self.walk(token),
+        }
+    }
+
+    fn walk_expr(&mut self, expr: &FSRExpr) {
+        self.walk(expr.get_left());
+        if expr.get_op() == "." || expr.get_op() == "::" {
+            self.walk_attr(expr.get_right());
+        } else if expr.get_op() == "as" {
+            // the right side of a cast is a type name
+        } else {
+            self.walk(expr.get_right());
+        }
+    }
+
+    fn walk_assign(&mut self, assign: &FSRAssign) {
+        self.walk(assign.get_assign_expr());
+        match assign.get_left().as_ref() {
+            FSRToken::Variable(v) => {
+                // `a += 1` reads `a` first
+                if assign.op_assign != "=" {
+                    self.lookup(v.get_name(), v.get_meta());
+                }
+            }
+            left => self.walk(left),
+        }
+    }
+
+    fn walk_fn(&mut self, fn_def: &FSRFnDef) {
+        self.scopes.push(Scope::new(ScopeKind::Function));
+        for name in &fn_def.generics {
+            self.bind(name, fn_def.get_meta(), BindKind::Other);
+        }
+        for arg in fn_def.get_args() {
+            if let FSRToken::Variable(v) = arg {
+                self.bind(v.get_name(), v.get_meta(), BindKind::Arg);
+            }
+        }
+
+        self.collect_block(fn_def.get_body());
+        self.walk_block(fn_def.get_body());
+
+        let scope = self.scopes.pop().unwrap();
+        for name in &scope.order {
+            let binding = &scope.bindings[name];
+            if binding.kind == BindKind::Local && !binding.used && !name.starts_with('_') {
+                self.report(
+                    ResolveLevel::Warning,
+                    &binding.pos,
+                    format!("unused local `{}`", name),
+                );
+            }
+        }
+    }
+}
+
+#[allow(unused)]
+mod test {
+    use std::collections::HashSet;
+
+    use frontend::ast::token::{base::FSRPosition, module::FSRModuleFrontEnd};
+
+    use super::{NameResolver, ResolveDiagnostic, ResolveLevel};
+
+    fn resolve(code: &str) -> Vec<ResolveDiagnostic> {
+        let builtins = ["println", "assert", "len"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<HashSet<_>>();
+        let chars = code.chars().collect::<Vec<char>>();
+        let (module, lines) = FSRModuleFrontEnd::parse(&chars, FSRPosition::new()).unwrap();
+        NameResolver::resolve(&module, &lines, &builtins)
+    }
+
+    #[test]
+    fn test_resolve_undefined() {
+        let v = resolve(
+            "
+fn abc(a) {
+    b = a + c
+    return b
+}
+
+println(abc(1).value)
+",
+        );
+        assert_eq!(v.len(), 1, "{:?}", v);
+        assert_eq!(v[0].level, ResolveLevel::Error);
+        assert!(v[0].msg.contains("`c`"));
+        assert_eq!(v[0].pos.line, 2);
+        assert!(NameResolver::has_error(&v));
+    }
+
+    #[test]
+    fn test_resolve_warnings() {
+        let v = resolve(
+            "
+fn abc(println) {
+    unused = 1
+    return println
+    assert(false)
+}
+
+x = 1
+fn ddc() {
+    y = 2
+    fn inner() {
+        return x + y
+    }
+    return inner
+}
+
+fn counter() {
+    count = 0
+    fn inc() {
+        count = count + 1
+        return count
+    }
+    return inc
+}
+",
+        );
+        let msgs = v.iter().map(|x| x.msg.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            msgs,
+            vec![
+                "`println` shadows a builtin",
+                "unused local `unused`",
+                "unreachable code",
+            ]
+        );
+        assert!(!NameResolver::has_error(&v));
+    }
+
+    #[test]
+    fn test_resolve_generics() {
+        let v = resolve(
+            "
+struct Pair[A, B] {
+    first: A
+    second: B
+
+    fn make(first: A, second: B) -> Ptr[Pair[A, B]] {
+        p: Ptr[Pair[A, B]] = Pair[A, B].alloc
+        return p
+    }
+}
+
+fn first_of[T](p: T) -> T {
+    q = Pair[T, u64].make(p, 1)
+    return q.first
+}
+
+println(Pair[u8, C].make(1, 2))
+println(f32x4(0.5))
+",
+        );
+        assert_eq!(v.len(), 1, "{:?}", v);
+        assert!(v[0].msg.contains("`C`"));
+    }
+}
@@ -0,0 +1,773 @@
+// Gradual type checking: a hint naming a class is checked, anything else is
+// `Any`. `@static` / `@jit` functions have their own type system. It only runs
+// under `--check`, a normal run only checks the hinted args of `@guard` fns.
+
+use std::{collections::HashMap, fmt::Display};
+
+use frontend::ast::token::{
+    assign::FSRAssign,
+    base::{FSRPosition, FSRToken, FSRTypeName},
+    block::FSRBlock,
+    call::FSRCall,
+    constant::{FSRConstType, FSRConstantType},
+    expr::FSRExpr,
+    function_def::FSRFnDef,
+    module::FSRModuleFrontEnd,
+};
+
+use crate::backend::compiler::{
+    bytecode::FSRByteInfo,
+    resolver::{ResolveDiagnostic, ResolveLevel},
+};
+
+/// Class names a hint can check without a class definition in the module,
+/// also the set runtime guards trust without looking the name up.
+pub const BUILTIN_TYPES: [&str; 12] = [
+    "Integer",
+    "Float",
+    "String",
+    "Bool",
+    "List",
+    "None",
+    "Fn",
+    "HashMap",
+    "HashSet",
+    "Bytes",
+    "Range",
+    "Exception",
+];
+
+/// Runtime class name of a hint, `Function` is spelled `Fn` at runtime.
+pub fn hint_class_name(hint: &FSRTypeName) -> &str {
+    match hint.name.as_str() {
+        "Function" => "Fn",
+        name => name,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HintType {
+    Any,
+    Class(String),
+}
+
+impl HintType {
+    fn class(name: &str) -> Self {
+        HintType::Class(name.to_string())
+    }
+
+    fn is_class(&self, name: &str) -> bool {
+        matches!(self, HintType::Class(s) if s == name)
+    }
+
+    fn accepts(&self, other: &HintType) -> bool {
+        match (self, other) {
+            (HintType::Class(a), HintType::Class(b)) => a == b,
+            _ => true,
+        }
+    }
+
+    fn join(&self, other: &HintType) -> HintType {
+        if self == other {
+            self.clone()
+        } else {
+            HintType::Any
+        }
+    }
+}
+
+impl Display for HintType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HintType::Any => write!(f, "Any"),
+            HintType::Class(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FnSig {
+    params: Vec<(String, HintType)>,
+    ret: HintType,
+}
+
+/// Type of every name at the current point of one scope
+type TypeEnv = HashMap<String, HintType>;
+
+#[derive(Debug, Default)]
+struct TypeScope {
+    /// `x: T` fixes the type of `x` for the whole scope
+    declared: HashMap<String, HintType>,
+    inferred: TypeEnv,
+    fns: HashMap<String, FnSig>,
+    is_class: bool,
+}
+
+pub struct TypeChecker<'a> {
+    lines: &'a [usize],
+    /// Classes defined anywhere in the module, constructor signature if it
+    /// has a `__new__`
+    classes: HashMap<String, Option<FnSig>>,
+    scopes: Vec<TypeScope>,
+    /// Declared return type of each function being checked
+    ret_types: Vec<(String, HintType)>,
+    diagnostics: Vec<ResolveDiagnostic>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(lines: &'a [usize]) -> Self {
+        Self {
+            lines,
+            classes: HashMap::new(),
+            scopes: vec![],
+            ret_types: vec![],
+            diagnostics: vec![],
+        }
+    }
+
+    /// Check a whole module, diagnostics are sorted by position.
+    pub fn check(module: &FSRModuleFrontEnd, lines: &'a [usize]) -> Vec<ResolveDiagnostic> {
+        let mut checker = Self::new(lines);
+        checker.collect_classes(&module.tokens);
+        checker.scopes.push(TypeScope::default());
+        checker.collect(&module.tokens);
+        checker.walk_tokens(&module.tokens);
+        checker.scopes.pop();
+
+        checker
+            .diagnostics
+            .sort_by_key(|x| (x.pos.line, x.pos.column));
+        checker.diagnostics
+    }
+
+    fn report(&mut self, meta: &FSRPosition, msg: String) {
+        let info = FSRByteInfo::new(self.lines, meta.clone());
+        self.diagnostics.push(ResolveDiagnostic {
+            level: ResolveLevel::Error,
+            pos: info.get_pos().clone(),
+            msg,
+        });
+    }
+
+    fn hint_type(&self, hint: Option<&FSRTypeName>) -> HintType {
+        let Some(hint) = hint else {
+            return HintType::Any;
+        };
+
+        let name = hint_class_name(hint);
+        if BUILTIN_TYPES.contains(&name) || self.classes.contains_key(name) {
+            return HintType::class(name);
+        }
+
+        HintType::Any
+    }
+
+    fn fn_sig(&self, fn_def: &FSRFnDef) -> FnSig {
+        let params = fn_def
+            .get_args()
+            .iter()
+            .filter_map(|x| match x {
+                FSRToken::Variable(v) => {
+                    Some((v.get_name().to_string(), self.hint_type(v.get_type_hint())))
+                }
+                _ => None,
+            })
+            .collect();
+
+        FnSig {
+            params,
+            ret: self.hint_type(fn_def.ret_type.as_ref()),
+        }
+    }
+
+    fn is_checked_fn(fn_def: &FSRFnDef) -> bool {
+        !fn_def.is_jit() && !fn_def.is_static()
+    }
+
+    /// Class names first, hints may name a class defined later in the file.
+    fn collect_classes(&mut self, tokens: &[FSRToken]) {
+        for token in tokens {
+            match token {
+                FSRToken::Class(cls) => {
+                    self.classes.insert(cls.get_name().to_string(), None);
+                    self.collect_classes(cls.get_block().get_tokens());
+                }
+                FSRToken::FunctionDef(fn_def) if Self::is_checked_fn(fn_def) => {
+                    self.collect_classes(fn_def.get_body().get_tokens());
+                }
+                _ => {}
+            }
+        }
+
+        for token in tokens {
+            let FSRToken::Class(cls) = token else {
+                continue;
+            };
+
+            for method in cls.get_block().get_tokens() {
+                if let FSRToken::FunctionDef(fn_def) = method
+                    && fn_def.get_name() == "__new__"
+                    && Self::is_checked_fn(fn_def)
+                {
+                    let mut sig = self.fn_sig(fn_def);
+                    // `self` is passed by the constructor call
+                    if !sig.params.is_empty() {
+                        sig.params.remove(0);
+                    }
+                    sig.ret = HintType::class(cls.get_name());
+                    self.classes.insert(cls.get_name().to_string(), Some(sig));
+                }
+            }
+        }
+    }
+
+    /// Function signatures and declared variables of one scope.
+    fn collect(&mut self, tokens: &[FSRToken]) {
+        for token in tokens {
+            match token {
+                FSRToken::FunctionDef(fn_def)
+                    if !fn_def.is_lambda() && Self::is_checked_fn(fn_def) =>
+                {
+                    let sig = self.fn_sig(fn_def);
+                    self.cur().fns.insert(fn_def.get_name().to_string(), sig);
+                }
+                FSRToken::Assign(assign) => {
+                    if let FSRToken::Variable(v) = assign.get_left().as_ref()
+                        && v.get_type_hint().is_some()
+                    {
+                        let t = self.hint_type(v.get_type_hint());
+                        self.cur().declared.insert(v.get_name().to_string(), t);
+                    }
+                }
+                FSRToken::IfExp(if_exp) => {
+                    self.collect_block(if_exp.get_block());
+                    if let Some(elses) = if_exp.get_elses() {
+                        for e in elses.get_elses() {
+                            self.collect_block(e.get_block());
+                        }
+                    }
+                }
+                FSRToken::WhileExp(while_exp) => self.collect_block(while_exp.get_block()),
+                FSRToken::ForBlock(for_block) => self.collect_block(for_block.get_block()),
+                FSRToken::TryBlock(try_block) => {
+                    self.collect_block(try_block.get_block());
+                    self.collect_block(&try_block.get_catch().body);
+                }
+                FSRToken::Block(block) => self.collect_block(block),
+                _ => {}
+            }
+        }
+    }
+
+    fn collect_block(&mut self, block: &FSRBlock) {
+        self.collect(block.get_tokens());
+    }
+
+    fn cur(&mut self) -> &mut TypeScope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    fn env(&self) -> TypeEnv {
+        self.scopes.last().unwrap().inferred.clone()
+    }
+
+    fn set_env(&mut self, env: TypeEnv) {
+        self.cur().inferred = env;
+    }
+
+    /// Names missing on one side may be unbound there, they become `Any`
+    fn join_env(a: &TypeEnv, b: &TypeEnv) -> TypeEnv {
+        let mut res = TypeEnv::new();
+        for (name, t) in a {
+            let joined = b.get(name).map(|x| t.join(x)).unwrap_or(HintType::Any);
+            res.insert(name.clone(), joined);
+        }
+
+        for name in b.keys() {
+            res.entry(name.clone()).or_insert(HintType::Any);
+        }
+
+        res
+    }
+
+    fn lookup_var(&self, name: &str) -> HintType {
+        match name {
+            "true" | "false" => return HintType::class("Bool"),
+            "none" => return HintType::class("None"),
+            _ => {}
+        }
+
+        let (cur, outer) = self.scopes.split_last().unwrap();
+        if let Some(t) = cur.declared.get(name) {
+            return t.clone();
+        }
+
+        if let Some(t) = cur.inferred.get(name) {
+            return t.clone();
+        }
+
+        if cur.fns.contains_key(name) {
+            return HintType::class("Fn");
+        }
+
+        // outer names may be reassigned after this function is defined,
+        // only a declaration is stable
+        for scope in outer.iter().rev().filter(|x| !x.is_class) {
+            if let Some(t) = scope.declared.get(name) {
+                return t.clone();
+            }
+
+            if scope.inferred.contains_key(name) {
+                return HintType::Any;
+            }
+
+            if scope.fns.contains_key(name) {
+                return HintType::class("Fn");
+            }
+        }
+
+        HintType::Any
+    }
+
+    fn lookup_fn(&self, name: &str) -> Option<FnSig> {
+        let last = self.scopes.len() - 1;
+        for (i, scope) in self.scopes.iter().enumerate().rev() {
+            if scope.is_class && i != last {
+                continue;
+            }
+
+            // a plain variable of the same name hides the function
+            if scope.inferred.contains_key(name) || scope.declared.contains_key(name) {
+                return None;
+            }
+
+            if let Some(sig) = scope.fns.get(name) {
+                return Some(sig.clone());
+            }
+        }
+
+        self.classes.get(name).cloned().flatten()
+    }
+
+    fn walk_tokens(&mut self, tokens: &[FSRToken]) {
+        for token in tokens {
+            self.walk(token);
+        }
+    }
+
+    fn walk_block(&mut self, block: &FSRBlock) {
+        self.walk_tokens(block.get_tokens());
+    }
+
+    /// Walk a loop body until the types at its entry are stable, the last
+    /// round is the one reported.
+    fn walk_loop(&mut self, block: &FSRBlock) {
+        loop {
+            let before = self.env();
+            let len = self.diagnostics.len();
+            self.walk_block(block);
+            self.diagnostics.truncate(len);
+            let after = Self::join_env(&before, &self.env());
+            self.set_env(after.clone());
+            if after == before {
+                break;
+            }
+        }
+
+        let before = self.env();
+        self.walk_block(block);
+        let after = Self::join_env(&before, &self.env());
+        self.set_env(after);
+    }
+
+    fn walk(&mut self, token: &FSRToken) {
+        match token {
+            FSRToken::FunctionDef(fn_def) => {
+                if Self::is_checked_fn(fn_def) {
+                    self.walk_fn(fn_def);
+                }
+            }
+            FSRToken::Class(cls) => {
+                self.scopes.push(TypeScope {
+                    is_class: true,
+                    ..Default::default()
+                });
+                self.collect_block(cls.get_block());
+                self.walk_block(cls.get_block());
+                self.scopes.pop();
+            }
+            FSRToken::IfExp(if_exp) => {
+                self.infer(if_exp.get_test());
+                let start = self.env();
+                self.walk_block(if_exp.get_block());
+                let mut end = self.env();
+                let mut has_else = false;
+                if let Some(elses) = if_exp.get_elses() {
+                    for e in elses.get_elses() {
+                        self.set_env(start.clone());
+                        match e.get_test() {
+                            Some(test) => {
+                                self.infer(test);
+                            }
+                            None => has_else = true,
+                        }
+                        self.walk_block(e.get_block());
+                        end = Self::join_env(&end, &self.env());
+                    }
+                }
+
+                if !has_else {
+                    end = Self::join_env(&end, &start);
+                }
+                self.set_env(end);
+            }
+            FSRToken::WhileExp(while_exp) => {
+                self.infer(while_exp.get_test());
+                self.walk_loop(while_exp.get_block());
+            }
+            FSRToken::ForBlock(for_block) => {
+                let iter = for_block.get_expr();
+                self.infer(iter);
+                let var_type = match iter {
+                    FSRToken::Expr(expr) if expr.get_op() == ".." => HintType::class("Integer"),
+                    _ => HintType::Any,
+                };
+                self.cur()
+                    .inferred
+                    .insert(for_block.get_var_name().to_string(), var_type);
+                self.walk_loop(for_block.get_block());
+            }
+            FSRToken::TryBlock(try_block) => {
+                let start = self.env();
+                self.walk_block(try_block.get_block());
+                let end = self.env();
+                // the catch block may start from any point of the try block
+                self.set_env(Self::join_env(&start, &end));
+                self.walk_block(&try_block.get_catch().body);
+                let catch_end = self.env();
+                self.set_env(Self::join_env(&end, &catch_end));
+            }
+            FSRToken::Block(block) => self.walk_block(block),
+            FSRToken::Assign(assign) => self.walk_assign(assign),
+            FSRToken::Return(ret) => {
+                let t = self.infer(ret.get_return_expr());
+                if let Some((name, expected)) = self.ret_types.last()
+                    && !expected.accepts(&t)
+                {
+                    let msg = format!("`{}` returns `{}`, found `{}`", name, expected, t);
+                    self.report(ret.get_meta(), msg);
+                }
+            }
+            FSRToken::Defer(defer) => {
+                self.infer(defer.get_defer_expr());
+            }
+            // static structs are checked by the static compiler
+            FSRToken::Struct(_) => {}
+            _ => {
+                self.infer(token);
+            }
+        }
+    }
+
+    fn walk_assign(&mut self, assign: &FSRAssign) {
+        let value = self.infer(assign.get_assign_expr());
+        let FSRToken::Variable(v) = assign.get_left().as_ref() else {
+            self.infer(assign.get_left());
+            return;
+        };
+
+        let name = v.get_name();
+        let value = match assign.op_assign.as_str() {
+            "=" => value,
+            op => {
+                let left = self.lookup_var(name);
+                Self::binary_type(op.trim_end_matches('='), &left, &value)
+            }
+        };
+
+        if let Some(declared) = self.scopes.last().unwrap().declared.get(name).cloned() {
+            if !declared.accepts(&value) {
+                let msg = format!(
+                    "`{}` is declared as `{}`, assigned `{}`",
+                    name, declared, value
+                );
+                self.report(assign.get_meta(), msg);
+            }
+            return;
+        }
+
+        self.cur().inferred.insert(name.to_string(), value);
+    }
+
+    fn walk_fn(&mut self, fn_def: &FSRFnDef) {
+        let mut scope = TypeScope::default();
+        // lambda args carry a placeholder `Function` hint
+        if !fn_def.is_lambda() {
+            for arg in fn_def.get_args() {
+                if let FSRToken::Variable(v) = arg
+                    && v.get_type_hint().is_some()
+                {
+                    let t = self.hint_type(v.get_type_hint());
+                    scope.declared.insert(v.get_name().to_string(), t);
+                }
+            }
+        }
+
+        for arg in fn_def.get_args() {
+            if let FSRToken::Variable(v) = arg {
+                scope
+                    .inferred
+                    .insert(v.get_name().to_string(), HintType::Any);
+            }
+        }
+
+        self.scopes.push(scope);
+        self.ret_types.push((
+            fn_def.get_name().to_string(),
+            self.hint_type(fn_def.ret_type.as_ref()),
+        ));
+        self.collect_block(fn_def.get_body());
+        self.walk_block(fn_def.get_body());
+        self.ret_types.pop();
+        self.scopes.pop();
+    }
+
+    fn infer(&mut self, token: &FSRToken) -> HintType {
+        match token {
+            FSRToken::Constant(c) => match c.get_const_type() {
+                FSRConstType::FormatString(format_string) => {
+                    for arg in &format_string.arg_expr {
+                        self.infer(&arg.expr);
+                    }
+                    HintType::class("String")
+                }
+                FSRConstType::RegexString => HintType::Any,
+                FSRConstType::Normal => match c.get_constant() {
+                    FSRConstantType::String(_) => HintType::class("String"),
+                    FSRConstantType::Integer(_) => HintType::class("Integer"),
+                    FSRConstantType::Float(_) => HintType::class("Float"),
+                },
+            },
+            FSRToken::Variable(v) => match v.single_op {
+                Some(_) => HintType::Any,
+                None => self.lookup_var(v.get_name()),
+            },
+            FSRToken::List(list) => {
+                for item in list.get_items() {
+                    self.infer(item);
+                }
+                HintType::class("List")
+            }
+            FSRToken::Expr(expr) => self.infer_expr(expr),
+            FSRToken::StackExpr((_, tokens)) => {
+                let mut t = HintType::Any;
+                for token in tokens {
+                    t = self.infer(token);
+                }
+                if tokens.len() == 1 { t } else { HintType::Any }
+            }
+            FSRToken::Call(call) => self.infer_call(call),
+            FSRToken::Getter(getter) => {
+                self.infer(getter.get_getter());
+                HintType::Any
+            }
+            FSRToken::FunctionDef(fn_def) => {
+                self.walk(token);
+                if fn_def.is_lambda() {
+                    HintType::class("Fn")
+                } else {
+                    HintType::Any
+                }
+            }
+            _ => HintType::Any,
+        }
+    }
+
+    fn infer_call(&mut self, call: &FSRCall) -> HintType {
+        let args = call
+            .get_args()
+            .iter()
+            .map(|x| self.infer(x))
+            .collect::<Vec<_>>();
+
+        let name = call.get_name();
+        if name.is_empty() {
+            return HintType::Any;
+        }
+
+        let Some(sig) = self.lookup_fn(name) else {
+            return match self.classes.contains_key(name) {
+                true => HintType::class(name),
+                false => HintType::Any,
+            };
+        };
+
+        if sig.params.len() != args.len() {
+            let msg = format!(
+                "`{}` takes {} arguments, {} given",
+                name,
+                sig.params.len(),
+                args.len()
+            );
+            self.report(call.get_meta(), msg);
+            return sig.ret;
+        }
+
+        for ((param, expected), (arg, actual)) in
+            sig.params.iter().zip(call.get_args().iter().zip(&args))
+        {
+            if !expected.accepts(actual) {
+                let msg = format!(
+                    "argument `{}` of `{}` expects `{}`, found `{}`",
+                    param, name, expected, actual
+                );
+                self.report(arg.get_meta(), msg);
+            }
+        }
+
+        sig.ret
+    }
+
+    fn infer_expr(&mut self, expr: &FSRExpr) -> HintType {
+        let left = self.infer(expr.get_left());
+        let op = expr.get_op();
+        if op == "." || op == "::" {
+            // attribute types are unknown, still check the call arguments
+            if let FSRToken::Call(call) = expr.get_right() {
+                for arg in call.get_args() {
+                    self.infer(arg);
+                }
+            } else if !matches!(expr.get_right(), FSRToken::Variable(_)) {
+                self.infer(expr.get_right());
+            }
+            return HintType::Any;
+        }
+
+        let right = self.infer(expr.get_right());
+        Self::binary_type(op, &left, &right)
+    }
+
+    fn binary_type(op: &str, left: &HintType, right: &HintType) -> HintType {
+        let is_num = |x: &HintType| x.is_class("Integer") || x.is_class("Float");
+        match op {
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => HintType::class("Bool"),
+            ".." => HintType::class("Range"),
+            "+" | "-" | "*" | "%" if left.is_class("Integer") && right.is_class("Integer") => {
+                HintType::class("Integer")
+            }
+            "+" | "-" | "*" | "/" if is_num(left) && is_num(right) => HintType::class("Float"),
+            "+" if left.is_class("String") && right.is_class("String") => HintType::class("String"),
+            _ => HintType::Any,
+        }
+    }
+}
+
+#[allow(unused)]
+mod test {
+    use frontend::ast::token::{base::FSRPosition, module::FSRModuleFrontEnd};
+
+    use crate::backend::compiler::resolver::ResolveDiagnostic;
+
+    use super::TypeChecker;
+
+    fn check(code: &str) -> Vec<ResolveDiagnostic> {
+        let chars = code.chars().collect::<Vec<char>>();
+        let (module, lines) = FSRModuleFrontEnd::parse(&chars, FSRPosition::new()).unwrap();
+        TypeChecker::check(&module, &lines)
+    }
+
+    #[test]
+    fn test_type_check_mismatch() {
+        let v = check(
+            "
+class Abc {
+    fn __new__(self, n: Integer) {
+        self.n = n
+        return self
+    }
+}
+
+fn add(a: Integer, b: Integer) -> Integer {
+    return a + b
+}
+
+fn name(a: Abc) -> String {
+    return 1
+}
+
+x: Integer = add(1, 'a')
+y = add(1, 2) / 2
+x = y
+name(Abc('a'))
+add(1)
+",
+        );
+        let msgs = v.iter().map(|x| x.msg.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            msgs,
+            vec![
+                "`name` returns `String`, found `Integer`",
+                "argument `b` of `add` expects `Integer`, found `String`",
+                "`x` is declared as `Integer`, assigned `Float`",
+                "argument `n` of `Abc` expects `Integer`, found `String`",
+                "`add` takes 2 arguments, 1 given",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_check_gradual() {
+        let v = check(
+            "
+fn add(a: Integer, b: Integer) -> Integer {
+    return a + b
+}
+
+fn any(v) {
+    return v
+}
+
+i = 0
+while i < 10 {
+    i = i + 1
+}
+add(i, 1)
+
+s = 1
+if s > 0 {
+    s = 'a'
+}
+add(s, any('a'))
+
+for j in 0..10 {
+    add(j, 1)
+}
+
+add(f'{i}'.len(), 1)
+
+l = |x| { return x }
+add(l(1), 2)
+",
+        );
+        assert!(v.is_empty(), "{:?}", v);
+    }
+
+    #[test]
+    fn test_type_check_loop() {
+        let v = check(
+            "
+fn add(a: Integer, b: Integer) -> Integer {
+    return a + b
+}
+
+a = 1
+while a < 10 {
+    add(a, 1)
+    a = 'a'
+}
+",
+        );
+        // `a` may be a String in the second round, not a mismatch for sure
+        assert!(v.is_empty(), "{:?}", v);
+    }
+}
@@ -0,0 +1,470 @@
+use std::fmt::Display;
+
+use crate::{
+    backend::compiler::bytecode::{
+        ArgType, Bytecode, BytecodeArg, BytecodeOperator, BytecodeResult, FSRPos,
+    },
+    utils::error::{FSRErrCode, FSRError},
+};
+
+#[derive(Debug, Clone)]
+pub struct VerifyDiagnostic {
+    pub fn_name: String,
+    /// (statement index, operator index inside the statement)
+    pub ip: (usize, usize),
+    pub pos: FSRPos,
+    pub operator: BytecodeOperator,
+    pub msg: String,
+}
+
+impl Display for VerifyDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{} ({:?} at ip {:?}): {}",
+            self.fn_name,
+            self.pos.line + 1,
+            self.pos.column,
+            self.operator,
+            self.ip,
+            self.msg
+        )
+    }
+}
+
+/// Checks operands, jump targets and stack depth of compiled code before it
+/// runs, so bad bytecode is reported with a position instead of a panic
+pub struct BytecodeVerifier<'a> {
+    code: &'a Bytecode,
+    diagnostics: Vec<VerifyDiagnostic>,
+}
+
+impl<'a> BytecodeVerifier<'a> {
+    pub fn new(code: &'a Bytecode) -> Self {
+        Self {
+            code,
+            diagnostics: vec![],
+        }
+    }
+
+    /// Verify a single function, returns every problem found.
+    pub fn verify(code: &'a Bytecode) -> Vec<VerifyDiagnostic> {
+        let mut verifier = Self::new(code);
+        for (ip_0, expr) in code.bytecode.iter().enumerate() {
+            verifier.verify_expr(ip_0, expr);
+        }
+
+        verifier.diagnostics
+    }
+
+    /// Verify all functions of a compile result, collapse diagnostics to one error.
+    pub fn verify_result(result: &BytecodeResult) -> Result<(), FSRError> {
+        let mut names = result.bytecode_map.keys().collect::<Vec<_>>();
+        names.sort();
+        let mut diagnostics = vec![];
+        for name in names {
+            diagnostics.extend(BytecodeVerifier::verify(&result.bytecode_map[name]));
+        }
+
+        if diagnostics.is_empty() {
+            return Ok(());
+        }
+
+        let msg = diagnostics
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        Err(FSRError::new(
+            format!("bytecode verify failed:\n{}", msg),
+            FSRErrCode::NotValidArgs,
+        ))
+    }
+
+    fn report(&mut self, ip: (usize, usize), arg: &BytecodeArg, msg: impl Into<String>) {
+        self.diagnostics.push(VerifyDiagnostic {
+            fn_name: self.code.name.clone(),
+            ip,
+            pos: arg.get_pos(),
+            operator: arg.get_operator(),
+            msg: msg.into(),
+        });
+    }
+
+    fn verify_expr(&mut self, ip_0: usize, expr: &[BytecodeArg]) {
+        let mut depth = 0usize;
+        let mut last_push = 0;
+        for (ip_1, arg) in expr.iter().enumerate() {
+            let ip = (ip_0, ip_1);
+            if let Some(expect) = self.expect_arg(arg) {
+                self.report(
+                    ip,
+                    arg,
+                    format!("expect {} operand, got {:?}", expect, arg.get_arg()),
+                );
+                // operand is not trusted, skip jump and stack checks of this statement
+                return;
+            }
+
+            self.verify_jump(ip, expr.len(), arg);
+
+            let (pop, push) = match arg.get_operator() {
+                // a bare `return` or `yield` has no value to pop
+                BytecodeOperator::ReturnValue | BytecodeOperator::Yield => (depth.min(1), 0),
+                _ => Self::stack_effect(arg),
+            };
+            if depth < pop {
+                self.report(
+                    ip,
+                    arg,
+                    format!(
+                        "expression stack underflow, need {} value(s), have {}",
+                        pop, depth
+                    ),
+                );
+                return;
+            }
+
+            depth = depth - pop + push;
+            last_push = push;
+        }
+
+        // the value of an expression statement is dropped when the statement ends
+        if depth != 0
+            && depth != last_push
+            && let Some(last) = expr.last()
+        {
+            self.report(
+                (ip_0, expr.len() - 1),
+                last,
+                format!("expression stack is not balanced, {} value(s) left", depth),
+            );
+        }
+    }
+
+    /// Return the expected operand kind if `arg` does not carry it.
+    fn expect_arg(&self, arg: &BytecodeArg) -> Option<&'static str> {
+        use BytecodeOperator as Op;
+        let is_jit = self.code.fn_info.is_jit;
+        let v = arg.get_arg();
+        let ok = match arg.get_operator() {
+            Op::Load | Op::LoadVar | Op::LoadConst => matches!(
+                v,
+                ArgType::Local(_)
+                    | ArgType::Global(_)
+                    | ArgType::ClosureVar(_)
+                    | ArgType::CurrentFn
+                    | ArgType::Const(_, _)
+                    | ArgType::ConstInteger(_, _, _)
+                    | ArgType::ConstFloat(_, _, _)
+                    | ArgType::ConstString(_, _)
+                    | ArgType::LoadTrue
+                    | ArgType::LoadFalse
+                    | ArgType::LoadNone
+                    | ArgType::LoadUninit
+                    | ArgType::JitFunction(_, _)
+            ),
+            Op::Assign => matches!(
+                v,
+                ArgType::Local(_) | ArgType::ClosureVar(_) | ArgType::None
+            ),
+            Op::AssignArgs => matches!(v, ArgType::Local(_) | ArgType::ClosureVar(_)),
+            Op::ClassDef | Op::EndDefineClass | Op::SpecialLoadFor => {
+                matches!(v, ArgType::Local(_))
+            }
+            Op::BinaryDot | Op::BinaryClassGetter | Op::AssignAttr => {
+                matches!(v, ArgType::Attr(_))
+            }
+            Op::Call | Op::TailCall => matches!(v, ArgType::CallArgsNumber(_)),
+            Op::CallMethod => {
+                matches!(v, ArgType::CallArgsNumberWithAttr(_))
+                    || (is_jit && matches!(v, ArgType::CallArgsNumber(_)))
+            }
+            Op::IfTest | Op::ElseIf | Op::ElseIfTest | Op::Else => {
+                matches!(v, ArgType::IfTestNext(_))
+            }
+            Op::WhileTest => matches!(v, ArgType::WhileTest(_)),
+            Op::WhileBlockEnd => matches!(v, ArgType::WhileEnd(_)),
+            Op::LoadForIter => matches!(v, ArgType::ForLine(_)),
+            Op::ForBlockEnd => matches!(v, ArgType::ForEnd(_)),
+            Op::AndJump | Op::OrJump => matches!(v, ArgType::AddOffset(_)),
+            Op::Try => matches!(v, ArgType::TryCatch(_, _)),
+            Op::DefineFn => matches!(v, ArgType::DefineFnArgs(_)),
+            Op::Import => matches!(v, ArgType::ImportModule(_, _)),
+            Op::LoadList => matches!(v, ArgType::LoadListNumber(_)),
+            Op::FormatString => matches!(v, ArgType::FormatStringLen(_, _)),
+            Op::OpAssign => matches!(v, ArgType::OpAssign(_)),
+            Op::CompareTest => matches!(v, ArgType::Compare(_)),
+            Op::AssignContainer => matches!(v, ArgType::AssignContainer(_)),
+            Op::Getter => matches!(v, ArgType::None | ArgType::TypeInfo(_)),
+            Op::SStructDef => matches!(v, ArgType::CreateStruct(_, _)),
+            Op::SDefAttr => matches!(v, ArgType::DefAttr(_)),
+            Op::SAlloc => matches!(v, ArgType::Alloc(_)),
+            Op::TypeGuard => matches!(v, ArgType::TypeGuard(_)),
+            Op::SCast => matches!(v, ArgType::TypeInfo(Some(_))),
+            Op::SStrMethod => matches!(v, ArgType::StrMethod(_)),
+            Op::SObjMethod => matches!(v, ArgType::ObjMethod(_)),
+            Op::SVecNew => matches!(v, ArgType::VecNew(_)),
+            _ => true,
+        };
+
+        if ok {
+            return None;
+        }
+
+        Some(match arg.get_operator() {
+            Op::Load | Op::LoadVar | Op::LoadConst => "variable or constant",
+            Op::Assign | Op::AssignArgs => "local or closure variable",
+            Op::ClassDef | Op::EndDefineClass | Op::SpecialLoadFor => "local variable",
+            Op::BinaryDot | Op::BinaryClassGetter | Op::AssignAttr => "attribute",
+            Op::Call | Op::TailCall => "call args number",
+            Op::CallMethod => "call args number with attribute",
+            Op::IfTest | Op::ElseIf | Op::ElseIfTest | Op::Else => "if test next",
+            Op::WhileTest => "while test",
+            Op::WhileBlockEnd => "while end",
+            Op::LoadForIter => "for line",
+            Op::ForBlockEnd => "for end",
+            Op::AndJump | Op::OrJump => "add offset",
+            Op::Try => "try catch",
+            Op::DefineFn => "define fn args",
+            Op::Import => "import module",
+            Op::LoadList => "load list number",
+            Op::FormatString => "format string len",
+            Op::OpAssign => "op assign",
+            Op::CompareTest => "compare",
+            Op::AssignContainer => "assign container",
+            Op::Getter => "type info",
+            Op::SStructDef => "create struct",
+            Op::SDefAttr => "struct attribute",
+            Op::SAlloc => "alloc",
+            Op::TypeGuard => "type guard",
+            Op::SCast => "cast type",
+            Op::SStrMethod => "string method",
+            Op::SObjMethod => "object method",
+            Op::SVecNew => "vector constructor",
+            _ => "valid",
+        })
+    }
+
+    /// Check jump targets stay inside the function (or the statement for
+    /// logic jumps), a target equal to the length means "fall off the end".
+    fn verify_jump(&mut self, ip: (usize, usize), expr_len: usize, arg: &BytecodeArg) {
+        let (ip_0, ip_1) = ip;
+        let fn_len = self.code.bytecode.len();
+        let arg_n = arg.arg_n;
+        let payload = match arg.get_arg() {
+            ArgType::IfTestNext(n) | ArgType::WhileTest(n) | ArgType::ForLine(n) => *n as i64,
+            ArgType::WhileEnd(n) | ArgType::ForEnd(n) => *n,
+            ArgType::AddOffset(n) => *n as i64,
+            ArgType::TryCatch(start, end) => {
+                let (start, end) = (*start as usize, *end as usize);
+                if start > end {
+                    self.report(
+                        ip,
+                        arg,
+                        format!("catch start {} is after catch end {}", start, end),
+                    );
+                } else if ip_0 + end > fn_len {
+                    self.report(
+                        ip,
+                        arg,
+                        format!(
+                            "catch end {} is out of function with {} lines",
+                            ip_0 + end,
+                            fn_len
+                        ),
+                    );
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        if payload != arg_n {
+            self.report(
+                ip,
+                arg,
+                format!("jump offset {} not match arg_n {}", payload, arg_n),
+            );
+            return;
+        }
+
+        if arg_n < 0 {
+            self.report(ip, arg, format!("negative jump offset {}", arg_n));
+            return;
+        }
+
+        let n = arg_n as usize;
+        let out_of_range = match arg.get_operator() {
+            BytecodeOperator::IfTest
+            | BytecodeOperator::ElseIf
+            | BytecodeOperator::ElseIfTest
+            | BytecodeOperator::Else
+            | BytecodeOperator::WhileTest => ip_0 + n + 1 > fn_len,
+            BytecodeOperator::LoadForIter => ip_0 + n > fn_len,
+            BytecodeOperator::WhileBlockEnd | BytecodeOperator::ForBlockEnd => n > ip_0,
+            // ip.1 is moved past the current operator before the jump is added
+            BytecodeOperator::AndJump | BytecodeOperator::OrJump => ip_1 + 1 + n > expr_len,
+            _ => false,
+        };
+
+        if out_of_range {
+            self.report(
+                ip,
+                arg,
+                format!("jump offset {} lands outside of function", arg_n),
+            );
+        }
+    }
+
+    /// (values popped, values pushed) on the expression stack
+    fn stack_effect(arg: &BytecodeArg) -> (usize, usize) {
+        use BytecodeOperator as Op;
+        match arg.get_operator() {
+            Op::Load | Op::LoadVar | Op::LoadConst | Op::LoadYield | Op::LoadSelfFn => (0, 1),
+            Op::Assign => match arg.get_arg() {
+                ArgType::Local(_) | ArgType::ClosureVar(_) => (1, 0),
+                _ => (2, 0),
+            },
+            Op::BinaryAdd
+            | Op::BinarySub
+            | Op::BinaryMul
+            | Op::BinaryDiv
+            | Op::BinaryReminder
+            | Op::BinaryRShift
+            | Op::BinaryLShift
+            | Op::BinaryRange
+            | Op::CompareTest
+            | Op::CompareEqual
+            | Op::Getter => (2, 1),
+            Op::BinaryDot | Op::BinaryClassGetter | Op::NotOperator | Op::SCast => (1, 1),
+            Op::Call | Op::TailCall => match arg.get_arg() {
+                ArgType::CallArgsNumber((n, _)) => (n + 1, 1),
+                _ => (0, 0),
+            },
+            Op::CallMethod => match arg.get_arg() {
+                ArgType::CallArgsNumberWithAttr((n, _, _, _)) => (n + 1, 1),
+                // static method call keeps the father object below the method
+                ArgType::CallArgsNumber((n, _)) => (n + 2, 1),
+                _ => (0, 0),
+            },
+            Op::LoadList => match arg.get_arg() {
+                ArgType::LoadListNumber(n) => (n.list_len, 1),
+                _ => (0, 0),
+            },
+            Op::FormatString => match arg.get_arg() {
+                ArgType::FormatStringLen(n, _) => (*n as usize, 1),
+                _ => (0, 0),
+            },
+            Op::IfTest | Op::ElseIfTest | Op::WhileTest | Op::LoadForIter => (1, 0),
+            // short cut path pushes the result back, the other path evaluates the right side
+            Op::AndJump | Op::OrJump => (1, 0),
+            Op::AssignAttr | Op::OpAssign => (2, 0),
+            Op::AssignContainer => (3, 0),
+            Op::ForBlockRefAdd | Op::TryException | Op::Await => (1, 1),
+            Op::Raise | Op::Delegate | Op::SFree => (1, 0),
+            Op::SStrMethod => match arg.get_arg() {
+                ArgType::StrMethod(m) => (m.args_len() + 1, 1),
+                _ => (0, 0),
+            },
+            Op::SObjMethod => match arg.get_arg() {
+                ArgType::ObjMethod(m) => (m.args_len() + 1, 1),
+                _ => (0, 0),
+            },
+            Op::SVecNew => match arg.get_arg() {
+                ArgType::VecNew((_, len)) => (*len, 1),
+                _ => (0, 0),
+            },
+            Op::SAlloc => match arg.get_arg() {
+                // array alloc takes the element count from the stack
+                ArgType::Alloc((_, _, is_array)) => (*is_array as usize, 1),
+                _ => (0, 0),
+            },
+            _ => (0, 0),
+        }
+    }
+}
+
+#[allow(unused)]
+mod test {
+    use crate::backend::compiler::bytecode::{Bytecode, BytecodeOperator};
+
+    use super::BytecodeVerifier;
+
+    /// Scripts the compiler rejects, they are not verified
+    const NOT_COMPILED: &[&str] = &[
+        // static struct with a `String` field, which static code does not know
+        "test_script/test/test_struct.fs",
+    ];
+
+    #[test]
+    fn test_verify_scripts() {
+        let mut dirs = vec![std::path::PathBuf::from("test_script")];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+
+                if path.extension().map(|x| x != "fs").unwrap_or(true) {
+                    continue;
+                }
+
+                let code = std::fs::read_to_string(&path).unwrap();
+                let result = std::panic::catch_unwind(|| Bytecode::compile("main", &code))
+                    .ok()
+                    .and_then(|x| x.ok());
+                let expect_fail = NOT_COMPILED.iter().any(|x| path == std::path::Path::new(x));
+                let result = match result {
+                    Some(_) if expect_fail => {
+                        panic!("{}: compiles now, remove it from NOT_COMPILED", path.display())
+                    }
+                    Some(result) => result,
+                    None if expect_fail => continue,
+                    None => panic!("{}: failed to compile", path.display()),
+                };
+                if let Err(e) = BytecodeVerifier::verify_result(&result) {
+                    panic!("{}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_bad_jump() {
+        let code = "
+        a = 1
+        while a < 10 {
+            a = a + 1
+        }
+        ";
+        let mut result = Bytecode::compile("main", code).unwrap();
+        let main = result.bytecode_map.get_mut("__main__").unwrap();
+        assert!(BytecodeVerifier::verify(main).is_empty());
+        let while_end = main
+            .bytecode
+            .iter_mut()
+            .flatten()
+            .find(|x| x.get_operator() == BytecodeOperator::WhileBlockEnd)
+            .unwrap();
+        while_end.arg_n = 100;
+        let diagnostics = BytecodeVerifier::verify(main);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].operator, BytecodeOperator::WhileBlockEnd);
+    }
+
+    #[test]
+    fn test_verify_unbalanced_stack() {
+        let mut result = Bytecode::compile("main", "a = 1").unwrap();
+        let main = result.bytecode_map.get_mut("__main__").unwrap();
+        assert!(BytecodeVerifier::verify(main).is_empty());
+        let stmt = &mut main.bytecode[0];
+        let load = stmt[0].clone();
+        stmt.insert(0, load);
+        let diagnostics = BytecodeVerifier::verify(main);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].msg.contains("not balanced"));
+    }
+}
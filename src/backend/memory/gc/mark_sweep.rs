@@ -1,6 +1,6 @@
 #![allow(clippy::vec_box)]
 
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use crate::backend::types::base::{Area, GlobalObj};
 
@@ -27,6 +27,9 @@ pub enum GcReason {
 
 const ESCAPE_COUNT: u32 = 2;
 
+/// Id of the next heap, 0 is kept for static objects
+static NEXT_HEAP: AtomicU32 = AtomicU32::new(1);
+
 pub struct MarkSweepGarbageCollector<'a> {
     marjor_arena: Vec<Option<Box<FSRObject<'a>>>>,
     // Store all objects
@@ -44,6 +47,10 @@ pub struct MarkSweepGarbageCollector<'a> {
     gc_reason: Option<GcReason>,
 
     pub(crate) small_integer: Box<[ObjId; 65536]>,
+
+    /// set in each object allocated here, a thread only marks and frees the
+    /// objects of its own heap
+    heap: u32,
 }
 
 const THROLD: usize = 10240 * 2;
@@ -82,9 +89,15 @@ impl<'a> MarkSweepGarbageCollector<'a> {
             marjor_arena: Vec::with_capacity(THROLD),
             gc_reason: None,
             small_integer: Box::new([0; 65536]),
+            heap: NEXT_HEAP.fetch_add(1, Ordering::Relaxed),
         }
     }
 
+    #[inline]
+    pub fn heap_id(&self) -> u32 {
+        self.heap
+    }
+
     pub fn set_reason(&mut self, reason: GcReason) {
         self.gc_reason = Some(reason);
     }
@@ -141,6 +154,7 @@ impl<'a> MarkSweepGarbageCollector<'a> {
             obj.cls = cls;
             obj.free = false;
             obj.area = Area::Minjor;
+            obj.heap = self.heap;
             #[cfg(feature = "track_memory_size")]
             {
                 self.tracker.memory_size += obj.get_size();
@@ -166,6 +180,7 @@ impl<'a> MarkSweepGarbageCollector<'a> {
             obj.cls = cls;
             obj.free = false;
             obj.area = Area::Minjor;
+            obj.heap = self.heap;
             self.tracker.minjar_object_count += 1;
             #[cfg(feature = "track_memory_size")]
             {
@@ -191,6 +206,7 @@ impl<'a> MarkSweepGarbageCollector<'a> {
                 gid(GlobalObj::NoneCls),
             ));
             obj.free = true;
+            obj.heap = self.heap;
             Some(obj)
         }));
 
@@ -211,15 +227,25 @@ impl<'a> MarkSweepGarbageCollector<'a> {
         self.tracker.object_count = self.objects.len() as u32 + self.marjor_arena.len() as u32;
     }
 
-    fn process_object(&mut self, i: usize, full: bool, freed_count: &mut u32) {
+    fn process_object(
+        &mut self,
+        i: usize,
+        full: bool,
+        sweep_frozen: bool,
+        freed_count: &mut u32,
+    ) {
         let obj = &mut self.objects[i];
         let mut is_mark = false;
         let mut count = 0;
+        let mut is_frozen = false;
         if let Some(obj) = obj {
             is_mark = obj.is_marked();
-            let is_not_skip = (!is_mark && !obj.free);
+            is_frozen = obj.is_frozen();
+            // another thread may still read a frozen object it can not mark
+            let is_not_skip = !is_mark && !obj.free && (!is_frozen || sweep_frozen);
             if is_not_skip && (obj.area == Area::Minjor || full) {
-                if obj.area == Area::Minjor {
+                // frozen objects are counted where they were allocated
+                if obj.area == Area::Minjor || is_frozen {
                     self.tracker.minjar_object_count -= 1;
                 } else {
                     self.tracker.marjor_object_count -= 1;
@@ -245,7 +271,7 @@ impl<'a> MarkSweepGarbageCollector<'a> {
         }
 
         // if is_mark && count > ESCAPE_COUNT {
-        if is_mark && count >= ESCAPE_COUNT {
+        if is_mark && count >= ESCAPE_COUNT && !is_frozen {
             let mut obj = obj.take().unwrap();
             obj.area = Area::Marjor;
             obj.undirty_object();
@@ -340,6 +366,7 @@ impl<'a> MarkSweepGarbageCollector<'a> {
         obj.cls = cls;
         obj.free = false;
         obj.area = Area::Marjor;
+        obj.heap = 0;
         #[cfg(feature = "track_memory_size")]
         {
             self.tracker.memory_size += obj.get_size();
@@ -365,8 +392,9 @@ impl<'a> MarkSweepGarbageCollector<'a> {
         //     self.shrink();
         // }
 
+        let sweep_frozen = FSRVM::single().running_threads() == 0;
         while i < self.objects.len() {
-            self.process_object(i, full, &mut freed_count);
+            self.process_object(i, full, sweep_frozen, &mut freed_count);
             i += 1;
         }
 
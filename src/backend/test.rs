@@ -1,10 +1,13 @@
 #[cfg(test)]
 pub mod tests {
 
-    use std::{borrow::Cow, io::Read, time::Instant};
+    use std::{borrow::Cow, io::Read, sync::atomic::Ordering, time::Instant};
 
     use crate::backend::{
-        compiler::{bytecode::Bytecode, jit::cranelift::CraneLiftJitBackend},
+        compiler::{
+            bytecode::Bytecode,
+            jit::{checked, cranelift::CraneLiftJitBackend, simd},
+        },
         types::{
             base::{FSRObject, FSRValue},
             code::FSRCode,
@@ -181,6 +184,17 @@ pub mod tests {
             "test_script/test/test_closure.fs",
             "test_script/bench/bench_iter_filter.fs",
             "test_script/test/test_iter_enumerate.fs",
+            "test_script/test/test_tail_call.fs",
+            "test_script/test/test_type_guard.fs",
+            "test_script/test/test_tier.fs",
+            "test_script/test/test_specialize.fs",
+            "test_script/test/test_osr.fs",
+            "test_script/test/ffi/test_ffi.fs",
+            "test_script/test/test_thread.fs",
+            "test_script/test/test_thread2.fs",
+            "test_script/test/test_thread3.fs",
+            "test_script/test/test_sync.fs",
+            "test_script/test/test_pool.fs",
         ];
         for i in vs {
             println!("Running script: {}", i);
@@ -214,6 +228,15 @@ pub mod tests {
             "test_script/test/jit/method_call.fs",
             "test_script/test/jit/test_ct_assign.fs",
             "test_script/test/jit/test_struct.fs",
+            "test_script/test/jit/test_numeric.fs",
+            "test_script/test/jit/test_string.fs",
+            "test_script/test/jit/jit_dump.fs",
+            "test_script/test/jit/struct_methods.fs",
+            "test_script/test/jit/generics.fs",
+            "test_script/test/jit/static_raise.fs",
+            "test_script/test/jit/static_object.fs",
+            "test_script/test/jit/simd.fs",
+            "test_script/test/jit/fn_pointer.fs",
         ];
         for i in vs {
             println!("Running script: {}", i);
@@ -237,6 +260,56 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn test_checked_script() {
+        FSRVM::single();
+        // on by default only in debug builds
+        checked::set_checked(true);
+        let file = "test_script/test/jit/checked_access.fs";
+        let source_code = std::fs::read_to_string(file).unwrap();
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", &source_code, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.start(obj_id, false).unwrap();
+    }
+
+    #[test]
+    fn test_simd_fallback() {
+        FSRVM::single();
+        let file = "test_script/test/jit/simd.fs";
+        let source_code = std::fs::read_to_string(file).unwrap();
+        let source_code = format!("{}\nexport(\"res\", [a, b, c, d, e])\n", source_code);
+        let run = |use_simd: bool| {
+            simd::set_simd(use_simd);
+            let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+            let obj_id = FSRVM::leak_object(obj);
+            let v = FSRCode::from_code("main", &source_code, obj_id).unwrap();
+            let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+            obj.as_mut_module().init_fn_map(v);
+            let mut runtime = FSRThreadRuntime::new_runtime();
+            runtime.start(obj_id, false).unwrap();
+
+            let module = FSRObject::id_to_obj(obj_id).as_module();
+            let res = FSRObject::id_to_obj(module.get_object("res").unwrap());
+            res.as_list()
+                .iter_values()
+                .map(|x| match &FSRObject::id_to_obj(x.load(Ordering::Relaxed)).value {
+                    FSRValue::Integer(i) => *i as f64,
+                    FSRValue::Float(f) => *f,
+                    v => panic!("not a number: {:?}", v),
+                })
+                .collect::<Vec<_>>()
+        };
+        let vector = run(true);
+        // same as `--no-simd`, every lane is done by the scalar fallback
+        let scalar = run(false);
+        simd::set_simd(true);
+        assert_eq!(vector, scalar);
+    }
+
     #[test]
     fn test_obj_size() {
         /*
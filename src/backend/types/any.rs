@@ -1,9 +1,21 @@
-use std::{any::Any, fmt::Debug};
+use std::{
+    any::Any,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
 
 use crate::{
-    backend::{types::base::FSRObject, vm::thread::FSRThreadRuntime},
+    backend::{
+        types::base::FSRObject,
+        vm::{
+            share::{SendError, SendValue},
+            thread::FSRThreadRuntime,
+            virtual_machine::FSRVM,
+        },
+    },
     to_rs_list,
-    utils::error::FSRError,
+    utils::error::{FSRErrCode, FSRError},
 };
 
 use super::{
@@ -56,10 +68,16 @@ impl FSRExtension {
     }
 }
 
+/// Value a thread returned, or its error
+pub type ThreadResult = Result<SendValue, SendError>;
+
+/// Shared by the handle object and the runtime which started the thread, the
+/// runtime joins the thread itself if the script did not
+pub type ThreadSlot = Arc<Mutex<Option<JoinHandle<ThreadResult>>>>;
+
 #[derive(Debug)]
 pub struct FSRThreadHandle {
-    pub thread: Option<std::thread::JoinHandle<()>>,
-    pub track_objects: Vec<ObjId>,
+    slot: ThreadSlot,
 }
 
 impl ExtensionTrait for FSRThreadHandle {
@@ -77,50 +95,89 @@ impl ExtensionTrait for FSRThreadHandle {
         _: &mut Vec<ObjId>,
         _: &mut bool,
     ) -> Box<dyn Iterator<Item = ObjId> + 'a> {
-        // Box::new(std::iter::empty())
-        Box::new(self.track_objects.iter().cloned())
+        // the args and the result of the thread are copies
+        Box::new(std::iter::empty())
     }
 
     fn set_undirty(&mut self) {}
 }
 
+fn as_thread_handle<'a>(obj: &'a FSRObject) -> Result<&'a FSRThreadHandle, FSRError> {
+    if let FSRValue::Extension(any) = &obj.value
+        && let Some(handle) = any.value.as_any().downcast_ref::<FSRThreadHandle>()
+    {
+        return Ok(handle);
+    }
+
+    Err(FSRError::new("not a thread handle", FSRErrCode::NotValidArgs))
+}
+
 fn join(
     args: *const ObjId,
     len: usize,
     thread: &mut FSRThreadRuntime,
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
-    let self_object = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashmap");
-
-    if let FSRValue::Extension(any) = &mut self_object.value {
-        if let Some(handle) = any.value.as_any_mut().downcast_mut::<FSRThreadHandle>() {
-            //thread.release();
-            let _ = handle.join();
-        } else {
-            unimplemented!()
-        }
+    let handle = as_thread_handle(FSRObject::id_to_obj(args[0]))?;
+    let res = handle.join(thread)?;
+    Ok(FSRRetValue::GlobalId(res))
+}
+
+fn is_finish(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let handle = as_thread_handle(FSRObject::id_to_obj(args[0]))?;
+    let finished = match handle.slot.lock().unwrap().as_ref() {
+        Some(th) => th.is_finished(),
+        None => true,
+    };
+    if finished {
+        Ok(FSRRetValue::GlobalId(FSRObject::true_id()))
     } else {
-        unimplemented!()
+        Ok(FSRRetValue::GlobalId(FSRObject::false_id()))
     }
-    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
 }
 
 impl FSRThreadHandle {
-    pub fn new(thread: std::thread::JoinHandle<()>) -> Self {
-        FSRThreadHandle {
-            thread: Some(thread),
-            track_objects: vec![],
-        }
+    pub fn new(slot: ThreadSlot) -> Self {
+        FSRThreadHandle { slot }
+    }
+
+    /// Wait for the thread, its result is built again in the heap of `thread`
+    /// and its error is raised there
+    pub fn join(&self, thread: &mut FSRThreadRuntime) -> Result<ObjId, FSRError> {
+        let th = self.slot.lock().unwrap().take().ok_or_else(|| {
+            FSRError::new("thread is already joined", FSRErrCode::NotSupportOperator)
+        })?;
+        Self::join_handle(th, thread)
     }
 
-    pub fn join(&mut self) -> Result<(), FSRError> {
-        if let Some(handle) = self.thread.take() {
-            handle.join().unwrap();
-        } else {
-            unimplemented!()
+    /// Join the thread of `slot` if no script did, a failure is only printed
+    /// since nobody waits for it
+    pub fn join_slot(slot: &ThreadSlot, thread: &mut FSRThreadRuntime) {
+        let th = slot.lock().unwrap().take();
+        if let Some(th) = th
+            && let Err(e) = Self::join_handle(th, thread)
+        {
+            eprintln!("error in thread: {}", e);
         }
+    }
 
-        Ok(())
+    fn join_handle(
+        th: JoinHandle<ThreadResult>,
+        thread: &mut FSRThreadRuntime,
+    ) -> Result<ObjId, FSRError> {
+        // the result may point to frozen objects, which are kept until here
+        let res = match th.join() {
+            Ok(Ok(value)) => value.into_object(thread),
+            Ok(Err(e)) => Err(e.into_error(thread)),
+            Err(_) => Err(FSRError::new("thread panicked", FSRErrCode::NotSupportOperator)),
+        };
+        FSRVM::single().thread_finished();
+        res
     }
 
     pub fn to_any_type(self) -> FSRValue<'static> {
@@ -133,7 +190,7 @@ impl FSRThreadHandle {
         let mut cls = FSRClass::new("Thread");
         let thread_join_fn = FSRFn::from_rust_fn_static(join, "__thread_join");
         cls.insert_attr("join", thread_join_fn);
-        let thread_finish_fn = FSRFn::from_rust_fn_static(join, "__thread_finish");
+        let thread_finish_fn = FSRFn::from_rust_fn_static(is_finish, "__thread_finish");
         cls.insert_attr("is_finish", thread_finish_fn);
         cls
     }
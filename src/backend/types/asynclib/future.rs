@@ -103,6 +103,7 @@ pub fn poll_future(
     }
     let args = to_rs_list!(args, len);
     let self_object = FSRObject::id_to_mut_obj(args[0]).expect("not a valid object");
+    self_object.check_mutable()?;
     if let FSRValue::Future(future) = &mut self_object.value {
         if future.state == FSRFutureState::Completed {
             return Ok(FSRRetValue::GlobalId(FSRObject::none_id()));
@@ -173,6 +174,7 @@ pub fn send_value(
     }
     let args = to_rs_list!(args, len);
     let self_object = FSRObject::id_to_mut_obj(args[0]).expect("not a valid object");
+    self_object.check_mutable()?;
     if let FSRValue::Future(future) = &mut self_object.value {
         //println!("future_inner: {:#?}", future);
         if let Some(delegate) = future.delegate_to {
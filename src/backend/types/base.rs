@@ -17,6 +17,7 @@ use crate::{
             any::ExtensionTrait, asynclib::future::FSRFuture, bytes::FSRInnerBytes, fn_def::FSRnE,
         },
         vm::{
+            inline_cache::AttrInlineCache,
             thread::FSRThreadRuntime,
             virtual_machine::{gid, FSRVM, OBJECTS},
         },
@@ -90,6 +91,14 @@ pub enum GlobalObj {
     BytesCls,
     HashSetCls,
     FutureCls,
+    MutexCls,
+    RwLockCls,
+    LockGuardCls,
+    CondvarCls,
+    AtomicIntCls,
+    ChannelCls,
+    ThreadPoolCls,
+    TaskFutureCls,
 }
 
 impl GlobalObj {
@@ -325,6 +334,9 @@ pub enum Area {
     Minjor,
     Marjor,
     Global,
+    /// shared with other threads, read only and only freed by its heap once
+    /// no other thread runs
+    Frozen,
 }
 
 impl Area {
@@ -333,6 +345,7 @@ impl Area {
             Area::Minjor => false,
             Area::Marjor => true,
             Area::Global => true,
+            Area::Frozen => true,
         }
     }
 }
@@ -350,6 +363,8 @@ pub struct FSRObject<'a> {
     pub(crate) gc_count: u32,
     pub(crate) area: Area,
     pub(crate) write_barrier: AtomicBool,
+    /// heap of the thread which allocated the object, 0 for static objects
+    pub(crate) heap: u32,
     // pub(crate) garbage_id: u32,
 }
 
@@ -388,6 +403,7 @@ impl<'a> FSRObject<'a> {
             area: Area::Global,
             write_barrier: AtomicBool::new(false),
             gc_count: 0,
+            heap: 0,
         }
     }
 
@@ -411,6 +427,23 @@ impl<'a> FSRObject<'a> {
         self.write_barrier.load(Ordering::Relaxed)
     }
 
+    #[cfg_attr(feature = "more_inline", inline(always))]
+    pub fn is_frozen(&self) -> bool {
+        self.area == Area::Frozen
+    }
+
+    /// Error for an object shared with other threads, called before it is changed
+    pub fn check_mutable(&self) -> Result<(), FSRError> {
+        if self.is_frozen() {
+            return Err(FSRError::new(
+                format!("can not change a `{}` shared with other threads", self.cls.get_name()),
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn as_mut_list(&mut self) -> &mut FSRList {
         match &mut self.value {
             FSRValue::List(fsrlist) => fsrlist,
@@ -486,6 +519,7 @@ impl<'a> FSRObject<'a> {
             area: Area::Global,
             write_barrier: AtomicBool::new(false),
             gc_count: 0,
+            heap: 0,
         }
     }
 
@@ -719,18 +753,28 @@ impl<'a> FSRObject<'a> {
         None
     }
 
-    #[inline]
-    pub fn get_cls_getter_attr(&self, name: &str) -> Option<&AtomicObjId> {
-        if let Some(s) = self.get_cls_attr(name) {
-            return Some(s);
+    /// Same as `get_attr`, but class attributes are resolved through the
+    /// inline cache returned by `cache`, only asked for when the class is searched
+    #[cfg_attr(feature = "more_inline", inline(always))]
+    pub fn get_attr_cached<'c>(
+        &self,
+        name: &str,
+        is_method: bool,
+        cache: impl FnOnce() -> Option<&'c AttrInlineCache>,
+    ) -> Option<&AtomicObjId> {
+        let cls_attr = |cache: Option<&AttrInlineCache>| match cache {
+            Some(cache) => cache.get_cls_attr(self.cls, name),
+            None => self.get_cls_attr(name),
+        };
+
+        if is_method {
+            return cls_attr(cache()).or_else(|| self.get_attr(name, false));
         }
 
         if let FSRValue::ClassInst(inst) = &self.value {
             let v = match inst.get_attr(name) {
                 Some(s) => s,
-                None => {
-                    return None;
-                }
+                None => return cls_attr(cache()),
             };
             return Some(v);
         }
@@ -739,6 +783,23 @@ impl<'a> FSRObject<'a> {
             return s.get_attr(name);
         }
 
+        None
+    }
+
+    #[inline]
+    pub fn get_cls_getter_attr(&self, name: &str) -> Option<ObjId> {
+        if let Some(s) = self.get_cls_attr(name) {
+            return Some(s.load(Ordering::Relaxed));
+        }
+
+        if let FSRValue::ClassInst(inst) = &self.value {
+            return inst.get_attr(name).map(|s| s.load(Ordering::Relaxed));
+        }
+
+        if let FSRValue::Class(s) = &self.value {
+            return s.get_attr(name).map(|s| s.load(Ordering::Relaxed));
+        }
+
         if let FSRValue::Module(m) = &self.value {
             return m.get_object(name);
         }
@@ -884,7 +945,7 @@ impl<'a> FSRObject<'a> {
             FSRValue::Range(r) => Box::new(r.get_references().into_iter()),
             FSRValue::Future(f) => f.get_reference(full, worklist, is_add),
             FSRValue::Module(m) => {
-                Box::new(m.object_map.iter().map(|(_, v)| v.load(Ordering::Relaxed)))
+                Box::new(m.object_ids().into_iter())
             }
             _ => Box::new(std::iter::empty()),
         }
@@ -900,7 +961,7 @@ impl<'a> FSRObject<'a> {
     pub fn get_value_ptr(&self) -> usize {
         match &self.value {
             FSRValue::Integer(i) => i as *const i64 as usize,
-            FSRValue::Float(f) => &f.to_bits() as *const u64 as usize,
+            FSRValue::Float(f) => f as *const f64 as usize,
             _ => self as *const Self as usize,
         }
     }
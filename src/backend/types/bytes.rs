@@ -157,6 +157,7 @@ pub fn set_item(
     let target_id = args[2];
 
     let obj = FSRObject::id_to_mut_obj(self_id).unwrap();
+    obj.check_mutable()?;
     let index_obj = FSRObject::id_to_obj(index_id);
     let target_obj = FSRObject::id_to_obj(target_id);
     let FSRValue::Bytes(l) = &obj.value else {
@@ -1,6 +1,6 @@
 use std::{
     collections::HashMap,
-    sync::{atomic::{AtomicUsize, Ordering}, Arc},
+    sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc},
 };
 
 use ahash::AHashMap;
@@ -21,6 +21,16 @@ pub struct FSRClass {
     pub(crate) name: Arc<String>,
     pub(crate) attrs: AHashMap<String, AtomicObjId>,
     pub(crate) offset_attrs: Vec<Option<AtomicObjId>>,
+    /// Changed on every attribute mutation, used to invalidate inline caches
+    pub(crate) version: AtomicU64,
+}
+
+/// Global counter so two classes never share a version, even if one
+/// is allocated at the address of a freed one.
+static CLASS_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_class_version() -> AtomicU64 {
+    AtomicU64::new(CLASS_VERSION.fetch_add(1, Ordering::Relaxed))
 }
 
 impl PartialEq for FSRClass {
@@ -233,6 +243,7 @@ impl FSRClass {
             attrs: AHashMap::new(),
             offset_attrs: vec![],
             offset_rust_fn: [None; 30],
+            version: next_class_version(),
         }
     }
 
@@ -258,10 +269,21 @@ impl FSRClass {
             offset_attrs: vec![],
             offset_rust_fn: [None; 30],
             object_id: None,
+            version: next_class_version(),
         }
     }
 
+    #[inline(always)]
+    pub fn get_version(&self) -> u64 {
+        self.version.load(Ordering::Relaxed)
+    }
+
+    fn bump_version(&mut self) {
+        self.version = next_class_version();
+    }
+
     pub fn insert_attr(&mut self, name: &str, object: FSRObject<'_>) {
+        self.bump_version();
         let obj_id = FSRVM::register_object(object);
         self.attrs.insert(name.to_string(), AtomicUsize::new(obj_id));
     }
@@ -269,6 +291,7 @@ impl FSRClass {
     /// Inserts an attribute with a given offset.
     /// Can be overridden by the class.
     pub fn insert_offset_attr(&mut self, offset: FastAttr, object: FSRObject<'_>) {
+        self.bump_version();
         if self.offset_attrs.len() <= offset as usize {
             self.offset_attrs.resize_with(offset as usize + 1, || None);
         }
@@ -295,6 +318,7 @@ impl FSRClass {
     }
 
     pub fn insert_offset_attr_obj_id(&mut self, offset: FastAttr, id: ObjId) {
+        self.bump_version();
         if self.offset_attrs.len() <= offset as usize {
             self.offset_attrs.resize_with(offset as usize + 1, || None);
         }
@@ -304,6 +328,7 @@ impl FSRClass {
     }
 
     pub fn insert_attr_id(&mut self, name: &str, obj_id: ObjId) {
+        self.bump_version();
         if let Some(v) = self.attrs.get_mut(name) {
             v.store(obj_id, Ordering::Relaxed);
         } else {
@@ -12,7 +12,11 @@ use ahash::AHashMap;
 
 use crate::{
     backend::{
-        compiler::bytecode::{Bytecode, BytecodeArg, FSRSTypeInfo},
+        compiler::{
+            bytecode::{Bytecode, BytecodeArg, FSRSTypeInfo},
+            jit::cache,
+            verifier::BytecodeVerifier,
+        },
         vm::virtual_machine::gid,
     },
     utils::error::FSRError,
@@ -29,6 +33,8 @@ pub struct FSRCode {
     name: String,
     bytecode: Bytecode,
     pub(crate) module: ObjId,
+    /// hash of the source of the module, keys the jit cache
+    source_hash: u64,
 }
 
 impl Debug for FSRCode {
@@ -64,13 +70,16 @@ impl FSRCode {
         code: &str,
         module: ObjId,
     ) -> Result<(HashMap<String, FSRObject<'a>>, FSRSTypeInfo), FSRError> {
-        let bytecode = Bytecode::compile(name, code).unwrap();
+        let bytecode = Bytecode::compile(name, code)?;
+        BytecodeVerifier::verify_result(&bytecode)?;
+        let source_hash = cache::source_hash(code);
         let mut res = HashMap::new();
         for code in bytecode.bytecode_map {
             let code = Self {
                 name: code.0.to_string(),
                 bytecode: code.1,
                 module,
+                source_hash,
             };
 
             let mut object = FSRObject::new();
@@ -88,6 +97,10 @@ impl FSRCode {
         self.bytecode.get(first_ip)
     }
 
+    pub(crate) fn source_hash(&self) -> u64 {
+        self.source_hash
+    }
+
     pub fn get_bytecode(&self) -> &Bytecode {
         &self.bytecode
     }
@@ -342,6 +342,7 @@ pub fn fsr_fn_hashmap_insert(
         ));
     }
     let hashmap = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashmap");
+    hashmap.check_mutable()?;
     let key = args[1];
     let value = args[2];
     if hashmap.area.is_long() {
@@ -512,6 +513,7 @@ pub fn fsr_fn_hashmap_set_default(
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
     let hashmap = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashmap");
+    hashmap.check_mutable()?;
     let default_value = args[1];
     if hashmap.area.is_long() {
         let default_value_obj = FSRObject::id_to_obj(default_value);
@@ -538,6 +540,7 @@ pub fn fsr_fn_hashmap_remove(
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
     let hashmap = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashmap");
+    hashmap.check_mutable()?;
     let key = args[1];
 
     if let FSRValue::Extension(any) = &mut hashmap.value {
@@ -599,6 +602,15 @@ impl FSRHashMap {
         self.len() == 0
     }
 
+    /// Key and value of each entry
+    pub fn iter_items(&self) -> impl Iterator<Item = (ObjId, ObjId)> + '_ {
+        self.segment_map
+            .iter()
+            .flat_map(|s| s.hashmap.values())
+            .flatten()
+            .map(|(k, v)| (k.load(Ordering::Relaxed), v.load(Ordering::Relaxed)))
+    }
+
     pub fn get_item(&self, key: u64) -> Option<&SmallVec<[(AtomicObjId, AtomicObjId); 1]>> {
         for segment in self.segment_map.iter() {
             if let Some(value) = segment.get(key) {
@@ -830,6 +842,13 @@ impl FSRHashMap {
         None
     }
 
+    /// Value of `key`, the default value of the map when the key is missing
+    pub fn get_or_default(&self, key: ObjId, thread: &mut FSRThreadRuntime) -> Option<ObjId> {
+        self.get(key, thread)
+            .map(|v| v.load(std::sync::atomic::Ordering::Relaxed))
+            .or(self.default_value)
+    }
+
     pub fn remove(&mut self, key: ObjId, thread: &mut FSRThreadRuntime) {
         let key_obj = FSRObject::id_to_obj(key);
         let hash_fn_id = key_obj
@@ -341,6 +341,7 @@ pub fn fsr_fn_hashset_insert(
         ));
     }
     let hashset = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashset");
+    hashset.check_mutable()?;
     let key = args[1];
     if hashset.area.is_long() {
         let key_obj = FSRObject::id_to_obj(key);
@@ -436,6 +437,7 @@ pub fn fsr_fn_hashset_remove(
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
     let hashset = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a any and hashset");
+    hashset.check_mutable()?;
     let key = args[1];
 
     if let FSRValue::Extension(any) = &mut hashset.value {
@@ -526,6 +528,14 @@ impl FSRHashSet {
         self.len() == 0
     }
 
+    pub fn iter_keys(&self) -> impl Iterator<Item = ObjId> + '_ {
+        self.segment_map
+            .iter()
+            .flat_map(|s| s.hashset.values())
+            .flatten()
+            .map(|k| k.load(Ordering::Relaxed))
+    }
+
     pub fn get_item(&self, key: u64) -> Option<&SmallVec<[(AtomicObjId); 1]>> {
         for segment in self.segment_map.iter() {
             if let Some(value) = segment.get(key) {
@@ -2,9 +2,10 @@ use std::{
     borrow::Cow,
     collections::HashMap,
     fmt::{Debug, Formatter},
+    marker::PhantomData,
     sync::{
-        Arc,
-        atomic::{AtomicBool, AtomicI16, Ordering},
+        Arc, Mutex, OnceLock, RwLock,
+        atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicUsize, Ordering},
     },
 };
 
@@ -12,7 +13,7 @@ use ahash::AHashMap;
 
 use crate::{
     backend::{
-        compiler::bytecode::Bytecode,
+        compiler::{bytecode::Bytecode, jit::baseline::DeoptPoint},
         vm::{
             thread::{FSRThreadRuntime, IndexMap, IndexMapObj, index_map_obj_to_ptr},
             virtual_machine::gid,
@@ -57,13 +58,218 @@ pub enum FSRnE {
     FSRFn(FSRFnInner),
 }
 
+/// Calls before a script function is compiled by the baseline tier
+pub const BASELINE_CALL_THRESHOLD: u32 = 1000;
+/// Loop iterations before a script function is compiled by the baseline tier
+pub const BASELINE_BACKEDGE_THRESHOLD: u32 = 10000;
+/// Calls before baseline code is recompiled with the recorded operand types
+pub const SPECIALIZE_CALL_THRESHOLD: u32 = 5000;
+/// Loop iterations before baseline code is recompiled with the recorded operand types
+pub const SPECIALIZE_BACKEDGE_THRESHOLD: u32 = 50000;
+/// Deoptimizations before a function stays in the baseline tier
+pub const MAX_DEOPT: u32 = 3;
+/// Loop iterations before the interpreter moves a running loop to compiled code
+pub const OSR_BACKEDGE_THRESHOLD: u32 = 5000;
+
+/// OSR slot of a loop which stays in the interpreter
+const NO_OSR: usize = 1;
+
+/// Operand types seen by one operator of baseline code
+#[derive(Debug, Default)]
+pub struct TypeFeedback(AtomicU8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedbackType {
+    Integer,
+    Float,
+}
+
+impl TypeFeedback {
+    const INTEGER: u8 = 1;
+    const FLOAT: u8 = 2;
+    const OTHER: u8 = 4;
+
+    fn kind(id: ObjId) -> u8 {
+        match FSRObject::id_to_obj(id).value {
+            FSRValue::Integer(_) => Self::INTEGER,
+            FSRValue::Float(_) => Self::FLOAT,
+            _ => Self::OTHER,
+        }
+    }
+
+    pub fn record(&self, left: ObjId, right: ObjId) {
+        let seen = Self::kind(left) | Self::kind(right);
+        if self.0.load(Ordering::Relaxed) & seen != seen {
+            self.0.fetch_or(seen, Ordering::Relaxed);
+        }
+    }
+
+    /// The single type seen for both operands, none if the operator was not
+    /// run or saw anything else
+    pub fn observed(&self) -> Option<FeedbackType> {
+        match self.0.load(Ordering::Relaxed) {
+            Self::INTEGER => Some(FeedbackType::Integer),
+            Self::FLOAT => Some(FeedbackType::Float),
+            _ => None,
+        }
+    }
+}
+
+/// Hotness counters and compiled code of a script function
 pub struct FSRJitInfo {
-    run_count: AtomicI16,
+    call_count: AtomicU32,
+    backedge_count: AtomicU32,
+    /// address of the baseline code, 0 if not compiled
+    baseline_code: AtomicUsize,
+    /// set when the baseline tier can not compile the function
+    no_baseline: AtomicBool,
+    /// address of the code specialized by the type feedback, 0 if not compiled
+    specialized_code: AtomicUsize,
+    deopt_count: AtomicU32,
+    /// one entry per profiled operator, filled by the baseline code
+    feedback: OnceLock<Box<[TypeFeedback]>>,
+    /// frame states read by specialized code when a guard fails, kept as long
+    /// as the function since old code may still run in outer calls
+    #[allow(clippy::vec_box, reason = "jitted code embeds the address of each point")]
+    deopt_points: Mutex<Vec<Box<DeoptPoint>>>,
+    /// OSR code by loop header line, read at every loop iteration so it
+    /// takes no lock: 0 while the loop was not compiled, `NO_OSR` if it can not be
+    osr_code: OnceLock<Box<[AtomicUsize]>>,
 }
 
 impl FSRJitInfo {
-    pub fn call_once(&self) {
-        self.run_count.fetch_add(1, Ordering::Relaxed);
+    pub fn new() -> Self {
+        Self {
+            call_count: AtomicU32::new(0),
+            backedge_count: AtomicU32::new(0),
+            baseline_code: AtomicUsize::new(0),
+            no_baseline: AtomicBool::new(false),
+            specialized_code: AtomicUsize::new(0),
+            deopt_count: AtomicU32::new(0),
+            feedback: OnceLock::new(),
+            deopt_points: Mutex::new(vec![]),
+            osr_code: OnceLock::new(),
+        }
+    }
+
+    /// Count a call, returns true once the function is hot enough to compile
+    pub fn call_once(&self) -> bool {
+        // not a locked add, a count lost to another thread only delays the tier up
+        let calls = self.call_count.load(Ordering::Relaxed) + 1;
+        self.call_count.store(calls, Ordering::Relaxed);
+        (calls >= BASELINE_CALL_THRESHOLD
+            || self.backedge_count.load(Ordering::Relaxed) >= BASELINE_BACKEDGE_THRESHOLD)
+            && !self.no_baseline.load(Ordering::Relaxed)
+    }
+
+    /// Count a loop iteration run by the interpreter
+    #[inline]
+    pub fn backedge(&self) {
+        let count = self.backedge_count.load(Ordering::Relaxed);
+        self.backedge_count.store(count + 1, Ordering::Relaxed);
+    }
+
+    /// Counter incremented by baseline code at each loop iteration
+    pub(crate) fn backedge_counter(&self) -> &AtomicU32 {
+        &self.backedge_count
+    }
+
+    pub fn get_call_count(&self) -> u32 {
+        self.call_count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_backedge_count(&self) -> u32 {
+        self.backedge_count.load(Ordering::Relaxed)
+    }
+
+    pub fn get_baseline_code(&self) -> Option<usize> {
+        match self.baseline_code.load(Ordering::Relaxed) {
+            0 => None,
+            code => Some(code),
+        }
+    }
+
+    pub fn set_baseline_code(&self, code: Option<usize>) {
+        match code {
+            Some(code) => self.baseline_code.store(code, Ordering::Relaxed),
+            None => self.no_baseline.store(true, Ordering::Relaxed),
+        }
+    }
+
+    /// Each deoptimization doubles the calls needed before the next try
+    pub fn should_specialize(&self) -> bool {
+        let deopts = self.deopt_count.load(Ordering::Relaxed);
+        deopts < MAX_DEOPT
+            && (self.get_call_count() >= SPECIALIZE_CALL_THRESHOLD << deopts
+                || self.get_backedge_count() >= SPECIALIZE_BACKEDGE_THRESHOLD << deopts)
+    }
+
+    pub fn get_specialized_code(&self) -> Option<usize> {
+        match self.specialized_code.load(Ordering::Relaxed) {
+            0 => None,
+            code => Some(code),
+        }
+    }
+
+    pub fn set_specialized_code(&self, code: Option<usize>, deopt_points: Vec<Box<DeoptPoint>>) {
+        match code {
+            Some(code) => {
+                self.deopt_points.lock().unwrap().extend(deopt_points);
+                self.specialized_code.store(code, Ordering::Relaxed);
+            }
+            // nothing to specialize, never try again
+            None => self.deopt_count.store(MAX_DEOPT, Ordering::Relaxed),
+        }
+    }
+
+    /// A guard of the specialized code failed, go back to baseline code
+    pub fn deopt(&self) {
+        self.specialized_code.store(0, Ordering::Relaxed);
+        self.deopt_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Specialized code failed too often or there is nothing to specialize
+    pub fn is_settled(&self) -> bool {
+        self.deopt_count.load(Ordering::Relaxed) >= MAX_DEOPT
+    }
+
+    pub fn get_deopt_count(&self) -> u32 {
+        self.deopt_count.load(Ordering::Relaxed)
+    }
+
+    pub fn init_feedback(&self, sites: usize) -> &[TypeFeedback] {
+        self.feedback
+            .get_or_init(|| (0..sites).map(|_| TypeFeedback::default()).collect())
+    }
+
+    pub fn get_feedback(&self) -> Option<&[TypeFeedback]> {
+        self.feedback.get().map(|x| x.as_ref())
+    }
+
+    /// OSR code of the loop at `header`, `Some(0)` once the loop failed to compile
+    #[inline]
+    pub fn get_osr_code(&self, header: usize) -> Option<usize> {
+        match self.osr_code.get()?.get(header)?.load(Ordering::Relaxed) {
+            0 => None,
+            NO_OSR => Some(0),
+            code => Some(code),
+        }
+    }
+
+    /// `lines` is the number of lines of the code the loop is in
+    pub fn set_osr_code(&self, header: usize, code: Option<usize>, lines: usize) {
+        let slots = self
+            .osr_code
+            .get_or_init(|| (0..lines).map(|_| AtomicUsize::new(0)).collect());
+        if let Some(slot) = slots.get(header) {
+            slot.store(code.unwrap_or(NO_OSR), Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for FSRJitInfo {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -72,9 +278,12 @@ pub struct FSRFn<'a> {
     pub(crate) code: ObjId,
     pub(crate) closure_fn: Vec<ObjId>, // fn define chain
     /// Store cells for closure variables
-    /// The key is the variable name, and the value is the object id
-    pub(crate) store_cells: AHashMap<&'a str, AtomicObjId>,
+    /// The key is the variable name, and the value is the object id,
+    /// locked since functions of the module are called by every thread
+    pub(crate) store_cells: RwLock<AHashMap<String, AtomicObjId>>,
     pub(crate) const_map: Arc<IndexMapObj>,
+    pub(crate) jit_info: FSRJitInfo,
+    _life: PhantomData<&'a ()>,
 }
 
 impl Debug for FSRFn<'_> {
@@ -96,17 +305,34 @@ pub struct FnDesc {
 }
 
 impl<'a> FSRFn<'a> {
+    /// Cell of this function only, not of its define chain
+    pub fn get_cell(&self, name: &str) -> Option<ObjId> {
+        let cells = self.store_cells.read().unwrap();
+        cells.get(name).map(|s| s.load(Ordering::Relaxed))
+    }
+
+    pub fn set_cell(&self, name: &str, obj_id: ObjId) {
+        if let Some(cell) = self.store_cells.read().unwrap().get(name) {
+            cell.store(obj_id, Ordering::Relaxed);
+            return;
+        }
+
+        self.store_cells
+            .write()
+            .unwrap()
+            .insert(name.to_string(), AtomicObjId::new(obj_id));
+    }
+
     pub fn get_closure_var(&self, name: &str) -> Option<ObjId> {
-        let obj = self.store_cells.get(name);
-        if let Some(s) = obj {
-            return Some(s.load(Ordering::Relaxed));
+        if let Some(s) = self.get_cell(name) {
+            return Some(s);
         }
         for i in self.closure_fn.iter().rev() {
             let obj = FSRObject::id_to_obj(*i);
             if let FSRValue::Function(f) = &obj.value {
                 //println!("check closure fn: {:?}", f.store_cells);
-                let v = match f.store_cells.get(name) {
-                    Some(s) => s.load(Ordering::Relaxed),
+                let v = match f.get_cell(name) {
+                    Some(s) => s,
                     None => continue,
                 };
                 return Some(v);
@@ -116,10 +342,16 @@ impl<'a> FSRFn<'a> {
     }
 
     pub fn get_references(&self) -> impl Iterator<Item = ObjId> + '_ {
-        self.store_cells
+        let cells = self.store_cells.read().unwrap();
+        let cells = cells
             .values()
             .map(|s| s.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        cells
+            .into_iter()
             .chain(self.const_map.iter().cloned())
+            // closure variables are read from the cells of the define chain
+            .chain(self.closure_fn.iter().cloned())
     }
 
     pub fn as_str(&self) -> String {
@@ -166,8 +398,10 @@ impl<'a> FSRFn<'a> {
             fn_def: FSRnE::FSRFn(fn_obj),
             code: 0,
             closure_fn: vec![],
-            store_cells: AHashMap::new(),
+            store_cells: RwLock::new(AHashMap::new()),
             const_map: Arc::new(IndexMapObj::new()),
+            jit_info: FSRJitInfo::new(),
+            _life: PhantomData,
         };
         FSRValue::Function(Box::new(v))
     }
@@ -195,8 +429,10 @@ impl<'a> FSRFn<'a> {
             fn_def: FSRnE::FSRFn(fn_obj),
             code: fn_desc.code_obj,
             closure_fn: c,
-            store_cells: AHashMap::new(),
+            store_cells: RwLock::new(AHashMap::new()),
             const_map: fn_desc.const_map,
+            jit_info: FSRJitInfo::new(),
+            _life: PhantomData,
         };
         FSRValue::Function(Box::new(v))
     }
@@ -206,8 +442,10 @@ impl<'a> FSRFn<'a> {
             fn_def: FSRnE::RustFn((name.to_string(), f)),
             code: 0,
             closure_fn: vec![],
-            store_cells: AHashMap::new(),
+            store_cells: RwLock::new(AHashMap::new()),
             const_map: Arc::new(IndexMapObj::new()),
+            jit_info: FSRJitInfo::new(),
+            _life: PhantomData,
         };
         FSRObject {
             value: FSRValue::Function(Box::new(v)),
@@ -217,6 +455,7 @@ impl<'a> FSRFn<'a> {
             area: Area::Global,
             write_barrier: AtomicBool::new(true),
             gc_count: 0,
+            heap: 0,
         }
     }
 
@@ -225,8 +464,10 @@ impl<'a> FSRFn<'a> {
             fn_def: FSRnE::RustFn((name.to_string(), f)),
             code: 0,
             closure_fn: vec![],
-            store_cells: AHashMap::new(),
+            store_cells: RwLock::new(AHashMap::new()),
             const_map: Arc::new(IndexMapObj::new()),
+            jit_info: FSRJitInfo::new(),
+            _life: PhantomData,
         };
 
         FSRValue::Function(Box::new(v))
@@ -272,7 +513,13 @@ impl<'a> FSRFn<'a> {
                 return v;
             }
             FSRnE::FSRFn(f) => {
+                let jit_code = thread.jit_code(self, f);
                 self.fsr_call_args_settting(f, args, thread, fn_id);
+                if let Some(code) = jit_code {
+                    let v = thread.run_jit_code(code, f)?;
+                    return Ok(FSRRetValue::GlobalId(v));
+                }
+
                 let v = FSRThreadRuntime::call_fn(thread, f)?;
                 return Ok(FSRRetValue::GlobalId(v));
             }
@@ -7,8 +7,11 @@ use crate::{
         types::{asynclib::future::poll_future, list::FSRList},
         vm::{thread::FSRThreadRuntime, virtual_machine::gid},
     },
-    std::iterator::{
-        enumerate::FSREnumerateIter, filter_iter::FSRFilterIter, map_iter::FSRMapIter,
+    std::{
+        core::pool::{fsr_fn_par_filter, fsr_fn_par_for_each, fsr_fn_par_map},
+        iterator::{
+            enumerate::FSREnumerateIter, filter_iter::FSRFilterIter, map_iter::FSRMapIter,
+        },
     },
     to_rs_list,
     utils::error::{FSRErrCode, FSRError},
@@ -49,6 +52,7 @@ pub fn next_obj(
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let mut result = None;
     if let FSRValue::Iterator(it) = &mut self_obj.value {
         let from_obj = FSRObject::id_to_obj(it.obj);
@@ -97,6 +101,7 @@ pub fn map(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let map_fn_id = args[1];
     let map_iterator = FSRMapIter {
         callback: map_fn_id,
@@ -126,6 +131,7 @@ pub fn filter(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let filter_fn_id = args[1];
     let filter_iterator = FSRFilterIter {
         filter: filter_fn_id,
@@ -155,6 +161,7 @@ pub fn enumerate(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let enumerate_iterator = FSREnumerateIter {
         prev_iterator: args[0],
         index: 0,
@@ -183,6 +190,7 @@ pub fn any(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let any_fn_id = args[1];
     let any_fn = FSRObject::id_to_obj(any_fn_id);
     if let FSRValue::Iterator(it) = &mut self_obj.value {
@@ -218,6 +226,7 @@ pub fn all(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let all_fn_id = args[1];
     let all_fn = FSRObject::id_to_obj(all_fn_id);
     if let FSRValue::Iterator(it) = &mut self_obj.value {
@@ -253,6 +262,7 @@ pub fn as_list(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     if let FSRValue::Iterator(it) = &mut self_obj.value {
         if let Some(it) = it.iterator.as_mut() {
             let mut list = vec![];
@@ -283,6 +293,7 @@ pub fn count(
         ));
     }
     let self_obj = FSRObject::id_to_mut_obj(args[0]).expect("msg: not a iterator");
+    self_obj.check_mutable()?;
     let FSRValue::Iterator(it) = &mut self_obj.value else {
         return Err(FSRError::new(
             "msg: object is not an iterator",
@@ -331,6 +342,13 @@ impl FSRInnerIterator {
         cls.insert_attr("all", all);
         let count = FSRFn::from_rust_fn_static(count, "inner_iterator_count");
         cls.insert_attr("count", count);
+        let par_map = FSRFn::from_rust_fn_static(fsr_fn_par_map, "inner_iterator_par_map");
+        cls.insert_attr("par_map", par_map);
+        let par_filter = FSRFn::from_rust_fn_static(fsr_fn_par_filter, "inner_iterator_par_filter");
+        cls.insert_attr("par_filter", par_filter);
+        let par_for_each =
+            FSRFn::from_rust_fn_static(fsr_fn_par_for_each, "inner_iterator_par_for_each");
+        cls.insert_attr("par_for_each", par_for_each);
         cls
     }
 
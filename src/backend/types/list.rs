@@ -15,6 +15,7 @@ use crate::{
         },
         vm::{thread::FSRThreadRuntime, virtual_machine::gid},
     },
+    std::core::pool::{fsr_fn_par_filter, fsr_fn_par_for_each, fsr_fn_par_map},
     to_rs_list,
     utils::error::{FSRErrCode, FSRError},
 };
@@ -207,6 +208,7 @@ pub fn set_item(
     let target_id = args[2];
 
     let obj = FSRObject::id_to_mut_obj(self_id).unwrap();
+    obj.check_mutable()?;
     let index_obj = FSRObject::id_to_obj(index_id);
     if obj.area.is_long() && FSRObject::id_to_obj(target_id).area == Area::Minjor {
         obj.set_write_barrier(true);
@@ -241,6 +243,7 @@ fn swap(
     let index2_id = args[2];
 
     let obj = FSRObject::id_to_mut_obj(self_id).unwrap();
+    obj.check_mutable()?;
     let index1_obj = FSRObject::id_to_obj(index1_id);
     let index2_obj = FSRObject::id_to_obj(index2_id);
     if obj.area.is_long()
@@ -278,6 +281,7 @@ pub fn sort(
     }
     let obj_id = args[0];
     let obj = FSRObject::id_to_mut_obj(obj_id).expect("msg: not a list");
+    obj.check_mutable()?;
     let FSRValue::List(l) = &mut obj.value else {
         return Err(FSRError::new(
             "sort args error not a list",
@@ -317,6 +321,7 @@ pub fn sort_by(
     }
     let obj_id = args[0];
     let obj = FSRObject::id_to_mut_obj(obj_id).expect("msg: not a list");
+    obj.check_mutable()?;
     let compare_fn_id = args[1];
     let compare_fn = FSRObject::id_to_obj(compare_fn_id);
     if let FSRValue::List(l) = &mut obj.value {
@@ -346,6 +351,7 @@ pub fn reverse(
     let args = to_rs_list!(args, len);
     let obj_id = args[0];
     let obj = FSRObject::id_to_mut_obj(obj_id).expect("msg: not a list");
+    obj.check_mutable()?;
     if let FSRValue::List(l) = &mut obj.value {
         l.vs.reverse();
     } else {
@@ -371,6 +377,7 @@ pub fn sort_key(
     }
     let obj_id = args[0];
     let obj = FSRObject::id_to_mut_obj(obj_id).expect("msg: not a list");
+    obj.check_mutable()?;
     let key_fn_id = args[1];
     let key_fn = FSRObject::id_to_obj(key_fn_id);
     let mut error = None;
@@ -433,6 +440,7 @@ pub fn push(
     }
     let self_id = args[0];
     let obj = FSRObject::id_to_mut_obj(self_id).expect("msg: not a list");
+    obj.check_mutable()?;
     if obj.area.is_long() && FSRObject::id_to_obj(args[1]).area == Area::Minjor {
         obj.set_write_barrier(true);
     }
@@ -462,6 +470,7 @@ pub fn extend(
     let extend_list_id = args[1];
     let extend_list_obj = FSRObject::id_to_mut_obj(extend_list_id).unwrap();
     let obj = FSRObject::id_to_mut_obj(self_id).expect("msg: not a list");
+    obj.check_mutable()?;
     if obj.area.is_long() && extend_list_obj.area == Area::Minjor {
         obj.set_write_barrier(true);
     }
@@ -651,6 +660,12 @@ impl FSRList {
         cls.insert_attr("extend", extend_fn);
         let swap_fn = FSRFn::from_rust_fn_static(swap, "list_swap");
         cls.insert_attr("swap", swap_fn);
+        let par_map = FSRFn::from_rust_fn_static(fsr_fn_par_map, "list_par_map");
+        cls.insert_attr("par_map", par_map);
+        let par_filter = FSRFn::from_rust_fn_static(fsr_fn_par_filter, "list_par_filter");
+        cls.insert_attr("par_filter", par_filter);
+        let par_for_each = FSRFn::from_rust_fn_static(fsr_fn_par_for_each, "list_par_for_each");
+        cls.insert_attr("par_for_each", par_for_each);
         cls
     }
 
@@ -662,6 +677,18 @@ impl FSRList {
         self.vs.iter().collect()
     }
 
+    pub fn len(&self) -> usize {
+        self.vs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vs.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&AtomicObjId> {
+        self.vs.get(index)
+    }
+
     pub fn new_value(vs: Vec<ObjId>) -> FSRValue<'static> {
         let vs = vs.into_iter().map(AtomicObjId::new).collect::<Vec<_>>();
         FSRValue::List(Box::new(Self { vs }))
@@ -1,8 +1,18 @@
-use std::{collections::HashMap, fmt::Debug, ptr::addr_of, sync::{Arc, atomic::AtomicUsize}};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    ptr::addr_of,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
 
 use ahash::AHashMap;
 
-use crate::backend::{compiler::bytecode::{FSRSType, FSRSTypeInfo, FSRStruct, FnCallSig}, vm::{thread::FSRThreadRuntime, virtual_machine::gid}};
+use frontend::ast::token::base::FSRTypeName;
+
+use crate::backend::{compiler::bytecode::{FSRSType, FSRSTypeInfo, FSRStruct, FnCallSig}, vm::{share::freeze, thread::{FSRThreadRuntime, MAIN_FN}, virtual_machine::gid}};
 
 use super::{base::{AtomicObjId, GlobalObj, FSRObject, FSRValue, ObjId}, class::FSRClass};
 
@@ -12,7 +22,11 @@ pub type NewModuleFn = fn(&mut FSRThreadRuntime) -> FSRValue<'static>;
 pub struct FSRModule<'a> {
     name: String,
     fn_map: HashMap<String, FSRObject<'a>>,
-    pub(crate) object_map: AHashMap<String, AtomicObjId>,
+    /// locked since the functions of a module are called by every thread
+    pub(crate) object_map: RwLock<AHashMap<String, AtomicObjId>>,
+    /// set once a thread is spawned with a function of the module, objects
+    /// registered from then on are frozen
+    shared: AtomicBool,
     pub(crate) jit_code_map: Vec<(Option<Arc<FSRSType>>, String, AtomicUsize)>, // JITed code address map
     pub(crate) type_info: FSRSTypeInfo
     // pub(crate) const_table: Vec<Option<ObjId>>,
@@ -35,26 +49,61 @@ impl Debug for FSRModule<'_> {
 
 impl<'a> FSRModule<'a> {
 
+    fn struct_name(s: &FSRSType) -> Option<&str> {
+        match s {
+            FSRSType::Struct(s) => Some(&s.name),
+            FSRSType::Ptr(inner) => match inner.as_ref() {
+                FSRSType::Struct(s) => Some(&s.name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Methods are matched by struct name, a struct type seen by a method
+    /// body may be a snapshot taken before all its methods were known
     pub fn get_jit_code_map(&self, s: Option<Arc<FSRSType>>, name: &str) -> Option<&AtomicUsize> {
+        let want = s.as_deref().map(Self::struct_name);
         for (struct_opt, fn_name, addr) in &self.jit_code_map {
-            if struct_opt.is_none() && s.is_none() && fn_name == name {
-                return Some(addr);
+            if fn_name != name {
+                continue;
             }
-            if let (Some(st1), Some(st2)) = (struct_opt, &s) {
-                if st1.eq(st2) && fn_name == name {
-                    return Some(addr);
-                }
 
-                if let FSRSType::Ptr(inner) = st2.as_ref() {
-                    if st1.eq(inner) && fn_name == name {
-                        return Some(addr);
-                    }
-                }
+            if struct_opt.as_deref().map(Self::struct_name) == want {
+                return Some(addr);
             }
         }
         None
     }
 
+    /// Code slot of a static function by its identify name, `Struct::method`
+    /// for methods
+    pub fn get_jit_code_slot(&self, identify_name: &str) -> Option<&AtomicUsize> {
+        match identify_name.split_once("::") {
+            Some((struct_name, fn_name)) => {
+                let father_type = self.type_info.get_type(&FSRTypeName::new(struct_name))?;
+                self.get_jit_code_map(Some(father_type), fn_name)
+            }
+            None => self.get_jit_code_map(None, identify_name),
+        }
+    }
+
+    /// Add an empty code slot for a static function, filled once the function
+    /// is compiled or loaded
+    pub fn add_jit_code_slot(&mut self, identify_name: &str) {
+        let slot = match identify_name.split_once("::") {
+            Some((struct_name, fn_name)) => {
+                let father_type = self
+                    .type_info
+                    .get_type(&FSRTypeName::new(struct_name))
+                    .unwrap();
+                (Some(father_type), fn_name.to_string(), AtomicUsize::new(0))
+            }
+            None => (None, identify_name.to_string(), AtomicUsize::new(0)),
+        };
+        self.jit_code_map.push(slot);
+    }
+
     /// Get the JITed function address pointer by name
     /// # Arguments
     /// * `name` - The name of the function
@@ -86,7 +135,8 @@ impl<'a> FSRModule<'a> {
         let module = FSRModule {
             name: name.to_string(),
             fn_map: HashMap::new(),
-            object_map: AHashMap::new(),
+            object_map: RwLock::new(AHashMap::new()),
+            shared: AtomicBool::new(false),
             jit_code_map: vec![],
             type_info: FSRSTypeInfo::new(),
             // const_table: vec![],
@@ -101,7 +151,8 @@ impl<'a> FSRModule<'a> {
         let module = FSRModule {
             name: name.to_string(),
             fn_map: HashMap::new(),
-            object_map: AHashMap::new(),
+            object_map: RwLock::new(AHashMap::new()),
+            shared: AtomicBool::new(false),
             jit_code_map: vec![],
             type_info: FSRSTypeInfo::new(),
             // const_table: vec![],
@@ -113,7 +164,8 @@ impl<'a> FSRModule<'a> {
         FSRModule {
             name: name.to_string(),
             fn_map: HashMap::new(),
-            object_map: AHashMap::new(),
+            object_map: RwLock::new(AHashMap::new()),
+            shared: AtomicBool::new(false),
             jit_code_map: vec![],
             type_info: FSRSTypeInfo::new(),
             // const_table: vec![],
@@ -138,13 +190,53 @@ impl<'a> FSRModule<'a> {
         self.fn_map.iter()
     }
 
-    pub fn register_object(&mut self, name: &'a str, obj_id: ObjId) {
+    /// Identify names of the `@static` functions and struct methods, sorted
+    /// so every build of a module lists them in the same order
+    pub fn static_fn_names(&self) -> Vec<String> {
+        let mut names = self
+            .fn_map
+            .iter()
+            .filter(|(name, obj)| match &obj.value {
+                FSRValue::Code(code) => {
+                    name.as_str() != MAIN_FN && code.get_bytecode().fn_info.is_jit
+                }
+                _ => false,
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    pub fn register_object(&self, name: &str, obj_id: ObjId) {
+        if self.is_shared() && !FSRObject::is_sp_object(obj_id) {
+            // other threads may read the object from now on
+            freeze(obj_id, FSRObject::id_to_obj(obj_id).heap);
+        }
+
         self.object_map
+            .write()
+            .unwrap()
             .insert(name.to_string(), AtomicObjId::new(obj_id));
     }
 
-    pub fn get_object(&self, name: &str) -> Option<&AtomicObjId> {
-        self.object_map.get(name)
+    pub fn get_object(&self, name: &str) -> Option<ObjId> {
+        let object_map = self.object_map.read().unwrap();
+        object_map.get(name).map(|x| x.load(Ordering::Relaxed))
+    }
+
+    pub fn object_ids(&self) -> Vec<ObjId> {
+        let object_map = self.object_map.read().unwrap();
+        object_map.values().map(|x| x.load(Ordering::Relaxed)).collect()
+    }
+
+    pub fn is_shared(&self) -> bool {
+        self.shared.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the module was not shared before
+    pub fn set_shared(&self) -> bool {
+        !self.shared.swap(true, Ordering::AcqRel)
     }
 
     // pub fn insert_const(&mut self, const_index: usize, obj: ObjId) {
@@ -4,7 +4,7 @@ use crate::{
     backend::{
         compiler::bytecode::FastAttr, memory::GarbageCollector, types::base::FSRObject,
         vm::{thread::FSRThreadRuntime, virtual_machine::gid},
-    }, std::iterator::{enumerate::FSREnumerateIter, filter_iter::FSRFilterIter, map_iter::FSRMapIter}, to_rs_list, utils::error::FSRError
+    }, std::{core::pool::{fsr_fn_par_filter, fsr_fn_par_for_each, fsr_fn_par_map}, iterator::{enumerate::FSREnumerateIter, filter_iter::FSRFilterIter, map_iter::FSRMapIter}}, to_rs_list, utils::error::FSRError
 };
 
 use super::{
@@ -267,6 +267,12 @@ impl FSRRange {
         r.insert_attr("contains", contains);
         let true_iter = FSRFn::from_rust_fn_static(true_iter_obj, "range_true_iter");
         r.insert_attr("true_iter", true_iter);
+        let par_map = FSRFn::from_rust_fn_static(fsr_fn_par_map, "range_par_map");
+        r.insert_attr("par_map", par_map);
+        let par_filter = FSRFn::from_rust_fn_static(fsr_fn_par_filter, "range_par_filter");
+        r.insert_attr("par_filter", par_filter);
+        let par_for_each = FSRFn::from_rust_fn_static(fsr_fn_par_for_each, "range_par_for_each");
+        r.insert_attr("par_for_each", par_for_each);
         r
     }
 
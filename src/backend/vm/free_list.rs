@@ -17,7 +17,9 @@ impl FrameFreeList {
     }
 
     #[cfg_attr(feature = "more_inline", inline(always))]
-    pub fn free(&mut self, frame: Box<CallFrame>) {
+    pub fn free(&mut self, mut frame: Box<CallFrame>) {
+        // the function returned, its locks must not wait for the frame reuse
+        frame.release_guards();
         self.list.push(frame);
     }
 
@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering, fence};
+
+use crate::backend::types::{base::AtomicObjId, class::FSRClass};
+
+/// Number of receiver classes a site remembers before it turns megamorphic.
+const POLY_SIZE: usize = 4;
+
+#[derive(Debug, Default)]
+struct CacheEntry {
+    cls: AtomicUsize,
+    version: AtomicU64,
+    slot: AtomicUsize,
+}
+
+/// Slots of an attribute in the classes seen by one `BinaryDot` or
+/// `CallMethod` site. Sites run on several threads, so the cache is a small
+/// seqlock: a fill makes `seq` odd and a probe that saw a fill misses.
+#[derive(Debug, Default)]
+pub struct AttrInlineCache {
+    /// odd while an entry is written
+    seq: AtomicU32,
+    entries: [CacheEntry; POLY_SIZE],
+    len: AtomicUsize,
+    megamorphic: AtomicBool,
+}
+
+impl Clone for AttrInlineCache {
+    /// A cache belongs to one site, a copied site starts empty
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl AttrInlineCache {
+    #[inline(always)]
+    fn probe<'a>(&self, cls: &'a FSRClass) -> Option<&'a AtomicObjId> {
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 == 1 {
+            return None;
+        }
+
+        let key = cls as *const FSRClass as usize;
+        let version = cls.get_version();
+        let len = self.len.load(Ordering::Relaxed).min(POLY_SIZE);
+        for entry in &self.entries[..len] {
+            if entry.cls.load(Ordering::Relaxed) == key
+                && entry.version.load(Ordering::Relaxed) == version
+            {
+                let slot = entry.slot.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) != seq {
+                    return None;
+                }
+
+                // Safety: the slot lives in `cls.attrs`, which has not been
+                // mutated since the entry was filled (same version).
+                return Some(unsafe { &*(slot as *const AtomicObjId) });
+            }
+        }
+
+        None
+    }
+
+    fn fill(&self, cls: &FSRClass, slot: &AtomicObjId) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        // another thread is filling this site, the lookup stays uncached
+        if seq & 1 == 1
+            || self
+                .seq
+                .compare_exchange(seq, seq.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        fence(Ordering::Release);
+
+        let key = cls as *const FSRClass as usize;
+        let write = |entry: &CacheEntry| {
+            entry.cls.store(key, Ordering::Relaxed);
+            entry.version.store(cls.get_version(), Ordering::Relaxed);
+            entry
+                .slot
+                .store(slot as *const AtomicObjId as usize, Ordering::Relaxed);
+        };
+
+        let len = self.len.load(Ordering::Relaxed);
+        // refresh entry of the same class after the class was mutated
+        if let Some(old) = self.entries[..len]
+            .iter()
+            .find(|x| x.cls.load(Ordering::Relaxed) == key)
+        {
+            write(old);
+        } else if len < POLY_SIZE {
+            write(&self.entries[len]);
+            self.len.store(len + 1, Ordering::Relaxed);
+        } else {
+            self.megamorphic.store(true, Ordering::Relaxed);
+        }
+
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Look up `name` in `cls`, same result as `FSRClass::get_attr`.
+    /// A cache belongs to one site, so `name` must be the same on every call.
+    #[cfg_attr(feature = "more_inline", inline(always))]
+    pub fn get_cls_attr<'a>(&self, cls: &'a FSRClass, name: &str) -> Option<&'a AtomicObjId> {
+        if let Some(s) = self.probe(cls) {
+            return Some(s);
+        }
+
+        let slot = cls.get_attr(name)?;
+        if !self.megamorphic.load(Ordering::Relaxed) {
+            self.fill(cls, slot);
+        }
+
+        Some(slot)
+    }
+
+    pub fn is_megamorphic(&self) -> bool {
+        self.megamorphic.load(Ordering::Relaxed)
+    }
+
+    /// Number of receiver classes currently cached.
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[allow(unused)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use crate::backend::types::class::FSRClass;
+
+    use super::{AttrInlineCache, POLY_SIZE};
+
+    #[test]
+    fn test_cache_invalidate() {
+        let mut cls = FSRClass::new_without_method("Test");
+        cls.insert_attr_id("abc", 1);
+        let cache = AttrInlineCache::default();
+        let v = cache.get_cls_attr(&cls, "abc").unwrap();
+        assert_eq!(v.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.len(), 1);
+
+        // mutate class, the stale entry must not be used
+        for i in 0..64 {
+            cls.insert_attr_id(&format!("attr_{}", i), i);
+        }
+        cls.insert_attr_id("abc", 2);
+        let v = cache.get_cls_attr(&cls, "abc").unwrap();
+        assert_eq!(v.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.len(), 1);
+        assert!(AttrInlineCache::default().get_cls_attr(&cls, "not_exists").is_none());
+    }
+
+    #[test]
+    fn test_cache_megamorphic() {
+        let classes = (0..POLY_SIZE + 1)
+            .map(|i| {
+                let mut cls = FSRClass::new_without_method(&format!("Test{}", i));
+                cls.insert_attr_id("abc", i);
+                cls
+            })
+            .collect::<Vec<_>>();
+        let cache = AttrInlineCache::default();
+        for (i, cls) in classes.iter().enumerate() {
+            let v = cache.get_cls_attr(cls, "abc").unwrap();
+            assert_eq!(v.load(Ordering::Relaxed), i);
+        }
+
+        assert!(cache.is_megamorphic());
+        assert_eq!(cache.len(), POLY_SIZE);
+        let v = cache.get_cls_attr(&classes[POLY_SIZE], "abc").unwrap();
+        assert_eq!(v.load(Ordering::Relaxed), POLY_SIZE);
+    }
+}
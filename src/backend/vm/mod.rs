@@ -4,4 +4,6 @@ pub mod asynclib;
 pub mod free_list;
 pub mod debugger;
 pub mod utils;
+pub mod inline_cache;
+pub mod share;
 // pub mod quick_op;
\ No newline at end of file
@@ -0,0 +1,351 @@
+// Objects reach another thread copied as `SendValue` or frozen read only
+use std::{
+    collections::HashSet,
+    ops::Range,
+    sync::{Arc, LazyLock, Mutex, atomic::Ordering},
+};
+
+use crate::{
+    backend::{
+        types::{
+            base::{Area, FSRObject, FSRValue, GlobalObj, ObjId},
+            bytes::FSRInnerBytes,
+            class_inst::FSRClassInst,
+            ext::{hashmap::FSRHashMap, hashset::FSRHashSet},
+            list::FSRList,
+            range::FSRRange,
+            string::FSRInnerString,
+        },
+        vm::{thread::FSRThreadRuntime, virtual_machine::gid},
+    },
+    utils::error::{FSRErrCode, FSRError},
+};
+
+/// Make `id` and every object of `heap` reachable from it read only. Modules
+/// reached on the way become shared, objects registered in them later are
+/// frozen when they are registered
+pub fn freeze(id: ObjId, heap: u32) {
+    let mut work_list = vec![id];
+    let mut scratch = vec![];
+    while let Some(id) = work_list.pop() {
+        if FSRObject::is_sp_object(id) {
+            continue;
+        }
+
+        let obj = FSRObject::id_to_obj(id);
+        if obj.heap == 0 {
+            if let FSRValue::Module(m) = &obj.value
+                && m.set_shared()
+            {
+                work_list.extend(m.object_ids());
+            }
+            continue;
+        }
+
+        if obj.heap != heap || obj.is_frozen() {
+            continue;
+        }
+
+        FSRObject::id_to_mut_obj(id).unwrap().area = Area::Frozen;
+        if let FSRValue::Function(f) = &obj.value
+            && f.code != 0
+        {
+            work_list.push(FSRObject::id_to_obj(f.code).as_code().module);
+        }
+
+        let mut is_add = false;
+        work_list.extend(obj.get_references(true, &mut scratch, &mut is_add));
+        work_list.append(&mut scratch);
+    }
+}
+
+/// Instances borrow their attribute names, a rebuilt instance gets names kept
+/// for the whole run, one copy of each
+fn intern_name(name: &str) -> &'static str {
+    static NAMES: LazyLock<Mutex<HashSet<&'static str>>> = LazyLock::new(Default::default);
+    let mut names = NAMES.lock().unwrap();
+    if let Some(s) = names.get(name) {
+        return s;
+    }
+
+    let s: &'static str = Box::leak(name.into());
+    names.insert(s);
+    s
+}
+
+/// Value moved to another thread. Numbers, strings, bytes, lists, ranges, maps,
+/// sets and class instances are copied and built again in the heap of the
+/// receiver, any other object is passed by reference and must be frozen
+#[derive(Debug, Clone)]
+pub enum SendValue {
+    None,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(Arc<FSRInnerString>),
+    Bytes(Vec<u8>),
+    List(Vec<SendValue>),
+    Range(Range<i64>),
+    HashMap(Vec<(SendValue, SendValue)>),
+    HashSet(Vec<SendValue>),
+    /// class object and attributes of an instance
+    ClassInst(ObjId, Vec<(Arc<str>, SendValue)>),
+    /// frozen object passed by reference
+    Frozen(ObjId),
+}
+
+impl SendValue {
+    /// Take `id` apart in the thread owning `heap`. `outlives` tells whether
+    /// that thread lives longer than the receiver, only then objects of its
+    /// heap can be passed frozen
+    pub fn from_object(id: ObjId, heap: u32, outlives: bool) -> Result<SendValue, FSRError> {
+        Self::from_object_path(id, heap, outlives, &mut vec![])
+    }
+
+    /// Take `id` apart to keep it in a sync object, only the main thread
+    /// outlives every thread which may read it
+    pub fn from_shared(id: ObjId, thread: &FSRThreadRuntime) -> Result<SendValue, FSRError> {
+        let heap = thread.garbage_collect.heap_id();
+        Self::from_object(id, heap, thread.is_main_thread())
+    }
+
+    /// Objects the value points to, the sync object keeping it marks them
+    pub fn push_references(&self, refs: &mut Vec<ObjId>) {
+        match self {
+            SendValue::List(items) | SendValue::HashSet(items) => {
+                items.iter().for_each(|x| x.push_references(refs));
+            }
+            SendValue::HashMap(items) => {
+                for (k, v) in items {
+                    k.push_references(refs);
+                    v.push_references(refs);
+                }
+            }
+            SendValue::ClassInst(cls, attrs) => {
+                refs.push(*cls);
+                attrs.iter().for_each(|(_, x)| x.push_references(refs));
+            }
+            SendValue::Frozen(id) => refs.push(*id),
+            _ => {}
+        }
+    }
+
+    fn from_object_path(
+        id: ObjId,
+        heap: u32,
+        outlives: bool,
+        path: &mut Vec<ObjId>,
+    ) -> Result<SendValue, FSRError> {
+        let obj = FSRObject::id_to_obj(id);
+        let value = match &obj.value {
+            FSRValue::None => SendValue::None,
+            FSRValue::Bool(b) => SendValue::Bool(*b),
+            FSRValue::Integer(i) => SendValue::Integer(*i),
+            FSRValue::Float(f) => SendValue::Float(*f),
+            FSRValue::String(s) => SendValue::String(s.clone()),
+            FSRValue::Bytes(b) => SendValue::Bytes(b.get_bytes().clone()),
+            FSRValue::Range(r) => SendValue::Range(r.range.clone()),
+            FSRValue::List(l) => {
+                Self::enter(id, path)?;
+                let items = l
+                    .iter_values()
+                    .map(|x| x.load(Ordering::Relaxed))
+                    .map(|x| Self::from_object_path(x, heap, outlives, path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                path.pop();
+                SendValue::List(items)
+            }
+            FSRValue::ClassInst(inst) => {
+                let cls = obj.cls.object_id.ok_or_else(|| {
+                    FSRError::new("class of instance is not defined", FSRErrCode::NoSuchObject)
+                })?;
+                // instances point to their class without keeping it
+                Self::check_frozen(cls, heap, outlives)?;
+                Self::enter(id, path)?;
+                let mut attrs = vec![];
+                for name in inst.list_attrs() {
+                    let attr = inst.get_attr(name).unwrap().load(Ordering::Relaxed);
+                    let value = Self::from_object_path(attr, heap, outlives, path)?;
+                    attrs.push((Arc::from(*name), value));
+                }
+                path.pop();
+                SendValue::ClassInst(cls, attrs)
+            }
+            FSRValue::Extension(any) if any.value.as_any().is::<FSRHashMap>() => {
+                let map = any.value.as_any().downcast_ref::<FSRHashMap>().unwrap();
+                Self::enter(id, path)?;
+                let mut items = vec![];
+                for (k, v) in map.iter_items() {
+                    let k = Self::from_object_path(k, heap, outlives, path)?;
+                    items.push((k, Self::from_object_path(v, heap, outlives, path)?));
+                }
+                path.pop();
+                SendValue::HashMap(items)
+            }
+            FSRValue::Extension(any) if any.value.as_any().is::<FSRHashSet>() => {
+                let set = any.value.as_any().downcast_ref::<FSRHashSet>().unwrap();
+                Self::enter(id, path)?;
+                let keys = set
+                    .iter_keys()
+                    .map(|x| Self::from_object_path(x, heap, outlives, path))
+                    .collect::<Result<Vec<_>, _>>()?;
+                path.pop();
+                SendValue::HashSet(keys)
+            }
+            _ => {
+                Self::check_frozen(id, heap, outlives)?;
+                SendValue::Frozen(id)
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn enter(id: ObjId, path: &mut Vec<ObjId>) -> Result<(), FSRError> {
+        if path.contains(&id) {
+            return Err(FSRError::new(
+                "can not send an object which contains itself to another thread",
+                FSRErrCode::NotValidArgs,
+            ));
+        }
+
+        path.push(id);
+        Ok(())
+    }
+
+    /// Freeze `id` to pass it by reference, an object of a heap dying before
+    /// the receiver can not be passed
+    fn check_frozen(id: ObjId, heap: u32, outlives: bool) -> Result<(), FSRError> {
+        let obj = FSRObject::id_to_obj(id);
+        if obj.heap != heap {
+            return Ok(());
+        }
+
+        if !outlives {
+            return Err(FSRError::new(
+                format!("can not send a `{}` out of the thread which made it", obj.cls.get_name()),
+                FSRErrCode::NotValidArgs,
+            ));
+        }
+
+        freeze(id, heap);
+        Ok(())
+    }
+
+    /// Build the value again in the heap of `thread`
+    pub fn into_object(self, thread: &mut FSRThreadRuntime) -> Result<ObjId, FSRError> {
+        let id = match self {
+            SendValue::None => FSRObject::none_id(),
+            SendValue::Bool(true) => FSRObject::true_id(),
+            SendValue::Bool(false) => FSRObject::false_id(),
+            SendValue::Integer(i) => thread.garbage_collect.get_integer(i),
+            SendValue::Float(f) => thread
+                .garbage_collect
+                .new_object(FSRValue::Float(f), gid(GlobalObj::FloatCls)),
+            SendValue::String(s) => thread
+                .garbage_collect
+                .new_object(FSRValue::String(s), gid(GlobalObj::StringCls)),
+            SendValue::Bytes(b) => thread.garbage_collect.new_object(
+                FSRValue::Bytes(Box::new(FSRInnerBytes::new(b))),
+                gid(GlobalObj::BytesCls),
+            ),
+            SendValue::Range(range) => thread.garbage_collect.new_object(
+                FSRValue::Range(Box::new(FSRRange { range })),
+                gid(GlobalObj::RangeCls),
+            ),
+            SendValue::List(items) => {
+                let items = Self::into_objects(items, thread)?;
+                thread
+                    .garbage_collect
+                    .new_object(FSRList::new_value(items), gid(GlobalObj::ListCls))
+            }
+            SendValue::HashMap(items) => {
+                let mut map = FSRHashMap::new_hashmap();
+                for (k, v) in items {
+                    let k = k.into_object(thread)?;
+                    Self::keep(k, thread);
+                    let v = v.into_object(thread)?;
+                    Self::keep(v, thread);
+                    map.insert(k, v, thread)?;
+                }
+                thread
+                    .garbage_collect
+                    .new_object(map.to_any_type(), gid(GlobalObj::HashMapCls))
+            }
+            SendValue::HashSet(keys) => {
+                let keys = Self::into_objects(keys, thread)?;
+                let mut set = FSRHashSet::new_hashset();
+                for k in keys {
+                    set.insert(k, thread)?;
+                }
+                thread
+                    .garbage_collect
+                    .new_object(set.to_any_type(), gid(GlobalObj::HashSetCls))
+            }
+            SendValue::ClassInst(cls, attrs) => {
+                let mut inst = FSRClassInst::new();
+                for (name, value) in attrs {
+                    let value = value.into_object(thread)?;
+                    Self::keep(value, thread);
+                    inst.set_attr(intern_name(&name), value);
+                }
+                thread
+                    .garbage_collect
+                    .new_object(FSRValue::ClassInst(Box::new(inst)), cls)
+            }
+            SendValue::Frozen(id) => id,
+        };
+
+        Ok(id)
+    }
+
+    fn into_objects(
+        values: Vec<SendValue>,
+        thread: &mut FSRThreadRuntime,
+    ) -> Result<Vec<ObjId>, FSRError> {
+        let mut ids = Vec::with_capacity(values.len());
+        for value in values {
+            let id = value.into_object(thread)?;
+            Self::keep(id, thread);
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+
+    /// Root a part of a value being built, hashing a key may run script code
+    fn keep(id: ObjId, thread: &mut FSRThreadRuntime) {
+        thread.get_cur_mut_frame().middle_value.push(id);
+    }
+}
+
+/// Error of a thread given to the thread joining it
+#[derive(Debug)]
+pub enum SendError {
+    /// value raised by the script
+    Raised(SendValue),
+    Error(FSRError),
+}
+
+impl SendError {
+    pub fn from_error(e: FSRError, heap: u32) -> SendError {
+        let FSRErrCode::RuntimeError(exception) = e.inner.code else {
+            return SendError::Error(e);
+        };
+
+        match SendValue::from_object(exception, heap, false) {
+            Ok(value) => SendError::Raised(value),
+            Err(e) => SendError::Error(e),
+        }
+    }
+
+    pub fn into_error(self, thread: &mut FSRThreadRuntime) -> FSRError {
+        match self {
+            SendError::Raised(value) => match value.into_object(thread) {
+                Ok(exception) => FSRError::new_runtime_error(exception),
+                Err(e) => e,
+            },
+            SendError::Error(e) => e,
+        }
+    }
+}
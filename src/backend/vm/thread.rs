@@ -7,7 +7,7 @@ use std::{
     path::PathBuf,
     str::FromStr,
     sync::{
-        Arc, Condvar, Mutex,
+        Arc, Condvar, Mutex, Weak,
         atomic::{AtomicPtr, AtomicUsize, Ordering},
     },
     time::Instant,
@@ -24,16 +24,22 @@ use crate::{
                 ArgType, BytecodeArg, BytecodeOperator, CompareOperator, FSRDbgFlag, FSRSType,
                 FastAttr, FnArgs, FnCallSig, LocalVar, OpAssign,
             },
-            jit::cranelift::CraneLiftJitBackend,
+            jit::{
+                aot,
+                cache,
+                baseline::{BaselineJitBackend, DEOPT_RET},
+                cranelift::CraneLiftJitBackend,
+            },
         },
         memory::{gc::mark_sweep::MarkSweepGarbageCollector, size_alloc::FSRObjectAllocator},
         types::{
+            any::{FSRThreadHandle, ThreadSlot},
             asynclib::future::{FSRFuture, poll_future},
             base::{Area, AtomicObjId, FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId},
             class::FSRClass,
             class_inst::FSRClassInst,
             code::FSRCode,
-            fn_def::{FSRFn, FSRFnInner, FSRnE, FnDesc},
+            fn_def::{FSRFn, FSRFnInner, FSRnE, FnDesc, OSR_BACKEDGE_THRESHOLD},
             list::{FSRList, push},
             module::FSRModule,
             range::FSRRange,
@@ -41,6 +47,7 @@ use crate::{
         },
         vm::{debugger::debug::FSRDebugger, virtual_machine::ModuleManager},
     },
+    std::core::{pool::ThreadPool, sync::LockGuard},
     utils::error::{FSRErrCode, FSRError},
 };
 
@@ -48,7 +55,9 @@ use frontend::ast::token::{base::FSRTypeName, constant::FSROrinStr2, expr::Singl
 
 use super::{
     free_list::FrameFreeList,
+    inline_cache::AttrInlineCache,
     // quick_op::Ops,
+    share,
     virtual_machine::{FSRVM, VM, gid},
 };
 
@@ -131,7 +140,7 @@ macro_rules! is_base_fn {
 }
 
 const ITER_METHOD: &str = "__iter__";
-const MAIN_FN: &str = "__main__";
+pub(crate) const MAIN_FN: &str = "__main__";
 #[derive(Debug)]
 pub struct IndexMap {
     vs: Vec<Option<NonZeroUsize>>,
@@ -141,7 +150,7 @@ pub struct IndexIterator<'a> {
     vs: core::slice::Iter<'a, Option<NonZeroUsize>>,
 }
 
-type CallArgs = SmallVec<[ObjId; 4]>;
+pub(crate) type CallArgs = SmallVec<[ObjId; 4]>;
 // type CallArgs = Vec<ObjId>;
 
 #[allow(clippy::new_without_default)]
@@ -336,6 +345,8 @@ pub struct CallFrame {
     pub(crate) is_module: bool,
     pub(crate) bytecode_slot: Option<AtomicPtr<Vec<BytecodeArg>>>,
     pub(crate) top_exp: usize,
+    /// locks taken by the function, released when it returns
+    pub(crate) guards: Vec<Arc<LockGuard>>,
 }
 
 impl CallFrame {
@@ -360,9 +371,17 @@ impl CallFrame {
         self.future = None;
         self.is_module = false;
         self.ip = (0, 0);
+        self.release_guards();
         //self.last_expr_val = FSRObject::none_id();
     }
 
+    /// Release the locks still held by the function of this frame
+    pub fn release_guards(&mut self) {
+        for guard in self.guards.drain(..) {
+            guard.release();
+        }
+    }
+
     #[inline]
     fn get_slot(&self, id: usize) -> Option<&BytecodeArg> {
         if let Some(slot) = &self.bytecode_slot {
@@ -372,6 +391,14 @@ impl CallFrame {
         None
     }
 
+    /// Inline cache of the running instruction, found through the expression
+    /// set by `cache_slot`
+    #[inline]
+    fn attr_cache(&self) -> Option<&AttrInlineCache> {
+        self.get_slot(self.ip.1.wrapping_sub(1))
+            .map(|x| x.get_attr_cache())
+    }
+
     #[cfg_attr(feature = "more_inline", inline(always))]
     pub fn get_var(&self, id: &u64) -> Option<NonZeroUsize> {
         self.local_var.get(id)
@@ -461,6 +488,7 @@ impl CallFrame {
             is_module: false,
             bytecode_slot: None,
             top_exp: 0,
+            guards: vec![],
         }
     }
 }
@@ -601,6 +629,25 @@ pub struct FSRThreadRuntime<'a> {
     pub(crate) thread_shared: ThreadShared,
     dbg_flag: bool,
     pub(crate) module_manager: ModuleManager,
+    /// buffers of strings built by static code, freed when the entry call returns
+    pub(crate) static_strings: Vec<Box<[u8]>>,
+    /// error raised in a helper called by baseline code or by static code,
+    /// taken when the code returns
+    jit_error: Option<FSRError>,
+    /// set while `jit_error` holds an error, static code tests it after calls
+    pub(crate) jit_failed: bool,
+    /// library with the static functions compiled ahead of time
+    aot_library: Option<PathBuf>,
+    /// directory of the on-disk cache of compiled static functions
+    jit_cache: Option<PathBuf>,
+    /// threads started here, joined before the heap they read is dropped
+    pub(crate) spawned: Vec<ThreadSlot>,
+    /// false for threads spawned by scripts, the main thread outlives them
+    pub(crate) is_main: bool,
+    /// pool of the parallel adapters, started when first used
+    pub(crate) default_pool: Option<Arc<ThreadPool>>,
+    /// pools made here, shut down with the spawned threads
+    pub(crate) pools: Vec<Weak<ThreadPool>>,
     #[cfg(feature = "count_bytecode")]
     pub(crate) bytecode_counter: Vec<usize>,
 }
@@ -674,9 +721,48 @@ impl<'a> FSRThreadRuntime<'a> {
             thread_shared: ThreadShared::new_share(),
             dbg_flag: false,
             module_manager: ModuleManager::new_manager(),
+            static_strings: vec![],
+            jit_error: None,
+            jit_failed: false,
+            aot_library: None,
+            jit_cache: None,
+            spawned: vec![],
+            is_main: true,
+            default_pool: None,
+            pools: vec![],
+        }
+    }
+
+    /// Wait for the threads started here which no script joined, then for
+    /// the workers of the pools made here
+    pub fn join_spawned(&mut self) {
+        for slot in std::mem::take(&mut self.spawned) {
+            FSRThreadHandle::join_slot(&slot, self);
+        }
+
+        let pools = std::mem::take(&mut self.pools);
+        let pools = pools.iter().filter_map(Weak::upgrade);
+        for pool in self.default_pool.take().into_iter().chain(pools) {
+            pool.shutdown();
         }
     }
 
+    pub fn default_pool(&mut self) -> Arc<ThreadPool> {
+        self.default_pool
+            .get_or_insert_with(ThreadPool::with_default_size)
+            .clone()
+    }
+
+    pub(crate) fn set_jit_error(&mut self, e: FSRError) {
+        self.jit_error = Some(e);
+        self.jit_failed = true;
+    }
+
+    pub(crate) fn take_jit_error(&mut self) -> Option<FSRError> {
+        self.jit_failed = false;
+        self.jit_error.take()
+    }
+
     pub fn set_dbg_flag(&mut self, flag: bool) {
         self.dbg_flag = flag;
     }
@@ -689,6 +775,12 @@ impl<'a> FSRThreadRuntime<'a> {
         self.thread_id
     }
 
+    /// Objects of the main thread may be kept by other threads, it lives until
+    /// every spawned thread is joined
+    pub fn is_main_thread(&self) -> bool {
+        self.is_main
+    }
+
     pub fn call_stack(&self) -> Vec<ObjId> {
         let mut fns = self.call_frames.iter().map(|x| x.fn_id).collect::<Vec<_>>();
         fns.push(self.get_cur_frame().fn_id);
@@ -738,6 +830,11 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     pub fn process_callframe(work_list: &mut Vec<ObjId>, it: &CallFrame) {
+        // after a tail call no caller holds the running fn
+        if !is_base_fn!(it.fn_id) {
+            work_list.push(it.fn_id);
+        }
+
         for obj in it.local_var.iter() {
             work_list.push(obj);
         }
@@ -758,22 +855,17 @@ impl<'a> FSRThreadRuntime<'a> {
             work_list.push(*value);
         }
 
-        for val in index_map_obj_from_ptr(it.const_map.unwrap()).iter() {
-            work_list.push(*val);
-        }
-
-        let module_id = FSRObject::id_to_obj(it.code).as_code().module;
-        let module = FSRObject::id_to_obj(module_id).as_module();
-        for obj in module
-            .object_map
-            .iter()
-            .map(|x| x.1.load(Ordering::Relaxed))
-        {
-            work_list.push(obj);
+        // the first frame of a spawned thread runs no code
+        if let Some(const_map) = it.const_map {
+            for val in index_map_obj_from_ptr(const_map).iter() {
+                work_list.push(*val);
+            }
         }
 
-        for obj in index_map_obj_from_ptr(it.const_map.unwrap()).iter() {
-            work_list.push(*obj);
+        if it.code != 0 {
+            let module_id = FSRObject::id_to_obj(it.code).as_code().module;
+            let module = FSRObject::id_to_obj(module_id).as_module();
+            work_list.extend(module.object_ids());
         }
 
         let mut others = it.flow_tracker.for_iter_obj.clone();
@@ -802,12 +894,17 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     fn process_refs(&mut self, id: ObjId, obj: &FSRObject, full: bool) {
+        let heap = self.garbage_collect.heap_id();
         let work_list = &mut self.gc_context.worklist;
         let mut is_add = false;
         let refs = obj.get_references(full, work_list, &mut is_add);
         // Only if all references object are Marjor, then not set write barrier
         for ref_id in refs {
             let obj = FSRObject::id_to_obj(ref_id);
+            if !Self::is_own_object(obj, heap) {
+                continue;
+            }
+
             if obj.area == Area::Minjor {
                 is_add = true;
             } else if !full {
@@ -824,6 +921,13 @@ impl<'a> FSRThreadRuntime<'a> {
         }
     }
 
+    /// Objects of other heaps are frozen and kept by the thread owning them,
+    /// static objects are walked by every thread
+    #[inline]
+    fn is_own_object(obj: &FSRObject, heap: u32) -> bool {
+        obj.heap == heap || obj.heap == 0
+    }
+
     pub fn set_ref_objects_mark(&mut self, full: bool, addition: &[ObjId]) {
         //if self.gc_context.gc_state == GcState::Stop {
         self.gc_context.worklist = self.add_worklist();
@@ -837,13 +941,14 @@ impl<'a> FSRThreadRuntime<'a> {
         //}
         //self.gc_context.gc_state = GcState::Running;
 
+        let heap = self.garbage_collect.heap_id();
         while let Some(id) = self.gc_context.worklist.pop() {
             if FSRObject::is_sp_object(id) {
                 continue;
             }
 
             let obj = FSRObject::id_to_obj(id);
-            if obj.is_marked() {
+            if obj.is_marked() || !Self::is_own_object(obj, heap) {
                 continue;
             }
 
@@ -859,6 +964,11 @@ impl<'a> FSRThreadRuntime<'a> {
 
     pub fn thread_unwrap(thread_rt: &FSRThreadRuntime, message: &str) -> Result<(), FSRError> {
         fn print_frame(idx: usize, frame: &crate::backend::vm::thread::CallFrame) {
+            // the first frame of a spawned thread runs no code
+            if frame.code == 0 {
+                return;
+            }
+
             let code = FSRObject::id_to_obj(frame.code).as_code();
             let pos = code
                 .get_expr(frame.ip.0)
@@ -1006,6 +1116,7 @@ impl<'a> FSRThreadRuntime<'a> {
         let assign_value = peek_exp!(self, len - 2).unwrap();
 
         let father_obj = FSRObject::id_to_mut_obj(father).unwrap();
+        father_obj.check_mutable()?;
 
         if let Some(op_assign) = attr_var.op_assign {
             let left_value = father_obj
@@ -1238,8 +1349,9 @@ impl<'a> FSRThreadRuntime<'a> {
 
         let dot_father_obj = FSRObject::id_to_obj(dot_father);
         let name = &attr_var.name;
+        let frame = self.get_cur_frame();
         let id = dot_father_obj
-            .get_attr(name, attr_var.is_method)
+            .get_attr_cached(name, attr_var.is_method, || frame.attr_cache())
             .ok_or_else(|| {
                 FSRError::new(
                     format!("not have this attr: `{}`", name),
@@ -1282,7 +1394,6 @@ impl<'a> FSRThreadRuntime<'a> {
         let id = dot_father_obj.get_cls_getter_attr(name);
 
         if let Some(id) = id {
-            let id = id.load(Ordering::Relaxed);
             self.get_cur_mut_frame().push_exp(id);
             push_middle!(self, dot_father);
             self.get_cur_mut_frame().middle_value.push(id);
@@ -1416,7 +1527,7 @@ impl<'a> FSRThreadRuntime<'a> {
         )
         .as_module();
         match module.get_object(name) {
-            Some(s) => Some(s.load(Ordering::Relaxed)),
+            Some(s) => Some(s),
             None => {
                 // Cache global object in call frame
                 let v = self.get_vm().get_global_obj_by_name(name).cloned()?;
@@ -1457,14 +1568,23 @@ impl<'a> FSRThreadRuntime<'a> {
                 .static_args
                 .push(FSRObject::id_to_obj(arg).get_value_ptr());
         }
+        // `Object` args are read from here, it keeps them alive too
+        self.get_cur_mut_frame().middle_value.extend(args.iter());
         let call_fn = unsafe {
             std::mem::transmute::<_, extern "C" fn(&mut FSRThreadRuntime<'a>, ObjId) -> ObjId>(
                 jit_code,
             )
         };
         let res = call_fn(self, self.get_cur_frame().code);
+        // the result was already copied out of the static strings
+        self.static_strings.clear();
         let v = self.pop_frame();
         self.frame_free_list.free(v);
+        // a check of checked static code failed
+        if let Some(e) = self.take_jit_error() {
+            return Err(e);
+        }
+
         push_exp!(self, res);
         Ok(RetState::Normal)
     }
@@ -1483,7 +1603,7 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     #[cfg_attr(feature = "more_inline", inline(always))]
-    fn call_process_ret(
+    pub(crate) fn call_process_ret(
         &mut self,
         fn_id: ObjId,
         args: &mut CallArgs,
@@ -1515,12 +1635,7 @@ impl<'a> FSRThreadRuntime<'a> {
             let v = Self::process_fsr_cls(self, fn_id, args)?;
         } else {
             args.reverse();
-            let v = match fn_obj.call(args, self) {
-                Ok(o) => o,
-                Err(e) => {
-                    panic!("error: in call_process_ret: {}", e);
-                }
-            };
+            let v = fn_obj.call(args, self)?;
 
             let id = v.get_id();
             push_exp!(self, id);
@@ -1529,6 +1644,35 @@ impl<'a> FSRThreadRuntime<'a> {
         Ok(RetState::Normal)
     }
 
+    /// Call `fn_id` like the `Call` operator but return the result, used by
+    /// baseline code which keeps its values out of the expression stack
+    pub(crate) fn call_obj(
+        &mut self,
+        fn_id: ObjId,
+        args: &[ObjId],
+        ret_type: &Option<Arc<FnCallSig>>,
+    ) -> Result<ObjId, FSRError> {
+        let mut args: CallArgs = args.iter().rev().cloned().collect();
+        self.call_process_ret(fn_id, &mut args, ret_type)?;
+        pop_exp!(self).ok_or_else(|| {
+            FSRError::new("call returned no value", FSRErrCode::EmptyExpStack)
+        })
+    }
+
+    /// Same as `call_obj`, for a method found on `father`
+    pub(crate) fn call_method_obj(
+        &mut self,
+        fn_id: ObjId,
+        father: ObjId,
+        args: &[ObjId],
+    ) -> Result<ObjId, FSRError> {
+        let mut args: CallArgs = args.iter().rev().cloned().collect();
+        self.call_method_ret(fn_id, &mut args, &Some(father))?;
+        pop_exp!(self).ok_or_else(|| {
+            FSRError::new("call returned no value", FSRErrCode::EmptyExpStack)
+        })
+    }
+
     #[cfg_attr(feature = "more_inline", inline(always))]
     fn call_method_ret(
         &mut self,
@@ -1538,6 +1682,16 @@ impl<'a> FSRThreadRuntime<'a> {
         //call_method: bool,
     ) -> Result<RetState, FSRError> {
         let fn_obj = FSRObject::id_to_obj(fn_id);
+        // `@entry` struct method reached through the struct's class, it
+        // takes no self
+        if fn_obj.is_fsr_function()
+            && let FSRnE::FSRFn(f) = &fn_obj.as_fn().fn_def
+            && f.jit_code.is_some()
+        {
+            args.reverse();
+            return self.jit_call(fn_id, args, f, &None);
+        }
+
         if let Some(object_id) = object_id {
             let v = Self::process_fn_is_attr(self, *object_id, fn_obj, args)?;
         } else {
@@ -1572,6 +1726,68 @@ impl<'a> FSRThreadRuntime<'a> {
         return self.call_process_ret(fn_id, &mut args, ret_type);
     }
 
+    /// Frame can be reused only by a plain script function, and only if nothing
+    /// else still needs the current frame (exception handler, future, module,
+    /// held lock guards)
+    fn can_reuse_frame(&self, fn_id: ObjId) -> bool {
+        let fn_obj = FSRObject::id_to_obj(fn_id);
+        if !fn_obj.is_fsr_function() {
+            return false;
+        }
+
+        let FSRnE::FSRFn(f) = &fn_obj.as_fn().fn_def else {
+            return false;
+        };
+
+        let frame = self.get_cur_frame();
+        f.jit_code.is_none()
+            && !f.is_async
+            && !frame.is_module
+            && frame.future.is_none()
+            && frame.catch_ends.is_empty()
+            && frame.handling_exception.is_none()
+            && frame.guards.is_empty()
+    }
+
+    fn tail_call_process(
+        self: &mut FSRThreadRuntime<'a>,
+        bytecode: &BytecodeArg,
+    ) -> Result<RetState, FSRError> {
+        let mut args: SmallVec<[usize; 4]> = SmallVec::<[ObjId; 4]>::new();
+
+        let ArgType::CallArgsNumber((args_num, ret_type)) = bytecode.get_arg() else {
+            return Err(FSRError::new(
+                "not support ArgType in tail_call_process",
+                FSRErrCode::NotValidArgs,
+            ));
+        };
+
+        Self::call_process_set_args(*args_num, self, &mut args)?;
+        let fn_id = pop_exp!(self).unwrap();
+        push_middle!(self, fn_id);
+
+        if !self.can_reuse_frame(fn_id) {
+            return self.call_process_ret(fn_id, &mut args, ret_type);
+        }
+
+        let fn_obj = FSRObject::id_to_obj(fn_id).as_fn();
+        let FSRnE::FSRFn(f) = &fn_obj.fn_def else {
+            unreachable!()
+        };
+
+        // Same state as `fsr_call_args_settting` leaves in a fresh frame. Cells
+        // live in the fn objects, the frame roots `fn_id` so they stay reachable.
+        let frame = self.get_cur_mut_frame();
+        frame.clear();
+        frame.code = fn_obj.code;
+        frame.fn_id = fn_id;
+        frame.const_map = Some(index_map_obj_to_ptr(&fn_obj.const_map));
+        frame.local_var.reserve(f.max_local_id);
+        frame.args.extend_from_slice(&args);
+
+        Ok(RetState::BreakCurLine)
+    }
+
     #[cfg_attr(feature = "more_inline", inline(always))]
     fn call_method_process(
         self: &mut FSRThreadRuntime<'a>,
@@ -1592,7 +1808,8 @@ impl<'a> FSRThreadRuntime<'a> {
 
         father = pop_exp!(self).unwrap();
         let father_cls = FSRObject::id_to_obj(father);
-        let method = match father_cls.get_attr(&pack.2, true) {
+        let frame = self.get_cur_frame();
+        let method = match father_cls.get_attr_cached(&pack.2, true, || frame.attr_cache()) {
             Some(s) => s.load(Ordering::Relaxed),
             None => {
                 return Err(FSRError::new(
@@ -1853,10 +2070,134 @@ impl<'a> FSRThreadRuntime<'a> {
         code: ObjId,
         is_entry: bool,
         call_sig: Option<Arc<FnCallSig>>,
-    ) -> *const u8 {
+    ) -> Result<*const u8, FSRError> {
         let mut jit = CraneLiftJitBackend::new();
-        jit.compile(full_name, code_obj.get_bytecode(), code, is_entry, call_sig)
-            .unwrap()
+        Ok(jit.compile(full_name, code_obj.get_bytecode(), code, is_entry, call_sig)?)
+    }
+
+    /// Code a script function runs: specialized code while its guards hold,
+    /// baseline code once the function is hot, else none for the interpreter
+    pub(crate) fn jit_code(&self, fn_obj: &FSRFn<'a>, f: &FSRFnInner) -> Option<usize> {
+        let info = &fn_obj.jit_info;
+        if let Some(code) = info.get_specialized_code() {
+            return Some(code);
+        }
+
+        match info.get_baseline_code() {
+            // no more specializing, the calls need no counting
+            Some(code) if info.is_settled() => Some(code),
+            Some(code) => {
+                info.call_once();
+                if info.should_specialize() {
+                    return self.specialize(fn_obj, f).or(Some(code));
+                }
+
+                Some(code)
+            }
+            None if info.call_once() => self.tier_up(fn_obj, f),
+            None => None,
+        }
+    }
+
+    /// Compile a hot script function with the baseline tier, a function the
+    /// tier can not compile keeps running in the interpreter
+    pub(crate) fn tier_up(&self, fn_obj: &FSRFn<'a>, f: &FSRFnInner) -> Option<usize> {
+        if self.dbg_flag || f.is_async {
+            fn_obj.jit_info.set_baseline_code(None);
+            return None;
+        }
+
+        let code = FSRObject::id_to_obj(fn_obj.code).as_code();
+        let mut jit = BaselineJitBackend::new();
+        let res = jit
+            .compile(f.get_name(), code.get_bytecode(), &fn_obj.const_map, &fn_obj.jit_info)
+            .ok()
+            .map(|x| x as usize);
+        fn_obj.jit_info.set_baseline_code(res);
+        res
+    }
+
+    /// Compile a function again to print its IR and machine code: static
+    /// functions with the static tier, script functions with the baseline tier
+    pub(crate) fn jit_dump(fn_obj: &FSRFn<'a>) -> Result<(), FSRError> {
+        if !fn_obj.is_fsr_function() {
+            return Err(FSRError::new(
+                "jit_dump needs a script function",
+                FSRErrCode::NotValidArgs,
+            ));
+        }
+
+        let code = FSRObject::id_to_obj(fn_obj.code).as_code();
+        let fn_info = &code.get_bytecode().fn_info;
+        if fn_info.is_jit {
+            let mut jit = CraneLiftJitBackend::new();
+            jit.set_dump(true);
+            jit.compile(
+                code.get_name(),
+                code.get_bytecode(),
+                fn_obj.code,
+                fn_info.is_entry,
+                fn_info.fn_type.clone(),
+            )?;
+        } else {
+            let mut jit = BaselineJitBackend::new();
+            jit.set_dump(true);
+            jit.compile(
+                code.get_name(),
+                code.get_bytecode(),
+                &fn_obj.const_map,
+                &fn_obj.jit_info,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Recompile baseline code with the operand types it recorded
+    fn specialize(&self, fn_obj: &FSRFn<'a>, f: &FSRFnInner) -> Option<usize> {
+        let code = FSRObject::id_to_obj(fn_obj.code).as_code();
+        let mut jit = BaselineJitBackend::new();
+        let res = jit.compile_specialized(
+            f.get_name(),
+            code.get_bytecode(),
+            &fn_obj.const_map,
+            &fn_obj.jit_info,
+        );
+        match res {
+            Ok((code, deopt_points)) => {
+                fn_obj
+                    .jit_info
+                    .set_specialized_code(Some(code as usize), deopt_points);
+                Some(code as usize)
+            }
+            Err(_) => {
+                fn_obj.jit_info.set_specialized_code(None, vec![]);
+                None
+            }
+        }
+    }
+
+    /// Run jit code in the frame pushed by the caller, the frame is popped
+    /// when the code returns like `ReturnValue` does. Specialized code whose
+    /// guard failed leaves the frame to the interpreter.
+    #[allow(clippy::missing_transmute_annotations)]
+    pub(crate) fn run_jit_code(&mut self, code: usize, f: &FSRFnInner) -> Result<ObjId, FSRError> {
+        let call_fn = unsafe {
+            std::mem::transmute::<_, extern "C" fn(&mut FSRThreadRuntime<'a>) -> ObjId>(code)
+        };
+        let res = call_fn(self);
+        if res == DEOPT_RET {
+            return Self::call_fn(self, f);
+        }
+
+        let frame = self.pop_frame();
+        self.frame_free_list.free(frame);
+        if res == 0 {
+            return Err(self.take_jit_error().unwrap_or_else(|| {
+                FSRError::new("jit code failed", FSRErrCode::NotSupportOperator)
+            }));
+        }
+
+        Ok(res)
     }
 
     fn get_const_map(
@@ -1932,20 +2273,33 @@ impl<'a> FSRThreadRuntime<'a> {
         fn_args: &FnArgs,
         fn_id: ObjId,
         define_fn_obj: ObjId,
-    ) {
+    ) -> Result<(), FSRError> {
         if fn_args.store_to_cell && !is_base_fn!(define_fn_obj) {
             let define_fn_obj = self.get_cur_frame().fn_id;
-            let define_fn_obj = FSRObject::id_to_mut_obj(define_fn_obj)
-                .expect("not a fn obj")
-                .as_mut_fn();
-            if let Some(s) = define_fn_obj.store_cells.get(fn_args.name.as_str()) {
-                s.store(fn_id, Ordering::Relaxed);
-            } else {
-                define_fn_obj
-                    .store_cells
-                    .insert(fn_args.name.as_str(), AtomicObjId::new(fn_id));
+            self.store_cell(define_fn_obj, fn_args.name.as_str(), fn_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Store `value` in the cell `name` of the function `fn_id`. A frozen
+    /// function only takes frozen values, and only from the thread owning it
+    fn store_cell(&self, fn_id: ObjId, name: &str, value: ObjId) -> Result<(), FSRError> {
+        let fn_obj = FSRObject::id_to_obj(fn_id);
+        if fn_obj.is_frozen() {
+            let heap = self.garbage_collect.heap_id();
+            if fn_obj.heap != heap {
+                return Err(FSRError::new(
+                    format!("can not change `{}`, a closure variable of another thread", name),
+                    FSRErrCode::NotSupportOperator,
+                ));
             }
+
+            share::freeze(value, heap);
         }
+
+        fn_obj.as_fn().set_cell(name, value);
+        Ok(())
     }
 
     fn define_fn_value(
@@ -1964,7 +2318,7 @@ impl<'a> FSRThreadRuntime<'a> {
         let fn_code_id = FSRObject::obj_to_id(fn_code);
 
         let jit_code = module
-            .get_jit_code_map(None, &fn_args.fn_identify_name)
+            .get_jit_code_slot(&fn_args.fn_identify_name)
             .map(|x| x.load(Ordering::Relaxed) as *const u8);
         let fn_obj = FSRFn::from_fsr_fn(
             fn_args.name.as_str(),
@@ -1991,14 +2345,14 @@ impl<'a> FSRThreadRuntime<'a> {
         fn_args: &FnArgs,
         fn_id: ObjId,
         is_jit: bool
-    ) {
+    ) -> Result<(), FSRError> {
         let frame = &mut self.cur_frame;
         if let Some(cur_cls) = &mut frame.cur_cls {
             let offset = FastAttr::from_alias_name(fn_args.name.as_str());
             if let Some(offset) = offset {
                 cur_cls.insert_offset_attr_obj_id(offset, fn_id);
                 self.get_cur_mut_frame().ip = (self.get_cur_frame().ip.0 + 1, 0);
-                return ;
+                return Ok(());
             }
             cur_cls.insert_attr_id(&fn_args.name, fn_id);
             self.get_cur_mut_frame().ip = (self.get_cur_frame().ip.0 + 1, 0);
@@ -2008,11 +2362,13 @@ impl<'a> FSRThreadRuntime<'a> {
 
             self.module_fn_define(&fn_args.name, fn_id, is_jit, define_fn_id);
 
-            self.fn_store_to_cell(fn_args, fn_id, define_fn_id);
+            self.fn_store_to_cell(fn_args, fn_id, define_fn_id)?;
 
             let ip_0 = self.get_cur_frame().ip.0;
             self.get_cur_mut_frame().ip = (ip_0 + 1, 0);
         }
+
+        Ok(())
     }
 
     fn define_fn(
@@ -2034,12 +2390,12 @@ impl<'a> FSRThreadRuntime<'a> {
             .garbage_collect
             .new_object(fn_obj, gid(GlobalObj::FnCls));
 
-        self.define_fn_scope(fn_args, fn_id, is_jit);
+        self.define_fn_scope(fn_args, fn_id, is_jit)?;
 
         Ok(RetState::BreakCurLine)
     }
 
-    fn op_assign_helper(
+    pub(crate) fn op_assign_helper(
         left_id: ObjId,
         right_id: ObjId,
         thread: &mut FSRThreadRuntime<'a>,
@@ -2150,7 +2506,7 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     #[cfg_attr(feature = "more_inline", inline(always))]
-    fn ret_value(
+    pub(crate) fn ret_value(
         self: &mut FSRThreadRuntime<'a>,
         //_bytecode: &BytecodeArg,
     ) -> Result<RetState, FSRError> {
@@ -2477,13 +2833,90 @@ impl<'a> FSRThreadRuntime<'a> {
         Ok(RetState::BreakCurLine)
     }
 
+    /// Count an iteration of the loop at `header`, once the function is hot
+    /// the rest of the loop runs in OSR code which leaves the ip after the loop
+    #[inline]
+    fn loop_backedge(&mut self, header: usize) -> Result<(), FSRError> {
+        let frame = self.get_cur_frame();
+        let fn_id = frame.fn_id;
+        // async functions are not compiled, their loops are not counted. The
+        // top level of an imported module runs with fn_id 0 so its names are
+        // registered on the module (see `load`), its loops stay interpreted
+        if is_base_fn!(fn_id) || frame.future.is_some() {
+            return Ok(());
+        }
+
+        let fn_obj = FSRObject::id_to_obj(fn_id).as_fn();
+        let info = &fn_obj.jit_info;
+        info.backedge();
+        let code = match info.get_osr_code(header) {
+            Some(code) => code,
+            None if info.get_backedge_count() >= OSR_BACKEDGE_THRESHOLD => {
+                self.compile_osr(fn_obj, header)
+            }
+            None => return Ok(()),
+        };
+        if code == 0 {
+            return Ok(());
+        }
+
+        self.run_osr_code(code)
+    }
+
+    /// Compile the loop at `header` of the current frame, 0 if it stays in the
+    /// interpreter
+    #[cold]
+    fn compile_osr(&self, fn_obj: &FSRFn<'a>, header: usize) -> usize {
+        let frame = self.get_cur_frame();
+        let code = FSRObject::id_to_obj(frame.code).as_code();
+        let lines = code.get_bytecode().bytecode.len();
+        if self.dbg_flag || frame.future.is_some() {
+            fn_obj.jit_info.set_osr_code(header, None, lines);
+            return 0;
+        }
+
+        // module code runs with the const map of its code object
+        let const_map = index_map_obj_from_ptr(frame.const_map.unwrap());
+        let mut jit = BaselineJitBackend::new();
+        let res = jit
+            .compile_osr(
+                code.get_name(),
+                code.get_bytecode(),
+                const_map,
+                &fn_obj.jit_info,
+                header,
+            )
+            .ok()
+            .map(|x| x as usize);
+        fn_obj.jit_info.set_osr_code(header, res, lines);
+        res.unwrap_or(0)
+    }
+
+    /// Run OSR code in the current frame, the interpreter goes on from the ip
+    /// the code leaves, or with the caller if the code returned
+    #[allow(clippy::missing_transmute_annotations)]
+    fn run_osr_code(&mut self, code: usize) -> Result<(), FSRError> {
+        let osr_fn = unsafe {
+            std::mem::transmute::<_, extern "C" fn(&mut FSRThreadRuntime<'a>) -> ObjId>(code)
+        };
+        if osr_fn(self) == 0 {
+            return Err(self.take_jit_error().unwrap_or_else(|| {
+                FSRError::new("jit code failed", FSRErrCode::NotSupportOperator)
+            }));
+        }
+
+        Ok(())
+    }
+
     #[inline]
     fn for_block_end(
         self: &mut FSRThreadRuntime<'a>,
         bytecode: &BytecodeArg,
     ) -> Result<RetState, FSRError> {
         let tmp = self.get_cur_frame().ip.0;
-        self.get_cur_mut_frame().ip = (tmp - bytecode.arg_n as usize, 0);
+        let header = tmp - bytecode.arg_n as usize;
+        self.get_cur_mut_frame().ip = (header, 0);
+        self.loop_backedge(header)?;
         return Ok(RetState::BreakCurLine);
     }
 
@@ -2493,7 +2926,9 @@ impl<'a> FSRThreadRuntime<'a> {
     ) -> Result<RetState, FSRError> {
         //if let ArgType::WhileEnd(n) = bytecode.get_arg() {
         let ip_0 = self.get_cur_frame().ip.0;
-        self.get_cur_mut_frame().ip = (ip_0 - bytecode.arg_n as usize, 0);
+        let header = ip_0 - bytecode.arg_n as usize;
+        self.get_cur_mut_frame().ip = (header, 0);
+        self.loop_backedge(header)?;
         return Ok(RetState::BreakCurLine);
         //}
     }
@@ -2515,39 +2950,26 @@ impl<'a> FSRThreadRuntime<'a> {
             ));
         }
 
-        let fn_obj = FSRObject::id_to_mut_obj(fn_obj_id)
-            .ok_or_else(|| FSRError::new("Not a function object", FSRErrCode::NoSuchObject))?
-            .as_mut_fn();
+        let fn_obj = FSRObject::id_to_obj(fn_obj_id).as_fn();
 
         let name = closure.1.as_str();
 
         if let Some(op_assign) = closure.2 {
-            let left_value = fn_obj.store_cells.get(name).ok_or_else(|| {
+            let left_id = fn_obj.get_cell(name).ok_or_else(|| {
                 FSRError::new(
                     "Closure variable not found for op assign",
                     FSRErrCode::NoSuchObject,
                 )
             })?;
-            let left_id = left_value.load(Ordering::Relaxed);
             let right_id = obj_id;
 
             let offset = op_assign.get_offset();
 
             let v = Self::op_assign_helper(left_id, right_id, self, offset)?;
-            match fn_obj.store_cells.get(name) {
-                Some(cell) => cell.store(v, Ordering::Relaxed),
-                None => {
-                    fn_obj.store_cells.insert(name, AtomicObjId::new(v));
-                }
-            }
+            self.store_cell(fn_obj_id, name, v)?;
         }
 
-        match fn_obj.store_cells.get(name) {
-            Some(cell) => cell.store(obj_id, Ordering::Relaxed),
-            None => {
-                fn_obj.store_cells.insert(name, AtomicObjId::new(obj_id));
-            }
-        }
+        self.store_cell(fn_obj_id, name, obj_id)?;
 
         Ok(())
     }
@@ -2572,6 +2994,78 @@ impl<'a> FSRThreadRuntime<'a> {
         Ok(RetState::Normal)
     }
 
+    /// A hint that is neither a builtin type nor a visible class is not
+    /// checked, like the static checker treats it as `Any`.
+    fn type_guard_process(
+        self: &mut FSRThreadRuntime<'a>,
+        bytecode: &BytecodeArg,
+    ) -> Result<RetState, FSRError> {
+        let ArgType::TypeGuard(guard) = bytecode.get_arg() else {
+            return Err(FSRError::new(
+                "not a type guard",
+                FSRErrCode::NotValidArgs,
+            ));
+        };
+
+        self.check_type_guard(guard)?;
+        Ok(RetState::Normal)
+    }
+
+    /// Class object named by a `@guard` hint
+    fn guard_class(&self, cls_name: &str) -> Option<ObjId> {
+        let cls = match cls_name {
+            "Integer" => GlobalObj::IntegerCls,
+            "Float" => GlobalObj::FloatCls,
+            "String" => GlobalObj::StringCls,
+            "Bool" => GlobalObj::BoolCls,
+            "List" => GlobalObj::ListCls,
+            "None" => GlobalObj::NoneCls,
+            "Fn" => GlobalObj::FnCls,
+            "HashMap" => GlobalObj::HashMapCls,
+            "HashSet" => GlobalObj::HashSetCls,
+            "Bytes" => GlobalObj::BytesCls,
+            "Range" => GlobalObj::RangeCls,
+            "Exception" => GlobalObj::Exception,
+            _ => {
+                return Self::get_chain_by_name(self, cls_name)
+                    .filter(|x| matches!(FSRObject::id_to_obj(*x).value, FSRValue::Class(_)));
+            }
+        };
+        Some(gid(cls))
+    }
+
+    /// Check an argument of a `@guard` function against its class hint, the
+    /// baseline tier calls it too
+    pub(crate) fn check_type_guard(
+        &self,
+        (id, name, cls_name): &(u64, String, String),
+    ) -> Result<(), FSRError> {
+        let Some(v) = self.get_cur_frame().get_var(id) else {
+            return Ok(());
+        };
+
+        let obj = FSRObject::id_to_obj(v.get());
+        // a hint which is not a class is never checked
+        let Some(cls_id) = self.guard_class(cls_name) else {
+            return Ok(());
+        };
+
+        // two classes may share a name, only the class object tells them apart
+        if std::ptr::eq(obj.cls, FSRObject::id_to_obj(cls_id).as_class()) {
+            return Ok(());
+        }
+
+        Err(FSRError::new(
+            format!(
+                "argument `{}` expects `{}`, found `{}`",
+                name,
+                cls_name,
+                obj.cls.get_name()
+            ),
+            FSRErrCode::NotValidArgs,
+        ))
+    }
+
     fn new_list(self: &mut FSRThreadRuntime<'a>, len: usize) -> Result<Vec<ObjId>, FSRError> {
         let mut list = Vec::with_capacity(len);
 
@@ -2775,7 +3269,7 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     #[inline]
-    fn last_for_iter_obj(&self) -> ObjId {
+    pub(crate) fn last_for_iter_obj(&self) -> ObjId {
         self.get_cur_frame()
             .flow_tracker
             .for_iter_obj
@@ -2875,7 +3369,8 @@ impl<'a> FSRThreadRuntime<'a> {
 
     fn is_catchable(&self) -> bool {
         for frame in self.call_frames.iter().rev() {
-            if !frame.catch_ends.is_empty() {
+            // the first frame of a spawned thread hands the error to `join`
+            if !frame.catch_ends.is_empty() || frame.code == 0 {
                 return true;
             }
         }
@@ -2925,6 +3420,8 @@ impl<'a> FSRThreadRuntime<'a> {
             BytecodeOperator::BinaryDot => Self::binary_dot_process(self, bytecode),
             BytecodeOperator::BinaryMul => Self::binary_mul_process(self),
             BytecodeOperator::Call => Self::call_process(self, bytecode),
+            BytecodeOperator::TailCall => Self::tail_call_process(self, bytecode),
+            BytecodeOperator::TypeGuard => Self::type_guard_process(self, bytecode),
             BytecodeOperator::IfTest => Self::if_test_process(self, bytecode),
             BytecodeOperator::WhileTest => Self::while_pre_process(self, bytecode),
             BytecodeOperator::DefineFn => Self::define_fn(self, bytecode),
@@ -3031,7 +3528,7 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     #[cold]
-    fn get_chains(thread: &FSRThreadRuntime, state: &CallFrame, var: &LocalVar) -> Option<ObjId> {
+    pub(crate) fn get_chains(thread: &FSRThreadRuntime, state: &CallFrame, var: &LocalVar) -> Option<ObjId> {
         let fn_id = state.fn_id;
         // if in __main__ the module base code
         if !is_base_fn!(fn_id) {
@@ -3046,7 +3543,6 @@ impl<'a> FSRThreadRuntime<'a> {
         let vm = thread.get_vm();
         let v = module
             .get_object(&var.name)
-            .map(|s| s.load(Ordering::Relaxed))
             .or_else(|| vm.get_global_obj_by_name(&var.name).copied());
 
         v
@@ -3073,14 +3569,13 @@ impl<'a> FSRThreadRuntime<'a> {
 
         let v = module
             .get_object(name)
-            .map(|s| s.load(Ordering::Relaxed))
             .or_else(|| vm.get_global_obj_by_name(name).copied());
 
         v
     }
 
     //#[cfg_attr(feature = "more_inline", inline(always))]
-    fn fetch_closure_variable(
+    pub(crate) fn fetch_closure_variable(
         &mut self,
         v: &(u64, String, Option<OpAssign>),
     ) -> Result<ObjId, FSRError> {
@@ -3275,7 +3770,7 @@ impl<'a> FSRThreadRuntime<'a> {
 
     #[cold]
     #[inline(never)]
-    fn collect_action(&mut self) {
+    pub(crate) fn collect_action(&mut self) {
         let st = std::time::Instant::now();
         if self.gc_context.gc_state == GcState::Stop {
             self.clear_marks();
@@ -3336,7 +3831,7 @@ impl<'a> FSRThreadRuntime<'a> {
             self.debugger_process(&expr[0]);
         }
 
-        //self.cache_slot(expr);
+        self.cache_slot(expr);
 
         while let Some(arg) = expr.get(self.get_cur_frame().ip.1) {
             // self.get_cur_mut_frame().ip.1 += 1;
@@ -3390,6 +3885,8 @@ impl<'a> FSRThreadRuntime<'a> {
             .unwrap();
         let code_id = FSRObject::obj_to_id(code);
 
+        // no base fn unlike `start`, names defined here are registered on the
+        // module for its importers, which also keeps its loops out of OSR
         let frame = self.frame_free_list.new_frame(code_id, 0, 2);
         let const_map = Self::get_const_map(self, code.as_code())?;
         self.push_frame(frame, index_map_obj_to_ptr(&const_map));
@@ -3424,82 +3921,62 @@ impl<'a> FSRThreadRuntime<'a> {
     }
 
     // compile jit function
-    fn compile_jit_fn(&self, module: ObjId) {
-        let mut h = HashMap::new();
-        let mut v = vec![];
-        for code in FSRObject::id_to_obj(module).as_module().iter_fn() {
-            if code.0 == MAIN_FN {
-                continue;
-            }
+    fn compile_jit_fn(&self, module: ObjId) -> Result<(), FSRError> {
+        let static_fns = FSRObject::id_to_obj(module).as_module().static_fn_names();
+        let mut_module = FSRObject::id_to_mut_obj(module).unwrap().as_mut_module();
+        for fn_name in static_fns.iter() {
+            mut_module.add_jit_code_slot(fn_name);
+        }
 
-            let fn_obj = code.1.as_code();
-            if fn_obj.get_bytecode().fn_info.is_jit {
-                v.push(code.0.to_string());
-            }
+        if let Some(path) = &self.aot_library {
+            // code compiled ahead of time, nothing is generated at runtime
+            aot::load_library(module, path)?;
+            return Ok(());
         }
-        let type_info_ref = &mut FSRObject::id_to_mut_obj(module)
-            .unwrap()
-            .as_mut_module()
-            .type_info;
-        for fn_name in v {
-            let mut mut_module = FSRObject::id_to_mut_obj(module).unwrap().as_mut_module();
-
-            let code_name = fn_name.split("::").collect::<Vec<&str>>();
-            if code_name.len() == 1 {
-                mut_module
-                    .jit_code_map
-                    .push((None, code_name[0].to_string(), AtomicUsize::new(0)));
-            } else {
-                let struct_name = code_name[0];
-                let father_type = type_info_ref
-                    .get_type(&FSRTypeName::new(struct_name))
-                    .unwrap();
-                mut_module.jit_code_map.push((
-                    Some(father_type),
-                    code_name[1].to_string(),
-                    AtomicUsize::new(0),
-                ));
+
+        // the cache is best effort, a failure to read or write it is reported
+        // and falls back to compiling in memory
+        if let Some(dir) = &self.jit_cache {
+            match cache::load_or_compile(module, dir) {
+                Ok(_) => return Ok(()),
+                Err(e) => eprintln!("jit cache {}: {:#}", dir.display(), e),
             }
         }
-        let mut start = Instant::now();
-        for code in FSRObject::id_to_obj(module).as_module().iter_fn() {
-            if code.0 == MAIN_FN {
-                continue;
-            }
 
-            let fn_obj = code.1.as_code();
-            if fn_obj.get_bytecode().fn_info.is_jit {
-                let jit = Self::compile_jit(
-                    &code.0,
-                    fn_obj,
-                    self.get_cur_frame().code,
-                    fn_obj.get_bytecode().fn_info.is_entry,
-                    fn_obj.get_bytecode().fn_info.fn_type.clone(),
-                );
-                h.insert(code.0.to_string(), jit);
-            }
+        let mut h = HashMap::new();
+        let mut start = Instant::now();
+        for fn_name in static_fns {
+            let fn_obj = mut_module.get_fn(&fn_name).unwrap().as_code();
+            let jit = Self::compile_jit(
+                &fn_name,
+                fn_obj,
+                self.get_cur_frame().code,
+                fn_obj.get_bytecode().fn_info.is_entry,
+                fn_obj.get_bytecode().fn_info.fn_type.clone(),
+            )?;
+            h.insert(fn_name, jit);
         }
         println!("JIT compile time: {:?}", start.elapsed());
 
         for code in h {
-            let mut mut_module = FSRObject::id_to_mut_obj(module).unwrap().as_mut_module();
-            let code_name = code.0.split("::").collect::<Vec<&str>>();
-            if code_name.len() == 1 {
-                mut_module
-                    .get_jit_code_map(None, code_name[0])
-                    .unwrap()
-                    .store(code.1 as usize, Ordering::Relaxed);
-            } else {
-                let struct_name = code_name[0];
-                let father_type = type_info_ref
-                    .get_type(&FSRTypeName::new(struct_name))
-                    .unwrap();
-                mut_module
-                    .get_jit_code_map(Some(father_type), code_name[1])
-                    .unwrap()
-                    .store(code.1 as usize, Ordering::Relaxed);
-            }
+            mut_module
+                .get_jit_code_slot(&code.0)
+                .unwrap()
+                .store(code.1 as usize, Ordering::Relaxed);
         }
+        Ok(())
+    }
+
+    /// Run the static functions from a library built by `aot::compile_library`
+    /// instead of compiling them when the module starts
+    pub fn set_aot_library(&mut self, path: impl Into<PathBuf>) {
+        self.aot_library = Some(path.into());
+    }
+
+    /// Keep the static functions compiled in `dir` and reuse them when the
+    /// same script starts again, see `cache::load_or_compile`
+    pub fn set_jit_cache(&mut self, dir: impl Into<PathBuf>) {
+        self.jit_cache = Some(dir.into());
     }
 
     pub fn start(&mut self, module: ObjId, start_dbg: bool) -> Result<(), FSRError> {
@@ -3519,10 +3996,11 @@ impl<'a> FSRThreadRuntime<'a> {
             }
         }
 
-        let base_fn = FSRFn::new_empty();
-        let base_fn = FSRObject::new_inst(base_fn, gid(GlobalObj::FnCls));
-        let base_fn = Box::new(base_fn);
-        let base_fn_id = FSRObject::obj_to_id(&base_fn);
+        // module variables live in the cells of the base fn, which is frozen
+        // with them once a thread is spawned
+        let base_fn_id = self
+            .garbage_collect
+            .new_object(FSRFn::new_empty(), gid(GlobalObj::FnCls));
 
         let const_map = Self::get_const_map(self, main_code.unwrap().as_code())?;
 
@@ -3540,12 +4018,20 @@ impl<'a> FSRThreadRuntime<'a> {
             self.startup_debug(code);
         }
 
-        Self::compile_jit_fn(self, module);
+        Self::compile_jit_fn(self, module)?;
 
+        let mut res = Ok(());
         while let Some(expr) = bs_code.get(self.get_cur_frame().ip.0) {
-            self.run_expr_wrapper(expr)?;
+            if let Err(e) = self.run_expr_wrapper(expr) {
+                res = Err(e);
+                break;
+            }
         }
 
+        // spawned threads read the objects of this heap
+        self.join_spawned();
+        res?;
+
         println!("count: {}", self.counter);
         #[cfg(feature = "count_bytecode")]
         {
@@ -3562,7 +4048,8 @@ impl<'a> FSRThreadRuntime<'a> {
         //     let offset = fn_def.get_ip();
         //     self.get_cur_mut_frame().ip = (offset.0, 0);
         // }
-        let mut code = &FSRObject::id_to_obj(self.get_cur_frame().code)
+        let mut code_id = self.get_cur_frame().code;
+        let mut code = &FSRObject::id_to_obj(code_id)
             .as_code()
             .get_bytecode()
             .bytecode;
@@ -3571,6 +4058,15 @@ impl<'a> FSRThreadRuntime<'a> {
             if v {
                 break;
             }
+
+            // frame was reused by a tail call to another function
+            if self.get_cur_frame().code != code_id {
+                code_id = self.get_cur_frame().code;
+                code = &FSRObject::id_to_obj(code_id)
+                    .as_code()
+                    .get_bytecode()
+                    .bytecode;
+            }
         }
 
         let cur = self.get_cur_mut_frame();
@@ -3612,16 +4108,17 @@ impl<'a> FSRThreadRuntime<'a> {
 
 #[allow(unused_imports)]
 mod test {
-    use std::sync::{Arc, Mutex};
+    use std::sync::{Arc, Mutex, atomic::Ordering};
 
     use crate::{
         backend::{
             types::{
-                base::{FSRObject, ObjId},
+                base::{FSRObject, GlobalObj, ObjId},
                 code::FSRCode,
                 module::FSRModule,
+                string::FSRString,
             },
-            vm::virtual_machine::FSRVM,
+            vm::virtual_machine::{FSRVM, gid},
         },
         utils::error::FSRError,
     };
@@ -3654,6 +4151,240 @@ mod test {
         // println!("{:?}", FSRObject::id_to_obj(v.get_object("abc").unwrap()));
     }
 
+    #[test]
+    fn test_baseline_tier() {
+        FSRVM::single();
+        let source_code = r#"
+        fn add(a, b) {
+            return a + b
+        }
+
+        fn format(a) {
+            return f"{a}"
+        }
+
+        @guard
+        fn guarded(a: Integer) {
+            return a
+        }
+
+        fn count_down(n) {
+            if n == 0 {
+                return 0
+            }
+            return count_down(n - 1)
+        }
+
+        fn count(n) {
+            i = 0
+            while i < n {
+                i += 1
+            }
+            return i
+        }
+
+        i = 0
+        while i < 1200 {
+            assert(add(i, 1) == i + 1)
+            format(i)
+            assert(guarded(i) == i)
+            count_down(1)
+            i += 1
+        }
+        assert(count(20000) == 20000)
+        count(1)
+        caught = false
+        try {
+            guarded("a")
+        } catch {
+            e = take_error()
+            caught = true
+        }
+        assert(caught)
+        assert(count_down(200000) == 0)
+        export("add", add)
+        export("format", format)
+        export("count", count)
+        export("guarded", guarded)
+        export("count_down", count_down)
+        "#;
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source_code, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.start(obj_id, false).unwrap();
+
+        let module = FSRObject::id_to_obj(obj_id).as_module();
+        let jit_info = |name: &str| {
+            let fn_id = module.get_object(name).unwrap();
+            &FSRObject::id_to_obj(fn_id).as_fn().jit_info
+        };
+        assert!(jit_info("add").get_baseline_code().is_some());
+        // format strings are not supported, the function stays interpreted
+        assert!(jit_info("format").get_baseline_code().is_none());
+        assert!(jit_info("guarded").get_baseline_code().is_some());
+        // a tail call reuses the interpreter frame, native code would grow the stack
+        assert!(jit_info("count_down").get_baseline_code().is_none());
+        // a single long loop makes the function hot for its next call
+        assert_eq!(jit_info("count").get_call_count(), 2);
+        assert!(jit_info("count").get_backedge_count() >= 20000);
+        assert!(jit_info("count").get_baseline_code().is_some());
+    }
+
+    #[test]
+    fn test_baseline_attr() {
+        FSRVM::single();
+        let source_code = r#"
+        class Point {
+            fn __new__(self, x) {
+                self.x = x
+            }
+
+            fn add(self, n) {
+                return self.x + n
+            }
+        }
+
+        class Other {
+            fn __new__(self) {
+                self.x = 10
+            }
+
+            fn add(self, n) {
+                return n
+            }
+        }
+
+        fn sum(p) {
+            return p.x + p.add(1)
+        }
+
+        p = Point(2)
+        o = Other()
+        i = 0
+        while i < 1200 {
+            assert(sum(p) == 5)
+            assert(sum(o) == 11)
+            i += 1
+        }
+        total = 0
+        for x in 0..20000 {
+            total += p.add(x) - p.x
+        }
+        assert(total == 199990000)
+        export("sum", sum)
+        "#;
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source_code, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.start(obj_id, false).unwrap();
+
+        let module = FSRObject::id_to_obj(obj_id).as_module();
+        let sum = module.get_object("sum").unwrap();
+        let info = &FSRObject::id_to_obj(sum).as_fn().jit_info;
+        assert!(info.get_baseline_code().is_some());
+        // the module loop calling a method runs as OSR code
+        assert!(runtime.counter < 100000);
+    }
+
+    #[test]
+    fn test_specialize_deopt() {
+        FSRVM::single();
+        let source_code = r#"
+        fn add(a, b) {
+            return a + b
+        }
+
+        fn count(n) {
+            i = 0
+            while i < n {
+                i += 1
+            }
+            return i
+        }
+
+        i = 0
+        while i < 6000 {
+            assert(add(i, 1) == i + 1)
+            assert(count(3) == 3)
+            i += 1
+        }
+        export("add", add)
+        export("count", count)
+        "#;
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source_code, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.start(obj_id, false).unwrap();
+
+        let module = FSRObject::id_to_obj(obj_id).as_module();
+        let get_fn = |name: &str| module.get_object(name).unwrap();
+        let add = get_fn("add");
+        let count = get_fn("count");
+        let add_info = &FSRObject::id_to_obj(add).as_fn().jit_info;
+        let count_info = &FSRObject::id_to_obj(count).as_fn().jit_info;
+        assert!(add_info.get_specialized_code().is_some());
+        assert!(count_info.get_specialized_code().is_some());
+
+        // strings fail the integer guard, the interpreter finishes the call
+        let a = runtime.garbage_collect.new_object(
+            FSRString::new_value("a"),
+            gid(GlobalObj::StringCls),
+        );
+        let ret = FSRObject::id_to_obj(add).call(&[a, a], &mut runtime).unwrap();
+        assert_eq!(FSRObject::id_to_obj(ret.get_id()).as_string(), "aa");
+        assert!(add_info.get_specialized_code().is_none());
+        assert_eq!(add_info.get_deopt_count(), 1);
+    }
+
+    #[test]
+    fn test_osr() {
+        FSRVM::single();
+        let source_code = r#"
+        fn scan(n) {
+            i = 0
+            while true {
+                if i == n {
+                    return i
+                }
+                i += 1
+            }
+        }
+
+        total = 0
+        for x in 0..100000 {
+            total += x
+        }
+        assert(total == 4999950000)
+        assert(scan(100000) == 100000)
+        export("scan", scan)
+        "#;
+        let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
+        let obj_id = FSRVM::leak_object(obj);
+        let v = FSRCode::from_code("main", source_code, obj_id).unwrap();
+        let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
+        obj.as_mut_module().init_fn_map(v);
+        let mut runtime = FSRThreadRuntime::new_runtime();
+        runtime.start(obj_id, false).unwrap();
+
+        // both loops leave the interpreter after their first iterations
+        assert!(runtime.counter < 200000);
+        let module = FSRObject::id_to_obj(obj_id).as_module();
+        let scan = module.get_object("scan").unwrap();
+        let info = &FSRObject::id_to_obj(scan).as_fn().jit_info;
+        assert_eq!(info.get_call_count(), 1);
+        assert!(info.get_baseline_code().is_none());
+        assert!(info.get_backedge_count() >= 100000);
+    }
+
     #[test]
     fn test_float() {
         FSRVM::single();
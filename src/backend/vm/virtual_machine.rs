@@ -1,7 +1,9 @@
 use std::{
-    cell::UnsafeCell,
     collections::HashMap,
-    sync::{atomic::AtomicBool, Arc, Mutex, Once},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, Once,
+    },
 };
 
 use ahash::AHashMap;
@@ -29,8 +31,12 @@ use crate::{
     },
     std::{
         core::{
-            gc::{Gc, init_gc}, io::init_io, thread::init_thread, time::Time, utils::init_utils
-        }, fs::{FSRFileSystem, file::FSRInnerFile}, os::FSROs, rand_fs::FSRRandModule, string::FSRStringModule
+            gc::{Gc, init_gc}, io::init_io, thread::init_thread, time::Time, utils::init_utils,
+            pool::{FSRTaskFuture, FSRThreadPool},
+            sync::{
+                FSRAtomicInt, FSRChannel, FSRCondvar, FSRLockGuard, FSRMutex, FSRRwLock, init_sync,
+            },
+        }, ffi::FSRFfiModule, fs::{FSRFileSystem, file::FSRInnerFile}, os::FSROs, rand_fs::FSRRandModule, string::FSRStringModule
     },
 };
 
@@ -66,8 +72,10 @@ pub struct FSRVM<'a> {
     global: AHashMap<String, ObjId>,
     global_modules: AHashMap<&'a str, ObjId>,
     pub(crate) core_module: AHashMap<&'static str, NewModuleFn>,
-    threads: Mutex<Vec<Option<UnsafeCell<FSRThreadRuntime<'a>>>>>,
-    
+    /// ids in use, each runtime is owned by the OS thread running it
+    threads: Mutex<Vec<bool>>,
+    /// threads spawned by scripts which have not finished
+    running: AtomicUsize,
 }
 
 pub static mut VM: Option<Arc<FSRVM<'static>>> = None;
@@ -124,6 +132,7 @@ impl<'a> FSRVM<'a> {
         res.insert("gc", Gc::new_module);
         res.insert("rand", FSRRandModule::new_module);
         res.insert("time", Time::new_module);
+        res.insert("ffi", FSRFfiModule::new_module);
         res
     }
 
@@ -134,6 +143,7 @@ impl<'a> FSRVM<'a> {
             global: AHashMap::new(),
             global_modules: AHashMap::new(),
             threads: Mutex::new(vec![]),
+            running: AtomicUsize::new(0),
             core_module,
             //module_manager: ModuleManager::new_manager(),
         };
@@ -151,34 +161,44 @@ impl<'a> FSRVM<'a> {
         unsafe { VM.as_ref().unwrap().clone() }
     }
 
-    /// **Warning**:: This function is not a rusty style function, use with caution
-    pub fn get_thread(&self, thread_id: usize) -> Option<&mut FSRThreadRuntime<'a>> {
-        let threads = self.threads.lock().unwrap();
-        if thread_id >= threads.len() {
-            return None;
-        }
-        let thread = unsafe { &mut *threads[thread_id].as_ref().unwrap().get() };
-        Some(thread)
-    }
-
-    pub fn add_thread(&self, mut thread: FSRThreadRuntime<'a>) -> usize {
+    /// Give `thread` the first free id, the runtime stays with its caller
+    pub fn add_thread(&self, thread: &mut FSRThreadRuntime<'_>) -> usize {
         let mut threads_guard = self.threads.lock().unwrap();
-        let mut id = 0;
-        for item in threads_guard.iter() {
-            if item.is_none() {
-                break;
+        let id = match threads_guard.iter().position(|x| !x) {
+            Some(id) => {
+                threads_guard[id] = true;
+                id
             }
-            id += 1;
-        }
+            None => {
+                threads_guard.push(true);
+                threads_guard.len() - 1
+            }
+        };
 
         thread.thread_id = id;
-        if id >= threads_guard.len() {
-            threads_guard.push(Some(UnsafeCell::new(thread)));
-        } else {
-            threads_guard[id] = Some(UnsafeCell::new(thread));
+        id
+    }
+
+    /// Free the id of a thread which is done
+    pub fn remove_thread(&self, thread_id: usize) {
+        let mut threads_guard = self.threads.lock().unwrap();
+        if let Some(item) = threads_guard.get_mut(thread_id) {
+            *item = false;
         }
+    }
 
-        id
+    /// Threads spawned by scripts and still running, frozen objects are only
+    /// freed when there is none
+    pub fn running_threads(&self) -> usize {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn thread_started(&self) {
+        self.running.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub(crate) fn thread_finished(&self) {
+        self.running.fetch_sub(1, Ordering::AcqRel);
     }
 
     #[cfg_attr(feature = "more_inline", inline(always))]
@@ -343,6 +363,62 @@ impl<'a> FSRVM<'a> {
                     )))),
                 );
 
+                OBJECTS.insert(
+                    GlobalObj::MutexCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRMutex::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::RwLockCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRRwLock::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::LockGuardCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRLockGuard::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::CondvarCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRCondvar::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::AtomicIntCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRAtomicInt::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::ChannelCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRChannel::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::ThreadPoolCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRThreadPool::get_class(),
+                    )))),
+                );
+
+                OBJECTS.insert(
+                    GlobalObj::TaskFutureCls as usize,
+                    Some(Self::new_stataic_object(FSRValue::Class(Box::new(
+                        FSRTaskFuture::get_class(),
+                    )))),
+                );
+
                 for object in OBJECTS.iter_mut().flatten() {
                     let obj_id = FSRObject::obj_to_id(object);
                     if let FSRValue::Class(c) = &mut object.value {
@@ -386,6 +462,18 @@ impl<'a> FSRVM<'a> {
             .insert("HashMap".to_string(), gid(GlobalObj::HashMapCls) as ObjId);
         self.global
             .insert("HashSet".to_string(), gid(GlobalObj::HashSetCls) as ObjId);
+        self.global
+            .insert("Mutex".to_string(), gid(GlobalObj::MutexCls) as ObjId);
+        self.global
+            .insert("RwLock".to_string(), gid(GlobalObj::RwLockCls) as ObjId);
+        self.global
+            .insert("Condvar".to_string(), gid(GlobalObj::CondvarCls) as ObjId);
+        self.global
+            .insert("AtomicInt".to_string(), gid(GlobalObj::AtomicIntCls) as ObjId);
+        self.global
+            .insert("Channel".to_string(), gid(GlobalObj::ChannelCls) as ObjId);
+        self.global
+            .insert("ThreadPool".to_string(), gid(GlobalObj::ThreadPoolCls) as ObjId);
     }
 
     pub fn init(&mut self) {
@@ -414,6 +502,12 @@ impl<'a> FSRVM<'a> {
             self.global.insert(obj.0.to_string(), id);
         }
 
+        let objs = init_sync();
+        for obj in objs {
+            let id = FSRVM::register_object(obj.1);
+            self.global.insert(obj.0.to_string(), id);
+        }
+
         self.init_global_object();
     }
 
@@ -428,6 +522,7 @@ impl<'a> FSRVM<'a> {
             area: Area::Global,
             write_barrier: AtomicBool::new(true),
             gc_count: 0,
+            heap: 0,
         }
     }
 
@@ -463,6 +558,10 @@ impl<'a> FSRVM<'a> {
         self.global.get(name)
     }
 
+    pub fn get_global_names(&self) -> impl Iterator<Item = &str> {
+        self.global.keys().map(|x| x.as_str())
+    }
+
     pub fn register_module(&mut self, name: &'a str, module: ObjId) {
         self.global_modules.insert(name, module);
     }
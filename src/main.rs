@@ -1,9 +1,11 @@
 use std::time::Instant;
 
-use std::io::Read;
+use std::{collections::HashSet, io::Read, path::Path};
 
 use fscript_rs::backend::{
-    compiler::bytecode::Bytecode,
+    compiler::{
+        bytecode::Bytecode, jit::{aot, checked, cranelift, debug, simd}, resolver::NameResolver, type_checker::TypeChecker,
+    },
     types::{base::FSRObject, code::FSRCode, module::FSRModule},
     vm::{thread::FSRThreadRuntime, virtual_machine::FSRVM},
 };
@@ -42,6 +44,37 @@ fn bench_compile() {
     println!("Bytecode Compile Time: {:?}", end - start);
 }
 
+/// Static check only, exit code is non-zero if any error is found
+fn check(file: &str, source_code: &str) -> i32 {
+    let vm = FSRVM::single();
+    let builtins = vm
+        .get_global_names()
+        .map(|x| x.to_string())
+        .collect::<HashSet<_>>();
+    let meta = FSRPosition::new();
+    let chars = source_code.chars().collect::<Vec<char>>();
+    let (module, lines) = match FSRModuleFrontEnd::parse(&chars, meta) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("{}: error: {}", file, e);
+            return 1;
+        }
+    };
+
+    let mut diagnostics = NameResolver::resolve(&module, &lines, &builtins);
+    diagnostics.extend(TypeChecker::check(&module, &lines));
+    diagnostics.sort_by_key(|x| (x.pos.line, x.pos.column));
+    for diagnostic in &diagnostics {
+        println!("{}:{}", file, diagnostic);
+    }
+
+    if NameResolver::has_error(&diagnostics) {
+        return 1;
+    }
+
+    0
+}
+
 fn main() {
     let mut vs = vec![];
     for i in std::env::args() {
@@ -80,6 +113,12 @@ fn main() {
     let mut source_code = String::new();
     f.read_to_string(&mut source_code).unwrap();
 
+    // name and type diagnostics are only reported here, running a script does
+    // not check it first
+    if vs.iter().any(|x| x.eq("--check")) {
+        std::process::exit(check(file, &source_code));
+    }
+
     if ast {
         let meta = FSRPosition::new();
         let chars = source_code.chars().collect::<Vec<char>>();
@@ -98,19 +137,84 @@ fn main() {
         return;
     }
 
-    let rt = FSRThreadRuntime::new_runtime();
-    let tid = vm.add_thread(rt);
-    // let runtime = Arc::new(rt);
+    let mut thread = FSRThreadRuntime::new_runtime();
+    vm.add_thread(&mut thread);
 
     let start = Instant::now();
-    let thread = vm.get_thread(tid).unwrap();
 
     let obj: Box<FSRObject<'_>> = Box::new(FSRModule::new_object("main"));
     let obj_id = FSRVM::leak_object(obj);
-    let v = FSRCode::from_code("main", &source_code, obj_id).unwrap();
+    let v = match FSRCode::from_code("main", &source_code, obj_id) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}: error: {}", file, e);
+            std::process::exit(1);
+        }
+    };
     let obj = FSRObject::id_to_mut_obj(obj_id).unwrap();
     obj.as_mut_module().init_fn_map(v);
-    thread.start(obj_id, debugger).unwrap();
+
+    // value of an option like `--aot lib.so`
+    let option = |name: &str| {
+        vs.iter()
+            .position(|x| x == name)
+            .and_then(|i| vs.get(i + 1))
+    };
+
+    debug::set_source_path(file);
+    if vs.iter().any(|x| x.eq("--perf-map")) {
+        debug::enable_perf_map();
+    }
+
+    if vs.iter().any(|x| x.eq("--gdb-jit")) {
+        debug::enable_gdb_jit();
+    }
+
+    if vs.iter().any(|x| x.eq("--jit-dump")) {
+        debug::enable_dump();
+    }
+
+    // only pointers from `alloc` are checked, others pass unchecked
+    if vs.iter().any(|x| x.eq("--checked")) {
+        checked::set_checked(true);
+    }
+
+    if vs.iter().any(|x| x.eq("--unchecked")) {
+        checked::set_checked(false);
+    }
+
+    if vs.iter().any(|x| x.eq("--no-simd")) {
+        simd::set_simd(false);
+    }
+
+    if let Some(level) = option("--jit-opt-level")
+        && let Err(e) = cranelift::set_opt_level(level)
+    {
+        eprintln!("--jit-opt-level: error: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(out) = option("--aot-out") {
+        if let Err(e) = aot::compile_library(obj_id, Path::new(out)) {
+            eprintln!("{}: error: {:#}", file, e);
+            std::process::exit(1);
+        }
+        println!("AOT library written to {}", out);
+        return;
+    }
+
+    if let Some(lib) = option("--aot") {
+        thread.set_aot_library(lib);
+    }
+
+    if let Some(dir) = option("--jit-cache") {
+        thread.set_jit_cache(dir);
+    }
+
+    if let Err(e) = thread.start(obj_id, debugger) {
+        eprintln!("{}: error: {}", file, e);
+        std::process::exit(1);
+    }
 
     let end = Instant::now();
     println!("{:?}", end - start);
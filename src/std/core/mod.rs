@@ -2,6 +2,8 @@ pub mod error;
 pub mod gc;
 pub mod io;
 pub mod iter;
+pub mod pool;
+pub mod sync;
 pub mod thread;
 pub mod utils;
 pub mod time;
\ No newline at end of file
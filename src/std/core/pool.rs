@@ -0,0 +1,632 @@
+// Thread pool and the `par_map`, `par_filter` and `par_for_each` adapters, which
+// run in place when called from a worker
+use std::{
+    collections::VecDeque,
+    panic::AssertUnwindSafe,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+};
+
+use crate::{
+    backend::{
+        types::{
+            any::ThreadResult,
+            base::{FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId},
+            class::FSRClass,
+            fn_def::FSRFn,
+            list::FSRList,
+        },
+        vm::{
+            share::{SendError, SendValue},
+            thread::FSRThreadRuntime,
+            virtual_machine::{FSRVM, gid},
+        },
+    },
+    std::core::{
+        sync::{as_ext, as_integer, check_args, impl_extension, ret_bool},
+        thread::run_thread,
+    },
+    to_rs_list,
+    utils::error::{FSRErrCode, FSRError},
+};
+
+/// What the parallel adapters do with the result of each item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParKind {
+    Map,
+    Filter,
+    ForEach,
+}
+
+#[derive(Debug)]
+enum Work {
+    /// `submit`, the function is called once with the args
+    Call(Vec<SendValue>),
+    /// chunk of a parallel adapter, the function is called with each item
+    Each(ParKind, Vec<SendValue>),
+}
+
+#[derive(Debug)]
+struct Task {
+    fn_id: ObjId,
+    work: Work,
+    slot: Arc<TaskSlot>,
+}
+
+#[derive(Debug, Default)]
+enum SlotState {
+    #[default]
+    Pending,
+    Done(ThreadResult),
+    /// the result was taken by `get`
+    Taken,
+}
+
+/// Result of a task, filled by the worker which ran it
+#[derive(Debug, Default)]
+pub struct TaskSlot {
+    state: Mutex<SlotState>,
+    cond: Condvar,
+}
+
+impl TaskSlot {
+    fn finish(&self, res: ThreadResult) {
+        *self.state.lock().unwrap() = SlotState::Done(res);
+        self.cond.notify_all();
+    }
+
+    fn is_done(&self) -> bool {
+        !matches!(*self.state.lock().unwrap(), SlotState::Pending)
+    }
+
+    fn wait(&self) -> Result<ThreadResult, FSRError> {
+        let mut state = self.state.lock().unwrap();
+        while matches!(*state, SlotState::Pending) {
+            state = self.cond.wait(state).unwrap();
+        }
+
+        match std::mem::replace(&mut *state, SlotState::Taken) {
+            SlotState::Done(res) => Ok(res),
+            _ => Err(FSRError::new(
+                "result of the future is already taken",
+                FSRErrCode::NotSupportOperator,
+            )),
+        }
+    }
+
+    fn references(&self) -> Box<dyn Iterator<Item = ObjId>> {
+        let mut refs = vec![];
+        if let SlotState::Done(Ok(value)) = &*self.state.lock().unwrap() {
+            value.push_references(&mut refs);
+        }
+        Box::new(refs.into_iter())
+    }
+}
+
+#[derive(Debug, Default)]
+struct PoolState {
+    tasks: VecDeque<Task>,
+    closed: bool,
+}
+
+/// Workers and the queue of tasks they take from
+#[derive(Debug)]
+pub struct ThreadPool {
+    state: Mutex<PoolState>,
+    cond: Condvar,
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    size: usize,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> Arc<Self> {
+        let pool = Arc::new(Self {
+            state: Mutex::new(PoolState::default()),
+            cond: Condvar::new(),
+            workers: Mutex::new(vec![]),
+            size,
+        });
+        for _ in 0..size {
+            pool.spawn_worker();
+        }
+
+        pool
+    }
+
+    /// Pool with a worker for each core, used by the parallel adapters
+    pub fn with_default_size() -> Arc<Self> {
+        let size = std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(4);
+        Self::new(size)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    fn spawn_worker(self: &Arc<Self>) {
+        let vm = FSRVM::single();
+        let pool = self.clone();
+        // workers read frozen objects, they count as running threads until
+        // they end
+        vm.thread_started();
+        let worker = std::thread::spawn(move || {
+            let mut runtime = FSRThreadRuntime::new_runtime();
+            runtime.is_main = false;
+            vm.add_thread(&mut runtime);
+            while let Some(task) = pool.take_task() {
+                let slot = task.slot.clone();
+                let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                    run_task(&mut runtime, task)
+                }));
+                match res {
+                    Ok(res) => slot.finish(res),
+                    Err(_) => {
+                        let e = FSRError::new("task panicked", FSRErrCode::NotSupportOperator);
+                        slot.finish(Err(SendError::Error(e)));
+                        // the runtime may be left in any state, a new worker
+                        // takes the place of this one
+                        if !pool.state.lock().unwrap().closed {
+                            pool.spawn_worker();
+                        }
+                        break;
+                    }
+                }
+            }
+
+            runtime.join_spawned();
+            vm.remove_thread(runtime.get_thread_id());
+            vm.thread_finished();
+        });
+        self.workers.lock().unwrap().push(worker);
+    }
+
+    /// Next task, `None` once the pool is closed and no task is left
+    fn take_task(&self) -> Option<Task> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(task) = state.tasks.pop_front() {
+                return Some(task);
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+
+    fn push(&self, fn_id: ObjId, work: Work) -> Result<Arc<TaskSlot>, FSRError> {
+        let slot = Arc::new(TaskSlot::default());
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(FSRError::new(
+                "thread pool is shut down",
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        state.tasks.push_back(Task {
+            fn_id,
+            work,
+            slot: slot.clone(),
+        });
+        drop(state);
+        self.cond.notify_one();
+        Ok(slot)
+    }
+
+    /// Split `items` into chunks for the workers and gather the results of
+    /// the chunks in order
+    fn run_chunks(
+        &self,
+        fn_id: ObjId,
+        kind: ParKind,
+        mut items: Vec<SendValue>,
+        thread: &mut FSRThreadRuntime,
+    ) -> Result<Vec<SendValue>, FSRError> {
+        // a few chunks for each worker, so a slow chunk does not hold the
+        // others back
+        let chunks = items.len().min(self.size * 4).max(1);
+        let chunk_len = items.len().div_ceil(chunks);
+        let mut slots = vec![];
+        while !items.is_empty() {
+            let rest = items.split_off(chunk_len.min(items.len()));
+            slots.push(self.push(fn_id, Work::Each(kind, items))?);
+            items = rest;
+        }
+
+        let mut out = vec![];
+        for slot in slots {
+            match slot.wait()? {
+                Ok(SendValue::List(values)) => out.extend(values),
+                Ok(_) => unreachable!("chunk result is a list"),
+                Err(e) => return Err(e.into_error(thread)),
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Let the workers finish the tasks left and wait for them
+    pub fn shutdown(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.cond.notify_all();
+        loop {
+            // a worker replacing a panicked one is pushed while joining
+            let Some(worker) = self.workers.lock().unwrap().pop() else {
+                break;
+            };
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Call the function of `task` in the runtime of a worker
+fn run_task(runtime: &mut FSRThreadRuntime, task: Task) -> ThreadResult {
+    let res = match task.work {
+        Work::Call(args) => run_thread(runtime, task.fn_id, args),
+        Work::Each(kind, items) => run_each(runtime, task.fn_id, kind, items),
+    };
+    // the args are kept in the base frame while the function runs, the
+    // worker runtime is reused by the next task
+    runtime.get_cur_mut_frame().middle_value.clear();
+    res
+}
+
+fn run_each(
+    runtime: &mut FSRThreadRuntime,
+    fn_id: ObjId,
+    kind: ParKind,
+    items: Vec<SendValue>,
+) -> ThreadResult {
+    let heap = runtime.garbage_collect.heap_id();
+    let mut call = || {
+        let f = FSRObject::id_to_obj(fn_id);
+        let mut out = Vec::with_capacity(items.len());
+        for item in items {
+            let arg = item.into_object(runtime)?;
+            runtime.get_cur_mut_frame().middle_value.push(arg);
+            let ret = f.call(&[arg], runtime)?.get_id();
+            runtime.get_cur_mut_frame().middle_value.pop();
+            match kind {
+                ParKind::Map => out.push(SendValue::from_object(ret, heap, false)?),
+                ParKind::Filter => out.push(SendValue::Bool(ret == FSRObject::true_id())),
+                ParKind::ForEach => {}
+            }
+        }
+
+        Ok(SendValue::List(out))
+    };
+
+    call().map_err(|e| SendError::from_error(e, heap))
+}
+
+/// Pool object of scripts, the workers end when it is freed
+#[derive(Debug)]
+pub struct FSRThreadPool {
+    pool: Arc<ThreadPool>,
+}
+
+impl Drop for FSRThreadPool {
+    fn drop(&mut self) {
+        self.pool.shutdown();
+    }
+}
+
+/// Result of a task given to `submit`
+#[derive(Debug)]
+pub struct FSRTaskFuture {
+    slot: Arc<TaskSlot>,
+}
+
+impl_extension!(FSRThreadPool, |_this| Box::new(std::iter::empty()));
+impl_extension!(FSRTaskFuture, |this| this.slot.references());
+
+/// Freeze the function run by the workers, a function made by a spawned
+/// thread dies with it and can not be run there
+fn send_fn(fn_id: ObjId, thread: &FSRThreadRuntime) -> Result<ObjId, FSRError> {
+    if !matches!(FSRObject::id_to_obj(fn_id).value, FSRValue::Function(_)) {
+        return Err(FSRError::new("expected a function", FSRErrCode::NotValidArgs));
+    }
+
+    SendValue::from_shared(fn_id, thread)?;
+    Ok(fn_id)
+}
+
+/// Items of a list, a range or an iterator, taken in the calling thread
+fn collect_items(id: ObjId, thread: &mut FSRThreadRuntime) -> Result<Vec<ObjId>, FSRError> {
+    let id = match &FSRObject::id_to_obj(id).value {
+        FSRValue::Iterator(_) => {
+            crate::backend::types::iterator::as_list([id].as_ptr(), 1, thread)?.get_id()
+        }
+        _ => id,
+    };
+
+    let obj = FSRObject::id_to_obj(id);
+    match &obj.value {
+        FSRValue::List(l) => Ok(l
+            .iter_values()
+            .map(|x| x.load(std::sync::atomic::Ordering::Relaxed))
+            .collect()),
+        FSRValue::Range(r) => Ok(r
+            .range
+            .clone()
+            .map(|x| thread.garbage_collect.get_integer(x))
+            .collect()),
+        _ => Err(FSRError::new(
+            format!("can not take items of a `{}`", obj.cls.get_name()),
+            FSRErrCode::NotValidArgs,
+        )),
+    }
+}
+
+/// Run the adapter in the calling thread
+fn run_in_place(
+    kind: ParKind,
+    fn_id: ObjId,
+    items: Vec<ObjId>,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    // items and results stay on the frame while `fn_id` runs, until the
+    // list holding them is made
+    let entry_len = thread.get_cur_frame().middle_value.len();
+    thread.get_cur_mut_frame().middle_value.extend(&items);
+    let res = call_each(kind, fn_id, items, thread).map(|out| {
+        if kind == ParKind::ForEach {
+            return FSRRetValue::GlobalId(FSRObject::none_id());
+        }
+
+        let list = thread
+            .garbage_collect
+            .new_object(FSRList::new_value(out), gid(GlobalObj::ListCls));
+        FSRRetValue::GlobalId(list)
+    });
+    thread.get_cur_mut_frame().middle_value.truncate(entry_len);
+    res
+}
+
+fn call_each(
+    kind: ParKind,
+    fn_id: ObjId,
+    items: Vec<ObjId>,
+    thread: &mut FSRThreadRuntime,
+) -> Result<Vec<ObjId>, FSRError> {
+    let f = FSRObject::id_to_obj(fn_id);
+    let mut out = vec![];
+    for id in items {
+        let ret = f.call(&[id], thread)?.get_id();
+        match kind {
+            ParKind::Map => {
+                thread.get_cur_mut_frame().middle_value.push(ret);
+                out.push(ret);
+            }
+            ParKind::Filter if ret == FSRObject::true_id() => out.push(id),
+            ParKind::Filter | ParKind::ForEach => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Call `fn_id` with each item of `items_id` in `pool`, or in the default
+/// pool of the runtime if not given, and gather the results in order
+fn par_run(
+    pool: Option<Arc<ThreadPool>>,
+    kind: ParKind,
+    items_id: ObjId,
+    fn_id: ObjId,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let items = collect_items(items_id, thread)?;
+    let pool = match pool {
+        Some(pool) => pool,
+        None if thread.is_main_thread() => thread.default_pool(),
+        None => return run_in_place(kind, fn_id, items, thread),
+    };
+
+    let fn_id = send_fn(fn_id, thread)?;
+    let values = items
+        .iter()
+        .map(|x| SendValue::from_shared(*x, thread))
+        .collect::<Result<Vec<_>, _>>()?;
+    let out = pool.run_chunks(fn_id, kind, values, thread)?;
+    let id = match kind {
+        ParKind::Map => SendValue::List(out).into_object(thread)?,
+        ParKind::Filter => {
+            let kept = items
+                .into_iter()
+                .zip(out)
+                .filter(|(_, keep)| matches!(keep, SendValue::Bool(true)))
+                .map(|(id, _)| id)
+                .collect();
+            thread
+                .garbage_collect
+                .new_object(FSRList::new_value(kept), gid(GlobalObj::ListCls))
+        }
+        ParKind::ForEach => FSRObject::none_id(),
+    };
+
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+fn par_adapter(
+    kind: ParKind,
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "parallel adapter")?;
+    par_run(None, kind, args[0], args[1], thread)
+}
+
+/// items.par_map(f) -> list of `f(item)`, in the order of the items
+pub fn fsr_fn_par_map(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    par_adapter(ParKind::Map, args, len, thread)
+}
+
+/// items.par_filter(f) -> list of the items for which `f` returns true
+pub fn fsr_fn_par_filter(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    par_adapter(ParKind::Filter, args, len, thread)
+}
+
+/// items.par_for_each(f), the results of `f` are dropped
+pub fn fsr_fn_par_for_each(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    par_adapter(ParKind::ForEach, args, len, thread)
+}
+
+/// ThreadPool::new(size), a worker for each core if size is not given
+pub fn fsr_fn_pool_new(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let pool = match args.first() {
+        Some(size) => {
+            let size = as_integer(*size)?;
+            if size <= 0 {
+                return Err(FSRError::new(
+                    "thread pool needs at least 1 worker",
+                    FSRErrCode::NotValidArgs,
+                ));
+            }
+            ThreadPool::new(size as usize)
+        }
+        None => ThreadPool::with_default_size(),
+    };
+
+    thread.pools.retain(|x| x.strong_count() > 0);
+    thread.pools.push(Arc::downgrade(&pool));
+    let pool = FSRThreadPool { pool };
+    let id = thread
+        .garbage_collect
+        .new_object(pool.to_any_type(), gid(GlobalObj::ThreadPoolCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+/// pool.submit(f, args...) -> TaskFuture of `f(args...)`
+pub fn fsr_fn_pool_submit(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "submit")?;
+    let pool = &as_ext::<FSRThreadPool>(args[0], "ThreadPool")?.pool;
+    let fn_id = send_fn(args[1], thread)?;
+    let task_args = args[2..]
+        .iter()
+        .map(|x| SendValue::from_shared(*x, thread))
+        .collect::<Result<Vec<_>, _>>()?;
+    let slot = pool.push(fn_id, Work::Call(task_args))?;
+    let future = FSRTaskFuture { slot };
+    let id = thread
+        .garbage_collect
+        .new_object(future.to_any_type(), gid(GlobalObj::TaskFutureCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+/// pool.map(f, items) -> list of `f(item)`, in the order of the items
+pub fn fsr_fn_pool_map(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 3, "map")?;
+    let pool = as_ext::<FSRThreadPool>(args[0], "ThreadPool")?.pool.clone();
+    par_run(Some(pool), ParKind::Map, args[2], args[1], thread)
+}
+
+pub fn fsr_fn_pool_size(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "size")?;
+    let pool = &as_ext::<FSRThreadPool>(args[0], "ThreadPool")?.pool;
+    let size = thread.garbage_collect.get_integer(pool.size() as i64);
+    Ok(FSRRetValue::GlobalId(size))
+}
+
+/// pool.shutdown(), wait for the tasks left, no task can be submitted after
+pub fn fsr_fn_pool_shutdown(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "shutdown")?;
+    as_ext::<FSRThreadPool>(args[0], "ThreadPool")?.pool.shutdown();
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+/// future.get() -> result of the task, its error is raised here
+pub fn fsr_fn_future_get(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "get")?;
+    let future = as_ext::<FSRTaskFuture>(args[0], "TaskFuture")?;
+    match future.slot.wait()? {
+        Ok(value) => Ok(FSRRetValue::GlobalId(value.into_object(thread)?)),
+        Err(e) => Err(e.into_error(thread)),
+    }
+}
+
+pub fn fsr_fn_future_done(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "done")?;
+    let future = as_ext::<FSRTaskFuture>(args[0], "TaskFuture")?;
+    ret_bool(future.slot.is_done())
+}
+
+impl FSRThreadPool {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("ThreadPool");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_pool_new, "pool_new");
+        cls.insert_attr("new", new);
+        let submit = FSRFn::from_rust_fn_static(fsr_fn_pool_submit, "pool_submit");
+        cls.insert_attr("submit", submit);
+        let map = FSRFn::from_rust_fn_static(fsr_fn_pool_map, "pool_map");
+        cls.insert_attr("map", map);
+        let size = FSRFn::from_rust_fn_static(fsr_fn_pool_size, "pool_size");
+        cls.insert_attr("size", size);
+        let shutdown = FSRFn::from_rust_fn_static(fsr_fn_pool_shutdown, "pool_shutdown");
+        cls.insert_attr("shutdown", shutdown);
+        cls
+    }
+}
+
+impl FSRTaskFuture {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("TaskFuture");
+        let get = FSRFn::from_rust_fn_static(fsr_fn_future_get, "task_future_get");
+        cls.insert_attr("get", get);
+        let done = FSRFn::from_rust_fn_static(fsr_fn_future_done, "task_future_done");
+        cls.insert_attr("done", done);
+        cls
+    }
+}
@@ -0,0 +1,971 @@
+// A lock returns a `LockGuard`, released by `unlock` or when the function
+// which took it returns
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicBool, AtomicI64, Ordering},
+    },
+};
+
+use crate::{
+    backend::{
+        types::{
+            base::{FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId},
+            class::FSRClass,
+            fn_def::FSRFn,
+            list::FSRList,
+        },
+        vm::{share::SendValue, thread::FSRThreadRuntime, virtual_machine::gid},
+    },
+    to_rs_list,
+    utils::error::{FSRErrCode, FSRError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GuardKind {
+    Read,
+    /// the only kind a `Mutex` hands out
+    Write,
+}
+
+#[derive(Debug)]
+struct LockState {
+    /// heap of the thread holding the write lock
+    writer: Option<u32>,
+    /// heaps of the threads holding a read lock
+    readers: Vec<u32>,
+    value: SendValue,
+}
+
+/// Lock of `Mutex` and `RwLock`. Holders are told apart by the heap of their
+/// thread, the guards outlive any native call so no std guard is kept
+#[derive(Debug)]
+pub struct RawLock {
+    state: Mutex<LockState>,
+    cond: Condvar,
+}
+
+impl RawLock {
+    fn new(value: SendValue) -> Self {
+        Self {
+            state: Mutex::new(LockState {
+                writer: None,
+                readers: vec![],
+                value,
+            }),
+            cond: Condvar::new(),
+        }
+    }
+
+    fn lock(&self, kind: GuardKind, owner: u32) -> Result<(), FSRError> {
+        let mut state = self.state.lock().unwrap();
+        // waiting for a lock the thread holds itself never ends
+        if state.writer == Some(owner)
+            || (kind == GuardKind::Write && state.readers.contains(&owner))
+        {
+            return Err(FSRError::new(
+                "lock is already held by this thread",
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        match kind {
+            GuardKind::Read => {
+                while state.writer.is_some() {
+                    state = self.cond.wait(state).unwrap();
+                }
+                state.readers.push(owner);
+            }
+            GuardKind::Write => {
+                while state.writer.is_some() || !state.readers.is_empty() {
+                    state = self.cond.wait(state).unwrap();
+                }
+                state.writer = Some(owner);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unlock(&self, kind: GuardKind, owner: u32) {
+        let mut state = self.state.lock().unwrap();
+        match kind {
+            GuardKind::Read => {
+                if let Some(i) = state.readers.iter().position(|x| *x == owner) {
+                    state.readers.swap_remove(i);
+                }
+            }
+            GuardKind::Write => state.writer = None,
+        }
+
+        drop(state);
+        self.cond.notify_all();
+    }
+
+    fn references(&self) -> Box<dyn Iterator<Item = ObjId>> {
+        let mut refs = vec![];
+        self.state.lock().unwrap().value.push_references(&mut refs);
+        Box::new(refs.into_iter())
+    }
+}
+
+/// A taken lock, kept by the guard object and by the frame of the function
+/// which took it
+#[derive(Debug)]
+pub struct LockGuard {
+    lock: Arc<RawLock>,
+    kind: GuardKind,
+    owner: u32,
+    released: AtomicBool,
+}
+
+impl LockGuard {
+    pub fn release(&self) {
+        if !self.released.swap(true, Ordering::AcqRel) {
+            self.lock.unlock(self.kind, self.owner);
+        }
+    }
+
+    fn check_held(&self, thread: &FSRThreadRuntime) -> Result<(), FSRError> {
+        if self.owner != thread.garbage_collect.heap_id() {
+            return Err(FSRError::new(
+                "lock guard belongs to another thread",
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        if self.released.load(Ordering::Acquire) {
+            return Err(FSRError::new(
+                "lock guard is already released",
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+macro_rules! impl_extension {
+    ($ty:ty, |$this:ident| $refs:expr) => {
+        impl $crate::backend::types::any::ExtensionTrait for $ty {
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+
+            fn get_reference<'a>(
+                &'a self,
+                _: bool,
+                _: &mut Vec<$crate::backend::types::base::ObjId>,
+                _: &mut bool,
+            ) -> Box<dyn Iterator<Item = $crate::backend::types::base::ObjId> + 'a> {
+                let $this = self;
+                $refs
+            }
+
+            fn set_undirty(&mut self) {}
+        }
+
+        impl $ty {
+            pub fn to_any_type(self) -> $crate::backend::types::base::FSRValue<'static> {
+                $crate::backend::types::base::FSRValue::Extension(Box::new(
+                    $crate::backend::types::any::FSRExtension {
+                        value: Box::new(self),
+                    },
+                ))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_extension;
+
+#[derive(Debug)]
+pub struct FSRMutex {
+    lock: Arc<RawLock>,
+}
+
+#[derive(Debug)]
+pub struct FSRRwLock {
+    lock: Arc<RawLock>,
+}
+
+#[derive(Debug)]
+pub struct FSRLockGuard {
+    guard: Arc<LockGuard>,
+}
+
+#[derive(Debug, Default)]
+pub struct FSRCondvar {
+    /// bumped by each notify, a waiter sleeps until it changes
+    generation: Mutex<u64>,
+    cond: Condvar,
+}
+
+#[derive(Debug, Default)]
+pub struct FSRAtomicInt {
+    value: AtomicI64,
+}
+
+/// Wakes a `select` waiting on several channels
+#[derive(Debug, Default)]
+struct Signal {
+    fired: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Signal {
+    fn fire(&self) {
+        *self.fired.lock().unwrap() = true;
+        self.cond.notify_all();
+    }
+
+    fn wait(&self) {
+        let mut fired = self.fired.lock().unwrap();
+        while !*fired {
+            fired = self.cond.wait(fired).unwrap();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ChannelState {
+    items: VecDeque<SendValue>,
+    /// `None` for an unbounded channel
+    cap: Option<usize>,
+    closed: bool,
+    selects: Vec<Arc<Signal>>,
+}
+
+impl ChannelState {
+    fn wake_selects(&self) {
+        for signal in &self.selects {
+            signal.fire();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FSRChannel {
+    state: Mutex<ChannelState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl_extension!(FSRMutex, |this| this.lock.references());
+impl_extension!(FSRRwLock, |this| this.lock.references());
+impl_extension!(FSRLockGuard, |_this| Box::new(std::iter::empty()));
+impl_extension!(FSRCondvar, |_this| Box::new(std::iter::empty()));
+impl_extension!(FSRAtomicInt, |_this| Box::new(std::iter::empty()));
+impl_extension!(FSRChannel, |this| {
+    let mut refs = vec![];
+    for item in this.state.lock().unwrap().items.iter() {
+        item.push_references(&mut refs);
+    }
+    Box::new(refs.into_iter())
+});
+
+pub(crate) fn as_ext<'a, T: 'static>(id: ObjId, name: &str) -> Result<&'a T, FSRError> {
+    if let FSRValue::Extension(any) = &FSRObject::id_to_obj(id).value
+        && let Some(v) = any.value.as_any().downcast_ref::<T>()
+    {
+        return Ok(v);
+    }
+
+    Err(FSRError::new(format!("not a {}", name), FSRErrCode::NotValidArgs))
+}
+
+pub(crate) fn as_integer(id: ObjId) -> Result<i64, FSRError> {
+    if let FSRValue::Integer(i) = FSRObject::id_to_obj(id).value {
+        return Ok(i);
+    }
+
+    Err(FSRError::new("expected an integer", FSRErrCode::NotValidArgs))
+}
+
+pub(crate) fn check_args(args: &[ObjId], len: usize, name: &str) -> Result<(), FSRError> {
+    if args.len() < len {
+        return Err(FSRError::new(
+            format!("{} requires {} arguments", name, len),
+            FSRErrCode::NotValidArgs,
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn ret_bool(b: bool) -> Result<FSRRetValue, FSRError> {
+    if b {
+        Ok(FSRRetValue::GlobalId(FSRObject::true_id()))
+    } else {
+        Ok(FSRRetValue::GlobalId(FSRObject::false_id()))
+    }
+}
+
+/// Take `lock` for the current function and return the guard object
+fn take_lock(
+    lock: &Arc<RawLock>,
+    kind: GuardKind,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let owner = thread.garbage_collect.heap_id();
+    lock.lock(kind, owner)?;
+    let guard = Arc::new(LockGuard {
+        lock: lock.clone(),
+        kind,
+        owner,
+        released: AtomicBool::new(false),
+    });
+    thread.get_cur_mut_frame().guards.push(guard.clone());
+    let guard = FSRLockGuard { guard };
+    let id = thread
+        .garbage_collect
+        .new_object(guard.to_any_type(), gid(GlobalObj::LockGuardCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+/// Initial value of `Mutex::new` and `RwLock::new`, `none` if not given
+fn initial_value(args: &[ObjId], thread: &FSRThreadRuntime) -> Result<SendValue, FSRError> {
+    match args.first() {
+        Some(id) => SendValue::from_shared(*id, thread),
+        None => Ok(SendValue::None),
+    }
+}
+
+pub fn fsr_fn_mutex_new(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let value = initial_value(args, thread)?;
+    let mutex = FSRMutex {
+        lock: Arc::new(RawLock::new(value)),
+    };
+    let id = thread
+        .garbage_collect
+        .new_object(mutex.to_any_type(), gid(GlobalObj::MutexCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+pub fn fsr_fn_mutex_lock(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "lock")?;
+    let mutex = as_ext::<FSRMutex>(args[0], "Mutex")?;
+    take_lock(&mutex.lock, GuardKind::Write, thread)
+}
+
+pub fn fsr_fn_rwlock_new(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let value = initial_value(args, thread)?;
+    let rwlock = FSRRwLock {
+        lock: Arc::new(RawLock::new(value)),
+    };
+    let id = thread
+        .garbage_collect
+        .new_object(rwlock.to_any_type(), gid(GlobalObj::RwLockCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+pub fn fsr_fn_rwlock_read(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "read")?;
+    let rwlock = as_ext::<FSRRwLock>(args[0], "RwLock")?;
+    take_lock(&rwlock.lock, GuardKind::Read, thread)
+}
+
+pub fn fsr_fn_rwlock_write(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "write")?;
+    let rwlock = as_ext::<FSRRwLock>(args[0], "RwLock")?;
+    take_lock(&rwlock.lock, GuardKind::Write, thread)
+}
+
+/// guard.get() -> copy of the value kept by the lock
+pub fn fsr_fn_guard_get(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "get")?;
+    let guard = &as_ext::<FSRLockGuard>(args[0], "LockGuard")?.guard;
+    guard.check_held(thread)?;
+    let value = guard.lock.state.lock().unwrap().value.clone();
+    Ok(FSRRetValue::GlobalId(value.into_object(thread)?))
+}
+
+/// guard.set(value), only a write guard changes the value
+pub fn fsr_fn_guard_set(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "set")?;
+    let guard = &as_ext::<FSRLockGuard>(args[0], "LockGuard")?.guard;
+    guard.check_held(thread)?;
+    if guard.kind == GuardKind::Read {
+        return Err(FSRError::new(
+            "can not set the value through a read guard",
+            FSRErrCode::NotSupportOperator,
+        ));
+    }
+
+    let value = SendValue::from_shared(args[1], thread)?;
+    guard.lock.state.lock().unwrap().value = value;
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+pub fn fsr_fn_guard_unlock(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "unlock")?;
+    let guard = &as_ext::<FSRLockGuard>(args[0], "LockGuard")?.guard;
+    guard.check_held(thread)?;
+    guard.release();
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+pub fn fsr_fn_condvar_new(
+    _args: *const ObjId,
+    _len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let id = thread.garbage_collect.new_object(
+        FSRCondvar::default().to_any_type(),
+        gid(GlobalObj::CondvarCls),
+    );
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+/// cv.wait(guard), release the mutex of `guard` until the condvar is
+/// notified, then take it again
+pub fn fsr_fn_condvar_wait(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "wait")?;
+    let condvar = as_ext::<FSRCondvar>(args[0], "Condvar")?;
+    let guard = &as_ext::<FSRLockGuard>(args[1], "LockGuard")?.guard;
+    guard.check_held(thread)?;
+
+    // the generation is read before the mutex is released, a notify sent
+    // after that wakes the waiter
+    let mut generation = condvar.generation.lock().unwrap();
+    let start = *generation;
+    guard.lock.unlock(guard.kind, guard.owner);
+    while *generation == start {
+        generation = condvar.cond.wait(generation).unwrap();
+    }
+
+    drop(generation);
+    guard.lock.lock(guard.kind, guard.owner)?;
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+fn notify(args: *const ObjId, len: usize, all: bool) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "notify")?;
+    let condvar = as_ext::<FSRCondvar>(args[0], "Condvar")?;
+    *condvar.generation.lock().unwrap() += 1;
+    if all {
+        condvar.cond.notify_all();
+    } else {
+        condvar.cond.notify_one();
+    }
+
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+pub fn fsr_fn_condvar_notify_one(
+    args: *const ObjId,
+    len: usize,
+    _thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    notify(args, len, false)
+}
+
+pub fn fsr_fn_condvar_notify_all(
+    args: *const ObjId,
+    len: usize,
+    _thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    notify(args, len, true)
+}
+
+pub fn fsr_fn_atomic_new(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let value = match args.first() {
+        Some(id) => as_integer(*id)?,
+        None => 0,
+    };
+    let atomic = FSRAtomicInt {
+        value: AtomicI64::new(value),
+    };
+    let id = thread
+        .garbage_collect
+        .new_object(atomic.to_any_type(), gid(GlobalObj::AtomicIntCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+pub fn fsr_fn_atomic_load(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "load")?;
+    let atomic = as_ext::<FSRAtomicInt>(args[0], "AtomicInt")?;
+    let v = atomic.value.load(Ordering::SeqCst);
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(v)))
+}
+
+pub fn fsr_fn_atomic_store(
+    args: *const ObjId,
+    len: usize,
+    _thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "store")?;
+    let atomic = as_ext::<FSRAtomicInt>(args[0], "AtomicInt")?;
+    atomic.value.store(as_integer(args[1])?, Ordering::SeqCst);
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+/// Run `op` on the value and the integer arg, returns the previous value
+fn atomic_update(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+    op: fn(&AtomicI64, i64) -> i64,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "atomic update")?;
+    let atomic = as_ext::<FSRAtomicInt>(args[0], "AtomicInt")?;
+    let prev = op(&atomic.value, as_integer(args[1])?);
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(prev)))
+}
+
+pub fn fsr_fn_atomic_fetch_add(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    atomic_update(args, len, thread, |a, v| a.fetch_add(v, Ordering::SeqCst))
+}
+
+pub fn fsr_fn_atomic_fetch_sub(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    atomic_update(args, len, thread, |a, v| a.fetch_sub(v, Ordering::SeqCst))
+}
+
+pub fn fsr_fn_atomic_swap(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    atomic_update(args, len, thread, |a, v| a.swap(v, Ordering::SeqCst))
+}
+
+/// a.compare_exchange(current, new) -> whether the value was `current`
+pub fn fsr_fn_atomic_compare_exchange(
+    args: *const ObjId,
+    len: usize,
+    _thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 3, "compare_exchange")?;
+    let atomic = as_ext::<FSRAtomicInt>(args[0], "AtomicInt")?;
+    let current = as_integer(args[1])?;
+    let new = as_integer(args[2])?;
+    let res = atomic
+        .value
+        .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst);
+    ret_bool(res.is_ok())
+}
+
+impl FSRChannel {
+    fn new(cap: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(ChannelState {
+                items: VecDeque::new(),
+                cap,
+                closed: false,
+                selects: vec![],
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    fn send(&self, value: SendValue) -> Result<(), FSRError> {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed && state.cap.is_some_and(|cap| state.items.len() >= cap) {
+            state = self.not_full.wait(state).unwrap();
+        }
+
+        if state.closed {
+            return Err(FSRError::new(
+                "send on a closed channel",
+                FSRErrCode::NotSupportOperator,
+            ));
+        }
+
+        state.items.push_back(value);
+        state.wake_selects();
+        drop(state);
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Next item, `None` once the channel is closed and drained. Only waits
+    /// for an item if `block` is set
+    fn recv(&self, block: bool) -> Option<SendValue> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(value) = state.items.pop_front() {
+                drop(state);
+                self.not_full.notify_one();
+                return Some(value);
+            }
+
+            if state.closed || !block {
+                return None;
+            }
+
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.wake_selects();
+        drop(state);
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+}
+
+fn new_channel(
+    cls: ObjId,
+    cap: Option<usize>,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let id = thread
+        .garbage_collect
+        .new_object(FSRChannel::new(cap).to_any_type(), cls);
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+/// Channel::new() -> unbounded channel
+pub fn fsr_fn_channel_new(
+    _args: *const ObjId,
+    _len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    new_channel(gid(GlobalObj::ChannelCls), None, thread)
+}
+
+/// Channel::bounded(cap) -> channel whose `send` waits while `cap` items are
+/// queued
+pub fn fsr_fn_channel_bounded(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "bounded")?;
+    let cap = as_integer(args[0])?;
+    if cap < 1 {
+        return Err(FSRError::new(
+            "capacity of a channel must be at least 1",
+            FSRErrCode::NotValidArgs,
+        ));
+    }
+
+    new_channel(gid(GlobalObj::ChannelCls), Some(cap as usize), thread)
+}
+
+pub fn fsr_fn_channel_send(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 2, "send")?;
+    let channel = as_ext::<FSRChannel>(args[0], "Channel")?;
+    channel.send(SendValue::from_shared(args[1], thread)?)?;
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+fn channel_recv(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+    block: bool,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "recv")?;
+    let channel = as_ext::<FSRChannel>(args[0], "Channel")?;
+    match channel.recv(block) {
+        Some(value) => Ok(FSRRetValue::GlobalId(value.into_object(thread)?)),
+        None => Ok(FSRRetValue::GlobalId(FSRObject::none_id())),
+    }
+}
+
+/// ch.recv() -> next item, `none` once the channel is closed and drained
+pub fn fsr_fn_channel_recv(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    channel_recv(args, len, thread, true)
+}
+
+/// ch.try_recv() -> next item, `none` if no item is queued
+pub fn fsr_fn_channel_try_recv(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    channel_recv(args, len, thread, false)
+}
+
+pub fn fsr_fn_channel_close(
+    args: *const ObjId,
+    len: usize,
+    _thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "close")?;
+    as_ext::<FSRChannel>(args[0], "Channel")?.close();
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+pub fn fsr_fn_channel_len(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "len")?;
+    let channel = as_ext::<FSRChannel>(args[0], "Channel")?;
+    let len = channel.state.lock().unwrap().items.len();
+    Ok(FSRRetValue::GlobalId(
+        thread.garbage_collect.get_integer(len as i64),
+    ))
+}
+
+/// select(channels) -> [index, item] of the first channel with an item,
+/// `none` once every channel is closed and drained
+pub fn fsr_fn_select(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    check_args(args, 1, "select")?;
+    let FSRValue::List(list) = &FSRObject::id_to_obj(args[0]).value else {
+        return Err(FSRError::new(
+            "select requires a list of channels",
+            FSRErrCode::NotValidArgs,
+        ));
+    };
+
+    let channels = list
+        .iter_values()
+        .map(|x| as_ext::<FSRChannel>(x.load(Ordering::Relaxed), "Channel"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (index, value) = loop {
+        let mut open = vec![false; channels.len()];
+        let mut item = None;
+        for (i, channel) in channels.iter().enumerate() {
+            if let Some(value) = channel.recv(false) {
+                item = Some((i, value));
+                break;
+            }
+
+            open[i] = !channel.state.lock().unwrap().closed;
+        }
+
+        if let Some(item) = item {
+            break item;
+        }
+
+        if !open.contains(&true) {
+            return Ok(FSRRetValue::GlobalId(FSRObject::none_id()));
+        }
+
+        // an item sent or a channel closed after the scan fires the signal
+        let signal = Arc::new(Signal::default());
+        for (i, channel) in channels.iter().enumerate() {
+            let mut state = channel.state.lock().unwrap();
+            if !state.items.is_empty() || (open[i] && state.closed) {
+                signal.fire();
+            }
+
+            state.selects.push(signal.clone());
+        }
+
+        signal.wait();
+        for channel in channels.iter() {
+            let mut state = channel.state.lock().unwrap();
+            state.selects.retain(|x| !Arc::ptr_eq(x, &signal));
+        }
+    };
+
+    let index = thread.garbage_collect.get_integer(index as i64);
+    thread.get_cur_mut_frame().middle_value.push(index);
+    let value = value.into_object(thread)?;
+    let id = thread
+        .garbage_collect
+        .new_object(FSRList::new_value(vec![index, value]), gid(GlobalObj::ListCls));
+    Ok(FSRRetValue::GlobalId(id))
+}
+
+impl FSRMutex {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Mutex");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_mutex_new, "mutex_new");
+        cls.insert_attr("new", new);
+        let lock = FSRFn::from_rust_fn_static(fsr_fn_mutex_lock, "mutex_lock");
+        cls.insert_attr("lock", lock);
+        cls
+    }
+}
+
+impl FSRRwLock {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("RwLock");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_rwlock_new, "rwlock_new");
+        cls.insert_attr("new", new);
+        let read = FSRFn::from_rust_fn_static(fsr_fn_rwlock_read, "rwlock_read");
+        cls.insert_attr("read", read);
+        let write = FSRFn::from_rust_fn_static(fsr_fn_rwlock_write, "rwlock_write");
+        cls.insert_attr("write", write);
+        cls
+    }
+}
+
+impl FSRLockGuard {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("LockGuard");
+        let get = FSRFn::from_rust_fn_static(fsr_fn_guard_get, "guard_get");
+        cls.insert_attr("get", get);
+        let set = FSRFn::from_rust_fn_static(fsr_fn_guard_set, "guard_set");
+        cls.insert_attr("set", set);
+        let unlock = FSRFn::from_rust_fn_static(fsr_fn_guard_unlock, "guard_unlock");
+        cls.insert_attr("unlock", unlock);
+        cls
+    }
+}
+
+impl FSRCondvar {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Condvar");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_condvar_new, "condvar_new");
+        cls.insert_attr("new", new);
+        let wait = FSRFn::from_rust_fn_static(fsr_fn_condvar_wait, "condvar_wait");
+        cls.insert_attr("wait", wait);
+        let notify_one =
+            FSRFn::from_rust_fn_static(fsr_fn_condvar_notify_one, "condvar_notify_one");
+        cls.insert_attr("notify_one", notify_one);
+        let notify_all =
+            FSRFn::from_rust_fn_static(fsr_fn_condvar_notify_all, "condvar_notify_all");
+        cls.insert_attr("notify_all", notify_all);
+        cls
+    }
+}
+
+impl FSRAtomicInt {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("AtomicInt");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_atomic_new, "atomic_new");
+        cls.insert_attr("new", new);
+        let load = FSRFn::from_rust_fn_static(fsr_fn_atomic_load, "atomic_load");
+        cls.insert_attr("load", load);
+        let store = FSRFn::from_rust_fn_static(fsr_fn_atomic_store, "atomic_store");
+        cls.insert_attr("store", store);
+        let fetch_add = FSRFn::from_rust_fn_static(fsr_fn_atomic_fetch_add, "atomic_fetch_add");
+        cls.insert_attr("fetch_add", fetch_add);
+        let fetch_sub = FSRFn::from_rust_fn_static(fsr_fn_atomic_fetch_sub, "atomic_fetch_sub");
+        cls.insert_attr("fetch_sub", fetch_sub);
+        let swap = FSRFn::from_rust_fn_static(fsr_fn_atomic_swap, "atomic_swap");
+        cls.insert_attr("swap", swap);
+        let compare_exchange = FSRFn::from_rust_fn_static(
+            fsr_fn_atomic_compare_exchange,
+            "atomic_compare_exchange",
+        );
+        cls.insert_attr("compare_exchange", compare_exchange);
+        cls
+    }
+}
+
+impl FSRChannel {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Channel");
+        let new = FSRFn::from_rust_fn_static(fsr_fn_channel_new, "channel_new");
+        cls.insert_attr("new", new);
+        let bounded = FSRFn::from_rust_fn_static(fsr_fn_channel_bounded, "channel_bounded");
+        cls.insert_attr("bounded", bounded);
+        let send = FSRFn::from_rust_fn_static(fsr_fn_channel_send, "channel_send");
+        cls.insert_attr("send", send);
+        let recv = FSRFn::from_rust_fn_static(fsr_fn_channel_recv, "channel_recv");
+        cls.insert_attr("recv", recv);
+        let try_recv = FSRFn::from_rust_fn_static(fsr_fn_channel_try_recv, "channel_try_recv");
+        cls.insert_attr("try_recv", try_recv);
+        let close = FSRFn::from_rust_fn_static(fsr_fn_channel_close, "channel_close");
+        cls.insert_attr("close", close);
+        let len = FSRFn::from_rust_fn_static(fsr_fn_channel_len, "channel_len");
+        cls.insert_attr("len", len);
+        cls
+    }
+}
+
+pub fn init_sync<'a>() -> HashMap<&'static str, FSRObject<'a>> {
+    let select_fn = FSRFn::from_rust_fn_static(fsr_fn_select, "select");
+    let mut m = HashMap::new();
+    m.insert("select", select_fn);
+    m
+}
@@ -1,13 +1,23 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use crate::{
     backend::{
-        memory::GarbageCollector,
         types::{
-            any::FSRThreadHandle, base::{FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId}, fn_def::FSRFn
+            any::{FSRThreadHandle, ThreadResult},
+            base::{FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId},
+            fn_def::FSRFn,
+        },
+        vm::{
+            share::{SendError, SendValue, freeze},
+            thread::FSRThreadRuntime,
+            virtual_machine::gid,
         },
-        vm::{thread::FSRThreadRuntime, virtual_machine::gid},
-    }, to_rs_list, utils::error::FSRError
+    },
+    to_rs_list,
+    utils::error::{FSRErrCode, FSRError},
 };
 
 pub fn fsr_get_cur_thread_id(
@@ -21,6 +31,25 @@ pub fn fsr_get_cur_thread_id(
     Ok(FSRRetValue::GlobalId(obj))
 }
 
+/// Call `fn_id` in the runtime of a new thread or a pool worker with the args
+/// built again there
+pub(crate) fn run_thread(runtime: &mut FSRThreadRuntime, fn_id: ObjId, args: Vec<SendValue>) -> ThreadResult {
+    let heap = runtime.garbage_collect.heap_id();
+    let mut call = || {
+        let mut th_args = Vec::with_capacity(args.len());
+        for arg in args {
+            let arg = arg.into_object(runtime)?;
+            runtime.get_cur_mut_frame().middle_value.push(arg);
+            th_args.push(arg);
+        }
+
+        let ret = FSRObject::id_to_obj(fn_id).call(&th_args, runtime)?.get_id();
+        SendValue::from_object(ret, heap, false)
+    };
+
+    call().map_err(|e| SendError::from_error(e, heap))
+}
+
 pub fn fsr_new_thread(
     args: *const ObjId,
     len: usize,
@@ -28,31 +57,37 @@ pub fn fsr_new_thread(
 ) -> Result<FSRRetValue, FSRError> {
     let args = to_rs_list!(args, len);
     let fn_id = args[0];
-    let th_thread_args = args[1..].iter().map(|x| Arc::new(*x)).collect::<Vec<_>>();
-    
-    // for arg in &th_thread_args {
-    //     thread.thread_shared.insert(arg.clone());
-    // }
-    let th2 = th_thread_args.clone();
+    if !matches!(FSRObject::id_to_obj(fn_id).value, FSRValue::Function(_)) {
+        return Err(FSRError::new("thread needs a function", FSRErrCode::NotValidArgs));
+    }
+
+    let heap = thread.garbage_collect.heap_id();
+    let th_args = args[1..]
+        .iter()
+        .map(|x| SendValue::from_object(*x, heap, true))
+        .collect::<Result<Vec<_>, _>>()?;
+    // the function and everything it reaches is read by the new thread
+    freeze(fn_id, heap);
 
     let vm = thread.get_vm();
+    vm.thread_started();
     let th = std::thread::spawn(move || {
-        
         let mut runtime = FSRThreadRuntime::new_runtime();
-        let mut args = vec![];
-        for arg in th_thread_args {
-            args.push(*arg);
-            //runtime.thread_shared.insert(arg.clone());
-        }
-        let thread_id = vm.add_thread(runtime);
-        let th = vm.get_thread(thread_id).unwrap();
-        let fn_obj = FSRObject::id_to_obj(fn_id);
-        let _ = fn_obj.call(&args, th);
+        runtime.is_main = false;
+        vm.add_thread(&mut runtime);
+        let res = run_thread(&mut runtime, fn_id, th_args);
+        runtime.join_spawned();
+        vm.remove_thread(runtime.get_thread_id());
+        res
     });
-    let mut handle = FSRThreadHandle::new(th);
-    handle.track_objects = th2.iter().map(|x| **x).collect();
-    let thread_obj = thread.garbage_collect.new_object(handle.to_any_type(), gid(GlobalObj::ThreadCls) as ObjId);
-    thread.thread_shared.insert(thread_obj);
+
+    let slot = Arc::new(Mutex::new(Some(th)));
+    thread.spawned.retain(|x| x.lock().unwrap().is_some());
+    thread.spawned.push(slot.clone());
+    let handle = FSRThreadHandle::new(slot);
+    let thread_obj = thread
+        .garbage_collect
+        .new_object(handle.to_any_type(), gid(GlobalObj::ThreadCls) as ObjId);
     Ok(FSRRetValue::GlobalId(thread_obj))
 }
 
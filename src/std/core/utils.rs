@@ -301,6 +301,26 @@ pub fn timestamp(
     Ok(FSRRetValue::GlobalId(obj))
 }
 
+/// Print the cranelift IR and machine code of a function
+pub fn fsr_fn_jit_dump(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if args.len() != 1 {
+        return Err(FSRError::new("jit_dump takes 1 argument", FSRErrCode::NotValidArgs));
+    }
+
+    let obj = FSRObject::id_to_obj(args[0]);
+    if !matches!(obj.value, FSRValue::Function(_)) {
+        return Err(FSRError::new("not a function", FSRErrCode::NotValidArgs));
+    }
+
+    FSRThreadRuntime::jit_dump(obj.as_fn())?;
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
 pub fn init_utils() -> HashMap<&'static str, FSRObject<'static>> {
     let assert_fn = FSRFn::from_rust_fn_static(fsr_fn_assert, "assert");
     let export_fn = FSRFn::from_rust_fn_static(fsr_fn_export, "export");
@@ -313,6 +333,7 @@ pub fn init_utils() -> HashMap<&'static str, FSRObject<'static>> {
     let get_class = FSRFn::from_rust_fn_static(fsr_get_class, "get_class");
     let breakpoint_fn = FSRFn::from_rust_fn_static(fsr_breakpoint, "breakpoint");
     let unwrap_fn = FSRFn::from_rust_fn_static(unwrap, "unwrap");
+    let jit_dump_fn = FSRFn::from_rust_fn_static(fsr_fn_jit_dump, "jit_dump");
     let mut m = HashMap::new();
     m.insert("assert", assert_fn);
     m.insert("export", export_fn);
@@ -325,5 +346,6 @@ pub fn init_utils() -> HashMap<&'static str, FSRObject<'static>> {
     m.insert("breakpoint", breakpoint_fn);
     m.insert("panic", unwrap_fn);
     m.insert("timestamp", timestamp_fn);
+    m.insert("jit_dump", jit_dump_fn);
     m
 }
@@ -0,0 +1,716 @@
+// Calls into C libraries, signatures use the static type names:
+// `ffi::func(libc, "strlen", ["Ptr[u8]"], "u64")`
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    ffi::{CStr, CString},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    backend::{
+        compiler::{
+            bytecode::{FSRSType, FSRSTypeInfo},
+            jit::{
+                cranelift::CraneLiftJitBackend,
+                ffi::{FfiCall, FfiSig},
+            },
+        },
+        types::{
+            any::{ExtensionTrait, FSRExtension},
+            base::{FSRObject, FSRRetValue, FSRValue, GlobalObj, ObjId},
+            class::FSRClass,
+            fn_def::FSRFn,
+            module::FSRModule,
+            string::FSRString,
+        },
+        vm::{thread::FSRThreadRuntime, virtual_machine::gid},
+    },
+    register_class, register_fn, to_rs_list,
+    utils::error::{FSRErrCode, FSRError},
+};
+use frontend::ast::token::base::FSRTypeName;
+
+unsafe extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+}
+
+/// Classes of the `Library`, `Func` and `Callback` objects, set when the
+/// module is loaded
+static LIBRARY_CLS: AtomicUsize = AtomicUsize::new(0);
+static FUNC_CLS: AtomicUsize = AtomicUsize::new(0);
+static CALLBACK_CLS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Thread running a C call, callbacks of the call run their function on it
+    static CALL_THREAD: Cell<usize> = const { Cell::new(0) };
+    /// First error of a callback, returned by the C call once C returns
+    static CALLBACK_ERROR: RefCell<Option<FSRError>> = const { RefCell::new(None) };
+}
+
+fn args_error(msg: impl Into<String>) -> FSRError {
+    FSRError::new(msg.into(), FSRErrCode::NotValidArgs)
+}
+
+/// Types of the module whose code called into `ffi`
+fn module_types<'a>(thread: &FSRThreadRuntime) -> &'a FSRSTypeInfo {
+    let code = FSRObject::id_to_obj(thread.get_cur_frame().code).as_code();
+    &FSRObject::id_to_obj(code.module).as_module().type_info
+}
+
+fn resolve_type(name: &str, types: &FSRSTypeInfo) -> Result<Arc<FSRSType>, FSRError> {
+    let name = name.trim();
+    if let Some(sub) = name.strip_prefix("Ptr[").and_then(|x| x.strip_suffix(']')) {
+        return Ok(Arc::new(FSRSType::Ptr(resolve_type(sub, types)?)));
+    }
+
+    if name == "Ptr" || name == "List" || name.contains('[') {
+        return Err(args_error(format!("not a ffi type: {}", name)));
+    }
+
+    types
+        .get_type(&FSRTypeName::new(name))
+        .ok_or_else(|| args_error(format!("unknown type: {}", name)))
+}
+
+fn type_arg(id: ObjId, thread: &FSRThreadRuntime) -> Result<Arc<FSRSType>, FSRError> {
+    match &FSRObject::id_to_obj(id).value {
+        FSRValue::String(s) => resolve_type(s.as_str(), module_types(thread)),
+        _ => Err(args_error("type must be a type name string")),
+    }
+}
+
+/// Signature from a list of parameter type names and a return type name
+fn sig_arg(params: ObjId, ret: ObjId, thread: &FSRThreadRuntime) -> Result<FfiSig, FSRError> {
+    let params = match &FSRObject::id_to_obj(params).value {
+        FSRValue::List(l) => l
+            .iter_values()
+            .map(|x| type_arg(x.load(Ordering::Relaxed), thread))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Err(args_error("parameter types must be a list")),
+    };
+    let ret = match &FSRObject::id_to_obj(ret).value {
+        FSRValue::String(s) if s.as_str() == "none" => None,
+        FSRValue::None => None,
+        _ => Some(type_arg(ret, thread)?),
+    };
+    FfiSig::new(params, ret).map_err(|e| args_error(e.to_string()))
+}
+
+fn string_arg(id: ObjId) -> Result<&'static str, FSRError> {
+    match &FSRObject::id_to_obj(id).value {
+        FSRValue::String(s) => Ok(s.as_str()),
+        _ => Err(args_error("expected a string")),
+    }
+}
+
+fn int_arg(id: ObjId) -> Result<i64, FSRError> {
+    match &FSRObject::id_to_obj(id).value {
+        FSRValue::Integer(i) => Ok(*i),
+        _ => Err(args_error("expected an integer")),
+    }
+}
+
+fn extension<T: 'static>(id: ObjId) -> Option<&'static T> {
+    match &FSRObject::id_to_obj(id).value {
+        FSRValue::Extension(e) => e.value.as_any().downcast_ref::<T>(),
+        _ => None,
+    }
+}
+
+/// Word passed to C for `id` as a value of `ty`, strings are copied to
+/// `keep` which must live until C returns
+fn to_word(id: ObjId, ty: &FSRSType, keep: &mut Vec<CString>) -> Result<u64, FSRError> {
+    let obj = FSRObject::id_to_obj(id);
+    let word = match (ty, &obj.value) {
+        (FSRSType::Float32 | FSRSType::Float64, FSRValue::Float(f)) => f.to_bits(),
+        (FSRSType::Float32 | FSRSType::Float64, FSRValue::Integer(i)) => (*i as f64).to_bits(),
+        (FSRSType::Float32 | FSRSType::Float64, _) => {
+            return Err(args_error(format!("expected a float for {:?}", ty)));
+        }
+        (_, FSRValue::Integer(i)) => *i as u64,
+        (_, FSRValue::Bool(b)) => *b as u64,
+        (FSRSType::Ptr(_), FSRValue::None) => 0,
+        (FSRSType::Ptr(_), FSRValue::String(s)) => {
+            let s = CString::new(s.as_str())
+                .map_err(|_| args_error("string passed to C contains a NUL byte"))?;
+            keep.push(s);
+            keep.last().unwrap().as_ptr() as u64
+        }
+        (FSRSType::Ptr(_), FSRValue::Extension(_)) => match extension::<FSRFfiCallback>(id) {
+            Some(callback) => callback.code as u64,
+            None => return Err(args_error("only a Callback object is passed as pointer")),
+        },
+        _ => return Err(args_error(format!("can not pass {:?} as {:?}", obj.value, ty))),
+    };
+    Ok(word)
+}
+
+/// Object of the word C returned as a value of `ty`
+fn from_word(word: u64, ty: &FSRSType, thread: &mut FSRThreadRuntime) -> ObjId {
+    match ty {
+        FSRSType::Bool if word & 0xff != 0 => FSRObject::true_id(),
+        FSRSType::Bool => FSRObject::false_id(),
+        FSRSType::Float32 | FSRSType::Float64 => thread
+            .garbage_collect
+            .new_object(FSRValue::Float(f64::from_bits(word)), gid(GlobalObj::FloatCls)),
+        _ => thread.garbage_collect.get_integer(word as i64),
+    }
+}
+
+/// Read a value of `ty` at `ptr` as a word
+unsafe fn read_word(ptr: usize, ty: &FSRSType) -> Result<u64, FSRError> {
+    let ptr = ptr as *const u8;
+    let word = unsafe {
+        match ty {
+            FSRSType::Bool | FSRSType::UInt8 => ptr.read_unaligned() as u64,
+            FSRSType::UInt16 => (ptr as *const u16).read_unaligned() as u64,
+            FSRSType::UInt32 => (ptr as *const u32).read_unaligned() as u64,
+            FSRSType::UInt64 | FSRSType::IInt64 | FSRSType::Ptr(_) => {
+                (ptr as *const u64).read_unaligned()
+            }
+            FSRSType::IInt8 => (ptr as *const i8).read_unaligned() as i64 as u64,
+            FSRSType::IInt16 => (ptr as *const i16).read_unaligned() as i64 as u64,
+            FSRSType::IInt32 => (ptr as *const i32).read_unaligned() as i64 as u64,
+            FSRSType::Float32 => ((ptr as *const f32).read_unaligned() as f64).to_bits(),
+            FSRSType::Float64 => (ptr as *const u64).read_unaligned(),
+            _ => return Err(args_error(format!("can not read {:?}", ty))),
+        }
+    };
+    Ok(word)
+}
+
+/// Write the word as a value of `ty` at `ptr`
+unsafe fn write_word(ptr: usize, ty: &FSRSType, word: u64) -> Result<(), FSRError> {
+    let ptr = ptr as *mut u8;
+    unsafe {
+        match ty {
+            FSRSType::Bool | FSRSType::UInt8 | FSRSType::IInt8 => ptr.write_unaligned(word as u8),
+            FSRSType::UInt16 | FSRSType::IInt16 => {
+                (ptr as *mut u16).write_unaligned(word as u16)
+            }
+            FSRSType::UInt32 | FSRSType::IInt32 => {
+                (ptr as *mut u32).write_unaligned(word as u32)
+            }
+            FSRSType::UInt64 | FSRSType::IInt64 | FSRSType::Ptr(_) | FSRSType::Float64 => {
+                (ptr as *mut u64).write_unaligned(word)
+            }
+            FSRSType::Float32 => {
+                (ptr as *mut f32).write_unaligned(f64::from_bits(word) as f32)
+            }
+            _ => return Err(args_error(format!("can not write {:?}", ty))),
+        }
+    }
+    Ok(())
+}
+
+fn ptr_arg(id: ObjId) -> Result<usize, FSRError> {
+    let ptr = int_arg(id)? as usize;
+    if ptr == 0 {
+        return Err(args_error("null pointer dereference"));
+    }
+    Ok(ptr)
+}
+
+#[derive(Debug)]
+pub struct FSRFfiLibrary {
+    lib: Arc<libloading::Library>,
+    path: String,
+}
+
+impl ExtensionTrait for FSRFfiLibrary {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_reference<'a>(
+        &'a self,
+        full: bool,
+        worklist: &mut Vec<ObjId>,
+        is_add: &mut bool,
+    ) -> Box<dyn Iterator<Item = ObjId> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn set_undirty(&mut self) {}
+}
+
+impl FSRFfiLibrary {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Library");
+        cls.init_method();
+        let sym = FSRFn::from_rust_fn_static(fsr_fn_library_sym, "sym");
+        cls.insert_attr("sym", sym);
+        cls
+    }
+
+    /// Address of the symbol `name`
+    fn sym(&self, name: &str) -> Result<usize, FSRError> {
+        let sym = unsafe { self.lib.get::<*const u8>(name.as_bytes()) }.map_err(|e| {
+            FSRError::new(
+                format!("no symbol {} in {}: {}", name, self.path, e),
+                FSRErrCode::NoSuchObject,
+            )
+        })?;
+        Ok(*sym as usize)
+    }
+}
+
+/// ffi::open(path), the running program when no path is given
+pub fn fsr_fn_open(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let (lib, path) = match args.first() {
+        Some(path) => {
+            let path = string_arg(*path)?;
+            let lib = unsafe { libloading::Library::new(path) }.map_err(|e| {
+                FSRError::new(format!("can not open {}: {}", path, e), FSRErrCode::FileError)
+            })?;
+            (lib, path.to_string())
+        }
+        None => (libloading::os::unix::Library::this().into(), "<self>".to_string()),
+    };
+
+    let lib = FSRFfiLibrary {
+        lib: Arc::new(lib),
+        path,
+    };
+    let value = FSRValue::Extension(Box::new(FSRExtension {
+        value: Box::new(lib),
+    }));
+    let object = thread
+        .garbage_collect
+        .new_object(value, LIBRARY_CLS.load(Ordering::Relaxed));
+    Ok(FSRRetValue::GlobalId(object))
+}
+
+/// lib.sym(name), the address of a symbol
+pub fn fsr_fn_library_sym(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 2 {
+        return Err(args_error("sym requires a symbol name"));
+    }
+    let lib = extension::<FSRFfiLibrary>(args[0]).ok_or_else(|| args_error("not a Library"))?;
+    let addr = lib.sym(string_arg(args[1])?)?;
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(addr as i64)))
+}
+
+#[derive(Debug)]
+pub struct FSRFfiFn {
+    /// keeps the library of `addr` loaded
+    lib: Arc<libloading::Library>,
+    name: String,
+    addr: usize,
+    sig: FfiSig,
+    call: FfiCall,
+}
+
+impl ExtensionTrait for FSRFfiFn {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_reference<'a>(
+        &'a self,
+        full: bool,
+        worklist: &mut Vec<ObjId>,
+        is_add: &mut bool,
+    ) -> Box<dyn Iterator<Item = ObjId> + 'a> {
+        Box::new(std::iter::empty())
+    }
+
+    fn set_undirty(&mut self) {}
+}
+
+impl FSRFfiFn {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Func");
+        cls.init_method();
+        let call = FSRFn::from_rust_fn_static(fsr_fn_func_call, "call");
+        cls.insert_attr("call", call);
+        cls
+    }
+
+    fn call(&self, args: &[ObjId], thread: &mut FSRThreadRuntime) -> Result<ObjId, FSRError> {
+        if args.len() != self.sig.params.len() {
+            return Err(args_error(format!(
+                "{} takes {} arguments, got {}",
+                self.name,
+                self.sig.params.len(),
+                args.len()
+            )));
+        }
+
+        let mut keep = vec![];
+        let words = args
+            .iter()
+            .zip(self.sig.params.iter())
+            .map(|(arg, ty)| to_word(*arg, ty, &mut keep))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut ret = 0u64;
+        let prev = CALL_THREAD.with(|x| x.replace(thread as *mut FSRThreadRuntime as usize));
+        unsafe { (self.call)(self.addr, words.as_ptr(), &mut ret) };
+        CALL_THREAD.with(|x| x.set(prev));
+
+        if let Some(e) = CALLBACK_ERROR.with(|x| x.borrow_mut().take()) {
+            return Err(e);
+        }
+
+        Ok(match &self.sig.ret {
+            Some(ty) => from_word(ret, ty, thread),
+            None => FSRObject::none_id(),
+        })
+    }
+}
+
+/// ffi::func(lib, name, [param types], ret type)
+pub fn fsr_fn_func(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 4 {
+        return Err(args_error(
+            "func requires a library, a name, parameter types and a return type",
+        ));
+    }
+    let lib = extension::<FSRFfiLibrary>(args[0]).ok_or_else(|| args_error("not a Library"))?;
+    let name = string_arg(args[1])?;
+    let sig = sig_arg(args[2], args[3], thread)?;
+    let addr = lib.sym(name)?;
+
+    let mut jit = CraneLiftJitBackend::new();
+    let call = jit.compile_ffi_call(&format!("ffi::{}", name), &sig)?;
+    let func = FSRFfiFn {
+        lib: lib.lib.clone(),
+        name: name.to_string(),
+        addr,
+        sig,
+        call,
+    };
+    let value = FSRValue::Extension(Box::new(FSRExtension {
+        value: Box::new(func),
+    }));
+    let object = thread
+        .garbage_collect
+        .new_object(value, FUNC_CLS.load(Ordering::Relaxed));
+    Ok(FSRRetValue::GlobalId(object))
+}
+
+/// func.call(args...)
+pub fn fsr_fn_func_call(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let func = extension::<FSRFfiFn>(args[0]).ok_or_else(|| args_error("not a Func"))?;
+    let ret = func.call(&args[1..], thread)?;
+    Ok(FSRRetValue::GlobalId(ret))
+}
+
+/// Function and signature a callback trampoline dispatches to
+#[derive(Debug)]
+struct CallbackData {
+    callback: ObjId,
+    sig: FfiSig,
+}
+
+#[derive(Debug)]
+pub struct FSRFfiCallback {
+    /// boxed, the trampoline holds its address
+    data: Box<CallbackData>,
+    code: usize,
+}
+
+impl ExtensionTrait for FSRFfiCallback {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn get_reference<'a>(
+        &'a self,
+        full: bool,
+        worklist: &mut Vec<ObjId>,
+        is_add: &mut bool,
+    ) -> Box<dyn Iterator<Item = ObjId> + 'a> {
+        Box::new(std::iter::once(self.data.callback))
+    }
+
+    fn set_undirty(&mut self) {}
+}
+
+impl FSRFfiCallback {
+    pub fn get_class() -> FSRClass {
+        let mut cls = FSRClass::new("Callback");
+        cls.init_method();
+        let addr = FSRFn::from_rust_fn_static(fsr_fn_callback_addr, "addr");
+        cls.insert_attr("addr", addr);
+        cls
+    }
+}
+
+fn dispatch(data: &CallbackData, args: *const u64, ret: *mut u64) -> Result<(), FSRError> {
+    let thread = CALL_THREAD.with(|x| x.get()) as *mut FSRThreadRuntime;
+    if thread.is_null() {
+        return Err(FSRError::new(
+            "callback called outside of a ffi call",
+            FSRErrCode::NotValidArgs,
+        ));
+    }
+
+    let thread = unsafe { &mut *thread };
+    let args = data
+        .sig
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, ty)| from_word(unsafe { *args.add(i) }, ty, thread))
+        .collect::<Vec<_>>();
+    let value = FSRObject::id_to_obj(data.callback).call(&args, thread)?.get_id();
+    if let Some(ty) = &data.sig.ret {
+        if matches!(&FSRObject::id_to_obj(value).value, FSRValue::String(_)) {
+            return Err(args_error("a callback can not return a string to C"));
+        }
+        let word = to_word(value, ty, &mut vec![])?;
+        unsafe { *ret = word };
+    }
+    Ok(())
+}
+
+/// Entry of every callback trampoline, errors are kept for the C call and
+/// C gets a zero result
+extern "C" fn c_ffi_dispatch(data: usize, args: *const u64, ret: *mut u64) {
+    let data = unsafe { &*(data as *const CallbackData) };
+    unsafe { *ret = 0 };
+    if CALLBACK_ERROR.with(|x| x.borrow().is_some()) {
+        return;
+    }
+
+    if let Err(e) = dispatch(data, args, ret) {
+        CALLBACK_ERROR.with(|x| *x.borrow_mut() = Some(e));
+    }
+}
+
+/// ffi::callback(fn, [param types], ret type), a C function calling `fn`,
+/// valid while the callback object lives
+pub fn fsr_fn_callback(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 3 {
+        return Err(args_error(
+            "callback requires a function, parameter types and a return type",
+        ));
+    }
+    if !matches!(FSRObject::id_to_obj(args[0]).value, FSRValue::Function(_)) {
+        return Err(args_error("callback must be a function"));
+    }
+    let sig = sig_arg(args[1], args[2], thread)?;
+    let data = Box::new(CallbackData {
+        callback: args[0],
+        sig,
+    });
+
+    let mut jit = CraneLiftJitBackend::new();
+    let data_addr = data.as_ref() as *const CallbackData as usize;
+    let code = jit.compile_ffi_callback("ffi::callback", &data.sig, c_ffi_dispatch, data_addr)?;
+    let callback = FSRFfiCallback {
+        data,
+        code: code as usize,
+    };
+    let value = FSRValue::Extension(Box::new(FSRExtension {
+        value: Box::new(callback),
+    }));
+    let object = thread
+        .garbage_collect
+        .new_object(value, CALLBACK_CLS.load(Ordering::Relaxed));
+    Ok(FSRRetValue::GlobalId(object))
+}
+
+/// callback.addr(), the address of its code
+pub fn fsr_fn_callback_addr(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    let callback =
+        extension::<FSRFfiCallback>(args[0]).ok_or_else(|| args_error("not a Callback"))?;
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(callback.code as i64)))
+}
+
+/// ffi::alloc(size), a block of the C heap
+pub fn fsr_fn_alloc(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 1 {
+        return Err(args_error("alloc requires a size"));
+    }
+    let size = int_arg(args[0])?;
+    if size < 0 {
+        return Err(args_error("alloc size must not be negative"));
+    }
+    let ptr = unsafe { malloc(size as usize) };
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(ptr as i64)))
+}
+
+/// ffi::free(ptr)
+pub fn fsr_fn_free(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 1 {
+        return Err(args_error("free requires a pointer"));
+    }
+    unsafe { free(int_arg(args[0])? as usize as *mut u8) };
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+/// ffi::read(ptr, type)
+pub fn fsr_fn_read(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 2 {
+        return Err(args_error("read requires a pointer and a type"));
+    }
+    let ptr = ptr_arg(args[0])?;
+    let ty = type_arg(args[1], thread)?;
+    let word = unsafe { read_word(ptr, &ty)? };
+    Ok(FSRRetValue::GlobalId(from_word(word, &ty, thread)))
+}
+
+/// ffi::write(ptr, type, value)
+pub fn fsr_fn_write(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 3 {
+        return Err(args_error("write requires a pointer, a type and a value"));
+    }
+    let ptr = ptr_arg(args[0])?;
+    let ty = type_arg(args[1], thread)?;
+    if matches!(&FSRObject::id_to_obj(args[2]).value, FSRValue::String(_)) {
+        return Err(args_error("write a string with its bytes, not as a pointer"));
+    }
+    let word = to_word(args[2], &ty, &mut vec![])?;
+    unsafe { write_word(ptr, &ty, word)? };
+    Ok(FSRRetValue::GlobalId(FSRObject::none_id()))
+}
+
+/// ffi::cstring(ptr), the NUL terminated string at `ptr`
+pub fn fsr_fn_cstring(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 1 {
+        return Err(args_error("cstring requires a pointer"));
+    }
+    let ptr = ptr_arg(args[0])?;
+    let s = unsafe { CStr::from_ptr(ptr as *const std::ffi::c_char) };
+    let value = FSRString::new_value(s.to_string_lossy().into_owned());
+    let object = thread.garbage_collect.new_object(value, gid(GlobalObj::StringCls));
+    Ok(FSRRetValue::GlobalId(object))
+}
+
+/// ffi::sizeof(type)
+pub fn fsr_fn_sizeof(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 1 {
+        return Err(args_error("sizeof requires a type"));
+    }
+    let size = type_arg(args[0], thread)?.size_of();
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(size as i64)))
+}
+
+/// ffi::offsetof(struct, field), the byte offset of a struct field
+pub fn fsr_fn_offsetof(
+    args: *const ObjId,
+    len: usize,
+    thread: &mut FSRThreadRuntime,
+) -> Result<FSRRetValue, FSRError> {
+    let args = to_rs_list!(args, len);
+    if len != 2 {
+        return Err(args_error("offsetof requires a struct and a field name"));
+    }
+    let ty = type_arg(args[0], thread)?;
+    let field = string_arg(args[1])?;
+    let offset = match ty.as_ref() {
+        FSRSType::Struct(s) => s.fields.get(field).map(|x| x.0),
+        _ => return Err(args_error("offsetof requires a struct")),
+    }
+    .ok_or_else(|| args_error(format!("no field {}", field)))?;
+    Ok(FSRRetValue::GlobalId(thread.garbage_collect.get_integer(offset as i64)))
+}
+
+pub struct FSRFfiModule {}
+
+impl FSRFfiModule {
+    pub fn new_module(thread: &mut FSRThreadRuntime) -> FSRValue<'static> {
+        let mut module = FSRModule::new_module("ffi");
+        register_class!(module, thread, "Library", FSRFfiLibrary::get_class());
+        register_class!(module, thread, "Func", FSRFfiFn::get_class());
+        register_class!(module, thread, "Callback", FSRFfiCallback::get_class());
+        for (name, cls) in [
+            ("Library", &LIBRARY_CLS),
+            ("Func", &FUNC_CLS),
+            ("Callback", &CALLBACK_CLS),
+        ] {
+            let id = module.get_object(name).unwrap();
+            cls.store(id, Ordering::Relaxed);
+        }
+        register_fn!(module, thread, "open", fsr_fn_open);
+        register_fn!(module, thread, "func", fsr_fn_func);
+        register_fn!(module, thread, "callback", fsr_fn_callback);
+        register_fn!(module, thread, "alloc", fsr_fn_alloc);
+        register_fn!(module, thread, "free", fsr_fn_free);
+        register_fn!(module, thread, "read", fsr_fn_read);
+        register_fn!(module, thread, "write", fsr_fn_write);
+        register_fn!(module, thread, "cstring", fsr_fn_cstring);
+        register_fn!(module, thread, "sizeof", fsr_fn_sizeof);
+        register_fn!(module, thread, "offsetof", fsr_fn_offsetof);
+        FSRValue::Module(Box::new(module))
+    }
+}
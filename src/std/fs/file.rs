@@ -165,6 +165,7 @@ pub fn fsr_fn_read_all(
     let args = to_rs_list!(args, len);
     let file_obj_id = args[0];
     let file_obj = FSRObject::id_to_mut_obj(file_obj_id).unwrap();
+    file_obj.check_mutable()?;
 
     if let FSRValue::Extension(any_type) = &mut file_obj.value {
         if let Some(inner_file) = any_type.value.as_any_mut().downcast_mut::<FSRInnerFile>() {
@@ -271,6 +272,7 @@ pub fn fsr_fn_read(
     let args = to_rs_list!(args, len);
     let file_obj_id = args[0];
     let file_obj = FSRObject::id_to_mut_obj(file_obj_id).unwrap();
+    file_obj.check_mutable()?;
     let offset = args[1];
     let size = args[2];
 
@@ -345,6 +347,7 @@ pub fn fsr_fn_file_close(
     let args = to_rs_list!(args, len);
     let file_obj_id = args[0];
     let file_obj = FSRObject::id_to_mut_obj(file_obj_id).unwrap();
+    file_obj.check_mutable()?;
 
     if let FSRValue::Extension(any_type) = &mut file_obj.value {
         if let Some(inner_file) = any_type.value.as_any_mut().downcast_mut::<FSRInnerFile>() {
@@ -374,6 +377,7 @@ pub fn fsr_fn_file_lines(
     let args = to_rs_list!(args, len);
     let file_obj_id = args[0];
     let file_obj = FSRObject::id_to_mut_obj(file_obj_id).unwrap();
+    file_obj.check_mutable()?;
 
     if let FSRValue::Extension(any_type) = &mut file_obj.value {
         if let Some(inner_file) = any_type.value.as_any_mut().downcast_mut::<FSRInnerFile>() {
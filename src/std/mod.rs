@@ -5,4 +5,5 @@ pub mod regex;
 pub mod iterator;
 pub mod os;
 pub mod string;
-pub mod rand_fs;
\ No newline at end of file
+pub mod rand_fs;
+pub mod ffi;
\ No newline at end of file
@@ -168,10 +168,14 @@ impl From<io::Error> for FSRError {
 
 impl From<anyhow::Error> for FSRError {
     fn from(value: anyhow::Error) -> Self {
+        let value = match value.downcast::<FSRError>() {
+            Ok(e) => return e,
+            Err(value) => value,
+        };
         Self {
             inner: Box::new(ErrorStruct {
                 code: FSRErrCode::AnyhowError,
-                msg: value.to_string(),
+                msg: format!("{:#}", value),
             }),
         }
     }
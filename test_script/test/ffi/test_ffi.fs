@@ -0,0 +1,68 @@
+import ffi
+import os
+
+struct Pair {
+    left: i32
+    right: i32
+}
+
+libc = ffi::open("libc.so.6")
+
+strlen = ffi::func(libc, "strlen", ["Ptr[u8]"], "u64")
+assert(strlen.call("hello ffi") == 9)
+
+getpid = ffi::func(libc, "getpid", [], "i32")
+assert(getpid.call() == os::get_pid())
+
+abs = ffi::func(libc, "abs", ["i32"], "i32")
+assert(abs.call(-7) == 7)
+
+# memory from C is freed by ffi::free
+strdup = ffi::func(libc, "strdup", ["Ptr[u8]"], "Ptr[u8]")
+s = strdup.call("copy")
+assert(ffi::cstring(s) == "copy")
+ffi::free(s)
+
+# qsort calls back into script code
+fn compare(a, b) {
+    return ffi::read(a, "i32") - ffi::read(b, "i32")
+}
+
+cmp = ffi::callback(compare, ["Ptr[i32]", "Ptr[i32]"], "i32")
+qsort = ffi::func(libc, "qsort", ["Ptr[i32]", "u64", "u64", "Ptr[u8]"], "none")
+values = [5, -3, 9, 1, 7]
+size = ffi::sizeof("i32")
+buf = ffi::alloc(5 * size)
+i = 0
+while i < 5 {
+    ffi::write(buf + i * size, "i32", values[i])
+    i = i + 1
+}
+qsort.call(buf, 5, size, cmp)
+i = 0
+while i < 4 {
+    assert(ffi::read(buf + i * size, "i32") <= ffi::read(buf + (i + 1) * size, "i32"))
+    i = i + 1
+}
+assert(ffi::read(buf, "i32") == -3)
+ffi::free(buf)
+
+# structs cross the boundary behind a pointer
+assert(ffi::sizeof("Pair") == 8)
+p = ffi::alloc(ffi::sizeof("Pair"))
+memset = ffi::func(libc, "memset", ["Ptr[Pair]", "i32", "u64"], "Ptr[Pair]")
+assert(memset.call(p, 0, ffi::sizeof("Pair")) == p)
+ffi::write(p + ffi::offsetof("Pair", "right"), "i32", 4)
+assert(ffi::read(p + ffi::offsetof("Pair", "right"), "i32") == 4)
+ffi::free(p)
+
+by_value = false
+try {
+    ffi::func(libc, "abs", ["Pair"], "i32")
+} catch {
+    e = take_error()
+    by_value = true
+}
+assert(by_value)
+
+println("ffi ok")
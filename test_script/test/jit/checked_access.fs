@@ -0,0 +1,85 @@
+struct Pair {
+    left: u64
+    right: u64
+}
+
+@entry
+fn list_get(i: u64) -> u64 {
+    t: [u64, 4] = uninit
+    t[0] = 7
+    t[3] = 9
+    return t[i]
+}
+
+@static
+fn read_at(p: Ptr[u64], i: u64) -> u64 {
+    return p[i]
+}
+
+@entry
+fn ptr_get(i: u64) -> u64 {
+    p: Ptr[u64] = Pair.alloc(2)
+    p[0] = 1
+    p[3] = 4
+    v: u64 = read_at(p, i)
+    p.free
+    return v
+}
+
+@entry
+fn use_after_free() -> u64 {
+    p: Ptr[Pair] = Pair.alloc
+    p.left = 1
+    p.free
+    return p.left
+}
+
+@entry
+fn double_free() -> u64 {
+    p: Ptr[Pair] = Pair.alloc
+    p.left = 0
+    p.free
+    p.free
+    return p.left
+}
+
+assert(list_get(3) == 9, "checked_access: testcase1: in range list index")
+assert(ptr_get(3) == 4, "checked_access: testcase2: in range pointer index")
+
+caught = false
+try {
+    list_get(4)
+} catch {
+    println(take_error())
+    caught = true
+}
+assert(caught, "checked_access: testcase3: list index 4 of 4")
+
+caught = false
+try {
+    ptr_get(4)
+} catch {
+    println(take_error())
+    caught = true
+}
+assert(caught, "checked_access: testcase4: pointer index past the block")
+
+caught = false
+try {
+    use_after_free()
+} catch {
+    println(take_error())
+    caught = true
+}
+assert(caught, "checked_access: testcase5: use after free")
+
+caught = false
+try {
+    double_free()
+} catch {
+    println(take_error())
+    caught = true
+}
+assert(caught, "checked_access: testcase6: double free")
+
+println("checked access ok")
@@ -0,0 +1,96 @@
+struct Op {
+    f: fn(u64) -> u64
+    n: u64
+}
+
+@static
+fn double(n: u64) -> u64 {
+    return n * 2
+}
+
+@static
+fn pick(neg: bool) -> fn(u64) -> u64 {
+    if neg {
+        return |x: u64| -> u64 { return x + 100 }
+    }
+    return double
+}
+
+@entry
+fn run_struct(n: u64) -> u64 {
+    op: Ptr[Op] = Op.alloc
+    op.f = pick(true)
+    op.n = n
+    g: fn(u64) -> u64 = op.f
+    h: fn(u64) -> u64 = pick(false)
+    return g(op.n) + h(n)
+}
+
+@static
+fn square(n: u64) -> u64 {
+    return n * n
+}
+
+@static
+fn apply(f: fn(u64) -> u64, n: u64) -> u64 {
+    return f(n)
+}
+
+@static
+fn sort(l: [u64, 6], less: fn(u64, u64) -> bool) -> [u64, 6] {
+    i: u64 = 1
+    while i < 6 {
+        j: u64 = i
+        while j > 0 {
+            if less(l[j], l[j - 1]) {
+                t: u64 = l[j]
+                l[j] = l[j - 1]
+                l[j - 1] = t
+                j = j - 1
+            } else {
+                j = 0
+            }
+        }
+        i = i + 1
+    }
+    return l
+}
+
+@entry
+fn run_apply(n: u64) -> u64 {
+    g: fn(u64) -> u64 = square
+    return apply(square, n) + g(2)
+}
+
+@entry
+fn run_lambda(n: u64) -> u64 {
+    add_one: fn(u64) -> u64 = |x: u64| -> u64 { return x + 1 }
+    return apply(add_one, n) + apply(|x: u64| -> u64 { return x * 10 }, n)
+}
+
+@entry
+fn run_sort() -> u64 {
+    l: [u64, 6] = uninit
+    l[0] = 5
+    l[1] = 3
+    l[2] = 9
+    l[3] = 1
+    l[4] = 7
+    l[5] = 2
+    desc: [u64, 6] = sort(l, |a: u64, b: u64| -> bool { return a > b })
+    return desc[0] * 100 + desc[5]
+}
+
+a = run_apply(3)
+println(f"a: {a}")
+assert(a == 13, "fn_pointer: testcase1: a should be 13")
+b = run_lambda(4)
+println(f"b: {b}")
+assert(b == 45, "fn_pointer: testcase2: b should be 45")
+c = run_sort()
+println(f"c: {c}")
+assert(c == 901, "fn_pointer: testcase3: c should be 901")
+
+d = run_struct(5)
+println(f"d: {d}")
+assert(d == 115, "fn_pointer: testcase4: d should be 115")
@@ -0,0 +1,73 @@
+@static
+fn max[T](a: T, b: T) -> T {
+    if a > b {
+        return a
+    }
+    return b
+}
+
+struct Pair[A, B] {
+    first: A
+    second: B
+
+    @static
+    fn make(first: A, second: B) -> Ptr[Pair[A, B]] {
+        p: Ptr[Pair[A, B]] = Pair[A, B].alloc
+        p.first = first
+        p.second = second
+        return p
+    }
+
+    @static
+    fn larger(self: Ptr[Pair[A, B]]) -> B {
+        if self.first > self.second {
+            return self.first as B
+        }
+        return self.second
+    }
+}
+
+@static
+fn first_of[A, B](p: Ptr[Pair[A, B]]) -> A {
+    return p.first
+}
+
+@entry
+fn test_max() -> u64 {
+    a: u32 = 7
+    b: u32 = 9
+    c: u64 = 40
+    d: u64 = 2
+    return max(a, b) + max(c, d)
+}
+
+@entry
+fn test_max_float() -> f64 {
+    a: f64 = 1.5
+    b: f64 = 2.5
+    return max(a, b)
+}
+
+@entry
+fn test_pair() -> u64 {
+    p: Ptr[Pair[u8, u64]] = Pair[u8, u64].make(200, 100)
+    q: Ptr[Pair[u32, u64]] = Pair[u32, u64].alloc
+    q.first = 3
+    q.second = 1000
+    res: u64 = p.larger() + q.larger() + first_of(q)
+    p.free
+    q.free
+    return res
+}
+
+a = test_max()
+println(f"a: {a}")
+assert(a == 49, "generics: testcase1: a should be 49")
+
+b = test_max_float()
+println(f"b: {b}")
+assert(b == 2.5, "generics: testcase2: b should be 2.5")
+
+c = test_pair()
+println(f"c: {c}")
+assert(c == 1203, "generics: testcase3: c should be 1203")
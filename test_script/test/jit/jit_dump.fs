@@ -0,0 +1,19 @@
+@static
+fn add(a: i64, b: i64) -> i64 {
+    return a + b
+}
+
+@entry
+fn entry_add(a: i64, b: i64) -> i64 {
+    return add(a, b)
+}
+
+fn twice(n) {
+    return n * 2
+}
+
+# the dumped code is compiled again, the functions keep running as before
+jit_dump(entry_add)
+jit_dump(twice)
+assert(entry_add(1, 2) == 3, "jit_dump: entry_add should still work")
+assert(twice(4) == 8, "jit_dump: twice should still work")
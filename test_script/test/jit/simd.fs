@@ -0,0 +1,89 @@
+@static
+fn axpy(a: f32, x: f32x4, y: f32x4) -> f32x4 {
+    return x * a + y
+}
+
+@static
+fn hsum(v: f64x4) -> f64 {
+    return v[0] + v[1] + v[2] + v[3]
+}
+
+@entry
+fn test_f32() -> f32 {
+    x: f32x4 = f32x4(1.0, 2.0, 3.0, 4.0)
+    y: f32x4 = f32x4(0.5)
+    r: f32x4 = axpy(2.0, x, y)
+    r[3] = r[3] - 1.0
+    return r[0] + r[1] + r[2] + r[3]
+}
+
+@entry
+fn test_f64() -> f64 {
+    a: f64x4 = f64x4(1.0, 2.0, 3.0, 4.0)
+    b: f64x4 = f64x4(4.0, 3.0, 2.0, 1.0)
+    c: f64x4 = a * b - b / 2.0
+    return hsum(c)
+}
+
+@entry
+fn test_i32() -> i64 {
+    a: i32x8 = i32x8(1, 2, 3, 4, 5, 6, 7, 8)
+    b: i32x8 = i32x8(3)
+    i: u64 = 0
+    acc: i32x8 = i32x8(0)
+    while i < 10 {
+        acc = acc + a * b
+        i = i + 1
+    }
+    d: i32x8 = acc / b
+    total: i64 = 0
+    j: u64 = 0
+    while j < 8 {
+        total = total + d[j]
+        j = j + 1
+    }
+    return total
+}
+
+@entry
+fn test_i64(n: i64) -> i64 {
+    a: i64x2 = i64x2(10, 20)
+    b: i64x2 = a % n
+    return b[0] + b[1]
+}
+
+@entry
+fn test_div(n: i32) -> i32 {
+    a: i32x4 = i32x4(8)
+    b: i32x4 = a / n
+    return b[2]
+}
+
+a = test_f32()
+println(f"a: {a}")
+assert(a == 21.0, "simd: testcase1: a should be 21.0")
+
+b = test_f64()
+println(f"b: {b}")
+assert(b == 15.0, "simd: testcase2: b should be 15.0")
+
+c = test_i32()
+println(f"c: {c}")
+assert(c == 360, "simd: testcase3: c should be 360")
+
+d = test_i64(7)
+println(f"d: {d}")
+assert(d == 9, "simd: testcase4: d should be 9")
+
+e = test_div(4)
+assert(e == 2, "simd: testcase5: e should be 2")
+
+caught = false
+try {
+    test_div(0)
+} catch {
+    err = take_error()
+    println(err)
+    caught = true
+}
+assert(caught, "simd: testcase6: division of a lane by zero should be caught")
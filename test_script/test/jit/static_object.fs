@@ -0,0 +1,96 @@
+class Point {
+    fn __new__(self, x, y) {
+        self.x = x
+        self.y = y
+        return self
+    }
+}
+
+@static
+fn sum_list(l: Object) -> i64 {
+    i: u64 = 0
+    total: i64 = 0
+    while i < l.len() {
+        total = total + l.get_int(i)
+        i = i + 1
+    }
+    return total
+}
+
+@entry
+fn run_sum(l: Object) -> i64 {
+    return sum_list(l)
+}
+
+@entry
+fn run_points(points: Object) -> f64 {
+    i: u64 = 0
+    total: f64 = 0.0
+    while i < points.len() {
+        p: Object = points.get(i)
+        total = total + p.get_float("x") * p.get_int("y")
+        i = i + 1
+    }
+    return total
+}
+
+@entry
+fn run_map(m: Object, key: string) -> i64 {
+    v: Object = m.get(key)
+    n: i64 = m.len() as i64
+    return v.as_int() + m.get_int(1) + n
+}
+
+@entry
+fn run_pick(l: Object, index: i64) -> Object {
+    return l.get(index)
+}
+
+a = run_sum([1, 2, 3, 4])
+println(f"a: {a}")
+assert(a == 10, "static_object: testcase1: a should be 10")
+
+b = run_points([Point(1.5, 2), Point(2, 3)])
+println(f"b: {b}")
+assert(b == 9.0, "static_object: testcase2: b should be 9.0")
+
+m = HashMap::new()
+m.insert("x", 40)
+m.insert(1, 2)
+c = run_map(m, "x")
+println(f"c: {c}")
+assert(c == 44, "static_object: testcase3: c should be 44")
+
+l = ["a", "b", "c"]
+d = run_pick(l, 1)
+assert(d == "b", "static_object: testcase4: d should be b")
+
+caught = false
+try {
+    run_pick(l, 5)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "static_object: testcase5: index out of range should be caught")
+
+caught = false
+try {
+    run_sum([1, "two"])
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "static_object: testcase6: non integer item should be caught")
+
+# collections during the loop keep the list alive
+big = []
+j = 0
+while j < 20000 {
+    big.push(j)
+    j = j + 1
+}
+s = run_sum(big)
+assert(s == 199990000, "static_object: testcase7: s should be 199990000")
@@ -0,0 +1,76 @@
+@static
+fn check_positive(n: i64) -> i64 {
+    if n < 0 {
+        "negative input".raise
+    }
+    return n
+}
+
+@static
+fn double_checked(n: i64) -> i64 {
+    v: i64 = check_positive(n)
+    return v * 2
+}
+
+@static
+fn div(a: i64, b: i64) -> i64 {
+    return a / b
+}
+
+@entry
+fn run(n: i64) -> i64 {
+    return double_checked(n) + 1
+}
+
+@entry
+fn run_div(a: i64, b: i64) -> i64 {
+    return div(a, b)
+}
+
+@entry
+fn run_rem(a: u32, b: u32) -> u32 {
+    return a % b
+}
+
+a = run(20)
+println(f"a: {a}")
+assert(a == 41, "static_raise: testcase1: a should be 41")
+
+caught = false
+try {
+    run(-1)
+    assert(false, "static_raise: testcase2: should not reach here")
+} catch {
+    e = take_error()
+    println(e)
+    assert(e == "negative input", "static_raise: testcase2: e should be the raised string")
+    caught = true
+}
+assert(caught, "static_raise: testcase2: raise should be caught")
+
+b = run_div(7, 2)
+assert(b == 3, "static_raise: testcase3: b should be 3")
+
+caught = false
+try {
+    run_div(7, 0)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "static_raise: testcase4: division by zero should be caught")
+
+caught = false
+try {
+    run_rem(7, 0)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "static_raise: testcase5: remainder by zero should be caught")
+
+# the thread is clean after an error
+c = run(5)
+assert(c == 11, "static_raise: testcase6: c should be 11")
@@ -0,0 +1,53 @@
+struct Counter {
+    count: u64
+    step: u64
+
+    @static
+    fn make(step: u64) -> Ptr[Counter] {
+        c: Ptr[Counter] = Counter.alloc
+        c.count = 0
+        c.step = step
+        return c
+    }
+
+    @static
+    fn tick(self: Ptr[Counter]) -> u64 {
+        self.count = self.count + self.step
+        return self.count
+    }
+
+    @static
+    fn tick_n(self: Ptr[Counter], n: u64) -> u64 {
+        i: u64 = 0
+        while i < n {
+            self.tick()
+            i = i + 1
+        }
+        return self.count
+    }
+
+    @entry
+    fn run(step: u64, n: u64) -> u64 {
+        c: Ptr[Counter] = Counter.make(step)
+        res: u64 = c.tick_n(n)
+        c.free
+        return res
+    }
+}
+
+@entry
+fn test() -> u64 {
+    c: Ptr[Counter] = Counter.make(3)
+    c.tick()
+    res: u64 = c.tick_n(4)
+    c.free
+    return res
+}
+
+a = test()
+println(f"a: {a}")
+assert(a == 15, "struct_methods: testcase1: a should be 15")
+
+b = Counter.run(5, 10)
+println(f"b: {b}")
+assert(b == 50, "struct_methods: testcase2: b should be 50")
@@ -0,0 +1,187 @@
+@entry
+fn ops_u8(a: u8, b: u8) -> u8 {
+    c: u8 = a + b
+    return c
+}
+
+@entry
+fn div_u8(a: u8, b: u8) -> u8 {
+    return a / b
+}
+
+@entry
+fn ops_u16(a: u16) -> u16 {
+    b: u16 = 2
+    return a + b
+}
+
+@entry
+fn div_u32(a: u32) -> u32 {
+    return a / 3
+}
+
+@entry
+fn rem_u32(a: u32) -> u32 {
+    return a % 7
+}
+
+@entry
+fn cmp_u32(a: u32) -> bool {
+    return a > 1
+}
+
+@entry
+fn div_u64() -> u64 {
+    a: u64 = 0
+    a = a - 1
+    return a / 2
+}
+
+@entry
+fn ops_i8(a: i8) -> i8 {
+    b: i8 = a - 100
+    return b
+}
+
+@entry
+fn div_i8(a: i8, b: i8) -> i8 {
+    return a / b
+}
+
+@entry
+fn rem_i16(a: i16, b: i16) -> i16 {
+    return a % b
+}
+
+@entry
+fn ops_i32(a: i32) -> i32 {
+    b: i32 = a - 2000000000
+    return b
+}
+
+@entry
+fn cmp_i32(a: i32) -> bool {
+    return a < 1
+}
+
+@entry
+fn div_i64(a: i64, b: i64) -> i64 {
+    return a / b
+}
+
+@entry
+fn ops_f32(a: f32) -> f32 {
+    b: f32 = a * 2.0
+    return b
+}
+
+@entry
+fn ops_f64(a: f64, b: f64) -> f64 {
+    return a / b
+}
+
+@entry
+fn rem_f64(a: f64, b: f64) -> f64 {
+    return a % b
+}
+
+@entry
+fn rem_f32(a: f32, b: f32) -> f32 {
+    return a % b
+}
+
+@entry
+fn mixed_f64(a: f64) -> f64 {
+    c: f64 = a + 1
+    c += 2
+    return c
+}
+
+@entry
+fn cmp_f64(a: f64, b: f64) -> bool {
+    return a < b
+}
+
+@entry
+fn widen(a: u8, b: u64) -> u64 {
+    c: u64 = a + b
+    return c
+}
+
+@entry
+fn narrow(a: u64) -> u32 {
+    c: u32 = a
+    return c
+}
+
+@entry
+fn cast_u8(a: i64) -> i64 {
+    b: i64 = a as u8
+    return b
+}
+
+@entry
+fn cast_truncate(a: f64) -> i64 {
+    return a as i64
+}
+
+@entry
+fn cast_float(a: i64) -> f64 {
+    return a as f64 / 2
+}
+
+@entry
+fn cast_zero_extend(a: u32) -> u64 {
+    return a as u64
+}
+
+@entry
+fn cast_sign_extend(a: i32) -> i64 {
+    return a as i64
+}
+
+@entry
+fn cast_unsigned(a: i64) -> u64 {
+    b: u64 = a as u64 / 2
+    return b
+}
+
+@entry
+fn not_const() -> bool {
+    b: bool = not 0
+    return b
+}
+
+assert(ops_u8(200, 100) == 44, "u8 add should wrap")
+assert(div_u8(250, 7) == 35)
+assert(ops_u16(65535) == 1, "u16 add should wrap")
+assert(div_u32(4000000000) == 1333333333, "u32 division should be unsigned")
+assert(rem_u32(4000000001) == 4)
+assert(cmp_u32(4000000000), "u32 compare should be unsigned")
+assert(div_u64() == 9223372036854775807, "u64 division should be unsigned")
+assert(ops_i8(-100) == 56, "i8 sub should wrap")
+assert(div_i8(-7, 2) == -3)
+assert(rem_i16(-7, 2) == -1)
+assert(ops_i32(-2000000000) == 294967296, "i32 sub should wrap")
+assert(cmp_i32(-5), "i32 compare should be signed")
+assert(div_i64(-7, 2) == -3)
+assert(ops_f32(1.5) == 3.0)
+assert(ops_f64(7.5, 2.0) == 3.75)
+assert(rem_f64(7.5, 2.0) == 1.5)
+assert(rem_f64(-7.5, 2.0) == -1.5)
+assert(rem_f64(100000000000000000000.0, 3.0) == 1.0, "float remainder should be exact")
+assert(rem_f32(7.5, 2.0) == 1.5)
+assert(mixed_f64(0.5) == 3.5, "integer literal should widen to float")
+assert(cmp_f64(0.5, 1.5))
+assert(cmp_f64(1.5, 0.5) == false)
+assert(widen(200, 1000) == 1200, "u8 should widen to u64")
+assert(narrow(4294967297) == 1, "u64 should narrow to u32")
+assert(cast_u8(300) == 44)
+assert(cast_truncate(3.75) == 3)
+assert(cast_truncate(-3.75) == -3)
+assert(cast_float(-3) == -1.5)
+assert(cast_zero_extend(4000000000) == 4000000000, "u32 should zero extend")
+assert(cast_sign_extend(-5) == -5, "i32 should sign extend")
+assert(cast_unsigned(-2) == 9223372036854775807)
+assert(not_const())
+println("test_numeric: ok")
@@ -0,0 +1,89 @@
+struct Named {
+    id: u64
+    name: string
+}
+
+@static
+fn greet(name: string) -> string {
+    return "hello, " + name
+}
+
+@entry
+fn entry_greet(name: string) -> string {
+    s: string = greet(name)
+    s += "!"
+    return s
+}
+
+@entry
+fn str_len(s: string) -> u64 {
+    return s.len()
+}
+
+@entry
+fn str_byte(s: string, i: u64) -> u8 {
+    return s[i]
+}
+
+@entry
+fn str_slice(s: string, start: u64, end: u64) -> string {
+    return s.slice(start, end)
+}
+
+@entry
+fn str_eq(a: string, b: string) -> bool {
+    return a == b
+}
+
+@entry
+fn str_less(a: string, b: string) -> bool {
+    return a < b
+}
+
+@entry
+fn count_byte(s: string, b: u8) -> u64 {
+    n: u64 = 0
+    i: u64 = 0
+    while i < s.len() {
+        if s[i] == b {
+            n += 1
+        }
+        i += 1
+    }
+    return n
+}
+
+@entry
+fn repeat(s: string, n: u64) -> string {
+    ret: string = ""
+    i: u64 = 0
+    while i < n {
+        ret = ret + s
+        i += 1
+    }
+    return ret
+}
+
+@entry
+fn struct_field(s: string) -> string {
+    named: Ptr[Named] = Named.alloc
+    named.id = 1
+    named.name = s
+    return named.name + "?"
+}
+
+assert(entry_greet("fscript") == "hello, fscript!")
+assert(str_len("hello") == 5)
+assert(str_len("") == 0)
+assert(str_byte("abc", 1) == 98)
+assert(str_byte("abc", 10) == 0, "out of range byte should be 0")
+assert(str_slice("hello world", 6, 11) == "world")
+assert(str_slice("hello", 3, 100) == "lo", "slice end should be clamped")
+assert(str_eq("abc", "abc"))
+assert(str_eq("abc", "abd") == false)
+assert(str_less("abc", "abd"))
+assert(str_less("b", "abc") == false)
+assert(count_byte("banana", 97) == 3)
+assert(repeat("ab", 3) == "ababab")
+assert(struct_field("name") == "name?")
+println("test_string: ok")
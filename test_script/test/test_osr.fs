@@ -0,0 +1,68 @@
+# loops of the main module run long enough to be compiled while running
+
+i = 0
+total = 0
+while i < 20000 {
+    if i % 3 == 0 {
+        total = total + 1
+    } else if i % 3 == 1 {
+        total = total + 2
+    } else {
+        total = total + 3
+    }
+    i = i + 1
+}
+assert(total == 39999)
+
+n = 0
+for x in 0..20000 {
+    if x == 15000 {
+        break
+    }
+    if x % 2 == 0 {
+        continue
+    }
+    n = n + x
+}
+assert(n == 56250000)
+
+outer = 0
+k = 0
+while k < 100 {
+    m = 0
+    while m < 200 {
+        outer = outer + 1
+        m = m + 1
+    }
+    k = k + 1
+}
+assert(outer == 20000)
+
+fn find(limit) {
+    j = 0
+    while true {
+        if j * j > limit {
+            return j
+        }
+        j = j + 1
+    }
+    return -1
+}
+
+assert(find(400000000) == 20001)
+
+caught = false
+c = 0
+try {
+    while c < 20000 {
+        if c == 10000 {
+            c = c + "a"
+        }
+        c = c + 1
+    }
+} catch {
+    caught = true
+}
+assert(caught)
+assert(c == 10000)
+println("test_osr: ok")
@@ -0,0 +1,92 @@
+fn square(x) {
+    return x * x
+}
+
+fn is_even(x) {
+    return x % 2 == 0
+}
+
+pool = ThreadPool::new(4)
+assert(pool.size() == 4, "pool: testcase1: pool should have 4 workers")
+
+f = pool.submit(square, 7)
+assert(f.get() == 49, "pool: testcase2: submit should return 49")
+
+futures = []
+i = 0
+while i < 20 {
+    futures.push(pool.submit(square, i))
+    i = i + 1
+}
+
+total = 0
+for fu in futures {
+    total = total + fu.get()
+}
+assert(total == 2470, "pool: testcase3: sum of squares should be 2470")
+
+caught = false
+try {
+    f.get()
+} catch {
+    e = take_error()
+    caught = true
+}
+assert(caught, "pool: testcase4: a result is taken once")
+
+fn fail(x) {
+    Exception().raise
+}
+
+caught = false
+try {
+    pool.submit(fail, 1).get()
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "pool: testcase5: error of a task should be raised by get")
+
+squares = pool.map(square, [1, 2, 3, 4, 5])
+assert(squares == [1, 4, 9, 16, 25], "pool: testcase6: map should keep the order")
+pool.shutdown()
+
+caught = false
+try {
+    pool.submit(square, 1)
+} catch {
+    e = take_error()
+    caught = true
+}
+assert(caught, "pool: testcase7: submit after shutdown should fail")
+
+l = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]
+assert(l.par_map(square) == l.map(square), "pool: testcase8: list par_map")
+assert(l.par_filter(is_even) == [2, 4, 6, 8, 10], "pool: testcase9: list par_filter")
+
+evens = (0..1000).par_filter(is_even)
+assert(evens.len() == 500, "pool: testcase10: range par_filter should keep 500 items")
+assert(evens[0] == 0 and evens[499] == 998, "pool: testcase11: range par_filter should keep the order")
+
+mapped = (0..5).map(|x| { return x + 1 }).par_map(square)
+assert(mapped == [1, 4, 9, 16, 25], "pool: testcase12: iterator par_map")
+
+counter = AtomicInt::new(0)
+
+fn add(x) {
+    counter.fetch_add(x)
+}
+
+(0..100).par_for_each(add)
+assert(counter.load() == 4950, "pool: testcase13: par_for_each should run every item")
+
+words = ["a", "bb", "ccc"].par_map(|x| { return x + "!" })
+assert(words == ["a!", "bb!", "ccc!"], "pool: testcase14: par_map with a lambda")
+
+fn nested(x) {
+    return [x, x + 1].par_map(square)
+}
+
+nested_res = [1, 2].par_map(nested)
+assert(nested_res == [[1, 4], [4, 9]], "pool: testcase15: adapters in a worker run in place")
@@ -0,0 +1,59 @@
+fn sum_to(n) {
+    total = 0
+    i = 0
+    while i < n {
+        total += i
+        i = i + 1
+    }
+    return total
+}
+
+fn scale(x, k) {
+    return x * k - x
+}
+
+fn half(x) {
+    return x / 2
+}
+
+fn mixed(a, b) {
+    ret = 0
+    for i in 0..3 {
+        if i == 1 {
+            ret = ret + a
+        } else if i == 2 {
+            j = 0
+            while j < 2 {
+                # the guard of this add fails when `a` is a string
+                ret = ret + (b + b)
+                j += 1
+            }
+        } else {
+            ret = a + a
+        }
+    }
+    return ret
+}
+
+fn rem(a, b) {
+    return a % b
+}
+
+i = 0
+while i < 6000 {
+    assert(sum_to(10) == 45)
+    assert(scale(4, 3) == 8)
+    assert(scale(1.5, 2.0) == 1.5)
+    assert(half(5) == 2.5)
+    assert(mixed(1, 2) == 11)
+    assert(rem(7, 3) == 1)
+    i = i + 1
+}
+
+# operands of a new type leave the specialized code for the interpreter
+assert(mixed(1.5, 0.5) == 6.5)
+assert(mixed("a", "b") == "aaabbbb")
+assert(sum_to(1000) == 499500)
+assert(rem(-7, -1) == 0)
+assert(half(-3) == -1.5)
+println("test_specialize: ok")
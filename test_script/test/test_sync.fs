@@ -0,0 +1,157 @@
+import thread
+
+# a guard is released when the function which took it returns
+fn add_one(m) {
+    g = m.lock()
+    g.set(g.get() + 1)
+}
+
+fn add_many(m) {
+    i = 0
+    while i < 200 {
+        add_one(m)
+        i = i + 1
+    }
+}
+
+counter = Mutex::new(0)
+threads = []
+j = 0
+while j < 4 {
+    threads.push(thread::Thread(add_many, counter))
+    j = j + 1
+}
+
+for th in threads {
+    th.join()
+}
+
+g = counter.lock()
+println(f"counter: {g.get()}")
+assert(g.get() == 800, "sync: testcase1: counter should be 800")
+g.unlock()
+
+caught = false
+g = counter.lock()
+try {
+    counter.lock()
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+g.unlock()
+assert(caught, "sync: testcase2: locking a held mutex twice should fail")
+
+# values are copied in and out of a lock
+items = RwLock::new([1, 2, 3])
+r = items.read()
+l = r.get()
+l.push(4)
+assert(r.get().len() == 3, "sync: testcase3: the kept list should not change")
+r.unlock()
+w = items.write()
+w.set(l)
+w.unlock()
+r = items.read()
+assert(r.get().len() == 4, "sync: testcase4: the kept list should have 4 items")
+r.unlock()
+
+fn count_up(a) {
+    i = 0
+    while i < 500 {
+        a.fetch_add(1)
+        i = i + 1
+    }
+}
+
+a = AtomicInt::new(0)
+t1 = thread::Thread(count_up, a)
+t2 = thread::Thread(count_up, a)
+t1.join()
+t2.join()
+assert(a.load() == 1000, "sync: testcase5: atomic should be 1000")
+assert(a.compare_exchange(1000, 1), "sync: testcase6: compare_exchange should succeed")
+assert(a.compare_exchange(1000, 2) == false, "sync: testcase7: compare_exchange should fail")
+assert(a.swap(9) == 1, "sync: testcase8: swap should return 1")
+
+fn produce(ch) {
+    i = 0
+    while i < 10 {
+        ch.send(i)
+        i = i + 1
+    }
+    ch.close()
+}
+
+ch = Channel::bounded(2)
+p = thread::Thread(produce, ch)
+total = 0
+v = ch.recv()
+while v != none {
+    total = total + v
+    v = ch.recv()
+}
+p.join()
+assert(total == 45, "sync: testcase9: total should be 45")
+
+fn wait_ready(state) {
+    m = state[0]
+    cv = state[1]
+    g = m.lock()
+    while g.get() == false {
+        cv.wait(g)
+    }
+    return "ready"
+}
+
+m = Mutex::new(false)
+cv = Condvar::new()
+waiter = thread::Thread(wait_ready, [m, cv])
+g = m.lock()
+g.set(true)
+g.unlock()
+cv.notify_all()
+assert(waiter.join() == "ready", "sync: testcase10: waiter should see ready")
+
+fn send_words(ch) {
+    ch.send("hello")
+    ch.close()
+}
+
+fn send_numbers(ch) {
+    ch.send(1)
+    ch.send(2)
+    ch.close()
+}
+
+words = Channel::new()
+numbers = Channel::new()
+t1 = thread::Thread(send_words, words)
+t2 = thread::Thread(send_numbers, numbers)
+sum = 0
+got = 0
+while got < 3 {
+    v = select([words, numbers])
+    if v[0] == 1 {
+        sum = sum + v[1]
+    }
+    got = got + 1
+}
+t1.join()
+t2.join()
+assert(sum == 3, "sync: testcase11: select should get both numbers")
+# every channel is closed and drained
+assert(select([words, numbers]) == none, "sync: testcase12: select should return none")
+
+# a tail call keeps the frame holding a guard
+fn read_guard(g) {
+    return g.get()
+}
+
+fn locked_read(m) {
+    g = m.lock()
+    return read_guard(g)
+}
+
+assert(locked_read(Mutex::new(5)) == 5, "sync: testcase13: a guard should outlive a tail call")
@@ -0,0 +1,64 @@
+fn count_down(n, acc) {
+    if n == 0 {
+        return acc
+    }
+
+    return count_down(n - 1, acc + 1)
+}
+
+# deep enough to overflow the stack without frame reuse
+assert(count_down(200000, 0) == 200000, "count_down error")
+
+fn ping(n, other) {
+    if n == 0 {
+        return "ping"
+    }
+
+    return other(n - 1, ping)
+}
+
+fn pong(n, other) {
+    if n == 0 {
+        return "pong"
+    }
+
+    return other(n - 1, pong)
+}
+
+# tail calls alternate between two functions
+assert(ping(100001, pong) == "pong", "ping pong error")
+
+fn apply(f, n) {
+    return f(n)
+}
+
+fn make_adder(n) {
+    a = n
+    b = n + 1
+    fn adder(x) {
+        return x + a + b
+    }
+
+    return apply(adder, 1)
+}
+
+assert(make_adder(1) == 4, "closure in tail call error")
+
+fn tail_in_try(n) {
+    try {
+        return count_down(n, 0)
+    } catch {
+        assert(false, "should not reach here")
+    }
+}
+
+assert(tail_in_try(10) == 10, "tail call in try error")
+
+fn tail_builtin(a) {
+    return id(a)
+}
+
+l = [1, 2, 3]
+assert(tail_builtin(l) == id(l), "tail call to builtin error")
+
+println("tail call ok")
@@ -0,0 +1,101 @@
+import thread
+
+fn sum_items(items) {
+    total = 0
+    for i in items {
+        total = total + i
+    }
+    items.push(100)
+    return total
+}
+
+# the arg is copied, the thread changes its own list
+items = [1, 2, 3, 4]
+t = thread::Thread(sum_items, items)
+a = t.join()
+println(f"a: {a}")
+assert(a == 10, "thread: testcase1: a should be 10")
+assert(items.len() == 4, "thread: testcase2: items of the main thread should not change")
+
+class Point {
+    fn __new__(self, x, y) {
+        self.x = x
+        self.y = y
+        return self
+    }
+}
+
+fn make_point(n) {
+    return Point(n, n * 2)
+}
+
+# the result is copied back, class instances keep their class
+p = thread::Thread(make_point, 3).join()
+assert(p.x == 3, "thread: testcase3: p.x should be 3")
+assert(p.y == 6, "thread: testcase4: p.y should be 6")
+
+fn raise_error(n) {
+    Exception().raise
+}
+
+caught = false
+try {
+    thread::Thread(raise_error, 1).join()
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "thread: testcase5: error of the thread should be raised by join")
+
+# module variables read by a thread are frozen
+shared_list = [1, 2, 3]
+
+fn read_shared(n) {
+    return shared_list.len()
+}
+
+b = thread::Thread(read_shared, 0).join()
+assert(b == 3, "thread: testcase6: b should be 3")
+
+caught = false
+try {
+    shared_list.push(4)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "thread: testcase7: frozen list should not change")
+
+# a thread starts its own threads
+fn spawn_inner(n) {
+    inner = thread::Thread(make_point, n)
+    return inner.join().y
+}
+
+c = thread::Thread(spawn_inner, 5).join()
+assert(c == 10, "thread: testcase8: c should be 10")
+
+fn count_to(n) {
+    i = 0
+    l = []
+    while i < n {
+        l.push(i)
+        i = i + 1
+    }
+    return l.len()
+}
+
+threads = []
+j = 0
+while j < 8 {
+    threads.push(thread::Thread(count_to, 20000))
+    j = j + 1
+}
+
+total = 0
+for th in threads {
+    total = total + th.join()
+}
+assert(total == 160000, "thread: testcase9: total should be 160000")
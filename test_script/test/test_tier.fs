@@ -0,0 +1,108 @@
+# functions called enough times are compiled by the baseline tier,
+# each one runs past the call threshold and is checked on every call
+
+fn fib(n) {
+    if n == 1 or n == 2 {
+        return 1
+    } else {
+        return fib(n - 1) + fib(n - 2)
+    }
+}
+
+fn classify(n) {
+    if n < 0 {
+        return 'negative'
+    } else if n == 0 {
+        return 'zero'
+    } else if n > 100 and n < 1000 {
+        return 'hundreds'
+    }
+    return 'other'
+}
+
+fn loops(n) {
+    s = 0
+    for i in 0..n {
+        if i == 3 {
+            continue
+        }
+        if i > 7 {
+            break
+        }
+        s += i
+    }
+    j = 0
+    while true {
+        j = j + 1
+        if j >= n {
+            break
+        }
+    }
+    return s + j
+}
+
+fn items(xs, i) {
+    return xs[i] * 2
+}
+
+fn negate(v) {
+    return not v
+}
+
+fn nothing() {
+    a = 1
+}
+
+base = 10
+fn use_global(n) {
+    return n + base
+}
+
+class Point {
+    fn __new__(self, x) {
+        self.x = x
+        return self
+    }
+}
+
+fn make_point(x) {
+    return Point(x)
+}
+
+fn fail(n) {
+    if n > 1500 {
+        return n + 'a'
+    }
+    return n
+}
+
+assert(fib(20) == 6765)
+i = 0
+while i < 1500 {
+    assert(classify(-1) == 'negative')
+    assert(classify(0) == 'zero')
+    assert(classify(200) == 'hundreds')
+    assert(classify(2000) == 'other')
+    assert(loops(10) == 35, "loop with continue and break")
+    assert(items([1, 2, 3], 1) == 4)
+    assert(negate(false))
+    assert(negate(1) == false)
+    assert(nothing() == none)
+    assert(use_global(i) == i + 10)
+    assert(make_point(i).x == i)
+    i += 1
+}
+
+i = 0
+caught = 0
+while i < 2000 {
+    try {
+        assert(fail(i) == i)
+    } catch {
+        e = take_error()
+        caught += 1
+    }
+    i += 1
+}
+assert(caught == 499, "errors raised in baseline code are caught by the caller")
+println("test_tier: ok")
@@ -0,0 +1,86 @@
+class Point {
+    fn __new__(self, x: Integer) {
+        self.x = x
+        return self
+    }
+}
+
+@guard
+fn add(a: Integer, b: Integer) -> Integer {
+    return a + b
+}
+
+@guard
+fn get_x(p: Point, default) {
+    return p.x
+}
+
+# `Iterator` is not a class, never checked
+@guard
+fn first(it: Iterator) {
+    return it
+}
+
+fn no_guard(a: Integer) {
+    return a
+}
+
+assert(add(1, 2) == 3, "add error")
+assert(get_x(Point(1), 2) == 1, "get_x error")
+assert(first("abc") == "abc", "unknown hint error")
+assert(no_guard("abc") == "abc", "guard without @guard")
+
+caught = false
+try {
+    add(1, "a")
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "String passed as Integer")
+
+caught = false
+try {
+    get_x(1, 2)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "Integer passed as Point")
+
+
+class Shape {
+    fn __new__(self) {
+        return self
+    }
+}
+
+old_shape = Shape()
+
+class Shape {
+    fn __new__(self) {
+        return self
+    }
+}
+
+@guard
+fn area(s: Shape) {
+    return 0
+}
+
+assert(area(Shape()) == 0, "guard of a redefined class")
+
+# the old class has the same name but is another class
+caught = false
+try {
+    area(old_shape)
+} catch {
+    e = take_error()
+    println(e)
+    caught = true
+}
+assert(caught, "same name guard error")
+
+println("type guard ok")